@@ -0,0 +1,76 @@
+//! Performance regression suite for the tagging pipeline, run on the CPU against the
+//! bundled `test_input_2.txt` corpus:
+//!
+//! * `model_load` - building a `POSModel` from the cached resources
+//! * `single_sentence` - latency of one short sentence on a loaded model
+//! * `batch_throughput` - the whole corpus through `tag_batched` with the default batch size
+//! * `tag_with_config` - the end-to-end path used by the CLI, model load included
+//!
+//! Run with `cargo bench --bench pipeline`; the library is imported as `rustlib`, the
+//! name of its target.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use tch::Device;
+
+use rustlib::config::{Config, DEFAULT_BATCH_SIZE};
+use rustlib::pos_tagging::{POSConfig, POSModel};
+use rustlib::rusttagr;
+use rustlib::sentences;
+use rustlib::warnings::Stderr;
+
+const CORPUS: &str = include_str!("../test_input_2.txt");
+const SENTENCE: &str = "My name is Amy. I live in Paris.";
+
+fn cpu_config() -> POSConfig {
+    POSConfig::default().device(Device::Cpu)
+}
+
+fn model_load(c: &mut Criterion) {
+    let mut group = c.benchmark_group("model_load");
+    group.sample_size(10);
+    group.bench_function("mobilebert_pos", |b| {
+        b.iter(|| POSModel::new(cpu_config()).unwrap())
+    });
+    group.finish();
+}
+
+fn single_sentence(c: &mut Criterion) {
+    let pos_model = POSModel::new(cpu_config()).unwrap();
+    c.bench_function("single_sentence", |b| {
+        b.iter(|| pos_model.predict(&[black_box(SENTENCE)]))
+    });
+}
+
+fn batch_throughput(c: &mut Criterion) {
+    let pos_model = POSModel::new(cpu_config()).unwrap();
+    let sentences = sentences::split(CORPUS);
+
+    let mut group = c.benchmark_group("batch_throughput");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(sentences.len() as u64));
+    group.bench_function("corpus", |b| {
+        b.iter(|| rusttagr::tag_batched(&pos_model, black_box(&sentences), DEFAULT_BATCH_SIZE))
+    });
+    group.finish();
+}
+
+fn tag_with_config(c: &mut Criterion) {
+    let mut config = Config::default();
+    config.device = Some(String::from("cpu"));
+
+    let mut group = c.benchmark_group("tag_with_config");
+    group.sample_size(10);
+    group.bench_function("corpus", |b| {
+        b.iter(|| rusttagr::tag_with_config(black_box(CORPUS), &config, &Stderr).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    model_load,
+    single_sentence,
+    batch_throughput,
+    tag_with_config
+);
+criterion_main!(benches);
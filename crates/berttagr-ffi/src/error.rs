@@ -0,0 +1,144 @@
+//! # Errors across the C interface
+//! Every entry point runs its body through `guard`, so that neither a panic nor an error
+//! unwinds into the host, which would be undefined behavior. Failures are reported with an
+//! error code, returned by the function or with `berttagr_last_error_code`, and a message
+//! kept for the calling thread until its next failure.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::fmt::Display;
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// The call succeeded
+pub const BERTTAGR_OK: c_int = 0;
+/// A pointer argument was null or a string was not valid UTF-8
+pub const BERTTAGR_ERROR_INVALID_ARGUMENT: c_int = 1;
+/// The model could not be loaded
+pub const BERTTAGR_ERROR_MODEL_LOAD: c_int = 2;
+/// The library panicked, for instance when the model failed during a prediction
+pub const BERTTAGR_ERROR_PANIC: c_int = 3;
+/// The callback of the host stopped the tagging
+pub const BERTTAGR_STOPPED: c_int = 4;
+
+thread_local! {
+    static LAST_ERROR: RefCell<(c_int, Option<CString>)> = RefCell::new((BERTTAGR_OK, None));
+}
+
+#[derive(Debug)]
+/// # Failure of an entry point
+pub struct Error {
+    pub code: c_int,
+    pub message: String,
+}
+
+impl Error {
+    pub fn new(code: c_int, message: String) -> Error {
+        Error { code, message }
+    }
+
+    pub fn invalid_argument(message: &str) -> Error {
+        Error {
+            code: BERTTAGR_ERROR_INVALID_ARGUMENT,
+            message: message.to_owned(),
+        }
+    }
+
+    pub fn model_load(err: impl Display) -> Error {
+        Error {
+            code: BERTTAGR_ERROR_MODEL_LOAD,
+            message: format!("Could not load the model: {}", err),
+        }
+    }
+}
+
+/// Run `body`, returning its value, or `failed` once its failure or panic has been
+/// recorded as the last error of the thread
+pub fn guard<T, F>(failed: T, body: F) -> T
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    catch(body).unwrap_or_else(|err| {
+        set_last_error(err);
+        failed
+    })
+}
+
+/// Like `guard` for entry points returning a code, returning the code of the failure
+pub fn guard_code<F>(body: F) -> c_int
+where
+    F: FnOnce() -> Result<c_int, Error>,
+{
+    catch(body).unwrap_or_else(|err| {
+        let code = err.code;
+        set_last_error(err);
+        code
+    })
+}
+
+/// Result of `body`, with a panic turned into an error
+fn catch<T, F>(body: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        Err(Error {
+            code: BERTTAGR_ERROR_PANIC,
+            message: panic_message(&*payload),
+        })
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<String>()
+        .cloned()
+        .or_else(|| {
+            payload
+                .downcast_ref::<&str>()
+                .map(|message| (*message).to_owned())
+        })
+        .unwrap_or_else(|| String::from("the library panicked"))
+}
+
+fn set_last_error(err: Error) {
+    let message = CString::new(err.message.replace('\0', " ")).ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = (err.code, message));
+}
+
+/// Code of the last failure on the calling thread, `BERTTAGR_OK` if none
+#[no_mangle]
+pub extern "C" fn berttagr_last_error_code() -> c_int {
+    LAST_ERROR.with(|last| last.borrow().0)
+}
+
+/// Message of the last failure on the calling thread, or null if none. The string belongs
+/// to the library and stays valid until the next failure on the thread.
+#[no_mangle]
+pub extern "C" fn berttagr_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| match &last.borrow().1 {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn records_failures_and_panics() {
+        let value = guard(0, || Err(Error::invalid_argument("text is null")));
+        assert_eq!(value, 0);
+        assert_eq!(berttagr_last_error_code(), BERTTAGR_ERROR_INVALID_ARGUMENT);
+
+        let code = guard_code(|| -> Result<c_int, Error> { panic!("out of memory") });
+        assert_eq!(code, BERTTAGR_ERROR_PANIC);
+        let message = unsafe { CStr::from_ptr(berttagr_last_error_message()) };
+        assert_eq!(message.to_str(), Ok("out of memory"));
+        assert_eq!(guard_code(|| Ok(BERTTAGR_STOPPED)), BERTTAGR_STOPPED);
+    }
+}
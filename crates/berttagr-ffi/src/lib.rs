@@ -0,0 +1,436 @@
+//! # C interface
+//! Tags text from C and from languages with a C foreign function interface (R, Python,
+//! Julia...). Built as a separate crate so that its ABI can evolve independently of the
+//! Rust API and of the command line, and so that hosts only link the tagging library.
+//!
+//! Strings are NUL-terminated UTF-8. Strings returned by the library belong to the caller
+//! and must be released with `berttagr_free_string`, batches with `berttagr_free_batch`.
+//!
+//! The functions without a handle tag with the default model, loaded by the first call
+//! and reused by the next ones. The batch functions tag an array of sentences in one call,
+//! so that hosts tagging thousands of sentences do not pay the call overhead for each:
+//!
+//! ```c
+//! const char *texts[] = {"My name is Amy.", "I live in Paris."};
+//! BerttagrBatch *batch = berttagr_tag_batch(texts, 2);
+//! for (size_t i = 0; i < batch->len; i++)
+//!     for (size_t j = 0; j < batch->sentences[i].len; j++)
+//!         printf("%s/%s ", batch->sentences[i].tags[j].word, batch->sentences[i].tags[j].label);
+//! berttagr_free_batch(batch);
+//! ```
+//!
+//! `berttagr_tag_stream` instead calls back the host with the tags of each sentence as soon
+//! as its batch is tagged, so that huge documents are never held in one returned buffer.
+//! It reuses the default model as well.
+//!
+//! Hosts keeping models loaded between calls create taggers with `berttagr_create`, from
+//! the settings of the configuration file given as JSON, and tag with their handles,
+//! `berttagr_tag` and the `_with` variants of the other functions:
+//!
+//! ```c
+//! uint64_t tagger = berttagr_create("{\"device\": \"cpu\", \"batch_size\": 64}");
+//! char *json = berttagr_tag(tagger, "My name is Amy.");
+//! berttagr_free_string(json);
+//! BerttagrBatch *batch = berttagr_tag_batch_with(tagger, texts, 2);
+//! berttagr_free_batch(batch);
+//! berttagr_destroy(tagger);
+//! ```
+//!
+//! No panic crosses the interface. Functions returning a pointer return null on failure,
+//! the others return an error code, and `berttagr_last_error_code` and
+//! `berttagr_last_error_message` tell why the last call of the thread failed.
+
+pub mod error;
+pub mod registry;
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::slice;
+
+use rustlib::config::{Config, DEFAULT_BATCH_SIZE};
+use rustlib::format::{format_tags, TagFormat};
+use rustlib::pos_tagging::POSTag;
+use rustlib::warnings::Warning;
+
+use crate::error::{
+    guard, guard_code, Error, BERTTAGR_ERROR_MODEL_LOAD, BERTTAGR_OK, BERTTAGR_STOPPED,
+};
+use crate::registry::{with_model, TAGGERS};
+
+#[repr(C)]
+/// # Word and its label
+pub struct BerttagrTag {
+    pub word: *mut c_char,
+    pub label: *mut c_char,
+}
+
+#[repr(C)]
+/// # Tags of one sentence
+pub struct BerttagrSentence {
+    pub tags: *mut BerttagrTag,
+    pub len: usize,
+}
+
+#[repr(C)]
+/// # Tags of a batch of sentences, in the order of the input
+pub struct BerttagrBatch {
+    pub sentences: *mut BerttagrSentence,
+    pub len: usize,
+}
+
+/// Tag `text` with the default model and return its sentences as a JSON array of
+/// `{"tokens": [{"word", "label"}]}` objects, or null on failure
+///
+/// # Safety
+///
+/// `text` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn berttagr_tag_json(text: *const c_char) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let text = str_arg(text)?;
+        let sentences = rustlib::sentences::split(text);
+        let tags = with_model(None, |pos_model| pos_model.predict(&sentences))?;
+        Ok(into_c_string(format_tags(&tags, TagFormat::Json)))
+    })
+}
+
+/// Tag the `count` sentences of `texts` with the default model and return their tags, one
+/// `BerttagrSentence` per input, or null on failure
+///
+/// # Safety
+///
+/// `texts` must point to `count` pointers to NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn berttagr_tag_batch(
+    texts: *const *const c_char,
+    count: usize,
+) -> *mut BerttagrBatch {
+    guard(ptr::null_mut(), || {
+        let tags = tag_batch(None, texts, count)?;
+        Ok(Box::into_raw(Box::new(into_batch(tags))))
+    })
+}
+
+/// Like `berttagr_tag_batch`, tagging with the tagger of `handle`
+///
+/// # Safety
+///
+/// `texts` must point to `count` pointers to NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn berttagr_tag_batch_with(
+    handle: u64,
+    texts: *const *const c_char,
+    count: usize,
+) -> *mut BerttagrBatch {
+    guard(ptr::null_mut(), || {
+        let tags = tag_batch(Some(handle), texts, count)?;
+        Ok(Box::into_raw(Box::new(into_batch(tags))))
+    })
+}
+
+/// Like `berttagr_tag_batch`, returning the tags as a JSON array with one
+/// `{"tokens": [{"word", "label"}]}` object per input
+///
+/// # Safety
+///
+/// `texts` must point to `count` pointers to NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn berttagr_tag_batch_json(
+    texts: *const *const c_char,
+    count: usize,
+) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let tags = tag_batch(None, texts, count)?;
+        Ok(into_c_string(format_tags(&tags, TagFormat::Json)))
+    })
+}
+
+/// Like `berttagr_tag_batch_json`, tagging with the tagger of `handle`
+///
+/// # Safety
+///
+/// `texts` must point to `count` pointers to NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn berttagr_tag_batch_json_with(
+    handle: u64,
+    texts: *const *const c_char,
+    count: usize,
+) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let tags = tag_batch(Some(handle), texts, count)?;
+        Ok(into_c_string(format_tags(&tags, TagFormat::Json)))
+    })
+}
+
+/// Called by `berttagr_tag_stream` with the `user_data` of the caller, the index of the
+/// sentence in the text and its `len` tags, which are only valid during the call. Returning
+/// a non-zero value stops the tagging.
+pub type BerttagrSentenceCallback = extern "C" fn(
+    user_data: *mut c_void,
+    index: usize,
+    tags: *const BerttagrTag,
+    len: usize,
+) -> c_int;
+
+/// Split `text` into sentences, tag them with the default model and call `callback` with
+/// the tags of each sentence in order. Returns `BERTTAGR_OK` once every sentence has been
+/// passed to `callback`, `BERTTAGR_STOPPED` if `callback` stopped the tagging,
+/// `BERTTAGR_ERROR_INVALID_ARGUMENT` if `callback` is null and the code of the error
+/// otherwise. The model stays locked until the call returns, so `callback`
+/// must not tag with it.
+///
+/// # Safety
+///
+/// `text` must be null or point to a NUL-terminated string. `user_data` is only passed to
+/// `callback`.
+#[no_mangle]
+pub unsafe extern "C" fn berttagr_tag_stream(
+    text: *const c_char,
+    callback: Option<BerttagrSentenceCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    guard_code(|| tag_stream(None, text, callback, user_data))
+}
+
+/// Like `berttagr_tag_stream`, tagging with the tagger of `handle`
+///
+/// # Safety
+///
+/// `text` must be null or point to a NUL-terminated string. `user_data` is only passed to
+/// `callback`.
+#[no_mangle]
+pub unsafe extern "C" fn berttagr_tag_stream_with(
+    handle: u64,
+    text: *const c_char,
+    callback: Option<BerttagrSentenceCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    guard_code(|| tag_stream(Some(handle), text, callback, user_data))
+}
+
+/// Load a tagger with the settings of `config_json`, a JSON object with the keys of the
+/// configuration file, or the default settings if it is null. Returns the handle of the
+/// tagger, or 0 on failure.
+///
+/// # Safety
+///
+/// `config_json` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn berttagr_create(config_json: *const c_char) -> u64 {
+    guard(0, || {
+        let config: Config = if config_json.is_null() {
+            Config::default()
+        } else {
+            serde_json::from_str(str_arg(config_json)?).map_err(|err| {
+                Error::invalid_argument(&format!("Invalid configuration: {}", err))
+            })?
+        };
+        //hosts have no channel for warnings, the unknown labels and unverified files are
+        //left out
+        let pos_model = config
+            .pos_model(&|_: Warning| {})
+            .map_err(|err| Error::new(BERTTAGR_ERROR_MODEL_LOAD, format!("{:#}", err)))?;
+        Ok(TAGGERS.insert(pos_model))
+    })
+}
+
+/// Tag `text` with the tagger of `handle` and return its sentences as in
+/// `berttagr_tag_json`, or null on failure
+///
+/// # Safety
+///
+/// `text` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn berttagr_tag(handle: u64, text: *const c_char) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let text = str_arg(text)?;
+        let sentences = rustlib::sentences::split(text);
+        let tags = with_model(Some(handle), |pos_model| pos_model.predict(&sentences))?;
+        Ok(into_c_string(format_tags(&tags, TagFormat::Json)))
+    })
+}
+
+/// Release the tagger of `handle` once its running calls return. Returns `BERTTAGR_OK`, or
+/// `BERTTAGR_ERROR_INVALID_ARGUMENT` if the handle is unknown.
+#[no_mangle]
+pub extern "C" fn berttagr_destroy(handle: u64) -> c_int {
+    guard_code(|| {
+        if TAGGERS.remove(handle) {
+            Ok(BERTTAGR_OK)
+        } else {
+            Err(Error::invalid_argument("unknown tagger handle"))
+        }
+    })
+}
+
+/// Release a batch returned by `berttagr_tag_batch`
+///
+/// # Safety
+///
+/// `batch` must be null or a batch returned by the library, not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn berttagr_free_batch(batch: *mut BerttagrBatch) {
+    if batch.is_null() {
+        return;
+    }
+    guard((), || {
+        let batch = Box::from_raw(batch);
+        let sentences = Box::from_raw(slice::from_raw_parts_mut(batch.sentences, batch.len));
+        for sentence in sentences.iter() {
+            for tag in Box::from_raw(slice::from_raw_parts_mut(sentence.tags, sentence.len)).iter()
+            {
+                berttagr_free_string(tag.word);
+                berttagr_free_string(tag.label);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Release a string returned by the library
+///
+/// # Safety
+///
+/// `string` must be null or a string returned by the library, not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn berttagr_free_string(string: *mut c_char) {
+    if string.is_null() {
+        return;
+    }
+    guard((), || {
+        drop(CString::from_raw(string));
+        Ok(())
+    })
+}
+
+/// Hand `string` over to the caller, cut at its first NUL byte
+fn into_c_string(string: String) -> *mut c_char {
+    c_string(string).into_raw()
+}
+
+/// `string` cut at its first NUL byte
+fn c_string(string: String) -> CString {
+    match CString::new(string) {
+        Ok(string) => string,
+        Err(err) => {
+            let end = err.nul_position();
+            let mut bytes = err.into_vec();
+            bytes.truncate(end);
+            CString::new(bytes).expect("cut at the first NUL byte")
+        }
+    }
+}
+
+/// The string at `text`, an error if it is null or not valid UTF-8
+unsafe fn str_arg<'a>(text: *const c_char) -> Result<&'a str, Error> {
+    if text.is_null() {
+        return Err(Error::invalid_argument("text is null"));
+    }
+    CStr::from_ptr(text)
+        .to_str()
+        .map_err(|_| Error::invalid_argument("text is not valid UTF-8"))
+}
+
+/// Call `callback` with the tags of each sentence of `text`, tagged by the tagger of
+/// `handle`, or the default model if there is none
+unsafe fn tag_stream(
+    handle: Option<u64>,
+    text: *const c_char,
+    callback: Option<BerttagrSentenceCallback>,
+    user_data: *mut c_void,
+) -> Result<c_int, Error> {
+    let callback = callback.ok_or_else(|| Error::invalid_argument("callback is null"))?;
+    let text = str_arg(text)?;
+    let sentences = rustlib::sentences::split(text);
+    with_model(handle, |pos_model| {
+        for (index, sentence) in pos_model
+            .predict_iter(sentences, DEFAULT_BATCH_SIZE)
+            .enumerate()
+        {
+            let strings: Vec<(CString, CString)> = sentence
+                .into_iter()
+                .map(|tag| (c_string(tag.word), c_string(tag.label)))
+                .collect();
+            let tags: Vec<BerttagrTag> = strings
+                .iter()
+                .map(|(word, label)| BerttagrTag {
+                    word: word.as_ptr() as *mut c_char,
+                    label: label.as_ptr() as *mut c_char,
+                })
+                .collect();
+            if callback(user_data, index, tags.as_ptr(), tags.len()) != 0 {
+                return BERTTAGR_STOPPED;
+            }
+        }
+        BERTTAGR_OK
+    })
+}
+
+/// Tags of the `count` texts of `texts`, each tagged as one sentence by the tagger of
+/// `handle`, or the default model if there is none
+unsafe fn tag_batch(
+    handle: Option<u64>,
+    texts: *const *const c_char,
+    count: usize,
+) -> Result<Vec<Vec<POSTag>>, Error> {
+    if texts.is_null() && count > 0 {
+        return Err(Error::invalid_argument("texts is null"));
+    }
+    let mut input = Vec::with_capacity(count);
+    for i in 0..count {
+        input.push(str_arg(*texts.add(i))?);
+    }
+    with_model(handle, |pos_model| pos_model.predict(&input))
+}
+
+/// Hand the tags of a batch over to the caller
+fn into_batch(tags: Vec<Vec<POSTag>>) -> BerttagrBatch {
+    let sentences: Box<[BerttagrSentence]> = tags
+        .into_iter()
+        .map(|sentence| {
+            let tags: Box<[BerttagrTag]> = sentence
+                .into_iter()
+                .map(|tag| BerttagrTag {
+                    word: into_c_string(tag.word),
+                    label: into_c_string(tag.label),
+                })
+                .collect();
+            let len = tags.len();
+            BerttagrSentence {
+                tags: Box::into_raw(tags) as *mut BerttagrTag,
+                len,
+            }
+        })
+        .collect();
+    let len = sentences.len();
+    BerttagrBatch {
+        sentences: Box::into_raw(sentences) as *mut BerttagrSentence,
+        len,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn batches_round_trip() {
+        let tags = vec![
+            vec![POSTag {
+                word: String::from("Hi"),
+                label: String::from("UH"),
+            }],
+            vec![],
+        ];
+        let batch = Box::into_raw(Box::new(into_batch(tags)));
+        unsafe {
+            assert_eq!((*batch).len, 2);
+            let first = &*(*batch).sentences;
+            assert_eq!(first.len, 1);
+            assert_eq!(CStr::from_ptr((*first.tags).label).to_str(), Ok("UH"));
+            assert_eq!((*(*batch).sentences.add(1)).len, 0);
+            berttagr_free_batch(batch);
+        }
+    }
+}
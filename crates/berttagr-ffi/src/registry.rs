@@ -0,0 +1,124 @@
+//! # Handles of the C interface
+//! Taggers created by `berttagr_create` live in a global registry and are referred to by
+//! numbered handles, so that hosts never hold pointers into Rust memory. Each tagger has its
+//! own lock: calls on different handles run concurrently, calls on the same handle wait for
+//! each other, and a tagger destroyed during a call is released once the call returns.
+//!
+//! The functions without a handle share the default model, loaded by the first of them
+//! and kept until the library is unloaded. Their calls wait for each other.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use lazy_static::lazy_static;
+use rustlib::pos_tagging::POSModel;
+
+use crate::error::Error;
+
+lazy_static! {
+    pub static ref TAGGERS: Registry<POSModel> = Registry::new();
+    static ref DEFAULT_MODEL: Mutex<Option<POSModel>> = Mutex::new(None);
+}
+
+/// Run `f` with the tagger of `handle`, or with the default model if there is none,
+/// loading it on first use. A failed load is retried by the next call.
+pub fn with_model<T, F>(handle: Option<u64>, f: F) -> Result<T, Error>
+where
+    F: FnOnce(&POSModel) -> T,
+{
+    match handle {
+        Some(handle) => {
+            let tagger = TAGGERS
+                .get(handle)
+                .ok_or_else(|| Error::invalid_argument("unknown tagger handle"))?;
+            let pos_model = tagger
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            Ok(f(&pos_model))
+        }
+        None => {
+            let mut default_model = DEFAULT_MODEL
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            if default_model.is_none() {
+                *default_model =
+                    Some(POSModel::new(Default::default()).map_err(Error::model_load)?);
+            }
+            Ok(f(default_model.as_ref().expect("loaded above")))
+        }
+    }
+}
+
+/// # Values shared between threads by handle
+pub struct Registry<T> {
+    entries: Mutex<Entries<T>>,
+}
+
+struct Entries<T> {
+    next_handle: u64,
+    values: HashMap<u64, Arc<Mutex<T>>>,
+}
+
+impl<T> Registry<T> {
+    pub fn new() -> Registry<T> {
+        Registry {
+            entries: Mutex::new(Entries {
+                next_handle: 1,
+                values: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Register `value` and return its handle, never 0 so that 0 can stand for a failure
+    pub fn insert(&self, value: T) -> u64 {
+        let mut entries = self.lock();
+        let handle = entries.next_handle;
+        entries.next_handle += 1;
+        entries.values.insert(handle, Arc::new(Mutex::new(value)));
+        handle
+    }
+
+    /// The value registered as `handle`
+    pub fn get(&self, handle: u64) -> Option<Arc<Mutex<T>>> {
+        self.lock().values.get(&handle).cloned()
+    }
+
+    /// Unregister `handle`, returning whether it was registered
+    pub fn remove(&self, handle: u64) -> bool {
+        self.lock().values.remove(&handle).is_some()
+    }
+
+    //a panic while the lock is held leaves the map consistent, so poisoning is ignored
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries<T>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T> Default for Registry<T> {
+    fn default() -> Registry<T> {
+        Registry::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hands_out_distinct_handles() {
+        let registry = Registry::new();
+        let first = registry.insert("first");
+        let second = registry.insert("second");
+        assert_ne!(first, 0);
+        assert_ne!(first, second);
+        let value = registry.get(second).unwrap();
+        assert!(registry.remove(second));
+        assert!(!registry.remove(second));
+        assert!(registry.get(second).is_none());
+        //a value removed during a call stays usable by the call
+        assert_eq!(*value.lock().unwrap(), "second");
+        assert_eq!(*registry.get(first).unwrap().lock().unwrap(), "first");
+    }
+}
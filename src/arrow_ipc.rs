@@ -0,0 +1,210 @@
+//! # Arrow IPC streams
+//! With the `arrow-ipc` feature, the texts of an Arrow IPC stream are tagged into another
+//! stream, so that data engineering systems (Spark, Polars, DuckDB, ...) exchange columns
+//! with the tagger instead of serializing them to JSON or CSV:
+//!
+//! ```text
+//! berttagr_file arrow --text-column body - - < reviews.arrows > tags.arrows
+//! ```
+//!
+//! The record batches of the input have a `Utf8` or `LargeUtf8` text column, named
+//! `DEFAULT_TEXT_COLUMN` unless another name is given. Each batch of the output holds the
+//! words of the texts of one input batch, a row per word with the columns of
+//! `output_schema`: the row of the text in the input stream, the sentence in the text, the
+//! word in the sentence, all from 0, then the word, its label and its score. Null texts
+//! have no words. Texts are split into sentences as the other inputs are, by line with
+//! `pretokenized_sentences` or `pretokenized`, and `filter_tags` and `min_score` leave
+//! words out.
+
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use anyhow::{bail, Context};
+use arrow::array::{Array, ArrayRef, Float64Array, LargeStringArray, StringArray, UInt32Array, UInt64Array};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::ipc::reader::StreamReader;
+use arrow::ipc::writer::StreamWriter;
+use arrow::record_batch::RecordBatch;
+
+use crate::config::Config;
+use crate::pos_tagging::PredictStats;
+use crate::tagger::{AdaptiveBatch, Tagger};
+use crate::warnings::WarningSink;
+
+/// Column of the texts when none is given
+pub const DEFAULT_TEXT_COLUMN: &str = "text";
+
+/// Schema of the output batches, one row per word
+pub fn output_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("row", DataType::UInt64, false),
+        Field::new("sentence", DataType::UInt32, false),
+        Field::new("token", DataType::UInt32, false),
+        Field::new("word", DataType::Utf8, false),
+        Field::new("label", DataType::Utf8, false),
+        Field::new("score", DataType::Float64, false),
+    ])
+}
+
+/// Tag the texts of the record batches read from `input` with the configured model, and
+/// write their words to `output`, one batch for each input batch, passing the warnings to
+/// `warnings`
+///
+/// # Returns
+///
+/// * `PredictStats` of the whole stream
+pub fn tag_stream<R: Read, W: Write>(
+    input: R,
+    output: W,
+    text_column: &str,
+    config: &Config,
+    warnings: &dyn WarningSink,
+) -> anyhow::Result<PredictStats> {
+    let tagger = config.tagger(warnings)?;
+    let config = &config.with_tuned_batch_size(tagger.as_ref(), warnings);
+    let mut adaptive_batch = config.adaptive_batch();
+    let reader = StreamReader::try_new(input).context("Could not read the Arrow stream")?;
+    let mut writer = StreamWriter::try_new(output, &output_schema())?;
+    let mut stats = PredictStats::default();
+    let mut first_row = 0;
+    for batch in reader {
+        let batch = batch.context("Could not read a record batch")?;
+        let (tagged, batch_stats) =
+            tag_batch(tagger.as_ref(), &mut adaptive_batch, config, &batch, text_column, first_row, warnings)?;
+        stats.add(&batch_stats);
+        writer.write(&tagged).context("Could not write a record batch")?;
+        first_row += batch.num_rows() as u64;
+    }
+    writer.finish()?;
+    Ok(stats)
+}
+
+/// Words of the texts of the `text_column` of `batch`, tagged by `tagger`, the rows of the
+/// batch being numbered from `first_row`
+pub fn tag_batch(
+    tagger: &dyn Tagger,
+    adaptive_batch: &mut AdaptiveBatch,
+    config: &Config,
+    batch: &RecordBatch,
+    text_column: &str,
+    first_row: u64,
+    warnings: &dyn WarningSink,
+) -> anyhow::Result<(RecordBatch, PredictStats)> {
+    let index = batch
+        .schema()
+        .index_of(text_column)
+        .with_context(|| format!("No column {} in the record batch", text_column))?;
+    let texts = text_values(batch.column(index))
+        .with_context(|| format!("Invalid text column {}", text_column))?;
+
+    //row and index in the text of each sentence
+    let mut positions = Vec::new();
+    let mut sentences = Vec::new();
+    for (row, text) in texts.into_iter().enumerate() {
+        for (index, sentence) in text.map(|text| config.sentences(text)).into_iter().flatten().enumerate() {
+            positions.push((first_row + row as u64, index as u32));
+            sentences.push(sentence);
+        }
+    }
+    let (scored, stats) = if sentences.is_empty() {
+        (Vec::new(), PredictStats::default())
+    } else {
+        adaptive_batch.tag(tagger, &sentences, warnings)?
+    };
+
+    let mut rows = Vec::new();
+    let mut sentence_indices = Vec::new();
+    let mut tokens = Vec::new();
+    let mut words = Vec::new();
+    let mut labels = Vec::new();
+    let mut scores = Vec::new();
+    for ((row, sentence), tags) in positions.into_iter().zip(scored) {
+        for (token, scored_tag) in tags.into_iter().enumerate() {
+            if let Some(filter) = &config.filter_tags {
+                if !filter.contains(&scored_tag.tag.label) {
+                    continue;
+                }
+            }
+            rows.push(row);
+            sentence_indices.push(sentence);
+            tokens.push(token as u32);
+            words.push(scored_tag.tag.word);
+            labels.push(scored_tag.tag.label);
+            scores.push(scored_tag.score);
+        }
+    }
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from(rows)),
+        Arc::new(UInt32Array::from(sentence_indices)),
+        Arc::new(UInt32Array::from(tokens)),
+        Arc::new(StringArray::from(words)),
+        Arc::new(StringArray::from(labels)),
+        Arc::new(Float64Array::from(scores)),
+    ];
+    Ok((RecordBatch::try_new(Arc::new(output_schema()), columns)?, stats))
+}
+
+/// Texts of a `Utf8` or `LargeUtf8` column, `None` when null
+fn text_values(column: &ArrayRef) -> anyhow::Result<Vec<Option<&str>>> {
+    let any = column.as_any();
+    if let Some(array) = any.downcast_ref::<StringArray>() {
+        Ok((0..array.len())
+            .map(|index| if array.is_null(index) { None } else { Some(array.value(index)) })
+            .collect())
+    } else if let Some(array) = any.downcast_ref::<LargeStringArray>() {
+        Ok((0..array.len())
+            .map(|index| if array.is_null(index) { None } else { Some(array.value(index)) })
+            .collect())
+    } else {
+        bail!("Expected Utf8 or LargeUtf8 text, found {:?}", column.data_type())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pos_tagging::{POSTag, ScoredTag};
+    use std::sync::Mutex;
+
+    /// Tags every word `W`
+    struct WordTagger;
+
+    impl Tagger for WordTagger {
+        fn tag(&self, input: &[&str]) -> Vec<Vec<ScoredTag>> {
+            input
+                .iter()
+                .map(|sentence| {
+                    sentence
+                        .split_whitespace()
+                        .map(|word| ScoredTag {
+                            tag: POSTag {
+                                word: word.to_owned(),
+                                label: "W".to_owned(),
+                            },
+                            score: 1.0,
+                        })
+                        .collect()
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn tags_a_row_per_word() {
+        let schema = Schema::new(vec![Field::new("body", DataType::Utf8, true)]);
+        let texts: ArrayRef = Arc::new(StringArray::from(vec![Some("One two. Three."), None, Some("Four")]));
+        let batch = RecordBatch::try_new(Arc::new(schema), vec![texts]).unwrap();
+        let mut adaptive_batch = AdaptiveBatch::new(8, 1, None);
+        let warnings = Mutex::new(Vec::new());
+        let config = Config::default();
+        let (tagged, stats) =
+            tag_batch(&WordTagger, &mut adaptive_batch, &config, &batch, "body", 10, &warnings).unwrap();
+        assert_eq!(stats.sentences, 3);
+        assert_eq!(tagged.num_rows(), 4);
+        let rows = tagged.column(0).as_any().downcast_ref::<UInt64Array>().unwrap();
+        let sentences = tagged.column(1).as_any().downcast_ref::<UInt32Array>().unwrap();
+        let words = tagged.column(3).as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!((rows.value(3), sentences.value(2), words.value(2)), (12, 1, "Three."));
+        assert!(tag_batch(&WordTagger, &mut adaptive_batch, &config, &batch, "text", 0, &warnings).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! # Benchmark
+//! Measures model load time, per-batch latency, throughput and peak memory of the
+//! tagging pipeline on a sample input, for the `bench` subcommand.
+
+use std::fmt;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use tch::Device;
+
+use crate::config::Config;
+use crate::pos_tagging::POSModel;
+use crate::rusttagr;
+use crate::sentences;
+use crate::warnings::WarningSink;
+
+/// # Benchmark results for one device
+pub struct BenchReport {
+    pub device: Device,
+    pub iterations: usize,
+    pub sentences: usize,
+    /// Tokens produced over all iterations
+    pub tokens: usize,
+    pub load_time: Duration,
+    /// Duration of the warm-up pass, excluded from the latencies
+    pub warm_up_time: Duration,
+    /// Latency of every batch over all iterations
+    pub batch_latencies: Vec<Duration>,
+    /// Peak resident memory of the process in kB, if the platform reports it
+    pub peak_memory_kb: Option<u64>,
+}
+
+impl BenchReport {
+    /// Total time spent in inference
+    pub fn inference_time(&self) -> Duration {
+        self.batch_latencies.iter().sum()
+    }
+
+    /// Tokens tagged per second of inference, 0 when nothing was tagged
+    pub fn tokens_per_sec(&self) -> f64 {
+        let secs = self.inference_time().as_secs_f64();
+        if secs > 0.0 {
+            self.tokens as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Batch latency at percentile `p` (0-100), nearest-rank method
+    pub fn latency_percentile(&self, p: f64) -> Duration {
+        let mut latencies = self.batch_latencies.clone();
+        latencies.sort();
+        let rank = ((p / 100.0) * latencies.len() as f64).ceil() as usize;
+        latencies
+            .get(rank.max(1) - 1)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Device:          {:?}", self.device)?;
+        writeln!(f, "Iterations:      {}", self.iterations)?;
+        writeln!(f, "Sentences:       {}", self.sentences)?;
+        writeln!(f, "Model load:      {:.3} s", self.load_time.as_secs_f64())?;
+        writeln!(f, "Warm-up:         {:.3} s", self.warm_up_time.as_secs_f64())?;
+        writeln!(f, "Inference:       {:.3} s", self.inference_time().as_secs_f64())?;
+        writeln!(f, "Throughput:      {:.1} tokens/s", self.tokens_per_sec())?;
+        for &p in &[50.0, 90.0, 99.0] {
+            writeln!(
+                f,
+                "Batch p{:<2}:       {:.1} ms",
+                p,
+                self.latency_percentile(p).as_secs_f64() * 1000.0
+            )?;
+        }
+        match self.peak_memory_kb {
+            Some(kb) => writeln!(f, "Peak memory:     {:.1} MB", kb as f64 / 1024.0),
+            None => writeln!(f, "Peak memory:     unavailable"),
+        }
+    }
+}
+
+/// Devices benchmarked when none is configured: the CPU, and the first GPU if there is one
+/// and the `cuda` feature is enabled
+pub fn default_devices() -> Vec<Device> {
+    let mut devices = vec![Device::Cpu];
+    if cfg!(feature = "cuda") && tch::Cuda::is_available() {
+        devices.push(Device::Cuda(0));
+    }
+    devices
+}
+
+/// Load the model on `device` and tag `input` `iterations` times
+///
+/// # Arguments
+///
+/// * `config` - Model and batching settings
+/// * `device` - Device to benchmark, overriding the configured one
+/// * `input` - Sample text
+/// * `iterations` - Number of passes over the sample
+/// * `warnings` - Receives the model files loaded unverified
+pub fn run(
+    config: &Config,
+    device: Device,
+    input: &str,
+    iterations: usize,
+    warnings: &dyn WarningSink,
+) -> anyhow::Result<BenchReport> {
+    let pos_config = config.pos_config(warnings)?.device(device);
+    let start = Instant::now();
+    let pos_model = POSModel::new(pos_config)?;
+    let load_time = start.elapsed();
+    let start = Instant::now();
+    pos_model.warm_up_batch(config.batch_size());
+    let warm_up_time = start.elapsed();
+
+    let sentences = sentences::split(input);
+    let mut tokens = 0;
+    let mut batch_latencies = Vec::new();
+    for _ in 0..iterations {
+        for batch in rusttagr::length_sorted_batches(&sentences, config.batch_size()) {
+            let texts: Vec<&str> = batch.iter().map(|&index| sentences[index]).collect();
+            let (_, stats) = pos_model.predict_with_stats(&texts);
+            batch_latencies.push(stats.inference_time);
+            tokens += stats.tokens;
+        }
+    }
+
+    Ok(BenchReport {
+        device,
+        iterations,
+        sentences: sentences.len(),
+        tokens,
+        load_time,
+        warm_up_time,
+        batch_latencies,
+        peak_memory_kb: peak_memory_kb(),
+    })
+}
+
+/// Peak resident set size of the process (`VmHWM`), Linux only
+fn peak_memory_kb() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find(|line| line.starts_with("VmHWM:"))?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nearest_rank_percentiles() {
+        let report = BenchReport {
+            device: Device::Cpu,
+            iterations: 1,
+            sentences: 4,
+            tokens: 40,
+            load_time: Duration::from_secs(1),
+            warm_up_time: Duration::from_millis(100),
+            batch_latencies: (1..=10).map(Duration::from_millis).collect(),
+            peak_memory_kb: None,
+        };
+        assert_eq!(report.latency_percentile(50.0), Duration::from_millis(5));
+        assert_eq!(report.latency_percentile(99.0), Duration::from_millis(10));
+        assert_eq!(report.latency_percentile(0.0), Duration::from_millis(1));
+        let empty = BenchReport {
+            batch_latencies: Vec::new(),
+            ..report
+        };
+        assert_eq!(empty.tokens_per_sec(), 0.0);
+    }
+}
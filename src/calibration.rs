@@ -0,0 +1,196 @@
+//! # Score calibration
+//! Maps the confidence scores of the model to the accuracy they actually reach, so that a
+//! `min_score` threshold of 0.9 keeps words labelled correctly about 90% of the time rather
+//! than words the overconfident softmax scores 0.9.
+//!
+//! The `calibrate` subcommand tags an annotated development corpus (CoNLL-U or word and
+//! label per line), measures the accuracy of the words in each score bin, and saves the
+//! mapping as JSON; `calibration = "calibration.json"` (or `--calibration`) applies it to
+//! every score. Temperature scaling would need the logits of every label, which the rust-bert
+//! pipeline does not expose, so the scores of the predicted labels are calibrated by
+//! histogram binning, made non-decreasing so that the ranking of the words is kept.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::hooks::PostProcessor;
+use crate::pos_tagging::ScoredTag;
+use crate::train::read_corpus;
+use crate::warnings::WarningSink;
+
+/// Number of score bins when none is given
+pub const DEFAULT_BINS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// # Accuracy of the words in each score bin
+pub struct Calibration {
+    /// Accuracy of the scores in `[i / n, (i + 1) / n)` for bin `i` of `n`
+    pub accuracies: Vec<f64>,
+    /// Number of development words in each bin
+    pub counts: Vec<usize>,
+}
+
+impl Calibration {
+    /// Fit `bins` bins to the scores of development words and whether their label was
+    /// correct. Empty bins take the accuracy of the bin below, or 0.
+    pub fn fit(scores: &[(f64, bool)], bins: usize) -> anyhow::Result<Calibration> {
+        if bins == 0 {
+            bail!("Calibration requires at least one bin");
+        }
+        if scores.is_empty() {
+            bail!("No words to calibrate on");
+        }
+        let mut counts = vec![0; bins];
+        let mut correct = vec![0; bins];
+        for &(score, is_correct) in scores {
+            let bin = bin_of(score, bins);
+            counts[bin] += 1;
+            if is_correct {
+                correct[bin] += 1;
+            }
+        }
+        let mut accuracies = Vec::with_capacity(bins);
+        let mut previous = 0.0;
+        for bin in 0..bins {
+            let accuracy = if counts[bin] == 0 {
+                previous
+            } else {
+                correct[bin] as f64 / counts[bin] as f64
+            };
+            accuracies.push(accuracy);
+            previous = accuracy;
+        }
+        pool_adjacent_violators(&mut accuracies, &counts);
+        Ok(Calibration { accuracies, counts })
+    }
+
+    /// Calibrated `score`
+    pub fn apply(&self, score: f64) -> f64 {
+        self.accuracies[bin_of(score, self.accuracies.len())]
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Calibration> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read calibration {}", path.display()))?;
+        let calibration: Calibration = serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid calibration {}", path.display()))?;
+        if calibration.accuracies.is_empty() {
+            bail!("Invalid calibration {}, no bins", path.display());
+        }
+        Ok(calibration)
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json + "\n").with_context(|| format!("Could not write {}", path.display()))
+    }
+}
+
+impl PostProcessor for Calibration {
+    fn process(&self, sentence: &mut Vec<ScoredTag>) {
+        for scored_tag in sentence.iter_mut() {
+            scored_tag.score = self.apply(scored_tag.score);
+        }
+    }
+}
+
+fn bin_of(score: f64, bins: usize) -> usize {
+    ((score.max(0.0) * bins as f64) as usize).min(bins - 1)
+}
+
+/// Make `values` non-decreasing by averaging, weighted by `weights`, the runs of bins that
+/// decrease
+fn pool_adjacent_violators(values: &mut [f64], weights: &[usize]) {
+    //blocks of (value, weight, length)
+    let mut blocks: Vec<(f64, f64, usize)> = Vec::with_capacity(values.len());
+    for (&value, &weight) in values.iter().zip(weights) {
+        blocks.push((value, weight as f64, 1));
+        while blocks.len() > 1 && blocks[blocks.len() - 2].0 > blocks[blocks.len() - 1].0 {
+            let (value, weight, length) = blocks.pop().unwrap();
+            let last = blocks.last_mut().unwrap();
+            let total = last.1 + weight;
+            if total > 0.0 {
+                last.0 = (last.0 * last.1 + value * weight) / total;
+            } else {
+                last.0 = (last.0 + value) / 2.0;
+            }
+            last.1 = total;
+            last.2 += length;
+        }
+    }
+    let mut index = 0;
+    for (value, _, length) in blocks {
+        for slot in &mut values[index..index + length] {
+            *slot = value;
+        }
+        index += length;
+    }
+}
+
+/// Tag the sentences of the annotated corpus at `path` with the configured model, without
+/// any calibration, and fit `bins` bins to the scores. Sentences the model splits into other
+/// words than the corpus are left out. The warnings of the model are passed to `warnings`.
+pub fn calibrate_file(
+    path: &Path,
+    bins: usize,
+    config: &Config,
+    warnings: &dyn WarningSink,
+) -> anyhow::Result<Calibration> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Could not read {}", path.display()))?;
+    let corpus = read_corpus(&contents)?;
+    let uncalibrated = Config {
+        calibration: None,
+        min_score: None,
+        ..config.clone()
+    };
+    let pos_model = uncalibrated.pos_model(warnings)?;
+    let texts: Vec<String> = corpus
+        .iter()
+        .map(|sentence| {
+            let words: Vec<&str> = sentence.iter().map(|(word, _)| word.as_str()).collect();
+            words.join(" ")
+        })
+        .collect();
+    let mut scores = Vec::new();
+    for (batch, gold) in texts.chunks(config.batch_size()).zip(corpus.chunks(config.batch_size())) {
+        let batch: Vec<&str> = batch.iter().map(String::as_str).collect();
+        for (predicted, gold) in pos_model.predict_scored(&batch).iter().zip(gold) {
+            let aligned = predicted.len() == gold.len()
+                && predicted.iter().zip(gold).all(|(scored_tag, (word, _))| scored_tag.tag.word == *word);
+            if aligned {
+                scores.extend(
+                    predicted
+                        .iter()
+                        .zip(gold)
+                        .map(|(scored_tag, (_, label))| (scored_tag.score, scored_tag.tag.label == *label)),
+                );
+            }
+        }
+    }
+    Calibration::fit(&scores, bins)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn maps_scores_to_accuracy() {
+        //overconfident: 0.95 scores right 70% of the time
+        let mut scores = vec![(0.95, true); 7];
+        scores.extend(vec![(0.95, false); 3]);
+        scores.extend(vec![(0.55, true); 3]);
+        scores.extend(vec![(0.55, false); 1]);
+        let calibration = Calibration::fit(&scores, 10).unwrap();
+        //0.75 accuracy at 0.55 is above 0.7, so both bins are pooled
+        assert!((calibration.apply(0.97) - 10.0 / 14.0).abs() < 1e-9);
+        assert!((calibration.apply(0.55) - 10.0 / 14.0).abs() < 1e-9);
+        assert!(calibration.accuracies.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(calibration.apply(0.05), 0.0);
+    }
+}
@@ -0,0 +1,103 @@
+//! # Checksum pinning
+//! Expected SHA-256 digests of the model files, set in the configuration file or in an
+//! entry of the model registry:
+//!
+//! ```toml
+//! [sha256]
+//! model = "3b8c1f..."
+//! config = "9a07d2..."
+//! vocab = "c2f5e1..."
+//! ```
+//!
+//! The pinned files are hashed once downloaded and before the model is loaded, and a
+//! mismatch stops the run, unless `--allow-unverified` makes it a warning. Files that are
+//! not pinned are not checked.
+
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use rust_bert::pipelines::token_classification::TokenClassificationConfig;
+use rust_bert::resources::Resource;
+use serde::Deserialize;
+
+use crate::metadata::sha256_file;
+use crate::warnings::{Warning, WarningSink};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// # Hexadecimal SHA-256 digests of the model files
+pub struct Checksums {
+    /// Weights, `rust_model.ot`
+    pub model: Option<String>,
+    /// `config.json`
+    pub config: Option<String>,
+    /// Vocabulary
+    pub vocab: Option<String>,
+    /// Merges, for RoBERTa models
+    pub merges: Option<String>,
+}
+
+impl Checksums {
+    /// Check the pinned files of `config`, downloaded already. A mismatch is an error, or a
+    /// warning passed to `warnings` when `allow_unverified`.
+    pub fn verify(
+        &self,
+        config: &TokenClassificationConfig,
+        allow_unverified: bool,
+        warnings: &dyn WarningSink,
+    ) -> anyhow::Result<()> {
+        let pins = [
+            ("model", Some(&config.model_resource), &self.model),
+            ("config", Some(&config.config_resource), &self.config),
+            ("vocab", Some(&config.vocab_resource), &self.vocab),
+            ("merges", config.merges_resource.as_ref(), &self.merges),
+        ];
+        for (kind, resource, expected) in pins.iter() {
+            let expected = match expected {
+                Some(expected) => expected,
+                None => continue,
+            };
+            let path = match resource {
+                Some(Resource::Local(local)) => &local.local_path,
+                Some(Resource::Remote(remote)) => bail!("The {} file {} was not downloaded to be verified", kind, remote.url),
+                None => bail!("The {} file is pinned but the model has none", kind),
+            };
+            if let Err(err) = verify_file(path, expected) {
+                if !allow_unverified {
+                    return Err(err.context("Refusing to load an unverified model, see --allow-unverified"));
+                }
+                warnings.warn(Warning::UnverifiedFile {
+                    path: path.display().to_string(),
+                    error: format!("{:#}", err),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check that the file at `path` has the SHA-256 digest `expected`
+pub fn verify_file(path: &Path, expected: &str) -> anyhow::Result<()> {
+    let actual = sha256_file(path).with_context(|| format!("Could not read {}", path.display()))?;
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        bail!("SHA-256 of {} is {}, expected {}", path.display(), actual, expected.trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn detects_mismatches() {
+        let path = std::env::temp_dir().join(format!("berttagr-checksums-{}.txt", std::process::id()));
+        fs::write(&path, "abc").unwrap();
+        let digest = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert!(verify_file(&path, digest).is_ok());
+        assert!(verify_file(&path, &digest.to_uppercase()).is_ok());
+        assert!(verify_file(&path, &digest.replace('b', "c")).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
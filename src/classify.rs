@@ -0,0 +1,74 @@
+//! # Zero-shot classification
+//! Assigns each sentence of a file one of a set of user-provided labels with rust-bert's
+//! zero-shot pipeline (BART fine-tuned on MNLI), for the `classify` subcommand. Sentence
+//! splitting, batching, device placement and output formats are shared with tagging.
+
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use rust_bert::pipelines::zero_shot_classification::{
+    ZeroShotClassificationConfig, ZeroShotClassificationModel,
+};
+
+use crate::config::Config;
+use crate::format::LabelWriter;
+use crate::sentences;
+
+/// Maximum length in tokens of a sentence paired with a label hypothesis
+const MAX_LENGTH: usize = 128;
+
+/// Classify each sentence of `in_path` into one of `labels` and write the results to `out_path`
+///
+/// # Arguments
+///
+/// * `in_path` - Input text file
+/// * `out_path` - Output file, created, replaced or appended to as set by `output_exists`
+/// * `labels` - Candidate labels (e.g. sports, politics, tech)
+/// * `config` - Device, threads, batching and format settings
+pub fn classify_file(
+    in_path: &Path,
+    out_path: &Path,
+    labels: &[String],
+    config: &Config,
+) -> anyhow::Result<()> {
+    let contents = fs::read_to_string(in_path)
+        .with_context(|| format!("Could not read {}", in_path.display()))?;
+    let (output, file) = config.output_policy().begin(out_path)?;
+    classify_to_writer(&contents, BufWriter::new(file), labels, config)?;
+    output.commit()
+}
+
+/// Classify each sentence of `input` into one of `labels` and write the results to `writer`
+pub fn classify_to_writer<W: Write>(
+    input: &str,
+    writer: W,
+    labels: &[String],
+    config: &Config,
+) -> anyhow::Result<()> {
+    if labels.is_empty() {
+        bail!("classify requires at least one label");
+    }
+    let mut label_writer = LabelWriter::new(writer, config.format())?;
+
+    let mut zero_shot_config = ZeroShotClassificationConfig::default();
+    if let Some(device) = config.device()? {
+        zero_shot_config.device = device;
+    }
+    if let Some(threads) = config.threads {
+        tch::set_num_threads(threads as i32);
+    }
+    let zero_shot_model = ZeroShotClassificationModel::new(zero_shot_config)?;
+
+    let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+    let sentences = sentences::split(input);
+    for batch in sentences.chunks(config.batch_size()) {
+        let output = zero_shot_model.predict(batch, &labels, None, MAX_LENGTH);
+        for (sentence, label) in batch.iter().zip(output) {
+            label_writer.write_label(sentence, &label.text, label.score, &label)?;
+        }
+    }
+    label_writer.finish()?;
+    Ok(())
+}
@@ -0,0 +1,893 @@
+//! # Command line arguments
+//! Parses the arguments of the `berttagr_file` binary. Flags produce a `Config` that is
+//! merged over the configuration file.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::slice::Iter;
+
+use anyhow::{anyhow, bail, Context};
+
+use crate::collocations::{CollocationCounter, Measure};
+use crate::calibration::DEFAULT_BINS;
+use crate::config::Config;
+use crate::coverage::DEFAULT_TOP;
+use crate::exit::ErrorFormat;
+use crate::inputs::STDIN;
+use crate::kwic::{KwicQuery, DEFAULT_CONTEXT};
+use crate::ngrams::NgramCounter;
+use crate::normalize::Normalizer;
+use crate::train::TrainOptions;
+
+pub const USAGE: &str = "USAGE: berttagr_file [--summary-json FILE] [OPTIONS] input.txt [input2.txt ...] output.txt
+       berttagr_file --file-list list.txt [--summary-json FILE] [OPTIONS] output.txt
+       find corpus -name '*.txt' -print0 | berttagr_file tag --files-from - -0 [OPTIONS] output.txt
+       berttagr_file --in-place[=SUFFIX] [--summary-json FILE] [OPTIONS] file.txt
+       berttagr_file --clipboard [--copy] [OPTIONS]
+       berttagr_file --follow [OPTIONS] input.txt output.txt
+       berttagr_file --kafka-in TOPIC --kafka-out TOPIC [--kafka-brokers HOSTS] [OPTIONS]
+       berttagr_file --redis-in KEY --redis-out KEY [--redis URL] [--workers N] [OPTIONS]
+       berttagr_file --worker HOST:PORT [OPTIONS]
+       berttagr_file bench --input sample.txt [--iterations N] [OPTIONS]
+       berttagr_file sentiment [OPTIONS] input.txt output.txt
+       berttagr_file classify --labels sports,politics,tech [OPTIONS] input.txt output.txt
+       berttagr_file redact [--mapping FILE] [OPTIONS] input.txt output.txt
+       berttagr_file train [--learning-rate R] [--epochs N] [--eval-split F] [OPTIONS] corpus.conllu model_dir
+       berttagr_file export [OPTIONS] output_dir
+       berttagr_file arrow [--text-column NAME] [OPTIONS] input.arrows output.arrows
+       berttagr_file diff [--confusion-csv FILE] [--confusion-html FILE] a.conllu b.conllu
+       berttagr_file editor [OPTIONS]
+       berttagr_file labels [OPTIONS]
+       berttagr_file model-info [OPTIONS]
+       berttagr_file metrics [OPTIONS] input.txt [input2.txt ...] metrics.csv
+       berttagr_file kwic [--word WORD] [--tag TAG] [--context N] [OPTIONS] input.txt [input2.txt ...]
+       berttagr_file lint [OPTIONS] input.txt [input2.txt ...]
+       berttagr_file ngrams [--n N] [--pattern ADJ+NOUN] [--min-count N] [OPTIONS] input.txt [input2.txt ...] ngrams.csv
+       berttagr_file calibrate [--bins N] [OPTIONS] dev.conllu calibration.json
+       berttagr_file coverage [--top N] [OPTIONS] input.txt [input2.txt ...]
+       berttagr_file collocations [--pattern ADJ+NOUN] [--min-count N] [--measure M] [OPTIONS] input.txt [input2.txt ...] collocations.csv
+
+OPTIONS:
+    --config <FILE>         Configuration file (default: $XDG_CONFIG_HOME/berttagr_file/berttagr.toml)
+    --model <NAME>          Model of the registry (models.toml), e.g. en-pos-mobilebert or en-ner-bert
+    --hub-model <REPO[@REVISION]>
+                            Hugging Face Hub repository of the model, pinned to REVISION if given
+    --model-dir <DIR>       Directory with rust_model.ot, config.json and vocab.txt (vocab.json and
+                            merges.txt for roberta, sentencepiece.bpe.model for xlm-roberta)
+    --model-type <TYPE>     mobilebert (default), bert, distilbert, roberta or xlm-roberta, the
+                            architecture of the model in --model-dir
+    --download-retries <N>  Retries of a failed model download, with exponential backoff (default: 3)
+    --download-timeout <SECONDS>
+                            Timeout of the model download requests (default: 30)
+    --proxy <URL>           Proxy of the model downloads (default: $HTTPS_PROXY)
+    --allow-unverified      Load the model when its files do not match the sha256 digests pinned
+                            in the configuration or the registry, with a warning
+    --device <DEVICE>       cpu, cuda, cuda:N or auto
+    --format <FORMAT>       debug, tsv, json or conllu (sentiment, classify: debug, tsv or json;
+                            labels, model-info, coverage, lint: json for JSON; metrics: json, CSV otherwise)
+    --batch-size <N>        Number of sentences sent to the model at once, or auto to tune it
+                            on the device at startup
+    --max-batch-bytes <N>   Memory budget of a batch, split to stay within N bytes of text
+    --min-batch-size <N>    Stop when out of memory at N sentences, instead of halving the batch
+                            size and tagging the batch again (default: 1)
+    --filter-tags <TAGS>    Comma separated labels to keep (e.g. NN,NNS)
+    --label-map <MAP>       Comma separated renamings of the labels of the model (e.g. LABEL_0=NN)
+    --calibration <FILE>    Calibrate the scores with the mapping written by calibrate
+    --min-score <F>         Leave out the words whose (calibrated) score is below F
+    --lemma                 Add the lemma of each word (tsv and json formats)
+    --morph                 Add the morphological features of each word (tsv and json formats)
+    --normalize             Normalize quotes, dashes, repeated characters and contractions before tagging
+    --protect-tokens        Label URLs, emails, hashtags and mentions without tokenizing them
+    --quantities            Label numbers, dates, times and quantities with their normalized value
+    --emoji-tag <TAG>       Label emoji and emoticons with TAG (e.g. SYM or EMOJI)
+    --parser-command <CMD>  External CoNLL-U dependency parser adding heads and relations
+    --max-file-size <N>     Size in bytes above which an input file hits the limit
+    --max-sentence-length <N>
+                            Length in characters above which a sentence hits the limit
+    --on-limit <ACTION>     skip (with a warning), truncate or error on inputs above the limits
+    --output-exists <POLICY>
+                            error (default), skip, overwrite or append when the output file exists
+    --language <LANG>       Language of the input text
+    --threads <N>           Threads used within an operation on the CPU
+    --interop-threads <N>   Threads running independent operations on the CPU
+    --deterministic         Produce identical outputs on repeated CPU runs
+    --seed <N>              Seed of the random number generators, implies --deterministic
+    --label-aggregation <AGGREGATION>
+                            first (default), last, mode or max-score, the label of words split
+                            into several sub-tokens
+    --no-lower-case         Keep the case of the text, for cased models
+    --no-strip-accents      Keep accents, for models trained on accented text
+    --error-format <FORMAT> text or json, the format of the error printed on failure
+
+TAG OPTIONS:
+    --file-list <FILE>      Tag the files listed in FILE, one per line, into one output
+    --files-from <FILE>     Same as --file-list, reading the list from stdin with -
+    -0, --null              The file list is separated by NUL bytes instead of newlines
+    --in-place[=SUFFIX]     Replace the input file with its tagged version, backing it up with SUFFIX
+    --clipboard             Tag the text of the clipboard and print the result
+    --copy                  With --clipboard, copy the result back to the clipboard instead
+    --follow                Keep tagging the lines appended to the input, like tail -f
+    --kafka-in <TOPIC>      Tag the messages of a Kafka topic (kafka feature)
+    --kafka-out <TOPIC>     Topic the tags of --kafka-in are published to, as JSON
+    --kafka-brokers <HOSTS> Comma separated host:port of the brokers (default: localhost:9092)
+    --kafka-group <ID>      Consumer group of the tagger (default: berttagr)
+    --redis-in <KEY>        Tag the jobs popped from a Redis list (redis-worker feature)
+    --redis-out <KEY>       List the results of --redis-in are pushed to, as JSON
+    --redis <URL>           Redis server (default: redis://127.0.0.1/)
+    --shard <I/N>           Tag the I-th of N contiguous shares of the input files, records or
+                            sentences, to split a run between N instances
+    --workers <N>           Threads tagging the batches of the input, or the Redis jobs, concurrently,
+                            each with its own copy of the model (default: 1)
+    --coordinator <HOST:PORT>
+                            Listen on HOST:PORT for --worker processes tagging the batches of the
+                            input, instead of loading the model
+    --worker <HOST:PORT>    Tag the batches of the --coordinator run listening on HOST:PORT
+    --retries <N>           Attempts at a failing job before it is set aside (default: 3)
+    --input-format <FORMAT> text, or json (array) or jsonl records with an id and a text, tagged
+                            as documents named after their ids; eml or mbox to tag the bodies of
+                            emails without quoted replies, named after their message ids
+    --pretokenized-sentences
+                            The input has one sentence per line: tag the lines as they are, one
+                            output sentence per line, blank lines giving empty sentences
+    --pretokenized          Each line is a sentence of tokens separated by spaces, or a JSON
+                            array of tokens: tag it with exactly one tag per token
+    --summary-json <FILE>   Write the run summary (counts, time, warnings, skipped inputs) as JSON
+    --report <FILE>         Write an HTML report of the scores, labels and lowest-confidence words
+    --metadata              Start the output with the model, options, time and input digests
+    --dry-run               Check the inputs, settings and model files and print what the run
+                            would tag, without loading the model
+
+BENCH OPTIONS:
+    --input <FILE>          Sample text to tag
+    --iterations <N>        Number of passes over the sample (default: 10)
+
+CLASSIFY OPTIONS:
+    --labels <LABELS>       Comma separated candidate labels
+
+REDACT OPTIONS:
+    --mapping <FILE>        JSON mapping of placeholders to the original text (default: output.txt.mapping.json)
+
+TRAIN OPTIONS:
+    --learning-rate <R>     Learning rate of the optimizer (default: 5e-5)
+    --epochs <N>            Number of passes over the corpus (default: 3)
+    --eval-split <F>        Fraction of the corpus held out for evaluation (default: 0.1)
+
+KWIC OPTIONS:
+    --word <WORD>           Word to look up, ignoring case
+    --tag <TAG>             Label to look up, alone or along with --word
+    --context <N>           Number of words shown on each side (default: 5)
+
+NGRAMS OPTIONS:
+    --n <N>                 Number of words of the n-grams (default: 2, or the length of --pattern)
+    --pattern <TAGS>        Tags the n-grams must match, separated by + (labels, universal tags or *)
+    --min-count <N>         Leave out the n-grams seen fewer times (default: 1)
+
+CALIBRATE OPTIONS:
+    --bins <N>              Number of score bins (default: 10)
+
+COVERAGE OPTIONS:
+    --top <N>               Number of split words listed (default: 20)
+
+COLLOCATIONS OPTIONS:
+    --pattern <TAGS>        Tags of the two words, as for ngrams (e.g. ADJ+NOUN)
+    --min-count <N>         Leave out the pairs seen fewer times (default: 1)
+    --measure <MEASURE>     log-likelihood (default) or pmi, the score the pairs are ranked by
+
+ARROW OPTIONS:
+    --text-column <NAME>    Column of the texts in the input record batches (default: text)
+
+DIFF OPTIONS:
+    --confusion-csv <FILE>  Write the confusion matrix of the labels as CSV
+    --confusion-html <FILE> Write the confusion matrix of the labels as an HTML heatmap
+
+Without --device, bench runs on the CPU and on the first GPU if one is available.
+
+EXIT CODES:
+    1 other errors, 2 usage, 3 reading or writing files, 4 loading the model, 5 tagging";
+
+/// Number of passes over the sample input when `--iterations` is not given
+pub const DEFAULT_ITERATIONS: usize = 10;
+
+/// # Subcommand to run
+pub enum Command {
+    /// Tag `inputs` and the files listed in `file_list` and write the result to `output`,
+    /// the run summary to `summary` and the quality report to `report`. Several inputs are written as documents of one
+    /// output. With `in_place`, `output` is the single input, replaced once tagged and
+    /// backed up with the suffix if it is not empty.
+    Tag {
+        inputs: Vec<String>,
+        file_list: Option<String>,
+        /// The file list is separated by NUL bytes
+        null_separated: bool,
+        output: String,
+        summary: Option<String>,
+        report: Option<String>,
+        in_place: Option<String>,
+        /// Print the plan of the run instead of tagging
+        dry_run: bool,
+    },
+    /// Tag the text of the clipboard, printing the result or copying it back if `copy` is set
+    Clipboard { copy: bool },
+    /// Tag `input` and the lines appended to it into `output` until interrupted
+    Follow { input: String, output: String },
+    /// Tag the messages of the Kafka topic `input_topic` and publish the results to
+    /// `output_topic`
+    Kafka {
+        brokers: Option<String>,
+        input_topic: String,
+        output_topic: String,
+        group_id: Option<String>,
+    },
+    /// Pop jobs from the Redis list `input_key` and push the results to `output_key`
+    Redis {
+        url: Option<String>,
+        input_key: String,
+        output_key: String,
+        retries: Option<usize>,
+    },
+    /// Tag the batches of the run listening on `coordinator`, see `distributed`
+    Worker { coordinator: String },
+    /// Benchmark the model on the sample text in `input`
+    Bench { input: String, iterations: usize },
+    /// Classify the sentiment of each sentence of `input` into `output`
+    Sentiment { input: String, output: String },
+    /// Classify each sentence of `input` into one of `labels`
+    Classify {
+        input: String,
+        output: String,
+        labels: Vec<String>,
+    },
+    /// Replace names, locations and numbers of `input` with placeholders into `output`,
+    /// writing the replaced text to `mapping`
+    Redact {
+        input: String,
+        output: String,
+        mapping: String,
+    },
+    /// Fine-tune the model on the annotated `corpus` and save it in `output_dir`
+    Train {
+        corpus: String,
+        output_dir: String,
+        options: TrainOptions,
+    },
+    /// Write the model, its configuration and its tokenizer settings to `output_dir`
+    Export { output_dir: String },
+    /// Tag the texts of the `text_column` of the Arrow IPC stream `input` into the stream
+    /// `output`, either being stdin or stdout with `-`, see `arrow_ipc`
+    Arrow {
+        input: String,
+        output: String,
+        text_column: Option<String>,
+    },
+    /// Compare the taggings `a` and `b` of the same text, writing the confusion matrix of
+    /// their labels to `confusion_csv` and `confusion_html`
+    Diff {
+        a: String,
+        b: String,
+        confusion_csv: Option<String>,
+        confusion_html: Option<String>,
+    },
+    /// Serve the tags of the documents open in an editor over stdio, see `editor`
+    Editor,
+    /// Print the labels of the model with their description
+    Labels,
+    /// Print the architecture, size, labels and files of the model
+    ModelInfo,
+    /// Write the readability and complexity metrics of each of `inputs` and of all of them
+    /// to `output`
+    Metrics { inputs: Vec<String>, output: String },
+    /// Print the sequences of words of `inputs` matching the lint rules
+    Lint { inputs: Vec<String> },
+    /// Print the occurrences of the words matching `query` in `inputs` with their context
+    Kwic { inputs: Vec<String>, query: KwicQuery },
+    /// Count the n-grams of `inputs` with `counter` and write those seen at least
+    /// `min_count` times to `output`
+    Ngrams {
+        inputs: Vec<String>,
+        output: String,
+        counter: NgramCounter,
+        min_count: usize,
+    },
+    /// Fit the calibration of the scores to the annotated `corpus` and write it to `output`
+    Calibrate {
+        corpus: String,
+        output: String,
+        bins: usize,
+    },
+    /// Report the words of `inputs` split into subwords by the tokenizer, listing the `top`
+    /// most frequent
+    Coverage { inputs: Vec<String>, top: usize },
+    /// Count the pairs of words of `inputs` with `counter` and write those seen at least
+    /// `min_count` times to `output`, ranked by `measure`
+    Collocations {
+        inputs: Vec<String>,
+        output: String,
+        counter: CollocationCounter,
+        min_count: usize,
+        measure: Measure,
+    },
+}
+
+impl Command {
+    /// Main output file of the command, subject to the `output_exists` policy
+    pub fn output(&self) -> Option<&str> {
+        match self {
+            Command::Tag {
+                output,
+                in_place: None,
+                ..
+            }
+            | Command::Sentiment { output, .. }
+            | Command::Classify { output, .. }
+            | Command::Redact { output, .. }
+            | Command::Metrics { output, .. }
+            | Command::Ngrams { output, .. }
+            | Command::Collocations { output, .. }
+            | Command::Calibrate { output, .. }
+            | Command::Follow { output, .. } => Some(output),
+            Command::Arrow { output, .. } if output.as_str() != STDIN => Some(output),
+            _ => None,
+        }
+    }
+}
+
+/// Names of the subcommands, the default being tagging
+const SUBCOMMANDS: &[&str] = &["tag", "bench", "sentiment", "classify", "redact", "train", "export", "arrow", "diff", "editor", "labels", "model-info", "metrics", "kwic", "lint", "ngrams", "collocations", "coverage", "calibrate"];
+
+/// # Parsed command line
+pub struct Args {
+    pub command: Command,
+    /// Explicit configuration file given with `--config`
+    pub config_path: Option<PathBuf>,
+    /// Settings given as flags, taking precedence over the configuration file
+    pub overrides: Config,
+}
+
+/// Parse the command line arguments (without the program name)
+pub fn parse_args(args: &[String]) -> anyhow::Result<Args> {
+    let (subcommand, args) = match args.split_first() {
+        Some((first, rest)) if SUBCOMMANDS.contains(&first.as_str()) => (first.as_str(), rest),
+        _ => ("tag", args),
+    };
+    let is_bench = subcommand == "bench";
+    let mut positional = Vec::new();
+    let mut config_path = None;
+    let mut overrides = Config::default();
+    let mut bench_input = None;
+    let mut iterations = None;
+    let mut labels = None;
+    let mut mapping = None;
+    let mut train_options = TrainOptions::default();
+    let mut confusion_csv = None;
+    let mut confusion_html = None;
+    let mut summary = None;
+    let mut report = None;
+    let mut in_place = None;
+    let mut dry_run = false;
+    let mut clipboard = false;
+    let mut copy = false;
+    let mut follow = false;
+    let mut kafka_in = None;
+    let mut kafka_out = None;
+    let mut kafka_brokers = None;
+    let mut kafka_group = None;
+    let mut redis_in = None;
+    let mut redis_out = None;
+    let mut redis_url = None;
+    let mut retries = None;
+    let mut worker = None;
+    let mut file_list = None;
+    let mut null_separated = false;
+    let mut kwic_word = None;
+    let mut kwic_tag = None;
+    let mut kwic_context = None;
+    let mut ngram_length = None;
+    let mut tag_pattern = None;
+    let mut min_count = None;
+    let mut measure = None;
+    let mut top = None;
+    let mut bins = None;
+    let mut text_column = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = Some(PathBuf::from(value(&mut args, arg)?)),
+            "--model-dir" => overrides.model_dir = Some(PathBuf::from(value(&mut args, arg)?)),
+            "--model" => overrides.model = Some(value(&mut args, arg)?.to_owned()),
+            "--hub-model" => overrides.hub_model = Some(value(&mut args, arg)?.to_owned()),
+            "--model-type" => overrides.model_type = Some(value(&mut args, arg)?.to_owned()),
+            "--download-retries" => overrides.download_retries = Some(number(&mut args, arg)?),
+            "--download-timeout" => overrides.download_timeout = Some(number(&mut args, arg)? as u64),
+            "--allow-unverified" => overrides.allow_unverified = Some(true),
+            "--pretokenized-sentences" => overrides.pretokenized_sentences = Some(true),
+            "--pretokenized" => overrides.pretokenized = Some(true),
+            "--proxy" => overrides.proxy = Some(value(&mut args, arg)?.to_owned()),
+            "--device" => overrides.device = Some(value(&mut args, arg)?.to_owned()),
+            "--format" => overrides.format = Some(value(&mut args, arg)?.parse()?),
+            "--batch-size" => overrides.batch_size = Some(value(&mut args, arg)?.parse()?),
+            "--max-batch-bytes" => overrides.max_batch_bytes = Some(number(&mut args, arg)?),
+            "--min-batch-size" => overrides.min_batch_size = Some(number(&mut args, arg)?),
+            "--filter-tags" => overrides.filter_tags = Some(list(value(&mut args, arg)?)),
+            "--label-map" => overrides.label_map = Some(label_map(value(&mut args, arg)?)?),
+            "--calibration" => overrides.calibration = Some(PathBuf::from(value(&mut args, arg)?)),
+            "--min-score" => overrides.min_score = Some(float(&mut args, arg)?),
+            "--max-file-size" => overrides.max_file_size = Some(number(&mut args, arg)? as u64),
+            "--max-sentence-length" => {
+                overrides.max_sentence_length = Some(number(&mut args, arg)?)
+            }
+            "--output-exists" => overrides.output_exists = Some(value(&mut args, arg)?.parse()?),
+            "--on-limit" => overrides.on_limit = Some(value(&mut args, arg)?.parse()?),
+            "--language" => overrides.language = Some(value(&mut args, arg)?.to_owned()),
+            "--threads" => overrides.threads = Some(number(&mut args, arg)?),
+            "--interop-threads" => overrides.interop_threads = Some(number(&mut args, arg)?),
+            "--lemma" => overrides.lemma = Some(true),
+            "--morph" => overrides.morph = Some(true),
+            "--normalize" => overrides.normalizer = Some(Normalizer::default()),
+            "--protect-tokens" => overrides.protect_tokens = Some(true),
+            "--quantities" => overrides.quantities = Some(true),
+            "--emoji-tag" => overrides.emoji_tag = Some(value(&mut args, arg)?.to_owned()),
+            "--parser-command" => {
+                overrides.parser_command = Some(value(&mut args, arg)?.to_owned())
+            }
+            "--deterministic" => overrides.deterministic = Some(true),
+            "--seed" => overrides.seed = Some(number(&mut args, arg)? as u64),
+            "--no-lower-case" => overrides.lower_case = Some(false),
+            "--no-strip-accents" => overrides.strip_accents = Some(false),
+            "--label-aggregation" => {
+                overrides.label_aggregation = Some(value(&mut args, arg)?.parse()?)
+            }
+            //read before parsing by `error_format`, only checked here
+            "--error-format" => {
+                value(&mut args, arg)?.parse::<ErrorFormat>()?;
+            }
+            "--input" if is_bench => bench_input = Some(value(&mut args, arg)?.to_owned()),
+            "--iterations" if is_bench => iterations = Some(number(&mut args, arg)?),
+            "--labels" if subcommand == "classify" => labels = Some(list(value(&mut args, arg)?)),
+            "--mapping" if subcommand == "redact" => {
+                mapping = Some(value(&mut args, arg)?.to_owned())
+            }
+            "--learning-rate" if subcommand == "train" => {
+                train_options.learning_rate = float(&mut args, arg)?
+            }
+            "--epochs" if subcommand == "train" => train_options.epochs = number(&mut args, arg)?,
+            "--eval-split" if subcommand == "train" => {
+                train_options.eval_split = float(&mut args, arg)?
+            }
+            "--summary-json" if subcommand == "tag" => {
+                summary = Some(value(&mut args, arg)?.to_owned())
+            }
+            "--input-format" if subcommand == "tag" => {
+                overrides.input_format = Some(value(&mut args, arg)?.parse()?)
+            }
+            "--dry-run" if subcommand == "tag" => dry_run = true,
+            "--report" if subcommand == "tag" => report = Some(value(&mut args, arg)?.to_owned()),
+            "-0" | "--null" if subcommand == "tag" => null_separated = true,
+            "--metadata" if subcommand == "tag" => overrides.metadata = Some(true),
+            "--file-list" | "--files-from" if subcommand == "tag" => {
+                file_list = Some(value(&mut args, arg)?.to_owned())
+            }
+            "--in-place" if subcommand == "tag" => in_place = Some(String::new()),
+            "--clipboard" if subcommand == "tag" => clipboard = true,
+            "--copy" if subcommand == "tag" => copy = true,
+            "--follow" if subcommand == "tag" => follow = true,
+            "--kafka-in" if subcommand == "tag" => kafka_in = Some(value(&mut args, arg)?.to_owned()),
+            "--kafka-out" if subcommand == "tag" => kafka_out = Some(value(&mut args, arg)?.to_owned()),
+            "--kafka-brokers" if subcommand == "tag" => {
+                kafka_brokers = Some(value(&mut args, arg)?.to_owned())
+            }
+            "--kafka-group" if subcommand == "tag" => {
+                kafka_group = Some(value(&mut args, arg)?.to_owned())
+            }
+            "--redis-in" if subcommand == "tag" => redis_in = Some(value(&mut args, arg)?.to_owned()),
+            "--redis-out" if subcommand == "tag" => redis_out = Some(value(&mut args, arg)?.to_owned()),
+            "--redis" if subcommand == "tag" => redis_url = Some(value(&mut args, arg)?.to_owned()),
+            "--shard" if subcommand == "tag" => overrides.shard = Some(value(&mut args, arg)?.parse()?),
+            "--workers" if subcommand == "tag" => overrides.workers = Some(number(&mut args, arg)?),
+            "--retries" if subcommand == "tag" => retries = Some(number(&mut args, arg)?),
+            "--coordinator" if subcommand == "tag" => {
+                overrides.coordinator = Some(value(&mut args, arg)?.to_owned())
+            }
+            "--worker" if subcommand == "tag" => worker = Some(value(&mut args, arg)?.to_owned()),
+            flag if subcommand == "tag" && flag.starts_with("--in-place=") => {
+                in_place = Some(flag["--in-place=".len()..].to_owned())
+            }
+            "--word" if subcommand == "kwic" => kwic_word = Some(value(&mut args, arg)?.to_owned()),
+            "--tag" if subcommand == "kwic" => kwic_tag = Some(value(&mut args, arg)?.to_owned()),
+            "--context" if subcommand == "kwic" => kwic_context = Some(number(&mut args, arg)?),
+            "--n" if subcommand == "ngrams" => ngram_length = Some(number(&mut args, arg)?),
+            "--pattern" if subcommand == "ngrams" || subcommand == "collocations" => {
+                tag_pattern = Some(value(&mut args, arg)?.parse()?)
+            }
+            "--min-count" if subcommand == "ngrams" || subcommand == "collocations" => {
+                min_count = Some(number(&mut args, arg)?)
+            }
+            "--text-column" if subcommand == "arrow" => text_column = Some(value(&mut args, arg)?.to_owned()),
+            "--bins" if subcommand == "calibrate" => bins = Some(number(&mut args, arg)?),
+            "--top" if subcommand == "coverage" => top = Some(number(&mut args, arg)?),
+            "--measure" if subcommand == "collocations" => measure = Some(value(&mut args, arg)?.parse()?),
+            "--confusion-csv" if subcommand == "diff" => {
+                confusion_csv = Some(value(&mut args, arg)?.to_owned())
+            }
+            "--confusion-html" if subcommand == "diff" => {
+                confusion_html = Some(value(&mut args, arg)?.to_owned())
+            }
+            flag if flag.starts_with("--") => bail!("Unknown option {}", flag),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    if overrides.shard.is_some() && (in_place.is_some() || clipboard || follow || kafka_in.is_some() || redis_in.is_some()) {
+        bail!("--shard splits the tagging of files, not --in-place, the clipboard, --follow, Kafka or Redis modes");
+    }
+    if overrides.coordinator.is_some() && (clipboard || follow || kafka_in.is_some() || redis_in.is_some() || worker.is_some()) {
+        bail!("--coordinator distributes the tagging of files, not the clipboard, --follow, Kafka, Redis or --worker modes");
+    }
+    if dry_run && (clipboard || follow || kafka_in.is_some() || kafka_out.is_some() || redis_in.is_some() || redis_out.is_some()) {
+        bail!("--dry-run plans the tagging of files, not the clipboard, --follow, Kafka or Redis modes");
+    }
+    let command = match subcommand {
+        "bench" => {
+            if let Some(arg) = positional.first() {
+                bail!("Unexpected argument {}", arg);
+            }
+            let iterations = iterations.unwrap_or(DEFAULT_ITERATIONS);
+            if iterations == 0 {
+                bail!("--iterations must be at least 1");
+            }
+            Command::Bench {
+                input: bench_input.ok_or_else(|| anyhow!("bench requires --input"))?,
+                iterations,
+            }
+        }
+        "sentiment" => {
+            let (input, output) = input_output(positional)?;
+            Command::Sentiment { input, output }
+        }
+        "classify" => {
+            let (input, output) = input_output(positional)?;
+            Command::Classify {
+                input,
+                output,
+                labels: labels.ok_or_else(|| anyhow!("classify requires --labels"))?,
+            }
+        }
+        "redact" => {
+            let (input, output) = input_output(positional)?;
+            Command::Redact {
+                mapping: mapping.unwrap_or_else(|| format!("{}.mapping.json", output)),
+                input,
+                output,
+            }
+        }
+        "train" => {
+            let (corpus, output_dir) = input_output(positional)?;
+            Command::Train {
+                corpus,
+                output_dir,
+                options: train_options,
+            }
+        }
+        "export" => match positional.as_slice() {
+            [output_dir] => Command::Export {
+                output_dir: output_dir.clone(),
+            },
+            _ => bail!("export requires one argument."),
+        },
+        "editor" => {
+            if let Some(arg) = positional.first() {
+                bail!("Unexpected argument {}", arg);
+            }
+            Command::Editor
+        }
+        "labels" => {
+            if let Some(arg) = positional.first() {
+                bail!("Unexpected argument {}", arg);
+            }
+            Command::Labels
+        }
+        "model-info" => {
+            if let Some(arg) = positional.first() {
+                bail!("Unexpected argument {}", arg);
+            }
+            Command::ModelInfo
+        }
+        "metrics" => match positional.as_slice() {
+            [inputs @ .., output] if !inputs.is_empty() => Command::Metrics {
+                inputs: inputs.to_vec(),
+                output: output.clone(),
+            },
+            _ => bail!("metrics requires at least two arguments."),
+        },
+        "ngrams" => match positional.as_slice() {
+            [inputs @ .., output] if !inputs.is_empty() => Command::Ngrams {
+                inputs: inputs.to_vec(),
+                output: output.clone(),
+                counter: NgramCounter::new(ngram_length, tag_pattern)?,
+                min_count: min_count.unwrap_or(1),
+            },
+            _ => bail!("ngrams requires at least two arguments."),
+        },
+        "calibrate" => {
+            let (corpus, output) = input_output(positional)?;
+            Command::Calibrate {
+                corpus,
+                output,
+                bins: bins.unwrap_or(DEFAULT_BINS),
+            }
+        }
+        "coverage" => {
+            if positional.is_empty() {
+                bail!("coverage requires at least one argument.");
+            }
+            Command::Coverage {
+                inputs: positional,
+                top: top.unwrap_or(DEFAULT_TOP),
+            }
+        }
+        "collocations" => match positional.as_slice() {
+            [inputs @ .., output] if !inputs.is_empty() => Command::Collocations {
+                inputs: inputs.to_vec(),
+                output: output.clone(),
+                counter: CollocationCounter::new(tag_pattern)?,
+                min_count: min_count.unwrap_or(1),
+                measure: measure.unwrap_or_default(),
+            },
+            _ => bail!("collocations requires at least two arguments."),
+        },
+        "lint" => {
+            if positional.is_empty() {
+                bail!("lint requires at least one argument.");
+            }
+            Command::Lint { inputs: positional }
+        }
+        "kwic" => {
+            if positional.is_empty() {
+                bail!("kwic requires at least one argument.");
+            }
+            Command::Kwic {
+                inputs: positional,
+                query: KwicQuery::new(kwic_word, kwic_tag, kwic_context.unwrap_or(DEFAULT_CONTEXT))?,
+            }
+        }
+        "arrow" => {
+            let (input, output) = input_output(positional)?;
+            Command::Arrow {
+                input,
+                output,
+                text_column,
+            }
+        }
+        "diff" => {
+            let (a, b) = input_output(positional)?;
+            Command::Diff {
+                a,
+                b,
+                confusion_csv,
+                confusion_html,
+            }
+        }
+        _ if clipboard => {
+            if let Some(arg) = positional.first() {
+                bail!("--clipboard takes no input or output, unexpected argument {}", arg);
+            }
+            if in_place.is_some() || file_list.is_some() {
+                bail!("--clipboard cannot be combined with --in-place or --file-list");
+            }
+            Command::Clipboard { copy }
+        }
+        _ if copy => bail!("--copy requires --clipboard"),
+        _ if kafka_in.is_some() || kafka_out.is_some() => {
+            if let Some(arg) = positional.first() {
+                bail!("The Kafka mode takes no input or output, unexpected argument {}", arg);
+            }
+            Command::Kafka {
+                brokers: kafka_brokers,
+                input_topic: kafka_in.ok_or_else(|| anyhow!("--kafka-out requires --kafka-in"))?,
+                output_topic: kafka_out.ok_or_else(|| anyhow!("--kafka-in requires --kafka-out"))?,
+                group_id: kafka_group,
+            }
+        }
+        _ if redis_in.is_some() || redis_out.is_some() => {
+            if let Some(arg) = positional.first() {
+                bail!("The Redis worker mode takes no input or output, unexpected argument {}", arg);
+            }
+            Command::Redis {
+                url: redis_url,
+                input_key: redis_in.ok_or_else(|| anyhow!("--redis-out requires --redis-in"))?,
+                output_key: redis_out.ok_or_else(|| anyhow!("--redis-in requires --redis-out"))?,
+                retries,
+            }
+        }
+        _ if worker.is_some() => {
+            if let Some(arg) = positional.first() {
+                bail!("The worker mode takes no input or output, unexpected argument {}", arg);
+            }
+            if in_place.is_some() || file_list.is_some() || dry_run {
+                bail!("--worker cannot be combined with --in-place, --file-list or --dry-run");
+            }
+            Command::Worker {
+                coordinator: worker.unwrap_or_default(),
+            }
+        }
+        _ if follow => {
+            if in_place.is_some() || file_list.is_some() {
+                bail!("--follow cannot be combined with --in-place or --file-list");
+            }
+            let (input, output) = input_output(positional)?;
+            Command::Follow { input, output }
+        }
+        _ => {
+            let (inputs, output) = match (&in_place, &file_list, positional.as_slice()) {
+                (Some(_), None, [file]) => (vec![file.clone()], file.clone()),
+                (Some(_), _, _) => bail!("--in-place requires one argument."),
+                (None, Some(_), [output]) => (Vec::new(), output.clone()),
+                (None, Some(_), _) => bail!("--file-list requires one argument."),
+                (None, None, [inputs @ .., output]) if !inputs.is_empty() => {
+                    (inputs.to_vec(), output.clone())
+                }
+                (None, None, _) => bail!("Requires at least two arguments."),
+            };
+            Command::Tag {
+                inputs,
+                file_list,
+                null_separated,
+                output,
+                summary,
+                report,
+                in_place,
+                dry_run,
+            }
+        }
+    };
+    Ok(Args {
+        command,
+        config_path,
+        overrides,
+    })
+}
+
+/// Format of the error report asked for with `--error-format`, looked up before parsing so
+/// that errors in the other arguments are reported in it too
+pub fn error_format(args: &[String]) -> ErrorFormat {
+    args.iter()
+        .position(|arg| arg == "--error-format")
+        .and_then(|index| args.get(index + 1))
+        .and_then(|format| format.parse().ok())
+        .unwrap_or_default()
+}
+
+fn input_output(mut positional: Vec<String>) -> anyhow::Result<(String, String)> {
+    if positional.len() != 2 {
+        bail!("Requires two arguments.");
+    }
+    let output = positional.pop().unwrap();
+    let input = positional.pop().unwrap();
+    Ok((input, output))
+}
+
+fn value<'a>(args: &mut Iter<'a, String>, flag: &str) -> anyhow::Result<&'a str> {
+    args.next()
+        .map(String::as_str)
+        .ok_or_else(|| anyhow!("{} requires a value", flag))
+}
+
+fn number(args: &mut Iter<String>, flag: &str) -> anyhow::Result<usize> {
+    value(args, flag)?
+        .parse()
+        .with_context(|| format!("{} expects a number", flag))
+}
+
+fn float(args: &mut Iter<String>, flag: &str) -> anyhow::Result<f64> {
+    value(args, flag)?
+        .parse()
+        .with_context(|| format!("{} expects a number", flag))
+}
+
+/// Parse `FROM=TO,...` pairs
+fn label_map(value: &str) -> anyhow::Result<BTreeMap<String, String>> {
+    list(value)
+        .iter()
+        .map(|pair| match pair.find('=') {
+            Some(index) if index > 0 && index + 1 < pair.len() => {
+                Ok((pair[..index].to_owned(), pair[index + 1..].to_owned()))
+            }
+            _ => bail!("Invalid label mapping '{}', expected FROM=TO", pair),
+        })
+        .collect()
+}
+
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::TagFormat;
+    use crate::shard::Shard;
+    use crate::tuning::BatchSize;
+
+    fn parse(args: &[&str]) -> anyhow::Result<Args> {
+        parse_args(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse(args) {
+            Ok(_) => panic!("{:?} should not parse", args),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn flags_override_config() {
+        let flags = ["--config", "tagger.toml", "--batch-size", "16", "--format", "tsv", "--seed", "7"];
+        let args = parse(&[&flags[..], &["a.txt", "out.tsv"][..]].concat()).unwrap();
+        assert_eq!(args.config_path, Some(PathBuf::from("tagger.toml")));
+        assert_eq!(args.overrides.seed, Some(7));
+        let file: Config = toml::from_str("device = \"cpu\"\nbatch_size = 8\nformat = \"json\"").unwrap();
+        let config = file.merge(args.overrides);
+        assert_eq!(config.device.as_deref(), Some("cpu"));
+        assert_eq!(config.batch_size, Some(BatchSize::Fixed(16)));
+        assert_eq!(config.format(), TagFormat::Tsv);
+        match args.command {
+            Command::Tag { inputs, output, .. } => {
+                assert_eq!((inputs, output.as_str()), (vec!["a.txt".to_owned()], "out.tsv"))
+            }
+            _ => panic!("expected the tag command"),
+        }
+    }
+
+    #[test]
+    fn parses_subcommands() {
+        match parse(&["bench", "--input", "sample.txt"]).unwrap().command {
+            Command::Bench { input, iterations } => {
+                assert_eq!((input.as_str(), iterations), ("sample.txt", DEFAULT_ITERATIONS))
+            }
+            _ => panic!("expected the bench command"),
+        }
+        match parse(&["train", "--epochs", "5", "corpus.conllu", "model"]).unwrap().command {
+            Command::Train { corpus, output_dir, options } => assert_eq!(
+                (corpus.as_str(), output_dir.as_str(), options.epochs),
+                ("corpus.conllu", "model", 5)
+            ),
+            _ => panic!("expected the train command"),
+        }
+        match parse(&["arrow", "--text-column", "body", "-", "-"]).unwrap().command {
+            Command::Arrow { text_column, .. } => assert_eq!(text_column.as_deref(), Some("body")),
+            _ => panic!("expected the arrow command"),
+        }
+        match parse(&["--worker", "host:7000"]).unwrap().command {
+            Command::Worker { coordinator } => assert_eq!(coordinator, "host:7000"),
+            _ => panic!("expected the worker mode"),
+        }
+    }
+
+    #[test]
+    fn parses_shards_and_workers() {
+        let args = parse(&["--shard", "2/8", "--workers", "4", "a.txt", "out.txt"]).unwrap();
+        assert_eq!(args.overrides.shard, Some(Shard { index: 2, count: 8 }));
+        assert_eq!(args.overrides.workers, Some(4));
+        assert!(error(&["--shard", "9/8", "a.txt", "out.txt"]).starts_with("Invalid shard '9/8'"));
+        assert_eq!(error(&["--workers", "many", "a.txt", "out.txt"]), "--workers expects a number");
+        assert!(error(&["--shard", "1/2", "--follow", "a.txt", "out.txt"]).starts_with("--shard splits"));
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert_eq!(error(&["a.txt"]), "Requires at least two arguments.");
+        assert_eq!(error(&["--batch-size"]), "--batch-size requires a value");
+        assert_eq!(error(&["--unknown", "a.txt", "out.txt"]), "Unknown option --unknown");
+        assert_eq!(error(&["bench", "--epochs", "2", "--input", "sample.txt"]), "Unknown option --epochs");
+        assert_eq!(error(&["bench"]), "bench requires --input");
+        assert_eq!(error(&["bench", "--input", "sample.txt", "--iterations", "0"]), "--iterations must be at least 1");
+        let coordinated = error(&["--coordinator", "0.0.0.0:7000", "--worker", "host:7000"]);
+        assert!(coordinated.starts_with("--coordinator distributes"));
+        assert_eq!(error(&["--copy", "a.txt", "out.txt"]), "--copy requires --clipboard");
+    }
+}
@@ -0,0 +1,82 @@
+//! # System clipboard
+//! Text of the clipboard for `--clipboard`, read and written through the clipboard tools of
+//! the platform rather than a windowing library: `pbpaste` and `pbcopy` on macOS,
+//! PowerShell on Windows, and `wl-paste`/`wl-copy`, `xclip` or `xsel` elsewhere, the first
+//! one installed being used.
+
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context};
+
+#[cfg(target_os = "macos")]
+const PASTE: &[&[&str]] = &[&["pbpaste"]];
+#[cfg(target_os = "macos")]
+const COPY: &[&[&str]] = &[&["pbcopy"]];
+
+#[cfg(windows)]
+const PASTE: &[&[&str]] = &[&["powershell", "-NoProfile", "-Command", "Get-Clipboard -Raw"]];
+#[cfg(windows)]
+const COPY: &[&[&str]] = &[&[
+    "powershell",
+    "-NoProfile",
+    "-Command",
+    "Set-Clipboard -Value ([Console]::In.ReadToEnd())",
+]];
+
+#[cfg(not(any(target_os = "macos", windows)))]
+const PASTE: &[&[&str]] = &[
+    &["wl-paste", "--no-newline"],
+    &["xclip", "-selection", "clipboard", "-out"],
+    &["xsel", "--clipboard", "--output"],
+];
+#[cfg(not(any(target_os = "macos", windows)))]
+const COPY: &[&[&str]] = &[
+    &["wl-copy"],
+    &["xclip", "-selection", "clipboard", "-in"],
+    &["xsel", "--clipboard", "--input"],
+];
+
+/// Text of the clipboard
+pub fn read() -> anyhow::Result<String> {
+    for command in PASTE {
+        let output = match Command::new(command[0]).args(&command[1..]).stderr(Stdio::null()).output() {
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            result => result.with_context(|| format!("Could not run {}", command[0]))?,
+        };
+        if !output.status.success() {
+            bail!("{} failed reading the clipboard ({})", command[0], output.status);
+        }
+        return String::from_utf8(output.stdout).context("The clipboard does not hold UTF-8 text");
+    }
+    bail!("No clipboard tool found, install one of {}", names(PASTE))
+}
+
+/// Replace the content of the clipboard with `text`
+pub fn write(text: &str) -> anyhow::Result<()> {
+    for command in COPY {
+        let mut child = match Command::new(command[0])
+            .args(&command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            result => result.with_context(|| format!("Could not run {}", command[0]))?,
+        };
+        //dropping stdin closes it, so that the tool sees the end of the text
+        child.stdin.take().expect("piped stdin").write_all(text.as_bytes())?;
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("{} failed writing the clipboard ({})", command[0], status);
+        }
+        return Ok(());
+    }
+    bail!("No clipboard tool found, install one of {}", names(COPY))
+}
+
+fn names(commands: &[&[&str]]) -> String {
+    let names: Vec<&str> = commands.iter().map(|command| command[0]).collect();
+    names.join(", ")
+}
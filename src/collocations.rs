@@ -0,0 +1,206 @@
+//! # Collocations
+//! Ranked collocate tables of the `collocations` subcommand: pairs of adjacent words,
+//! optionally restricted by a tag pattern of two elements (see `ngrams::TagPattern`), scored
+//! by how much more often they occur together than their frequencies predict. Pointwise
+//! mutual information favours rare exclusive pairs, the log-likelihood ratio (Dunning's G²)
+//! frequent reliable ones.
+//!
+//! ```text
+//! word1,word2,count,pmi,log_likelihood
+//! prime,minister,14,9.8412,203.5127
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+
+use crate::config::Config;
+use crate::confusion::csv_field;
+use crate::ngrams::{NgramCounter, TagPattern};
+use crate::pos_tagging::POSTag;
+use crate::tagger::for_each_sentence;
+use crate::tagset::to_upos;
+use crate::warnings::WarningSink;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// # Association measure the table is ranked by
+pub enum Measure {
+    Pmi,
+    LogLikelihood,
+}
+
+impl Default for Measure {
+    fn default() -> Measure {
+        Measure::LogLikelihood
+    }
+}
+
+impl FromStr for Measure {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Measure, Self::Err> {
+        match s {
+            "pmi" => Ok(Measure::Pmi),
+            "log-likelihood" | "llr" => Ok(Measure::LogLikelihood),
+            _ => bail!("Unknown measure '{}', expected pmi or log-likelihood", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// # Pair of words with its association scores
+pub struct Collocation<'a> {
+    pub words: &'a [String],
+    pub count: usize,
+    pub pmi: f64,
+    pub log_likelihood: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// # Counts of words and adjacent pairs of tagged sentences
+pub struct CollocationCounter {
+    pub pairs: NgramCounter,
+    /// Count of each word, lower-cased, punctuation excluded
+    pub words: HashMap<String, usize>,
+    /// Number of words counted
+    pub total: usize,
+}
+
+impl CollocationCounter {
+    /// Counter of the pairs matching `pattern`, or of every pair of words without it
+    pub fn new(pattern: Option<TagPattern>) -> anyhow::Result<CollocationCounter> {
+        if pattern.as_ref().map_or(false, |pattern| pattern.tags.len() != 2) {
+            bail!("Collocations are pairs of words, the tag pattern must have two elements");
+        }
+        Ok(CollocationCounter {
+            pairs: NgramCounter::new(Some(2), pattern)?,
+            words: HashMap::new(),
+            total: 0,
+        })
+    }
+
+    pub fn add_sentence(&mut self, sentence: &[POSTag]) {
+        self.pairs.add_sentence(sentence);
+        for tag in sentence {
+            if to_upos(&tag.label) != "PUNCT" {
+                *self.words.entry(tag.word.to_lowercase()).or_default() += 1;
+                self.total += 1;
+            }
+        }
+    }
+
+    /// Pairs seen at least `min_count` times, best ranked by `measure` first
+    pub fn collocations(&self, min_count: usize, measure: Measure) -> Vec<Collocation<'_>> {
+        let mut collocations: Vec<Collocation> = self
+            .pairs
+            .frequencies(min_count)
+            .into_iter()
+            .map(|(words, count)| {
+                let count_of = |word: &String| self.words.get(word).copied().unwrap_or(0).max(count);
+                let (first, second) = (count_of(&words[0]), count_of(&words[1]));
+                Collocation {
+                    words,
+                    count,
+                    pmi: pmi(count, first, second, self.total),
+                    log_likelihood: log_likelihood(count, first, second, self.total),
+                }
+            })
+            .collect();
+        let score = |collocation: &Collocation| match measure {
+            Measure::Pmi => collocation.pmi,
+            Measure::LogLikelihood => collocation.log_likelihood,
+        };
+        collocations.sort_by(|a, b| {
+            score(b)
+                .partial_cmp(&score(a))
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.words.cmp(b.words))
+        });
+        collocations
+    }
+
+    /// Write the table of the pairs seen at least `min_count` times, ranked by `measure`
+    pub fn write_csv<W: Write>(&self, mut writer: W, min_count: usize, measure: Measure) -> io::Result<()> {
+        writeln!(writer, "word1,word2,count,pmi,log_likelihood")?;
+        for collocation in self.collocations(min_count, measure) {
+            writeln!(
+                writer,
+                "{},{},{},{:.4},{:.4}",
+                csv_field(&collocation.words[0]),
+                csv_field(&collocation.words[1]),
+                collocation.count,
+                collocation.pmi,
+                collocation.log_likelihood
+            )?;
+        }
+        writer.flush()
+    }
+}
+
+/// Pointwise mutual information, in bits, of a pair seen `pair` times whose words are seen
+/// `first` and `second` times among `total` words
+pub fn pmi(pair: usize, first: usize, second: usize, total: usize) -> f64 {
+    if pair == 0 || first == 0 || second == 0 {
+        return 0.0;
+    }
+    (pair as f64 * total as f64 / (first as f64 * second as f64)).log2()
+}
+
+/// Dunning's log-likelihood ratio G² of the 2x2 contingency table of the pair
+pub fn log_likelihood(pair: usize, first: usize, second: usize, total: usize) -> f64 {
+    //no fewer words than the table holds, so that no cell is negative
+    let total = total.max(first + second - pair.min(first));
+    let cells = [
+        (pair, first, second),
+        (first.saturating_sub(pair), first, total - second),
+        (second.saturating_sub(pair), total - first, second),
+        ((total + pair).saturating_sub(first + second), total - first, total - second),
+    ];
+    let n = total as f64;
+    2.0 * cells
+        .iter()
+        .filter(|(observed, _, _)| *observed > 0)
+        .map(|&(observed, row, column)| {
+            let observed = observed as f64;
+            observed * (observed * n / (row as f64 * column as f64)).ln()
+        })
+        .sum::<f64>()
+}
+
+/// Count the words and pairs of each file of `paths`, tagged with the configured tagger
+pub fn count_files(
+    paths: &[PathBuf],
+    counter: &mut CollocationCounter,
+    config: &Config,
+    warnings: &dyn WarningSink,
+) -> anyhow::Result<()> {
+    let tagger = config.tagger(warnings)?;
+    for path in paths {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        for_each_sentence(&*tagger, &contents, config.batch_size(), |sentence| {
+            counter.add_sentence(&sentence)
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scores_associated_pairs_higher() {
+        //"new york" always together, "the" everywhere
+        assert!(pmi(10, 10, 10, 1000) > pmi(10, 100, 200, 1000));
+        assert!(log_likelihood(10, 10, 10, 1000) > log_likelihood(10, 100, 200, 1000));
+        //independent words
+        assert!(log_likelihood(20, 100, 200, 1000).abs() < 1e-9);
+        assert_eq!(pmi(0, 10, 10, 1000), 0.0);
+        assert!(CollocationCounter::new(Some("ADJ+NOUN+NOUN".parse().unwrap())).is_err());
+    }
+}
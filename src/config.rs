@@ -0,0 +1,600 @@
+//! # Configuration file
+//! Settings read from a `berttagr.toml` file, either given with `--config` or found in the
+//! XDG configuration directory. Every setting is optional and command line flags take
+//! precedence over the file.
+//!
+//! ```toml
+//! model_dir = "/models/mobilebert-pos"
+//! device = "cuda:0"
+//! format = "tsv"
+//! batch_size = "auto"
+//! filter_tags = ["NN", "NNS", "NNP"]
+//! language = "en"
+//! threads = 4
+//!
+//! [label_map]
+//! LABEL_0 = "NN"
+//! ```
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use tch::Device;
+
+use crate::calibration::Calibration;
+use crate::checksums::Checksums;
+use crate::depparse::ExternalParser;
+#[cfg(feature = "remote-models")]
+use crate::download::DownloadPolicy;
+use crate::emoji::EmojiTagger;
+use crate::exit::{ModelLoadError, UsageError};
+use crate::format::{TagFormat, TagWriter};
+use crate::limits::{LimitAction, Limits};
+use crate::lint::LintRule;
+use crate::metadata::RunMetadata;
+use crate::models::ModelRegistry;
+use crate::normalize::Normalizer;
+use crate::output::OutputPolicy;
+use crate::pipeline::StageConfig;
+use crate::pos_tagging::{
+    default_device, parse_model_type, LabelAggregation, POSConfig, POSModel, ScoredTag,
+};
+use crate::protect::{TokenKind, TokenProtector};
+use crate::quantities::{QuantityKind, QuantityTagger};
+use crate::records::InputFormat;
+use crate::sentences::{self, Sentences};
+use crate::shard::Shard;
+use crate::tagset::{self, LabelMap};
+use crate::tagger::{AdaptiveBatch, Tagger};
+use crate::tuning::{self, BatchSize};
+use crate::warnings::{Warning, WarningSink};
+
+/// Name of the configuration file looked up in the XDG configuration directory
+pub const CONFIG_FILE_NAME: &str = "berttagr.toml";
+
+/// Number of sentences sent to the model at once when no batch size is set
+pub const DEFAULT_BATCH_SIZE: usize = 32;
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[non_exhaustive]
+/// # Tagger settings
+pub struct Config {
+    /// Name of a model of the registry (see `models`), instead of `model_dir`
+    pub model: Option<String>,
+    /// Hugging Face Hub repository of the model, `owner/name` or `owner/name@revision`
+    pub hub_model: Option<String>,
+    /// Directory holding `rust_model.ot`, `config.json` and the vocabulary
+    pub model_dir: Option<PathBuf>,
+    /// Architecture of the model in `model_dir`: `mobilebert` (default), `bert`,
+    /// `distilbert`, `roberta` or `xlm-roberta`
+    pub model_type: Option<String>,
+    /// Retries of a failed model download (3 by default)
+    pub download_retries: Option<usize>,
+    /// Timeout of the model download requests, in seconds (30 by default)
+    pub download_timeout: Option<u64>,
+    /// Proxy URL of the model downloads, instead of `HTTPS_PROXY`
+    pub proxy: Option<String>,
+    /// Expected SHA-256 digests of the model files (see `checksums`), replacing those of
+    /// the registry entry
+    pub sha256: Option<Checksums>,
+    /// Load a model whose files do not match their pinned digests, with a warning
+    pub allow_unverified: Option<bool>,
+    /// Device to run on: `cpu`, `cuda`, `cuda:N` or `auto`
+    pub device: Option<String>,
+    /// Output format
+    pub format: Option<TagFormat>,
+    /// Format of the input files: `text` (default), `json` or `jsonl` records, or `eml` or
+    /// `mbox` emails, tagged as documents named after their ids (see `records`)
+    pub input_format: Option<InputFormat>,
+    /// The input holds one sentence per line: lines are tagged as they are instead of being
+    /// split into sentences, so that the sentences of the output match the input lines
+    pub pretokenized_sentences: Option<bool>,
+    /// Every line is a sentence of tokens separated by spaces, or a JSON array of tokens,
+    /// tagged with one tag per token (see `pretokenized`)
+    pub pretokenized: Option<bool>,
+    /// Number of sentences sent to the model at once, or `"auto"` to tune it on the device
+    /// at startup (see `tuning`)
+    pub batch_size: Option<BatchSize>,
+    /// Share of the input tagged by this instance of a run split between several, as
+    /// `"i/N"` (see `shard`)
+    pub shard: Option<Shard>,
+    /// Threads tagging the batches of a run, or the jobs of the Redis worker, in parallel,
+    /// each with its own copy of the model (1 by default)
+    pub workers: Option<usize>,
+    /// Address, as `host:port`, a run listens on for the worker processes tagging its
+    /// batches instead of loading the model (see `distributed`)
+    pub coordinator: Option<String>,
+    /// Memory budget of a batch, in bytes of text: batches are split to stay within it
+    pub max_batch_bytes: Option<usize>,
+    /// Batch size below which running out of memory stops the run, instead of halving the
+    /// batch size and tagging the batch again (1 by default)
+    pub min_batch_size: Option<usize>,
+    /// Only keep tokens whose label is in this list
+    pub filter_tags: Option<Vec<String>>,
+    /// Labels of the model renamed in the output, e.g. `LABEL_0 = "NN"`
+    pub label_map: Option<BTreeMap<String, String>>,
+    /// Calibration of the scores written by the `calibrate` subcommand (see `calibration`)
+    pub calibration: Option<PathBuf>,
+    /// Leave out the words whose score, calibrated if configured, is below this value
+    pub min_score: Option<f64>,
+    /// Language of the input text
+    pub language: Option<String>,
+    /// Threads used within an operation on the CPU
+    pub threads: Option<usize>,
+    /// Threads running independent operations in parallel on the CPU
+    pub interop_threads: Option<usize>,
+    /// Produce identical outputs on repeated CPU runs, see `POSConfig::deterministic`
+    pub deterministic: Option<bool>,
+    /// Seed of the random number generators, implies `deterministic`
+    pub seed: Option<u64>,
+    /// Label of words split into several sub-tokens: `first`, `last`, `mode` or `max-score`
+    pub label_aggregation: Option<LabelAggregation>,
+    /// Lower-case the text before tokenization, which uncased models require (true by default)
+    pub lower_case: Option<bool>,
+    /// Remove accents before tokenization (along with lower-casing by default)
+    pub strip_accents: Option<bool>,
+    /// Add the lemma of each word to the output
+    pub lemma: Option<bool>,
+    /// Add the morphological features of each word to the output
+    pub morph: Option<bool>,
+    /// External CoNLL-U dependency parser run on the tagged sentences
+    pub parser_command: Option<String>,
+    /// Processing stages replacing the default tagging chain
+    pub pipeline: Option<Vec<StageConfig>>,
+    /// Normalization of the text before tagging
+    pub normalizer: Option<Normalizer>,
+    /// Label given to emoji and emoticons (e.g. SYM or EMOJI) instead of the predicted one
+    pub emoji_tag: Option<String>,
+    /// Label URLs, emails, hashtags and mentions without passing them to the tokenizer
+    pub protect_tokens: Option<bool>,
+    /// Label numbers, dates, times and quantities `CD`, `DATE`, `TIME` and `QUANTITY`, with
+    /// their normalized value in the output (see `quantities`)
+    pub quantities: Option<bool>,
+    /// Size in bytes above which an input file is skipped, truncated or rejected
+    pub max_file_size: Option<u64>,
+    /// Length in characters above which a sentence is skipped, truncated or rejected
+    pub max_sentence_length: Option<usize>,
+    /// What to do with inputs above the limits (skip by default)
+    pub on_limit: Option<LimitAction>,
+    /// What to do when the output file already exists (error by default)
+    pub output_exists: Option<OutputPolicy>,
+    /// Start tagged outputs with the description of the run (see `metadata`)
+    pub metadata: Option<bool>,
+    /// Rules of the `lint` subcommand, replacing the default ones (see `lint`)
+    pub lint_rules: Option<Vec<LintRule>>,
+    /// Description of the current run, set by the binary when `metadata` is enabled
+    #[serde(skip)]
+    pub run_metadata: Option<RunMetadata>,
+}
+
+impl Config {
+    /// Read a configuration file
+    pub fn from_file(path: &Path) -> anyhow::Result<Config> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read config file {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// Default location of the configuration file,
+    /// `$XDG_CONFIG_HOME/berttagr_file/berttagr.toml` (or `~/.config/...`)
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(config_home.join("berttagr_file").join(CONFIG_FILE_NAME))
+    }
+
+    /// Load the configuration from `path`, or from the default location if it exists
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Config> {
+        match path {
+            Some(path) => Config::from_file(path),
+            None => match Config::default_path() {
+                Some(path) if path.is_file() => Config::from_file(&path),
+                _ => Ok(Config::default()),
+            },
+        }
+    }
+
+    /// Returns a configuration where every setting present in `overrides` replaces the current one
+    pub fn merge(self, overrides: Config) -> Config {
+        Config {
+            model: overrides.model.or(self.model),
+            hub_model: overrides.hub_model.or(self.hub_model),
+            model_dir: overrides.model_dir.or(self.model_dir),
+            model_type: overrides.model_type.or(self.model_type),
+            download_retries: overrides.download_retries.or(self.download_retries),
+            download_timeout: overrides.download_timeout.or(self.download_timeout),
+            proxy: overrides.proxy.or(self.proxy),
+            sha256: overrides.sha256.or(self.sha256),
+            allow_unverified: overrides.allow_unverified.or(self.allow_unverified),
+            device: overrides.device.or(self.device),
+            format: overrides.format.or(self.format),
+            input_format: overrides.input_format.or(self.input_format),
+            pretokenized_sentences: overrides.pretokenized_sentences.or(self.pretokenized_sentences),
+            pretokenized: overrides.pretokenized.or(self.pretokenized),
+            batch_size: overrides.batch_size.or(self.batch_size),
+            shard: overrides.shard.or(self.shard),
+            workers: overrides.workers.or(self.workers),
+            coordinator: overrides.coordinator.or(self.coordinator),
+            max_batch_bytes: overrides.max_batch_bytes.or(self.max_batch_bytes),
+            min_batch_size: overrides.min_batch_size.or(self.min_batch_size),
+            filter_tags: overrides.filter_tags.or(self.filter_tags),
+            label_map: overrides.label_map.or(self.label_map),
+            calibration: overrides.calibration.or(self.calibration),
+            min_score: overrides.min_score.or(self.min_score),
+            language: overrides.language.or(self.language),
+            threads: overrides.threads.or(self.threads),
+            interop_threads: overrides.interop_threads.or(self.interop_threads),
+            deterministic: overrides.deterministic.or(self.deterministic),
+            seed: overrides.seed.or(self.seed),
+            label_aggregation: overrides.label_aggregation.or(self.label_aggregation),
+            lower_case: overrides.lower_case.or(self.lower_case),
+            strip_accents: overrides.strip_accents.or(self.strip_accents),
+            lemma: overrides.lemma.or(self.lemma),
+            morph: overrides.morph.or(self.morph),
+            parser_command: overrides.parser_command.or(self.parser_command),
+            pipeline: overrides.pipeline.or(self.pipeline),
+            normalizer: overrides.normalizer.or(self.normalizer),
+            emoji_tag: overrides.emoji_tag.or(self.emoji_tag),
+            protect_tokens: overrides.protect_tokens.or(self.protect_tokens),
+            quantities: overrides.quantities.or(self.quantities),
+            max_file_size: overrides.max_file_size.or(self.max_file_size),
+            max_sentence_length: overrides.max_sentence_length.or(self.max_sentence_length),
+            on_limit: overrides.on_limit.or(self.on_limit),
+            output_exists: overrides.output_exists.or(self.output_exists),
+            metadata: overrides.metadata.or(self.metadata),
+            lint_rules: overrides.lint_rules.or(self.lint_rules),
+            run_metadata: overrides.run_metadata.or(self.run_metadata),
+        }
+    }
+
+    pub fn format(&self) -> TagFormat {
+        self.format.unwrap_or_default()
+    }
+
+    pub fn input_format(&self) -> InputFormat {
+        self.input_format.unwrap_or_default()
+    }
+
+    /// Sentences of `text`: its lines with `pretokenized_sentences` or `pretokenized`, split
+    /// otherwise
+    pub fn sentences<'a>(&self, text: &'a str) -> Sentences<'a> {
+        if self.pretokenized_sentences.unwrap_or(false) || self.pretokenized.unwrap_or(false) {
+            sentences::iter_lines(text)
+        } else {
+            sentences::iter(text)
+        }
+    }
+
+    /// Batch size, `DEFAULT_BATCH_SIZE` when it is `auto` and not tuned yet
+    pub fn batch_size(&self) -> usize {
+        match self.batch_size {
+            Some(BatchSize::Fixed(size)) => size.max(1),
+            Some(BatchSize::Auto) | None => DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    /// Number of inference threads, 1 by default
+    pub fn workers(&self) -> usize {
+        self.workers.unwrap_or(1).max(1)
+    }
+
+    /// This configuration with an `auto` batch size replaced by the one tuned on `tagger`,
+    /// passed to `warnings`
+    pub fn with_tuned_batch_size(&self, tagger: &dyn Tagger, warnings: &dyn WarningSink) -> Config {
+        if self.batch_size != Some(BatchSize::Auto) {
+            return self.clone();
+        }
+        let size = tuning::tune(tagger);
+        warnings.warn(Warning::BatchSizeTuned { batch_size: size });
+        Config {
+            batch_size: Some(BatchSize::Fixed(size)),
+            ..self.clone()
+        }
+    }
+
+    /// Retries, timeout and proxy of the model downloads
+    #[cfg(feature = "remote-models")]
+    pub fn download_policy(&self) -> DownloadPolicy {
+        let default = DownloadPolicy::default();
+        DownloadPolicy {
+            retries: self.download_retries.map_or(default.retries, |retries| retries as u32),
+            timeout: self.download_timeout.map_or(default.timeout, std::time::Duration::from_secs),
+            proxy: self.proxy.clone(),
+        }
+    }
+
+    /// Batching of the model, lowered when it runs out of memory
+    pub fn adaptive_batch(&self) -> AdaptiveBatch {
+        AdaptiveBatch {
+            pretokenized: self.pretokenized.unwrap_or(false),
+            ..AdaptiveBatch::new(self.batch_size(), self.min_batch_size.unwrap_or(1), self.max_batch_bytes)
+        }
+    }
+
+    pub fn output_policy(&self) -> OutputPolicy {
+        self.output_exists.unwrap_or_default()
+    }
+
+    /// Input limits
+    pub fn limits(&self) -> Limits {
+        Limits {
+            max_file_size: self.max_file_size,
+            max_sentence_length: self.max_sentence_length,
+            action: self.on_limit.unwrap_or_default(),
+        }
+    }
+
+    /// Configured device, if any
+    pub fn device(&self) -> anyhow::Result<Option<Device>> {
+        self.device.as_deref().map(parse_device).transpose()
+    }
+
+    /// Output writer with the configured format and columns
+    pub fn tag_writer<W: Write>(&self, writer: W) -> TagWriter<W> {
+        TagWriter::new(writer, self.format())
+            .with_lemmas(self.lemma.unwrap_or(false))
+            .with_features(self.morph.unwrap_or(false))
+            .with_values(self.quantities.unwrap_or(false))
+            .with_metadata(self.run_metadata.clone())
+    }
+
+    /// Dependency parser to run after tagging, if one is configured
+    pub fn dependency_parser(&self) -> anyhow::Result<Option<ExternalParser>> {
+        match &self.parser_command {
+            Some(_) if self.filter_tags.is_some() || self.min_score.is_some() => bail!(
+                "Dependency parsing needs every word of a sentence, it cannot be combined with filter_tags or min_score"
+            ),
+            Some(command) => Ok(Some(ExternalParser::new(command)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Load the model described by these settings, with the configured label map, token
+    /// protection, normalizer and emoji handling. The labels of the model outside the
+    /// expected inventories (see `tagset::is_known`) that are not mapped are passed to
+    /// `warnings`, as are the model files loaded unverified.
+    pub fn pos_model(&self, warnings: &dyn WarningSink) -> anyhow::Result<POSModel> {
+        let mut pos_model = POSModel::new(self.pos_config(warnings)?).context(ModelLoadError)?;
+        let label_map = LabelMap::new(self.label_map.clone().unwrap_or_default());
+        let labels: Vec<String> = pos_model
+            .label_set()
+            .into_iter()
+            .map(|label| label_map.apply(&label.label).to_owned())
+            .collect();
+        for label in tagset::unknown_labels(labels.iter().map(String::as_str)) {
+            warnings.warn(Warning::UnknownLabel { label: label.to_owned() });
+        }
+        self.check_filter_tags(&labels).context(UsageError)?;
+        if !label_map.map.is_empty() {
+            pos_model.add_post_processor(label_map);
+        }
+        //the scores of the model, before the rule-based labels
+        if let Some(path) = &self.calibration {
+            pos_model.add_post_processor(Calibration::from_file(path)?);
+        }
+        //protected tokens are replaced before the normalizer can alter them
+        if self.protect_tokens.unwrap_or(false) {
+            pos_model.add_replacer(TokenProtector::new());
+        }
+        //after token protection, so that URLs keep their digits
+        if self.quantities.unwrap_or(false) {
+            pos_model.add_replacer(QuantityTagger::new());
+        }
+        if let Some(normalizer) = &self.normalizer {
+            pos_model.add_pre_processor(normalizer.clone());
+        }
+        if let Some(emoji_tag) = &self.emoji_tag {
+            let emoji_tagger = EmojiTagger::new(emoji_tag);
+            pos_model.add_pre_processor(emoji_tagger.clone());
+            pos_model.add_post_processor(emoji_tagger);
+        }
+        //last, once the placeholders are restored
+        if let Some(min_score) = self.min_score {
+            pos_model.add_post_processor(move |sentence: &mut Vec<ScoredTag>| {
+                sentence.retain(|scored_tag| scored_tag.score >= min_score)
+            });
+        }
+        Ok(pos_model)
+    }
+
+    /// Check that the `filter_tags` can be output by a model predicting `labels` (once
+    /// mapped) with these settings
+    fn check_filter_tags(&self, labels: &[String]) -> anyhow::Result<()> {
+        let filter_tags = match &self.filter_tags {
+            Some(filter_tags) => filter_tags,
+            None => return Ok(()),
+        };
+        let mut known = labels.to_vec();
+        //uncertain punctuation
+        known.push(String::from("."));
+        if self.protect_tokens.unwrap_or(false) {
+            known.extend(TokenKind::ALL.iter().map(|kind| kind.label().to_owned()));
+        }
+        if self.quantities.unwrap_or(false) {
+            known.extend(QuantityKind::ALL.iter().map(|kind| kind.label().to_owned()));
+        }
+        known.extend(self.emoji_tag.iter().cloned());
+        let unknown: Vec<&str> = filter_tags
+            .iter()
+            .filter(|tag| !known.contains(tag))
+            .map(String::as_str)
+            .collect();
+        if !unknown.is_empty() {
+            bail!(
+                "Unknown filter_tags {}, see the labels of the model with `berttagr_file labels`",
+                unknown.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// The tagger described by these settings: the model of `pos_model`, or the rule-based
+    /// `MockTagger` in builds with the `test-util` feature when `BERTTAGR_MOCK_TAGGER` is set
+    pub fn tagger(&self, warnings: &dyn WarningSink) -> anyhow::Result<Box<dyn Tagger>> {
+        #[cfg(feature = "test-util")]
+        {
+            if env::var_os(crate::mock::MOCK_TAGGER_VAR).is_some() {
+                return Ok(Box::new(crate::mock::MockTagger::new()));
+            }
+        }
+        Ok(Box::new(self.pos_model(warnings)?))
+    }
+
+    /// Build the `POSConfig` described by these settings, its remote files downloaded and
+    /// the pinned ones verified, those loaded unverified passed to `warnings`
+    pub fn pos_config(&self, warnings: &dyn WarningSink) -> anyhow::Result<POSConfig> {
+        let mut pos_config = self.remote_pos_config()?;
+        fetch_resources(&mut pos_config, self)?;
+        if let Some(sha256) = self.checksums()? {
+            let allow_unverified = self.allow_unverified.unwrap_or(false);
+            sha256
+                .verify(pos_config.token_classification_config(), allow_unverified, warnings)
+                .context(ModelLoadError)?;
+        }
+        Ok(pos_config)
+    }
+
+    /// Digests pinned by these settings, or else by the registry entry of `model`
+    pub fn checksums(&self) -> anyhow::Result<Option<Checksums>> {
+        if self.sha256.is_some() {
+            return Ok(self.sha256.clone());
+        }
+        match &self.model {
+            Some(name) => Ok(ModelRegistry::load()?.get(name)?.sha256.clone()),
+            None => Ok(None),
+        }
+    }
+
+    /// The `POSConfig` described by these settings, with the URLs of its remote files
+    pub fn remote_pos_config(&self) -> anyhow::Result<POSConfig> {
+        let sources = [self.model.is_some(), self.hub_model.is_some(), self.model_dir.is_some()];
+        if sources.iter().filter(|&&set| set).count() > 1 {
+            bail!("Set only one of model, hub_model and model_dir");
+        }
+        let mut pos_config = if let Some(name) = &self.model {
+            ModelRegistry::load()?.get(name)?.pos_config(name)?
+        } else if let Some(hub_model) = &self.hub_model {
+            hub_pos_config(hub_model, self)?
+        } else {
+            POSConfig::default()
+        };
+        if let Some(model_type) = &self.model_type {
+            if self.model_dir.is_none() {
+                bail!("model_type requires model_dir");
+            }
+            pos_config = pos_config.model_type(parse_model_type(model_type)?);
+        }
+        match &self.model_dir {
+            Some(model_dir) => pos_config = pos_config.local_model(model_dir),
+            None if self.model.is_some() || self.hub_model.is_some() => {}
+            None => match self.language.as_deref() {
+                None | Some("en") => {}
+                Some(language) => bail!(
+                    "No part-of-speech model available for language '{}', set model_dir",
+                    language
+                ),
+            },
+        }
+        if let Some(device) = self.device()? {
+            pos_config = pos_config.device(device);
+        }
+        if let Some(threads) = self.threads {
+            pos_config = pos_config.cpu_threads(threads);
+        }
+        if let Some(threads) = self.interop_threads {
+            pos_config = pos_config.interop_threads(threads);
+        }
+        if let Some(deterministic) = self.deterministic {
+            pos_config = pos_config.deterministic(deterministic);
+        }
+        if let Some(seed) = self.seed {
+            pos_config = pos_config.seed(seed);
+        }
+        if let Some(aggregation) = self.label_aggregation {
+            pos_config = pos_config.label_aggregation(aggregation);
+        }
+        if let Some(lower_case) = self.lower_case {
+            pos_config = pos_config.lower_case(lower_case);
+        }
+        if let Some(strip_accents) = self.strip_accents {
+            pos_config = pos_config.strip_accents(strip_accents);
+        }
+        pos_config.check_tokenizer().context(UsageError)?;
+        Ok(pos_config)
+    }
+}
+
+#[cfg(feature = "remote-models")]
+fn hub_pos_config(hub_model: &str, config: &Config) -> anyhow::Result<POSConfig> {
+    let hub_model: crate::hub::HubModel = hub_model.parse()?;
+    hub_model.pos_config(&config.download_policy()).context(ModelLoadError)
+}
+
+#[cfg(not(feature = "remote-models"))]
+fn hub_pos_config(_hub_model: &str, _config: &Config) -> anyhow::Result<POSConfig> {
+    bail!("hub_model requires building with the remote-models feature")
+}
+
+/// Download the remote resources of `pos_config` with the retries and timeout of `config`
+#[cfg(feature = "remote-models")]
+fn fetch_resources(pos_config: &mut POSConfig, config: &Config) -> anyhow::Result<()> {
+    config
+        .download_policy()
+        .fetch(pos_config.token_classification_config_mut())
+        .context(ModelLoadError)
+}
+
+#[cfg(not(feature = "remote-models"))]
+fn fetch_resources(_pos_config: &mut POSConfig, _config: &Config) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Parse a device name (`cpu`, `cuda`, `cuda:N` or `auto`)
+pub fn parse_device(name: &str) -> anyhow::Result<Device> {
+    if name.starts_with("cuda") && !cfg!(feature = "cuda") {
+        bail!("Device '{}' requires building with the cuda feature", name);
+    }
+    match name {
+        "cpu" => Ok(Device::Cpu),
+        "cuda" => Ok(Device::Cuda(0)),
+        "auto" => Ok(default_device()),
+        _ => match name.strip_prefix("cuda:").map(str::parse::<usize>) {
+            Some(Ok(index)) => Ok(Device::Cuda(index)),
+            _ => bail!("Unknown device '{}', expected cpu, cuda, cuda:N or auto", name),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn flags_override_file() {
+        let file: Config = toml::from_str("device = \"cpu\"\nbatch_size = 8\nformat = \"tsv\"").unwrap();
+        let flags = Config {
+            batch_size: Some(BatchSize::Fixed(64)),
+            ..Config::default()
+        };
+        let config = file.merge(flags);
+        assert_eq!(config.device.as_deref(), Some("cpu"));
+        assert_eq!(config.batch_size(), 64);
+        assert_eq!(config.format(), TagFormat::Tsv);
+        let auto: Config = toml::from_str("batch_size = \"auto\"").unwrap();
+        assert_eq!(auto.batch_size, Some(BatchSize::Auto));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(toml::from_str::<Config>("batchsize = 8").is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! # Confusion matrix
+//! Counts of each pair of reference and predicted labels, filled by the `diff` subcommand and
+//! exported as CSV or as an HTML heatmap so that error patterns (e.g. VBD predicted as VBN)
+//! can be inspected without other tools.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+#[derive(Debug, Default, Clone, PartialEq)]
+/// # Confusion matrix between reference and predicted labels
+pub struct ConfusionMatrix {
+    counts: BTreeMap<(String, String), usize>,
+}
+
+impl ConfusionMatrix {
+    pub fn new() -> ConfusionMatrix {
+        ConfusionMatrix::default()
+    }
+
+    /// Count a token labelled `reference` in the first tagging and `predicted` in the second
+    pub fn add(&mut self, reference: &str, predicted: &str) {
+        *self
+            .counts
+            .entry((reference.to_owned(), predicted.to_owned()))
+            .or_default() += 1;
+    }
+
+    /// Number of tokens labelled `reference` and `predicted`
+    pub fn count(&self, reference: &str, predicted: &str) -> usize {
+        self.counts
+            .get(&(reference.to_owned(), predicted.to_owned()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Labels found in either tagging, sorted
+    pub fn labels(&self) -> Vec<&str> {
+        self.counts
+            .keys()
+            .flat_map(|(reference, predicted)| vec![reference.as_str(), predicted.as_str()])
+            .collect::<BTreeSet<&str>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Write the matrix as CSV, a row per reference label and a column per predicted label
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let labels = self.labels();
+        write!(writer, "reference\\predicted")?;
+        for label in &labels {
+            write!(writer, ",{}", csv_field(label))?;
+        }
+        writeln!(writer)?;
+        for reference in &labels {
+            write!(writer, "{}", csv_field(reference))?;
+            for predicted in &labels {
+                write!(writer, ",{}", self.count(reference, predicted))?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Write the matrix as a standalone HTML page, each cell shaded by its share of the row
+    pub fn write_html<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let labels = self.labels();
+        writeln!(writer, "<!DOCTYPE html>")?;
+        writeln!(writer, "<html><head><meta charset=\"utf-8\"><title>Confusion matrix</title>")?;
+        writeln!(
+            writer,
+            "<style>table{{border-collapse:collapse;font-family:monospace}}\
+             td,th{{border:1px solid #ccc;padding:2px 6px;text-align:right}}</style>"
+        )?;
+        writeln!(writer, "</head><body><table>")?;
+        write!(writer, "<tr><th>reference \\ predicted</th>")?;
+        for label in &labels {
+            write!(writer, "<th>{}</th>", html_escape(label))?;
+        }
+        writeln!(writer, "</tr>")?;
+        for reference in &labels {
+            let row_total: usize = labels.iter().map(|p| self.count(reference, p)).sum();
+            write!(writer, "<tr><th>{}</th>", html_escape(reference))?;
+            for predicted in &labels {
+                let count = self.count(reference, predicted);
+                let share = if row_total > 0 {
+                    count as f64 / row_total as f64
+                } else {
+                    0.0
+                };
+                //agreements in green, confusions in red
+                let color = if reference == predicted { "0,128,0" } else { "200,0,0" };
+                write!(
+                    writer,
+                    "<td style=\"background:rgba({},{:.3})\" title=\"{} as {}: {}\">{}</td>",
+                    color,
+                    share,
+                    html_escape(reference),
+                    html_escape(predicted),
+                    count,
+                    count
+                )?;
+            }
+            writeln!(writer, "</tr>")?;
+        }
+        writeln!(writer, "</table></body></html>")
+    }
+}
+
+/// `field` quoted if it holds a comma, a quote or a newline
+pub fn csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// `text` with the HTML special characters escaped
+pub fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn writes_csv_with_quoted_labels() {
+        let mut matrix = ConfusionMatrix::new();
+        matrix.add("VBD", "VBD");
+        matrix.add("VBD", "VBN");
+        matrix.add(",", ",");
+        let mut csv = Vec::new();
+        matrix.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "reference\\predicted,\",\",VBD,VBN\n\
+             \",\",1,0,0\n\
+             VBD,0,1,1\n\
+             VBN,0,0,0\n"
+        );
+    }
+}
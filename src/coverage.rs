@@ -0,0 +1,181 @@
+//! # Vocabulary coverage
+//! Report of the `coverage` subcommand estimating how well a model fits the vocabulary of a
+//! new domain before its tags are trusted: words missing from the tokenizer vocabulary are
+//! split into several subword pieces, and words split into many pieces get less reliable
+//! labels. The report gives the share of such words and the most frequent of them:
+//!
+//! ```text
+//! 12840 words, 1.31 pieces per word
+//! 2216 words (17.26%) split into subwords, 412 (3.21%) into 3 or more
+//! pieces  count  word
+//! 4       57     tachycardia
+//! ```
+//!
+//! Words are separated by whitespace and stripped of surrounding punctuation; numbers are
+//! left out. Only the tokenizer is loaded, not the model.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use rust_bert::pipelines::common::TokenizerOption;
+use serde_json::json;
+
+use crate::config::Config;
+use crate::exit::ModelLoadError;
+use crate::warnings::WarningSink;
+
+/// Number of pieces from which a word counts as heavily split
+pub const HEAVY_SPLIT: usize = 3;
+
+/// Number of words listed when none is given
+pub const DEFAULT_TOP: usize = 20;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+/// # Subword splitting of the words of a corpus
+pub struct CoverageReport {
+    /// Number of words
+    pub words: usize,
+    /// Number of subword pieces of the words
+    pub pieces: usize,
+    /// Occurrences of words split into several pieces
+    pub split_words: usize,
+    /// Occurrences of words split into `HEAVY_SPLIT` pieces or more
+    pub heavily_split_words: usize,
+    /// Number of pieces and occurrences of each split word
+    pub split: HashMap<String, (usize, usize)>,
+    /// Number of split words listed
+    pub top: usize,
+}
+
+impl CoverageReport {
+    pub fn new(top: usize) -> CoverageReport {
+        CoverageReport {
+            top,
+            ..CoverageReport::default()
+        }
+    }
+
+    /// Add a word split into `pieces` subword pieces
+    pub fn add_word(&mut self, word: &str, pieces: usize) {
+        self.words += 1;
+        self.pieces += pieces;
+        if pieces < 2 {
+            return;
+        }
+        self.split_words += 1;
+        if pieces >= HEAVY_SPLIT {
+            self.heavily_split_words += 1;
+        }
+        self.split.entry(word.to_owned()).or_insert((pieces, 0)).1 += 1;
+    }
+
+    /// Split words, most frequent first, then most split
+    pub fn most_frequent(&self) -> Vec<(&str, usize, usize)> {
+        let mut words: Vec<(&str, usize, usize)> = self
+            .split
+            .iter()
+            .map(|(word, &(pieces, count))| (word.as_str(), pieces, count))
+            .collect();
+        words.sort_by(|a, b| b.2.cmp(&a.2).then(b.1.cmp(&a.1)).then(a.0.cmp(b.0)));
+        words.truncate(self.top);
+        words
+    }
+
+    fn share(&self, count: usize) -> f64 {
+        if self.words == 0 {
+            0.0
+        } else {
+            count as f64 / self.words as f64
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let words: Vec<serde_json::Value> = self
+            .most_frequent()
+            .into_iter()
+            .map(|(word, pieces, count)| json!({ "word": word, "pieces": pieces, "count": count }))
+            .collect();
+        json!({
+            "words": self.words,
+            "pieces_per_word": self.share(self.pieces),
+            "split_words": self.split_words,
+            "heavily_split_words": self.heavily_split_words,
+            "most_frequent": words,
+        })
+    }
+}
+
+impl fmt::Display for CoverageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} words, {:.2} pieces per word", self.words, self.share(self.pieces))?;
+        writeln!(
+            f,
+            "{} words ({:.2}%) split into subwords, {} ({:.2}%) into {} or more",
+            self.split_words,
+            self.share(self.split_words) * 100.0,
+            self.heavily_split_words,
+            self.share(self.heavily_split_words) * 100.0,
+            HEAVY_SPLIT
+        )?;
+        writeln!(f, "pieces\tcount\tword")?;
+        for (word, pieces, count) in self.most_frequent() {
+            writeln!(f, "{}\t{}\t{}", pieces, count, word)?;
+        }
+        Ok(())
+    }
+}
+
+/// Words of `text` measured by the report: separated by whitespace, stripped of surrounding
+/// punctuation, numbers excluded
+pub fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty() && !word.chars().all(|c| c.is_numeric()))
+}
+
+/// Measure the words of `text` split by `tokenizer`
+pub fn add_text(report: &mut CoverageReport, tokenizer: &TokenizerOption, text: &str) {
+    for word in words(text) {
+        report.add_word(word, tokenizer.tokenize(word).len());
+    }
+}
+
+/// Measure the words of each file of `paths` with the tokenizer of the configured model,
+/// listing the `top` most frequent split words
+pub fn coverage_files(
+    paths: &[PathBuf],
+    top: usize,
+    config: &Config,
+    warnings: &dyn WarningSink,
+) -> anyhow::Result<CoverageReport> {
+    let tokenizer = config.pos_config(warnings)?.tokenizer().context(ModelLoadError)?;
+    let mut report = CoverageReport::new(top);
+    for path in paths {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        add_text(&mut report, &tokenizer, &contents);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_split_words() {
+        assert_eq!(words("(Tachycardia), 120 bpm!").collect::<Vec<_>>(), ["Tachycardia", "bpm"]);
+        let mut report = CoverageReport::new(1);
+        report.add_word("the", 1);
+        report.add_word("tachycardia", 4);
+        report.add_word("tachycardia", 4);
+        report.add_word("bpm", 2);
+        assert_eq!(report.words, 4);
+        assert_eq!(report.split_words, 3);
+        assert_eq!(report.heavily_split_words, 2);
+        assert_eq!(report.most_frequent(), vec![("tachycardia", 4, 2)]);
+    }
+}
@@ -0,0 +1,154 @@
+//! # Dependency parsing
+//! Integration point for a dependency-parsing stage run after tagging, filling the HEAD and
+//! DEPREL columns of CoNLL-U output. Parsers implement `DependencyParser`; `ExternalParser`
+//! runs an external Universal Dependencies parser that reads and writes CoNLL-U, such as
+//! `udpipe --input=conllu --parse english-ewt.udpipe`.
+
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use anyhow::{anyhow, bail, Context};
+
+use crate::format::{TagFormat, TagWriter};
+use crate::pos_tagging::POSTag;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// # Syntactic head of a word
+pub struct Dependency {
+    /// Position (1-based) of the head word in the sentence, 0 for the root
+    pub head: usize,
+    /// Universal Dependencies relation to the head (e.g. nsubj, obj...)
+    pub deprel: String,
+}
+
+/// # Dependency parser of tagged sentences
+pub trait DependencyParser {
+    /// Heads and relations of the words of each sentence, aligned with the tags
+    fn parse(&self, sentences: &[Vec<POSTag>]) -> anyhow::Result<Vec<Vec<Dependency>>>;
+}
+
+/// # External CoNLL-U parser
+/// Runs a command once per batch, writing the tagged sentences as CoNLL-U to its standard
+/// input and reading the parsed CoNLL-U from its standard output.
+pub struct ExternalParser {
+    program: String,
+    args: Vec<String>,
+}
+
+impl ExternalParser {
+    /// Parser running `command`, a program followed by its whitespace-separated arguments
+    pub fn new(command: &str) -> anyhow::Result<ExternalParser> {
+        let mut words = command.split_whitespace().map(String::from);
+        let program = words
+            .next()
+            .ok_or_else(|| anyhow!("The parser command is empty"))?;
+        Ok(ExternalParser {
+            program,
+            args: words.collect(),
+        })
+    }
+}
+
+impl DependencyParser for ExternalParser {
+    fn parse(&self, sentences: &[Vec<POSTag>]) -> anyhow::Result<Vec<Vec<Dependency>>> {
+        let mut tag_writer = TagWriter::new(Vec::new(), TagFormat::Conllu);
+        for sentence in sentences {
+            tag_writer.write_sentence(sentence)?;
+        }
+        let input = tag_writer.finish()?;
+
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Could not run the parser {}", self.program))?;
+
+        //write from another thread so that a parser streaming its output cannot deadlock
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let feeder = thread::spawn(move || stdin.write_all(&input));
+        let mut output = String::new();
+        child
+            .stdout
+            .take()
+            .expect("stdout is piped")
+            .read_to_string(&mut output)?;
+        feeder
+            .join()
+            .map_err(|_| anyhow!("Parser input thread panicked"))??;
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("The parser {} failed with {}", self.program, status);
+        }
+
+        let parsed = read_conllu_dependencies(&output)?;
+        if parsed.len() != sentences.len()
+            || parsed.iter().zip(sentences).any(|(deps, tags)| deps.len() != tags.len())
+        {
+            bail!("The parser output does not match its input sentences");
+        }
+        Ok(parsed)
+    }
+}
+
+/// Read the HEAD and DEPREL columns of each sentence of a CoNLL-U document,
+/// skipping comments, multiword tokens and empty nodes
+pub fn read_conllu_dependencies(conllu: &str) -> anyhow::Result<Vec<Vec<Dependency>>> {
+    let mut sentences = Vec::new();
+    let mut current = Vec::new();
+    for line in conllu.lines() {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                sentences.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 10 {
+            bail!("Invalid CoNLL-U line: {}", line);
+        }
+        if fields[0].contains('-') || fields[0].contains('.') {
+            continue;
+        }
+        current.push(Dependency {
+            head: fields[6]
+                .parse()
+                .with_context(|| format!("Invalid HEAD in CoNLL-U line: {}", line))?,
+            deprel: fields[7].to_owned(),
+        });
+    }
+    if !current.is_empty() {
+        sentences.push(current);
+    }
+    Ok(sentences)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_heads_and_relations() {
+        let conllu = "# sent_id = 1\n\
+                      1\tI\ti\tPRON\tPRP\t_\t2\tnsubj\t_\t_\n\
+                      2\tran\trun\tVERB\tVBD\t_\t0\troot\t_\t_\n\
+                      \n\
+                      1-2\tdon't\t_\t_\t_\t_\t_\t_\t_\t_\n\
+                      1\tdo\tdo\tAUX\tVBP\t_\t0\troot\t_\t_\n\
+                      2\tn't\tnot\tPART\tRB\t_\t1\tadvmod\t_\t_\n";
+        let parsed = read_conllu_dependencies(conllu).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(
+            parsed[0][0],
+            Dependency {
+                head: 2,
+                deprel: String::from("nsubj")
+            }
+        );
+        assert_eq!(parsed[1].len(), 2);
+    }
+}
@@ -0,0 +1,197 @@
+//! # Annotation diff
+//! Compares two taggings of the same text for the `diff` subcommand: the tokens both
+//! taggings agree on are aligned by their position in the text, differing labels are listed
+//! with their context, and the disagreement rate of each tag is summarized. Used to compare
+//! model versions and to adjudicate annotations.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::confusion::ConfusionMatrix;
+use crate::train::read_corpus;
+
+/// Number of words shown on each side of a differing token
+const CONTEXT_WORDS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+/// # Token labelled differently by the two taggings
+pub struct TokenDiff {
+    /// Position of the token among the words of the first tagging
+    pub position: usize,
+    pub word: String,
+    pub label_a: String,
+    pub label_b: String,
+    /// Surrounding words, with the token in brackets
+    pub context: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+/// # Differences between two taggings
+pub struct DiffReport {
+    /// Tokens found in both taggings
+    pub aligned: usize,
+    /// Tokens of each tagging split or merged differently by the other
+    pub unaligned_a: usize,
+    pub unaligned_b: usize,
+    pub differences: Vec<TokenDiff>,
+    /// Number of aligned tokens and of disagreements per label of the first tagging
+    pub per_tag: BTreeMap<String, (usize, usize)>,
+    /// Labels of the aligned tokens, the first tagging being the reference
+    pub confusion: ConfusionMatrix,
+}
+
+impl DiffReport {
+    /// Fraction of the aligned tokens labelled differently
+    pub fn disagreement_rate(&self) -> f64 {
+        if self.aligned == 0 {
+            0.0
+        } else {
+            self.differences.len() as f64 / self.aligned as f64
+        }
+    }
+}
+
+impl fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diff in &self.differences {
+            writeln!(
+                f,
+                "{}\t{}\t{} -> {}\t{}",
+                diff.position, diff.word, diff.label_a, diff.label_b, diff.context
+            )?;
+        }
+        writeln!(
+            f,
+            "{} aligned tokens, {} differing ({:.2}%), {}/{} tokens not aligned",
+            self.aligned,
+            self.differences.len(),
+            self.disagreement_rate() * 100.0,
+            self.unaligned_a,
+            self.unaligned_b
+        )?;
+        writeln!(f, "tag\ttokens\tdiffering\trate")?;
+        for (tag, (count, differing)) in &self.per_tag {
+            writeln!(
+                f,
+                "{}\t{}\t{}\t{:.2}%",
+                tag,
+                count,
+                differing,
+                *differing as f64 / *count as f64 * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the taggings in the files `a_path` and `b_path` (CoNLL-U or word and label per line)
+pub fn diff_files(a_path: &Path, b_path: &Path) -> anyhow::Result<DiffReport> {
+    let read = |path: &Path| -> anyhow::Result<Vec<(String, String)>> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        Ok(read_corpus(&contents)?.into_iter().flatten().collect())
+    };
+    Ok(diff(&read(a_path)?, &read(b_path)?))
+}
+
+/// Compare two taggings given as words and labels. Tokens are aligned when they cover the
+/// same characters of the text, whitespace excluded.
+pub fn diff(a: &[(String, String)], b: &[(String, String)]) -> DiffReport {
+    let spans_a = spans(a);
+    let spans_b = spans(b);
+    let mut report = DiffReport::default();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let (span_a, span_b) = (spans_a[i], spans_b[j]);
+        if span_a == span_b {
+            let (word, label_a) = &a[i];
+            let label_b = &b[j].1;
+            report.aligned += 1;
+            report.confusion.add(label_a, label_b);
+            let per_tag = report.per_tag.entry(label_a.clone()).or_default();
+            per_tag.0 += 1;
+            if label_a != label_b {
+                per_tag.1 += 1;
+                report.differences.push(TokenDiff {
+                    position: i,
+                    word: word.clone(),
+                    label_a: label_a.clone(),
+                    label_b: label_b.clone(),
+                    context: context(a, i),
+                });
+            }
+            i += 1;
+            j += 1;
+        } else {
+            //skip the token ending first, or both when they end together
+            if span_a.1 <= span_b.1 {
+                report.unaligned_a += 1;
+                i += 1;
+            }
+            if span_b.1 <= span_a.1 {
+                report.unaligned_b += 1;
+                j += 1;
+            }
+        }
+    }
+    report.unaligned_a += a.len() - i;
+    report.unaligned_b += b.len() - j;
+    report
+}
+
+/// Character range of each word in the text with its whitespace removed
+fn spans(tagging: &[(String, String)]) -> Vec<(usize, usize)> {
+    let mut position = 0;
+    tagging
+        .iter()
+        .map(|(word, _)| {
+            let start = position;
+            position += word.chars().filter(|c| !c.is_whitespace()).count();
+            (start, position)
+        })
+        .collect()
+}
+
+fn context(tagging: &[(String, String)], position: usize) -> String {
+    let start = position.saturating_sub(CONTEXT_WORDS);
+    let end = (position + CONTEXT_WORDS + 1).min(tagging.len());
+    (start..end)
+        .map(|i| {
+            if i == position {
+                format!("[{}]", tagging[i].0)
+            } else {
+                tagging[i].0.clone()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tagging(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|&(word, label)| (word.to_owned(), label.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn aligns_differently_tokenized_taggings() {
+        let a = tagging(&[("I", "PRP"), ("don't", "VBP"), ("run", "VB"), ("fast", "RB")]);
+        let b = tagging(&[("I", "PRP"), ("do", "VBP"), ("n't", "RB"), ("run", "VB"), ("fast", "JJ")]);
+        let report = diff(&a, &b);
+        assert_eq!(report.aligned, 3);
+        assert_eq!((report.unaligned_a, report.unaligned_b), (1, 2));
+        assert_eq!(report.differences.len(), 1);
+        assert_eq!(report.differences[0].word, "fast");
+        assert_eq!(report.differences[0].context, "I don't run [fast]");
+        assert_eq!(report.per_tag["RB"], (1, 1));
+    }
+}
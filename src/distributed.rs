@@ -0,0 +1,452 @@
+//! # Distributed tagging
+//! A corpus too large for one machine can be tagged by worker processes on several: the
+//! coordinator is the usual run with `--coordinator host:port`, listening on that address
+//! instead of loading the model, and each worker is started with `--worker host:port` and
+//! the model settings of the run:
+//!
+//! ```text
+//! berttagr_file --coordinator 0.0.0.0:7878 --format conllu corpus.txt corpus.conllu   # on host a
+//! berttagr_file --worker a:7878 --device cuda                                          # on hosts b, c, ...
+//! ```
+//!
+//! The coordinator reads, batches and writes as in `stream`, only the inference moves to
+//! the workers, each connection tagging one batch at a time. Filtering, dependency parsing
+//! and quality reports stay on the coordinator. Workers can join at any point of the run,
+//! which waits for at least one. The batch of a worker that disconnects, fails or does not
+//! reply within `REPLY_TIMEOUT` is tagged again by another one, up to `MAX_ATTEMPTS` times, and the batches are put back in order
+//! before writing, so the output is that of a local run whatever the number of workers.
+//!
+//! The protocol is one JSON object per line over TCP: the coordinator sends
+//! `{"sentences": [...]}`, the worker replies `{"tagged": {"sentences": [[...]], ...}}` with
+//! the scored tags of each sentence, or `{"error": "..."}`. It is neither authenticated nor
+//! encrypted, workers and coordinator should only be reachable on a trusted network.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::config::Config;
+use crate::pos_tagging::{PredictStats, ScoredTag};
+use crate::warnings::{SharedSink, Warning, WarningSink};
+
+/// Attempts at a batch, on any worker, before the run fails
+pub const MAX_ATTEMPTS: usize = 3;
+
+/// How long a worker tries to reach the coordinator before giving up
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Interval between the attempts of a worker to connect
+const CONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the coordinator waits for a worker to take a batch and reply before dropping it
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Interval between the checks of the coordinator for new workers and batches
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Batches read ahead of the workers
+const QUEUE_CAPACITY: usize = 16;
+
+#[derive(Debug, Deserialize)]
+/// # Batch sent to a worker
+struct Request {
+    sentences: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// # Answer of a worker to a batch
+enum Reply {
+    Tagged {
+        /// Scored tags of each sentence of the batch
+        sentences: Vec<Vec<ScoredTag>>,
+        /// Calls to the model
+        batches: usize,
+        /// Time spent in the model
+        inference_secs: f64,
+    },
+    Error(String),
+}
+
+/// Batch waiting for a worker, with its number in the order of the input
+struct Pending<B> {
+    sequence: usize,
+    batch: B,
+    /// Failed attempts so far
+    attempts: usize,
+}
+
+struct State<B> {
+    queue: VecDeque<Pending<B>>,
+    /// Batches taken by a connection and not tagged yet
+    in_flight: usize,
+    /// Set once the run is over, to close the connections
+    finished: bool,
+    /// First error stopping the run
+    error: Option<anyhow::Error>,
+}
+
+/// Batches shared by the coordinator and the threads of the connections, which wait for
+/// changes on `changed`
+struct Shared<B> {
+    state: Mutex<State<B>>,
+    changed: Condvar,
+    /// Receives the batches tagged again and the workers dropped
+    warnings: SharedSink,
+}
+
+impl<B> Shared<B> {
+    fn new(warnings: SharedSink) -> Shared<B> {
+        Shared {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                in_flight: 0,
+                finished: false,
+                error: None,
+            }),
+            changed: Condvar::new(),
+            warnings,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<B>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Next batch to tag, waiting for one, `None` once the run is over
+    fn take(&self) -> Option<Pending<B>> {
+        let mut state = self.lock();
+        loop {
+            if state.finished || state.error.is_some() {
+                return None;
+            }
+            if let Some(pending) = state.queue.pop_front() {
+                state.in_flight += 1;
+                return Some(pending);
+            }
+            state = self.changed.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Record the outcome of a batch taken with `take`
+    fn complete(&self, result: anyhow::Result<()>) {
+        let mut state = self.lock();
+        state.in_flight -= 1;
+        if let Err(err) = result {
+            state.error.get_or_insert(err);
+        }
+        self.changed.notify_all();
+    }
+
+    /// Queue a batch that failed with `err` again, first in line, or stop the run if it
+    /// has failed `MAX_ATTEMPTS` times
+    fn retry(&self, mut pending: Pending<B>, err: anyhow::Error) {
+        let mut state = self.lock();
+        state.in_flight -= 1;
+        pending.attempts += 1;
+        if pending.attempts >= MAX_ATTEMPTS {
+            let err = err.context(format!("Batch {} failed {} times", pending.sequence, pending.attempts));
+            state.error.get_or_insert(err);
+        } else {
+            self.warnings.warn(Warning::BatchRetried {
+                batch: pending.sequence,
+                error: format!("{:#}", err),
+            });
+            state.queue.push_front(pending);
+        }
+        self.changed.notify_all();
+    }
+}
+
+/// Tag the batches received from `batches` on the workers connecting to `address`, until
+/// the channel is closed and every batch is tagged
+///
+/// # Arguments
+///
+/// * `sentences` - Sentences of a batch, sent to the workers. Empty batches are not sent.
+/// * `done` - Called, on the thread of a connection, with the number of each batch in the
+///   order of `batches`, the batch, the tags of its sentences and the statistics of the
+///   worker. An error stops the run.
+/// * `warnings` - Receives the batches tagged again and the workers dropped
+pub fn coordinate<B, F>(
+    address: &str,
+    batches: Receiver<B>,
+    sentences: fn(&B) -> &[String],
+    done: F,
+    warnings: &SharedSink,
+) -> anyhow::Result<()>
+where
+    B: Send + 'static,
+    F: Fn(usize, B, Vec<Vec<ScoredTag>>, PredictStats) -> anyhow::Result<()> + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(address).with_context(|| format!("Could not listen on {}", address))?;
+    //polled, so that new workers are accepted while waiting for the batches
+    listener.set_nonblocking(true)?;
+    eprintln!("Waiting for workers on {}", address);
+
+    let shared = Arc::new(Shared::new(Arc::clone(warnings)));
+    let done = Arc::new(done);
+    let mut connections = Vec::new();
+    let mut sequence = 0;
+    let mut reading = true;
+    let result = loop {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                eprintln!("Worker {} connected", peer);
+                let shared = Arc::clone(&shared);
+                let done = Arc::clone(&done);
+                connections.push(thread::spawn(move || serve(stream, &shared, sentences, &*done)));
+                continue;
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+            Err(err) => break Err(err).context("Could not accept a worker"),
+        }
+
+        let mut state = shared.lock();
+        if let Some(err) = state.error.take() {
+            break Err(err);
+        }
+        while reading && state.queue.len() < QUEUE_CAPACITY {
+            match batches.try_recv() {
+                Ok(batch) if sentences(&batch).is_empty() => {
+                    if let Err(err) = done(sequence, batch, Vec::new(), PredictStats::default()) {
+                        state.error.get_or_insert(err);
+                    }
+                    sequence += 1;
+                }
+                Ok(batch) => {
+                    state.queue.push_back(Pending {
+                        sequence,
+                        batch,
+                        attempts: 0,
+                    });
+                    sequence += 1;
+                    shared.changed.notify_all();
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => reading = false,
+            }
+        }
+        if !reading && state.queue.is_empty() && state.in_flight == 0 {
+            break Ok(());
+        }
+        drop(shared.changed.wait_timeout(state, POLL_INTERVAL));
+    };
+
+    //closing the connections ends the workers, and dropping `batches` the reader
+    shared.lock().finished = true;
+    shared.changed.notify_all();
+    drop(batches);
+    for connection in connections {
+        connection.join().map_err(|_| anyhow!("Connection thread panicked"))?;
+    }
+    result
+}
+
+/// Send the batches of `shared` to the worker connected on `stream` until the run is over
+/// or the connection fails
+fn serve<B, F>(stream: TcpStream, shared: &Shared<B>, sentences: fn(&B) -> &[String], done: &F)
+where
+    F: Fn(usize, B, Vec<Vec<ScoredTag>>, PredictStats) -> anyhow::Result<()>,
+{
+    let peer = stream.peer_addr().map_or_else(|_| "?".to_owned(), |peer| peer.to_string());
+    let mut reader = match stream
+        .set_nonblocking(false)
+        .and_then(|()| stream.set_read_timeout(Some(REPLY_TIMEOUT)))
+        .and_then(|()| stream.set_write_timeout(Some(REPLY_TIMEOUT)))
+        .and_then(|()| stream.try_clone())
+        .map(BufReader::new)
+    {
+        Ok(reader) => reader,
+        Err(err) => {
+            shared.warnings.warn(Warning::WorkerDropped {
+                worker: peer,
+                error: err.to_string(),
+            });
+            return;
+        }
+    };
+    let mut writer = stream;
+    while let Some(pending) = shared.take() {
+        let texts = sentences(&pending.batch);
+        let reply = write_line(&mut writer, &json!({ "sentences": texts }))
+            .and_then(|()| read_line(&mut reader))
+            .and_then(|reply| reply.context("Connection closed"));
+        match reply {
+            Ok(Reply::Tagged {
+                sentences: tags,
+                batches,
+                inference_secs,
+            }) if tags.len() == texts.len() => {
+                let stats = PredictStats {
+                    sentences: tags.len(),
+                    tokens: tags.iter().map(Vec::len).sum(),
+                    batches,
+                    inference_time: Duration::from_secs_f64(inference_secs.max(0.0)),
+                };
+                shared.complete(done(pending.sequence, pending.batch, tags, stats));
+            }
+            Ok(Reply::Tagged { sentences: tags, .. }) => {
+                let err = anyhow!("Worker {} returned {} sentences for {}", peer, tags.len(), texts.len());
+                shared.retry(pending, err);
+            }
+            Ok(Reply::Error(message)) => shared.retry(pending, anyhow!("Worker {} failed: {}", peer, message)),
+            Err(err) if is_timeout(&err) => {
+                let err = anyhow!("Worker {} did not reply within {} s", peer, REPLY_TIMEOUT.as_secs());
+                shared.retry(pending, err);
+                return;
+            }
+            Err(err) => {
+                shared.retry(pending, err.context(format!("Lost worker {}", peer)));
+                return;
+            }
+        }
+    }
+}
+
+/// Whether `err` is a read or write on a connection that timed out
+fn is_timeout(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<std::io::Error>())
+        .any(|err| matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut))
+}
+
+/// Tag the batches of the coordinator at `address` with the configured model until it
+/// closes the connection at the end of its run, passing the warnings of the worker to
+/// `warnings`
+pub fn run_worker(address: &str, config: &Config, warnings: &dyn WarningSink) -> anyhow::Result<()> {
+    let tagger = config.tagger(warnings)?;
+    let config = config.with_tuned_batch_size(tagger.as_ref(), warnings);
+    let mut adaptive_batch = config.adaptive_batch();
+    let stream = connect(address, warnings)?;
+    eprintln!("Connected to coordinator {}", address);
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    while let Some(request) = read_line::<Request>(&mut reader)? {
+        let texts: Vec<&str> = request.sentences.iter().map(String::as_str).collect();
+        let reply = match adaptive_batch.tag(tagger.as_ref(), &texts, warnings) {
+            Ok((sentences, stats)) => Reply::Tagged {
+                sentences,
+                batches: stats.batches,
+                inference_secs: stats.inference_time.as_secs_f64(),
+            },
+            Err(err) => {
+                let error = format!("{:#}", err);
+                warnings.warn(Warning::BatchFailed { error: error.clone() });
+                Reply::Error(error)
+            }
+        };
+        write_line(&mut writer, &reply)?;
+    }
+    Ok(())
+}
+
+/// Connect to `address`, trying again until `CONNECT_TIMEOUT` so that workers can be
+/// started before the coordinator
+fn connect(address: &str, warnings: &dyn WarningSink) -> anyhow::Result<TcpStream> {
+    let start = Instant::now();
+    loop {
+        match TcpStream::connect(address) {
+            Ok(stream) => return Ok(stream),
+            Err(err) if start.elapsed() < CONNECT_TIMEOUT => {
+                if err.kind() != ErrorKind::ConnectionRefused {
+                    warnings.warn(Warning::ConnectionFailed {
+                        address: address.to_owned(),
+                        error: err.to_string(),
+                    });
+                }
+                thread::sleep(CONNECT_INTERVAL);
+            }
+            Err(err) => bail!("Could not connect to the coordinator {}: {}", address, err),
+        }
+    }
+}
+
+/// Write `message` as one line of JSON
+fn write_line<T: Serialize>(writer: &mut impl Write, message: &T) -> anyhow::Result<()> {
+    serde_json::to_writer(&mut *writer, message)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read a line of JSON, `None` at the end of the stream
+fn read_line<T: for<'de> Deserialize<'de>>(reader: &mut impl BufRead) -> anyhow::Result<Option<T>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let message = serde_json::from_str(&line).with_context(|| format!("Invalid message {}", line.trim_end()))?;
+    Ok(Some(message))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pos_tagging::POSTag;
+    use std::sync::mpsc::sync_channel;
+
+    #[test]
+    fn tags_batches_on_workers_in_order() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let (sender, receiver) = sync_channel(4);
+        let (results, tagged) = sync_channel(8);
+        let coordinator_address = address.clone();
+        let coordinator = thread::spawn(move || {
+            let results = Mutex::new(results);
+            let warnings: SharedSink = Arc::new(Mutex::new(Vec::new()));
+            coordinate(
+                &coordinator_address,
+                receiver,
+                Vec::as_slice,
+                move |sequence, batch: Vec<String>, tags, _| {
+                    results.lock().unwrap().send((sequence, batch.len(), tags.len()))?;
+                    Ok(())
+                },
+                &warnings,
+            )
+        });
+        for batch in vec![vec!["One.".to_owned()], Vec::new(), vec!["Two.".to_owned(), "Three.".to_owned()]] {
+            sender.send(batch).unwrap();
+        }
+        drop(sender);
+
+        //a fake worker tagging every sentence with one word
+        let stream = connect(&address, &Mutex::new(Vec::new())).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        while let Some(request) = read_line::<Request>(&mut reader).unwrap() {
+            let sentences = request
+                .sentences
+                .into_iter()
+                .map(|word| {
+                    vec![ScoredTag {
+                        tag: POSTag { word, label: "W".to_owned() },
+                        score: 1.0,
+                    }]
+                })
+                .collect();
+            let reply = Reply::Tagged {
+                sentences,
+                batches: 1,
+                inference_secs: 0.0,
+            };
+            write_line(&mut writer, &reply).unwrap();
+        }
+        coordinator.join().unwrap().unwrap();
+        let mut results: Vec<_> = tagged.iter().collect();
+        results.sort_unstable();
+        assert_eq!(results, vec![(0, 1, 1), (1, 0, 0), (2, 2, 2)]);
+    }
+}
@@ -0,0 +1,120 @@
+//! # Model downloads
+//! Remote resources are fetched here rather than inside rust-bert, so that downloads on
+//! flaky networks can be tuned: failed requests are retried `retries` times with an
+//! exponential backoff of up to `MAX_BACKOFF`, each request times out after `timeout`, and
+//! a proxy can be set with `proxy`, `HTTPS_PROXY` and `HTTP_PROXY` being honored otherwise.
+//! Errors name the URL that failed.
+//!
+//! Files go to the rust-bert cache (`$RUSTBERT_CACHE`, or `~/.cache/.rustbert`), where the
+//! model finds them once its resources are replaced with the local paths.
+
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use cached_path::{Cache, Options};
+use rust_bert::pipelines::token_classification::TokenClassificationConfig;
+use rust_bert::resources::{LocalResource, RemoteResource, Resource};
+
+/// Retries of a failed request when none are given
+pub const DEFAULT_RETRIES: u32 = 3;
+
+/// Timeout of a request when none is given
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest wait between two attempts
+pub const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Timeout of the connection to the server
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// # Retries, timeout and proxy of the downloads
+pub struct DownloadPolicy {
+    /// Attempts at a failed request after the first one
+    pub retries: u32,
+    /// Timeout of each request
+    pub timeout: Duration,
+    /// Proxy URL of the requests, the environment's when `None`
+    pub proxy: Option<String>,
+}
+
+impl Default for DownloadPolicy {
+    fn default() -> Self {
+        DownloadPolicy {
+            retries: DEFAULT_RETRIES,
+            timeout: DEFAULT_TIMEOUT,
+            proxy: None,
+        }
+    }
+}
+
+impl DownloadPolicy {
+    /// Cache downloading with this policy
+    fn cache(&self) -> anyhow::Result<Cache> {
+        let mut client = reqwest::blocking::ClientBuilder::new()
+            .timeout(self.timeout)
+            .connect_timeout(CONNECT_TIMEOUT);
+        if let Some(proxy) = &self.proxy {
+            client = client.proxy(
+                reqwest::Proxy::all(proxy.as_str()).with_context(|| format!("Invalid proxy URL {}", proxy))?,
+            );
+        }
+        Cache::builder()
+            .dir(cache_dir()?)
+            .client_builder(client)
+            .max_retries(self.retries)
+            .max_backoff(MAX_BACKOFF.as_millis() as u32)
+            .build()
+            .context("Could not open the model cache")
+    }
+
+    /// Local path of `resource`, downloaded first if remote
+    pub fn local_path(&self, resource: &Resource) -> anyhow::Result<PathBuf> {
+        match resource {
+            Resource::Local(local) => Ok(local.local_path.clone()),
+            Resource::Remote(remote) => self
+                .cache()?
+                .cached_path_with_options(&remote.url, &Options::default().subdir(&remote.cache_subdir))
+                .with_context(|| format!("Could not download {}", remote.url)),
+        }
+    }
+
+    /// Download the remote resources of `config` and replace them with their local paths
+    pub fn fetch(&self, config: &mut TokenClassificationConfig) -> anyhow::Result<()> {
+        let resources = [
+            Some(&mut config.model_resource),
+            Some(&mut config.config_resource),
+            Some(&mut config.vocab_resource),
+            config.merges_resource.as_mut(),
+        ];
+        for resource in resources.iter_mut().flatten() {
+            let local_path = self.local_path(resource)?;
+            **resource = Resource::Local(LocalResource { local_path });
+        }
+        Ok(())
+    }
+}
+
+/// Path of `remote` in the cache if it was downloaded already, without connecting to the
+/// server
+pub fn cached_path(remote: &RemoteResource) -> Option<PathBuf> {
+    Cache::builder()
+        .dir(cache_dir().ok()?)
+        .offline(true)
+        .build()
+        .ok()?
+        .cached_path_with_options(&remote.url, &Options::default().subdir(&remote.cache_subdir))
+        .ok()
+}
+
+/// Directory of the rust-bert cache
+pub fn cache_dir() -> anyhow::Result<PathBuf> {
+    match env::var_os("RUSTBERT_CACHE") {
+        Some(dir) => Ok(PathBuf::from(dir)),
+        None => env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".cache").join(".rustbert"))
+            .ok_or_else(|| anyhow!("No model cache directory, set RUSTBERT_CACHE")),
+    }
+}
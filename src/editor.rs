@@ -0,0 +1,371 @@
+//! # Editor integration
+//! `berttagr_file editor` is the backend of editor plugins highlighting the part of speech
+//! (or entity) of each word as the user types. It speaks a subset of the Language Server
+//! Protocol over stdin and stdout: JSON-RPC messages framed by a `Content-Length` header.
+//!
+//! * `initialize` is answered with incremental text synchronization, `shutdown` with
+//!   `null`, and `exit` stops the process
+//! * `textDocument/didOpen` tags the document, and `textDocument/didChange` applies the
+//!   changes, ranged or full, only tagging again the sentences they touch (see
+//!   `incremental`)
+//! * after either, the tags of the document are published with a
+//!   `berttagr/publishAnnotations` notification; `textDocument/didClose` publishes none
+//!
+//! ```text
+//! {"jsonrpc":"2.0","method":"berttagr/publishAnnotations","params":{"uri":"file:///a.txt",
+//!  "version":2,"annotations":[{"range":{"start":{"line":0,"character":0},
+//!  "end":{"line":0,"character":2}},"label":"PRP$","score":0.98}, ...]}}
+//! ```
+//!
+//! Positions are lines and UTF-16 code units, as in the Language Server Protocol. Other
+//! requests are answered with a `MethodNotFound` error and other notifications ignored.
+//! Invalid notifications are reported with `window/showMessage` and the session goes on.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::incremental::TaggedText;
+use crate::sentences;
+use crate::tagger::Tagger;
+use crate::warnings::WarningSink;
+
+/// Method of the notifications publishing the tags of a document
+pub const PUBLISH_METHOD: &str = "berttagr/publishAnnotations";
+
+/// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// # Line and UTF-16 column in a document
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// # Range between two positions of a document
+pub struct LspRange {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// # Tag of a word of a document
+pub struct Annotation {
+    pub range: LspRange,
+    pub label: String,
+    pub score: f64,
+}
+
+#[derive(Deserialize)]
+struct TextDocumentItem {
+    uri: String,
+    version: i64,
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct VersionedTextDocument {
+    uri: String,
+    version: i64,
+}
+
+#[derive(Deserialize)]
+struct ContentChange {
+    range: Option<LspRange>,
+    text: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DidOpenParams {
+    text_document: TextDocumentItem,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DidChangeParams {
+    text_document: VersionedTextDocument,
+    content_changes: Vec<ContentChange>,
+}
+
+#[derive(Deserialize)]
+struct TextDocumentIdentifier {
+    uri: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DidCloseParams {
+    text_document: TextDocumentIdentifier,
+}
+
+/// Serve the editor on stdin and stdout until it sends `exit` or closes stdin, passing
+/// the warnings of the model to `warnings`
+pub fn run(config: &Config, warnings: &dyn WarningSink) -> anyhow::Result<()> {
+    let tagger = config.tagger(warnings)?;
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut server = Server::new(tagger.as_ref());
+    server.serve(&mut stdin.lock(), &mut stdout.lock())
+}
+
+/// # Open documents of an editor session
+pub struct Server<'t> {
+    tagger: &'t dyn Tagger,
+    documents: HashMap<String, TaggedText>,
+}
+
+impl<'t> Server<'t> {
+    pub fn new(tagger: &'t dyn Tagger) -> Server<'t> {
+        Server {
+            tagger,
+            documents: HashMap::new(),
+        }
+    }
+
+    /// Answer the messages read from `input` on `output` until `exit` or the end of `input`
+    pub fn serve<R: BufRead, W: Write>(&mut self, input: &mut R, output: &mut W) -> anyhow::Result<()> {
+        while let Some(content) = read_message(input)? {
+            let message: Value = match serde_json::from_str(&content) {
+                Ok(message) => message,
+                Err(err) => {
+                    write_message(output, &error_response(Value::Null, PARSE_ERROR, &err.to_string()))?;
+                    continue;
+                }
+            };
+            let method = message.get("method").and_then(Value::as_str).unwrap_or("");
+            if method == "exit" {
+                break;
+            }
+            let params = message.get("params").cloned().unwrap_or(Value::Null);
+            match message.get("id").cloned() {
+                Some(id) => {
+                    let response = match self.request(method) {
+                        Some(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                        None => error_response(id, METHOD_NOT_FOUND, &format!("Unsupported method {}", method)),
+                    };
+                    write_message(output, &response)?
+                }
+                None => match self.notify(method, params) {
+                    Ok(Some(notification)) => write_message(output, &notification)?,
+                    Ok(None) => {}
+                    //notifications have no response, the error is shown to the user instead
+                    Err(err) => write_message(output, &json!({
+                        "jsonrpc": "2.0",
+                        "method": "window/showMessage",
+                        "params": {"type": 1, "message": format!("{:#}", err)},
+                    }))?,
+                },
+            }
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Result of the request `method`, `None` if it is not supported
+    fn request(&self, method: &str) -> Option<Value> {
+        match method {
+            "initialize" => Some(json!({
+                "capabilities": {"textDocumentSync": 2},
+                "serverInfo": {"name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION")},
+            })),
+            "shutdown" => Some(Value::Null),
+            _ => None,
+        }
+    }
+
+    /// Notification to publish after the notification `method`, if any
+    fn notify(&mut self, method: &str, params: Value) -> anyhow::Result<Option<Value>> {
+        match method {
+            "textDocument/didOpen" => {
+                let params: DidOpenParams = serde_json::from_value(params).context("Invalid didOpen parameters")?;
+                let document = params.text_document;
+                let tagged = TaggedText::new(self.tagger, document.text);
+                let notification = publish(&document.uri, Some(document.version), annotations(&tagged));
+                self.documents.insert(document.uri, tagged);
+                Ok(Some(notification))
+            }
+            "textDocument/didChange" => {
+                let params: DidChangeParams =
+                    serde_json::from_value(params).context("Invalid didChange parameters")?;
+                let document = params.text_document;
+                let mut tagged = self
+                    .documents
+                    .remove(&document.uri)
+                    .ok_or_else(|| anyhow!("{} is not open", document.uri))?;
+                //a document whose change fails is out of sync with the editor, it stays closed
+                for change in params.content_changes {
+                    tagged = match change.range {
+                        Some(range) => byte_range(&tagged.text, &range)
+                            .and_then(|range| tagged.edit(self.tagger, range, &change.text))
+                            .with_context(|| format!("Invalid change of {}, closed until opened again", document.uri))?,
+                        None => TaggedText::new(self.tagger, change.text),
+                    };
+                }
+                let notification = publish(&document.uri, Some(document.version), annotations(&tagged));
+                self.documents.insert(document.uri, tagged);
+                Ok(Some(notification))
+            }
+            "textDocument/didClose" => {
+                let params: DidCloseParams = serde_json::from_value(params).context("Invalid didClose parameters")?;
+                let document = params.text_document;
+                self.documents.remove(&document.uri);
+                Ok(Some(publish(&document.uri, None, Vec::new())))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Notification publishing `annotations` for version `version` of the document `uri`
+fn publish(uri: &str, version: Option<i64>, annotations: Vec<Annotation>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": PUBLISH_METHOD,
+        "params": {"uri": uri, "version": version, "annotations": annotations},
+    })
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+/// Annotations of the words of `tagged`, leaving out the words not found in their sentence
+pub fn annotations(tagged: &TaggedText) -> Vec<Annotation> {
+    let lines = LineIndex::new(&tagged.text);
+    let mut annotations = Vec::new();
+    for sentence in &tagged.sentences {
+        let text = &tagged.text[sentence.range.clone()];
+        let mut position = 0;
+        for scored_tag in &sentence.tags {
+            let found = match sentences::find_word(text, position, &scored_tag.tag.word) {
+                Some(found) => found,
+                None => continue,
+            };
+            position = found.end;
+            let start = sentence.range.start + found.start;
+            let end = sentence.range.start + found.end;
+            annotations.push(Annotation {
+                range: LspRange {
+                    start: lines.position(&tagged.text, start),
+                    end: lines.position(&tagged.text, end),
+                },
+                label: scored_tag.tag.label.clone(),
+                score: scored_tag.score,
+            });
+        }
+    }
+    annotations
+}
+
+/// # Byte offsets of the lines of a text
+struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> LineIndex {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        LineIndex { starts }
+    }
+
+    /// Position of the byte `offset` of `text`
+    fn position(&self, text: &str, offset: usize) -> Position {
+        let line = match self.starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        let character = text[self.starts[line]..offset].encode_utf16().count();
+        Position { line, character }
+    }
+}
+
+/// Byte offset of `position` in `text`. Columns past the end of a line are the end of the
+/// line, as the protocol asks.
+pub fn byte_offset(text: &str, position: Position) -> anyhow::Result<usize> {
+    let lines = LineIndex::new(text);
+    let start = *lines
+        .starts
+        .get(position.line)
+        .ok_or_else(|| anyhow!("Line {} is past the end of the document", position.line))?;
+    let line_end = text[start..].find('\n').map_or(text.len(), |index| start + index);
+    let mut units = 0;
+    for (index, c) in text[start..line_end].char_indices() {
+        if units >= position.character {
+            return Ok(start + index);
+        }
+        units += c.len_utf16();
+    }
+    Ok(line_end)
+}
+
+/// Byte range of `range` in `text`
+fn byte_range(text: &str, range: &LspRange) -> anyhow::Result<Range<usize>> {
+    let start = byte_offset(text, range.start)?;
+    let end = byte_offset(text, range.end)?;
+    if start > end {
+        bail!("The range {:?} ends before it starts", range);
+    }
+    Ok(start..end)
+}
+
+/// Content of the next message of `input`, `None` at the end of the input
+fn read_message<R: BufRead>(input: &mut R) -> anyhow::Result<Option<String>> {
+    let mut length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>().context("Invalid Content-Length header")?);
+        }
+    }
+    let length = length.ok_or_else(|| anyhow!("Message without a Content-Length header"))?;
+    let mut content = vec![0; length];
+    input.read_exact(&mut content)?;
+    Ok(Some(String::from_utf8(content).context("The message is not UTF-8")?))
+}
+
+fn write_message<W: Write>(output: &mut W, message: &Value) -> io::Result<()> {
+    let content = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", content.len(), content)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converts_utf16_positions() {
+        let text = "héllo\n𝄞 world";
+        let position = Position { line: 1, character: 3 };
+        assert_eq!(byte_offset(text, position).unwrap(), text.find('w').unwrap());
+        assert_eq!(LineIndex::new(text).position(text, text.find('w').unwrap()), position);
+        assert_eq!(byte_offset(text, Position { line: 0, character: 99 }).unwrap(), 6);
+        assert!(byte_offset(text, Position { line: 2, character: 0 }).is_err());
+    }
+
+    #[test]
+    fn reads_framed_messages() {
+        let mut input = io::Cursor::new("Content-Length: 2\r\nContent-Type: x\r\n\r\n{}Content-Length: 1\r\n\r\n1");
+        assert_eq!(read_message(&mut input).unwrap().as_deref(), Some("{}"));
+        assert_eq!(read_message(&mut input).unwrap().as_deref(), Some("1"));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+    }
+}
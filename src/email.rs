@@ -0,0 +1,237 @@
+//! # Email messages
+//! Bodies of RFC 822 messages for `--input-format eml` (one message per file) and `mbox`
+//! (archives of messages, each starting with a `From ` line): the headers are dropped, the
+//! first `text/plain` part of multipart messages is kept and decoded from quoted-printable
+//! or base64, and quoted replies are removed, so that only the text written in each message
+//! is tagged.
+//!
+//! Quoted replies are the lines starting with `>`, and everything from a reply introduction
+//! (`On ... wrote:`) or a forwarded original (`-----Original Message-----`) to the end of
+//! the body. Messages are identified by their `Message-ID`, without the angle brackets.
+
+/// Message id and body of each message of `contents`, a single message if `mbox` is not
+/// set and an mbox archive otherwise
+pub fn parse_messages(contents: &str, mbox: bool) -> Vec<(Option<String>, String)> {
+    let messages = if mbox { split_mbox(contents) } else { vec![contents.to_owned()] };
+    messages
+        .iter()
+        .map(|raw| {
+            let (headers, body) = split_message(raw);
+            let id = header(&headers, "message-id")
+                .map(|id| id.trim().trim_start_matches('<').trim_end_matches('>').to_owned())
+                .filter(|id| !id.is_empty());
+            (id, strip_quoted(&body_text(&headers, body)))
+        })
+        .collect()
+}
+
+/// Messages of an mbox archive, with the `From ` separator lines removed and `>From ` lines
+/// unescaped
+pub fn split_mbox(contents: &str) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut current: Option<String> = None;
+    let mut previous_blank = true;
+    for line in contents.lines() {
+        if line.starts_with("From ") && previous_blank {
+            messages.extend(current.take());
+            current = Some(String::new());
+        } else if let Some(message) = current.as_mut() {
+            let line = line.strip_prefix('>').filter(|line| line.starts_with("From ")).unwrap_or(line);
+            message.push_str(line);
+            message.push('\n');
+        }
+        previous_blank = line.trim().is_empty();
+    }
+    messages.extend(current);
+    messages
+}
+
+/// Headers, unfolded, and body of a message
+fn split_message(raw: &str) -> (Vec<(String, String)>, &str) {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut rest = raw;
+    while !rest.is_empty() {
+        let end = rest.find('\n').map_or(rest.len(), |end| end + 1);
+        let line = rest[..end].trim_end_matches(|c| c == '\r' || c == '\n');
+        rest = &rest[end..];
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with(|c: char| c == ' ' || c == '\t') {
+            //folded continuation of the previous header
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some(colon) = line.find(':') {
+            headers.push((line[..colon].trim().to_lowercase(), line[colon + 1..].trim().to_owned()));
+        }
+    }
+    (headers, rest)
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+}
+
+/// Value of the parameter `name` of a header such as `Content-Type`
+fn parameter(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_at(param.find('=')?);
+        if key.trim().eq_ignore_ascii_case(name) {
+            Some(value[1..].trim().trim_matches('"').to_owned())
+        } else {
+            None
+        }
+    })
+}
+
+/// Decoded plain text of a body with `headers`, empty if it has no text part
+fn body_text(headers: &[(String, String)], body: &str) -> String {
+    let content_type = header(headers, "content-type").unwrap_or("text/plain");
+    let mime_type = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+    if mime_type.starts_with("multipart/") {
+        let boundary = match parameter(content_type, "boundary") {
+            Some(boundary) => format!("--{}", boundary),
+            None => return String::new(),
+        };
+        return body
+            .split(boundary.as_str())
+            .skip(1)
+            .take_while(|part| !part.starts_with("--"))
+            .map(|part| {
+                let part = part.trim_start_matches(|c| c == '\r' || c == '\n');
+                let (part_headers, part_body) = split_message(part);
+                body_text(&part_headers, part_body)
+            })
+            .find(|text| !text.trim().is_empty())
+            .unwrap_or_default();
+    }
+    if mime_type != "text/plain" {
+        return String::new();
+    }
+    let encoding = header(headers, "content-transfer-encoding").unwrap_or("").to_lowercase();
+    match encoding.as_str() {
+        "quoted-printable" => decode_quoted_printable(body),
+        "base64" => String::from_utf8_lossy(&decode_base64(body)).into_owned(),
+        _ => body.to_owned(),
+    }
+}
+
+fn decode_quoted_printable(text: &str) -> String {
+    let mut bytes = Vec::with_capacity(text.len());
+    for line in text.lines() {
+        let line = line.trim_end();
+        let (line, soft_break) = match line.strip_suffix('=') {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        let raw = line.as_bytes();
+        let mut i = 0;
+        while i < raw.len() {
+            let hex = raw.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+            match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                Some(byte) if raw[i] == b'=' => {
+                    bytes.push(byte);
+                    i += 3;
+                }
+                _ => {
+                    bytes.push(raw[i]);
+                    i += 1;
+                }
+            }
+        }
+        if !soft_break {
+            bytes.push(b'\n');
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn decode_base64(text: &str) -> Vec<u8> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for sextet in text.bytes().filter_map(value) {
+        buffer = (buffer << 6) | u32::from(sextet);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    bytes
+}
+
+/// `body` without quoted lines and what follows a reply introduction or a forwarded original
+pub fn strip_quoted(body: &str) -> String {
+    let lines: Vec<&str> = body.lines().collect();
+    let mut kept = Vec::with_capacity(lines.len());
+    for (index, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("-----Original Message-----") {
+            break;
+        }
+        //"On Mon, 3 May 2021, Alex wrote:", possibly wrapped over two lines
+        let introduction = if trimmed.starts_with("On ") && !trimmed.ends_with("wrote:") {
+            lines.get(index + 1).map_or(false, |next| next.trim().ends_with("wrote:"))
+        } else {
+            trimmed.starts_with("On ") && trimmed.ends_with("wrote:")
+        };
+        if introduction {
+            break;
+        }
+        if !trimmed.starts_with('>') {
+            kept.push(*line);
+        }
+    }
+    kept.join("\n").trim().to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keeps_the_new_text_of_each_message() {
+        let mbox = "From alex@example.com Mon May  3 10:00:00 2021\n\
+                    Message-ID: <1@example.com>\n\
+                    Subject: Re: lunch\n\
+                    \n\
+                    Sounds good.\n\
+                    >From what I heard it opens at noon.\n\
+                    \n\
+                    On Mon, 3 May 2021, Sam wrote:\n\
+                    > Lunch at noon?\n\
+                    \n\
+                    From sam@example.com Mon May  3 11:00:00 2021\n\
+                    Content-Type: multipart/alternative; boundary=\"b1\"\n\
+                    \n\
+                    --b1\n\
+                    Content-Type: text/plain; charset=utf-8\n\
+                    Content-Transfer-Encoding: quoted-printable\n\
+                    \n\
+                    Caf=C3=A9 it is, see =\n\
+                    you there.\n\
+                    --b1\n\
+                    Content-Type: text/html\n\
+                    \n\
+                    <p>Caf&eacute;</p>\n\
+                    --b1--\n";
+        let messages = parse_messages(mbox, true);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].0.as_deref(), Some("1@example.com"));
+        assert_eq!(messages[0].1, "Sounds good.\nFrom what I heard it opens at noon.");
+        assert_eq!(messages[1].0, None);
+        assert_eq!(messages[1].1, "Café it is, see you there.");
+        assert_eq!(decode_base64("SGVsbG8h\n"), b"Hello!");
+    }
+}
@@ -0,0 +1,194 @@
+//! # Emoji and emoticons
+//! Tags emoji (including skin tone, flag and joined sequences) and ASCII emoticons with a
+//! configurable label such as `SYM` or `EMOJI`, instead of whatever the model predicts for
+//! the unknown tokens they become. Enabled with `emoji_tag` in the configuration file or
+//! with `--emoji-tag`.
+//!
+//! `EmojiTagger` runs on both sides of the model: as a `PreProcessor` it separates emoji from
+//! the words they are glued to ("great😀"), as a `PostProcessor` it relabels emoji and joins
+//! the punctuation tokens the tokenizer splits emoticons into (":" and ")" into ":)"). The
+//! tokens carry no offsets, so separated tokens spelling an emoticon (": )") are joined too.
+
+use crate::hooks::{PostProcessor, PreProcessor};
+use crate::pos_tagging::{POSTag, ScoredTag};
+
+/// ASCII emoticons, matched case-sensitively. Ambiguous ones (":/" in URLs, "8)" closing
+/// list items) are left out.
+const EMOTICONS: &[&str] = &[
+    ":)", ":-)", ":(", ":-(", ";)", ";-)", ":D", ":-D", ":P", ":-P", ":p", ":-p", ":O", ":o",
+    ":-/", ":|", ":'(", ":*", "<3", "</3", "xD", "XD", "^_^", "^^", "-_-", "o_O", "O_o",
+    "T_T", ">_<", "=)", "=(",
+];
+
+/// Most tokens an emoticon is split into by the tokenizer
+const MAX_EMOTICON_TOKENS: usize = 4;
+
+/// Whether `c` is a pictographic emoji character
+pub fn is_emoji_char(c: char) -> bool {
+    matches!(c as u32,
+        0x1F000..=0x1FAFF
+        | 0x2600..=0x27BF
+        | 0x2300..=0x23FF
+        | 0x2B00..=0x2BFF
+        | 0x3030 | 0x303D | 0x3297 | 0x3299
+        | 0x203C | 0x2049)
+}
+
+/// Whether `c` combines with an emoji: zero width joiner, variation selectors, keycap
+/// and tag characters
+fn is_emoji_modifier(c: char) -> bool {
+    matches!(c as u32, 0x200D | 0xFE0E | 0xFE0F | 0x20E3 | 0xE0020..=0xE007F)
+}
+
+/// Whether `word` is made of emoji only, such as "👍🏽", "🇫🇷" or "👩‍💻"
+///
+/// # Example
+///
+/// ```
+/// use rustlib::emoji::is_emoji;
+///
+/// assert!(is_emoji("👩‍💻"));
+/// assert!(!is_emoji("great😀"));
+/// ```
+pub fn is_emoji(word: &str) -> bool {
+    word.chars().any(is_emoji_char)
+        && word.chars().all(|c| is_emoji_char(c) || is_emoji_modifier(c))
+}
+
+/// Whether `word` is an ASCII emoticon such as ":)" or "<3"
+pub fn is_emoticon(word: &str) -> bool {
+    EMOTICONS.contains(&word)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// # Labels emoji and emoticons with a fixed tag
+pub struct EmojiTagger {
+    tag: String,
+}
+
+impl EmojiTagger {
+    pub fn new(tag: &str) -> EmojiTagger {
+        EmojiTagger {
+            tag: tag.to_owned(),
+        }
+    }
+
+    fn scored_tag(&self, word: String) -> ScoredTag {
+        ScoredTag {
+            tag: POSTag {
+                word,
+                label: self.tag.clone(),
+            },
+            score: 1.0,
+        }
+    }
+}
+
+impl PreProcessor for EmojiTagger {
+    /// Surround each run of emoji with spaces
+    fn process(&self, text: &str) -> String {
+        let mut spaced = String::with_capacity(text.len() + 8);
+        let mut in_emoji = false;
+        for c in text.chars() {
+            let emoji = is_emoji_char(c) || (in_emoji && is_emoji_modifier(c));
+            let needs_space = spaced.chars().last().map_or(false, |last| !last.is_whitespace());
+            if emoji != in_emoji && needs_space && !c.is_whitespace() {
+                spaced.push(' ');
+            }
+            spaced.push(c);
+            in_emoji = emoji;
+        }
+        spaced
+    }
+}
+
+impl PostProcessor for EmojiTagger {
+    fn process(&self, sentence: &mut Vec<ScoredTag>) {
+        let mut tagged = Vec::with_capacity(sentence.len());
+        let mut tokens = std::mem::take(sentence).into_iter();
+        let mut pending: Vec<ScoredTag> = Vec::new();
+        loop {
+            //keep a window of the next tokens to find emoticons split into several of them
+            while pending.len() < MAX_EMOTICON_TOKENS {
+                match tokens.next() {
+                    Some(token) => pending.push(token),
+                    None => break,
+                }
+            }
+            if pending.is_empty() {
+                break;
+            }
+            let emoticon_len = (1..=pending.len()).rev().find(|&len| {
+                let word: String = pending[..len].iter().map(|t| t.tag.word.as_str()).collect();
+                is_emoticon(&word)
+            });
+            match emoticon_len {
+                Some(len) => {
+                    let word = pending.drain(..len).map(|t| t.tag.word).collect();
+                    tagged.push(self.scored_tag(word));
+                }
+                None => {
+                    let token = pending.remove(0);
+                    if is_emoji(&token.tag.word) {
+                        tagged.push(self.scored_tag(token.tag.word));
+                    } else {
+                        tagged.push(token);
+                    }
+                }
+            }
+        }
+        *sentence = tagged;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn scored_tags(tokens: &[(&str, &str)]) -> Vec<ScoredTag> {
+        tokens
+            .iter()
+            .map(|&(word, label)| ScoredTag {
+                tag: POSTag {
+                    word: word.to_owned(),
+                    label: label.to_owned(),
+                },
+                score: 0.5,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn separates_and_labels_emoji_and_emoticons() {
+        let emoji_tagger = EmojiTagger::new("EMOJI");
+        assert_eq!(
+            PreProcessor::process(&emoji_tagger, "great😀👍🏽ok"),
+            "great 😀👍🏽 ok"
+        );
+
+        let mut sentence = scored_tags(&[
+            ("great", "JJ"),
+            ("😀👍🏽", "NN"),
+            (":", ":"),
+            (")", "-RRB-"),
+            ("<", "JJR"),
+            ("3", "CD"),
+            ("!", "."),
+        ]);
+        PostProcessor::process(&emoji_tagger, &mut sentence);
+        let tagged: Vec<(&str, &str)> = sentence
+            .iter()
+            .map(|t| (t.tag.word.as_str(), t.tag.label.as_str()))
+            .collect();
+        assert_eq!(
+            tagged,
+            vec![
+                ("great", "JJ"),
+                ("😀👍🏽", "EMOJI"),
+                (":)", "EMOJI"),
+                ("<3", "EMOJI"),
+                ("!", "."),
+            ]
+        );
+    }
+}
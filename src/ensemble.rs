@@ -0,0 +1,160 @@
+//! # Ensemble tagging
+//! Combines the predictions of several `Tagger` backends by confidence-weighted voting. The
+//! errors of a single model on noisy text are systematic, the ones of unrelated models
+//! mostly are not, so the label most members agree on is right more often.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::pos_tagging::{POSTag, ScoredTag};
+use crate::tagger::Tagger;
+
+/// Number of words of a member looked at to find the word of the first member it tagged,
+/// when the members tokenize a text differently
+const ALIGNMENT_WINDOW: usize = 3;
+
+/// # Tagger voting over the predictions of its members
+///
+/// Each member votes for its label of a word with its confidence times its weight, and the
+/// label with the most votes wins. The words are the ones of the first member; words the
+/// other members split or merge differently are left to the members that agree on them.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> anyhow::Result<()> {
+/// use rustlib::ensemble::EnsembleTagger;
+/// use rustlib::pos_tagging::{POSConfig, POSModel};
+/// use rustlib::tagger::Tagger;
+/// use std::path::Path;
+///
+/// let ensemble = EnsembleTagger::new()
+///     .member(POSModel::new(Default::default())?, 1.0)
+///     .member(POSModel::new(POSConfig::default().local_model(Path::new("model")))?, 0.5);
+/// let output = ensemble.tag(&["My name is Amy."]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct EnsembleTagger {
+    members: Vec<(Box<dyn Tagger>, f64)>,
+}
+
+impl EnsembleTagger {
+    pub fn new() -> EnsembleTagger {
+        EnsembleTagger::default()
+    }
+
+    /// Add a member whose votes are multiplied by `weight`
+    pub fn member<T: Tagger + 'static>(mut self, tagger: T, weight: f64) -> EnsembleTagger {
+        self.members.push((Box::new(tagger), weight));
+        self
+    }
+}
+
+impl Tagger for EnsembleTagger {
+    /// Tags elected by the members, with the share of the votes cast for each label as its score
+    fn tag(&self, input: &[&str]) -> Vec<Vec<ScoredTag>> {
+        let mut outputs = self
+            .members
+            .iter()
+            .map(|(tagger, weight)| (tagger.tag(input), *weight))
+            .collect::<Vec<(Vec<Vec<ScoredTag>>, f64)>>();
+        if outputs.is_empty() {
+            return input.iter().map(|_| Vec::new()).collect();
+        }
+        let (reference, _) = outputs.remove(0);
+        let weight = self.members[0].1;
+        reference
+            .into_iter()
+            .enumerate()
+            .map(|(index, sentence)| {
+                let others = outputs
+                    .iter()
+                    .map(|(output, weight)| (output[index].as_slice(), *weight))
+                    .collect::<Vec<(&[ScoredTag], f64)>>();
+                vote(sentence, weight, &others)
+            })
+            .collect()
+    }
+}
+
+/// Elect the label of each word of `reference`, a sentence tagged by a member of weight
+/// `weight`, with the votes of the `others` tagging of the same sentence
+fn vote(reference: Vec<ScoredTag>, weight: f64, others: &[(&[ScoredTag], f64)]) -> Vec<ScoredTag> {
+    let mut positions = vec![0; others.len()];
+    reference
+        .into_iter()
+        .map(|scored_tag| {
+            let mut votes: HashMap<String, f64> = HashMap::new();
+            let mut total = weight;
+            *votes.entry(scored_tag.tag.label.clone()).or_default() += weight * scored_tag.score;
+            for ((tags, weight), position) in others.iter().zip(positions.iter_mut()) {
+                let end = (*position + ALIGNMENT_WINDOW).min(tags.len());
+                let aligned = (*position..end).find(|&i| tags[i].tag.word == scored_tag.tag.word);
+                if let Some(i) = aligned {
+                    *votes.entry(tags[i].tag.label.clone()).or_default() += weight * tags[i].score;
+                    total += weight;
+                    *position = i + 1;
+                }
+            }
+            let (label, score) = votes
+                .into_iter()
+                .max_by(|a, b| {
+                    a.1.partial_cmp(&b.1)
+                        .unwrap_or(Ordering::Equal)
+                        .then_with(|| b.0.cmp(&a.0))
+                })
+                .expect("the reference member always votes");
+            ScoredTag {
+                tag: POSTag {
+                    word: scored_tag.tag.word,
+                    label,
+                },
+                score: if total > 0.0 { score / total } else { 0.0 },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Tagger with fixed labels and scores per word
+    struct FixedTagger(Vec<(&'static str, &'static str, f64)>);
+
+    impl Tagger for FixedTagger {
+        fn tag(&self, input: &[&str]) -> Vec<Vec<ScoredTag>> {
+            input
+                .iter()
+                .map(|_| {
+                    self.0
+                        .iter()
+                        .map(|&(word, label, score)| ScoredTag {
+                            tag: POSTag {
+                                word: word.to_owned(),
+                                label: label.to_owned(),
+                            },
+                            score,
+                        })
+                        .collect()
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn confident_majority_wins() {
+        let ensemble = EnsembleTagger::new()
+            .member(FixedTagger(vec![("lol", "NN", 0.6), ("ok", "JJ", 0.9)]), 1.0)
+            .member(FixedTagger(vec![("lol", "UH", 0.8), ("ok", "JJ", 0.9)]), 1.0)
+            .member(FixedTagger(vec![("lo", "UH", 0.4), ("l", "NN", 0.4), ("ok", "UH", 0.3)]), 1.0);
+        let output = ensemble.tag(&["lol ok"]);
+        let labels: Vec<&str> = output[0].iter().map(|t| t.tag.label.as_str()).collect();
+        assert_eq!(labels, vec!["UH", "JJ"]);
+        //the third member tokenized "lol" differently and only voted on "ok"
+        assert!((output[0][0].score - 0.4).abs() < 1e-9);
+        assert!((output[0][1].score - 0.6).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,178 @@
+//! # Exit codes and error reports
+//! Failures of the binary end with a distinct exit code, so that orchestration tools can
+//! tell a bad invocation from a missing file or a broken model:
+//!
+//! | Code | Failure |
+//! |------|---------|
+//! | 1 | other errors |
+//! | 2 | usage: bad arguments or configuration |
+//! | 3 | reading or writing files |
+//! | 4 | loading the model |
+//! | 5 | tagging |
+//!
+//! The error is printed to stderr as text, or with `--error-format json` as one JSON object
+//! with the `error` kind, the `code`, the `message` and its `causes`.
+//!
+//! The library marks its errors with the `UsageError`, `ModelLoadError` and `InferenceError`
+//! contexts, and IO errors are recognized anywhere in the chain of causes.
+
+use std::fmt;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use serde_json::json;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// # Exit code of a failed run
+pub enum ExitCode {
+    Failure = 1,
+    Usage = 2,
+    Io = 3,
+    ModelLoad = 4,
+    Inference = 5,
+}
+
+impl ExitCode {
+    /// Exit code of `error`, from the first context marking it or an IO error among its causes
+    pub fn of(error: &anyhow::Error) -> ExitCode {
+        if error.downcast_ref::<UsageError>().is_some() {
+            ExitCode::Usage
+        } else if error.downcast_ref::<ModelLoadError>().is_some() {
+            ExitCode::ModelLoad
+        } else if error.downcast_ref::<InferenceError>().is_some() {
+            ExitCode::Inference
+        } else if error.chain().any(|cause| cause.is::<io::Error>()) {
+            ExitCode::Io
+        } else {
+            ExitCode::Failure
+        }
+    }
+
+    /// Name of the failure in JSON reports
+    pub fn name(self) -> &'static str {
+        match self {
+            ExitCode::Failure => "failure",
+            ExitCode::Usage => "usage",
+            ExitCode::Io => "io",
+            ExitCode::ModelLoad => "model_load",
+            ExitCode::Inference => "inference",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Context of errors in the arguments or the configuration
+pub struct UsageError;
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Invalid arguments")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Context of errors loading a model
+pub struct ModelLoadError;
+
+impl fmt::Display for ModelLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Could not load the model")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Context of errors raised by the model during a prediction
+pub struct InferenceError;
+
+impl fmt::Display for InferenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Tagging failed")
+    }
+}
+
+/// Run the prediction `predict`, turning a panic of the model into an `InferenceError`.
+/// rust-bert and tch panic on errors during inference, such as running out of memory.
+pub fn catch_inference<T, F: FnOnce() -> T>(predict: F) -> anyhow::Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(predict)).map_err(|payload| {
+        let message = payload
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| payload.downcast_ref::<&str>().copied())
+            .unwrap_or("the model panicked");
+        anyhow!("{}", message).context(InferenceError)
+    })
+}
+
+/// Whether `err` is the model running out of memory, on the GPU or the CPU
+pub fn is_out_of_memory(err: &anyhow::Error) -> bool {
+    err.chain()
+        .any(|cause| cause.to_string().to_lowercase().contains("out of memory"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// # Format of the error printed on failure
+pub enum ErrorFormat {
+    Text,
+    Json,
+}
+
+impl Default for ErrorFormat {
+    fn default() -> ErrorFormat {
+        ErrorFormat::Text
+    }
+}
+
+impl FromStr for ErrorFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<ErrorFormat, Self::Err> {
+        match s {
+            "text" => Ok(ErrorFormat::Text),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(anyhow!("Unknown error format '{}', expected text or json", s)),
+        }
+    }
+}
+
+/// Print `error` to stderr in `format` and return its exit code
+pub fn report(error: &anyhow::Error, format: ErrorFormat) -> ExitCode {
+    let code = ExitCode::of(error);
+    match format {
+        ErrorFormat::Text => eprintln!("Error: {:#}", error),
+        ErrorFormat::Json => {
+            let causes: Vec<String> = error.chain().skip(1).map(|cause| cause.to_string()).collect();
+            let report = json!({
+                "error": code.name(),
+                "code": code as i32,
+                "message": error.to_string(),
+                "causes": causes,
+            });
+            eprintln!("{}", report);
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn classifies_errors() {
+        let io_error = io::Error::new(io::ErrorKind::NotFound, "missing");
+        let read: anyhow::Result<()> = Err(io_error).context("Could not read input.txt");
+        assert_eq!(ExitCode::of(&read.unwrap_err()), ExitCode::Io);
+
+        let load: anyhow::Result<()> = Err(anyhow!("missing vocab.txt")).context(ModelLoadError);
+        let load = load.context("Could not tag input.txt").unwrap_err();
+        assert_eq!(ExitCode::of(&load), ExitCode::ModelLoad);
+
+        let inference = catch_inference(|| panic!("out of memory")).unwrap_err();
+        assert_eq!(ExitCode::of(&inference), ExitCode::Inference);
+        assert!(is_out_of_memory(&inference));
+        assert_eq!(ExitCode::of(&anyhow!("other")), ExitCode::Failure);
+    }
+}
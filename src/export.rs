@@ -0,0 +1,57 @@
+//! # Model export
+//! Writes the configured model (pretrained or fine-tuned with `train`) to a self-contained
+//! directory for the `export` subcommand, so it can be deployed outside this crate.
+//!
+//! The LibTorch bindings used here can load but not trace TorchScript modules, and have no
+//! ONNX exporter, so the weights are written as a NumPy archive (`model.npz`, one array per
+//! parameter under its PyTorch name) next to the model configuration, the vocabulary and the
+//! tokenizer settings. The archive loads into the PyTorch model with `torch.from_numpy`, from
+//! where `torch.onnx.export` or `torch.jit.trace` produce ONNX or TorchScript.
+
+use std::fs::{self, File};
+use std::path::Path;
+
+use anyhow::Context;
+use rust_bert::pipelines::token_classification::TokenClassificationConfig;
+use serde_json::json;
+use tch::Tensor;
+
+use crate::config::Config;
+use crate::warnings::WarningSink;
+
+/// Export the configured model to `out_dir`
+///
+/// # Arguments
+///
+/// * `out_dir` - Directory created to hold `model.npz`, `rust_model.ot`, `config.json`,
+/// `vocab.txt` and `tokenizer_config.json`
+/// * `config` - Model settings
+/// * `warnings` - Receives the model files loaded unverified
+pub fn export(out_dir: &Path, config: &Config, warnings: &dyn WarningSink) -> anyhow::Result<()> {
+    let model_config: TokenClassificationConfig = config.pos_config(warnings)?.into();
+    let weights_path = model_config.model_resource.get_local_path()?;
+    let config_path = model_config.config_resource.get_local_path()?;
+    let vocab_path = model_config.vocab_resource.get_local_path()?;
+
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("Could not create {}", out_dir.display()))?;
+    let weights = Tensor::load_multi(&weights_path)
+        .with_context(|| format!("Could not load the weights {}", weights_path.display()))?;
+    Tensor::write_npz(&weights, out_dir.join("model.npz"))?;
+    fs::copy(&weights_path, out_dir.join("rust_model.ot"))?;
+    fs::copy(&config_path, out_dir.join("config.json"))?;
+    fs::copy(&vocab_path, out_dir.join("vocab.txt"))?;
+    if let Some(merges_resource) = &model_config.merges_resource {
+        fs::copy(merges_resource.get_local_path()?, out_dir.join("merges.txt"))?;
+    }
+
+    let tokenizer_config = json!({
+        "model_type": format!("{:?}", model_config.model_type),
+        "do_lower_case": model_config.lower_case,
+        "strip_accents": model_config.strip_accents,
+        "add_prefix_space": model_config.add_prefix_space,
+    });
+    let file = File::create(out_dir.join("tokenizer_config.json"))?;
+    serde_json::to_writer_pretty(file, &tokenizer_config)?;
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! # Following a growing file
+//! `--follow` tags a file like `tail -f` follows it: the current content is tagged, then the
+//! lines appended to the file are tagged as they arrive and their tags appended to the
+//! output, with the model loaded once. An incomplete last line waits for its newline. If the
+//! file shrinks (truncated or rotated in place), it is tagged again from the start.
+//!
+//! The run only stops when interrupted, so the output is written as it goes rather than
+//! replacing the output file at the end. The JSON format, a single array, and dependency
+//! parsing are not supported.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+
+use crate::config::Config;
+use crate::format::TagFormat;
+use crate::limits::{LimitReport, Limits};
+use crate::rusttagr;
+use crate::sentences;
+use crate::tagger::drop_scores;
+use crate::warnings::{Warning, WarningSink};
+
+/// Time between two checks for new lines
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Tag the file at `in_path` and the lines appended to it into `out_path`, until the
+/// process is interrupted, passing the warnings of the run to `warnings`
+pub fn follow_file(in_path: &Path, out_path: &Path, config: &Config, warnings: &dyn WarningSink) -> anyhow::Result<()> {
+    if config.format() == TagFormat::Json {
+        bail!("--follow appends to the output, use the debug, tsv or conllu format");
+    }
+    if config.parser_command.is_some() {
+        bail!("--follow cannot be combined with a dependency parser");
+    }
+    let tagger = config.tagger(warnings)?;
+    let config = &config.with_tuned_batch_size(tagger.as_ref(), warnings);
+    let mut file = File::open(in_path)
+        .with_context(|| format!("Could not read {}", in_path.display()))?;
+    let output = File::create(out_path)
+        .with_context(|| format!("Could not create {}", out_path.display()))?;
+    let mut tag_writer = config.tag_writer(BufWriter::new(output));
+    let mut adaptive_batch = config.adaptive_batch();
+    let limits = config.limits();
+    let mut report = LimitReport::default();
+
+    //bytes tagged, and read since the last complete line
+    let mut position = 0;
+    let mut pending = Vec::new();
+    let mut sentence_index = 0;
+    loop {
+        let len = fs::metadata(in_path)
+            .with_context(|| format!("Could not read {}", in_path.display()))?
+            .len();
+        if len < (position + pending.len()) as u64 {
+            warnings.warn(Warning::RestartedFile {
+                path: in_path.display().to_string(),
+            });
+            file.seek(SeekFrom::Start(0))?;
+            position = 0;
+            pending.clear();
+        }
+        if file.read_to_end(&mut pending)? == 0 {
+            thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+        let end = match pending.iter().rposition(|&byte| byte == b'\n') {
+            Some(end) => end + 1,
+            None => continue,
+        };
+        let lines: Vec<u8> = pending.drain(..end).collect();
+        let text = String::from_utf8_lossy(&lines);
+        let split = within_limits(&text, config, &limits, &mut sentence_index, &mut report, warnings)?;
+        let (scored, _) = adaptive_batch.tag(&*tagger, &split, warnings)?;
+        for (sentence, mut pos_tags) in split.iter().zip(drop_scores(scored)) {
+            if let Some(labels) = &config.filter_tags {
+                rusttagr::filter_labels(&mut pos_tags, labels);
+            }
+            let start = position + sentences::offset_in(&text, sentence);
+            tag_writer.write_located_sentence(sentence, start, &pos_tags, None)?;
+        }
+        tag_writer.flush()?;
+        position += end;
+    }
+}
+
+/// Sentences of `text` within the sentence limit, numbered from `sentence_index`
+fn within_limits<'a>(
+    text: &'a str,
+    config: &Config,
+    limits: &Limits,
+    sentence_index: &mut usize,
+    report: &mut LimitReport,
+    warnings: &dyn WarningSink,
+) -> anyhow::Result<Vec<&'a str>> {
+    let mut split = Vec::new();
+    for sentence in config.sentences(text) {
+        if let Some(sentence) = limits.check_sentence(sentence, *sentence_index, report, warnings)? {
+            split.push(sentence);
+        }
+        *sentence_index += 1;
+    }
+    Ok(split)
+}
@@ -0,0 +1,512 @@
+//! # Output formats
+//! Serializes tagged sentences for writing to the output file.
+//!
+//! The output is ordered by document, in the order of the inputs, then by sentence, in the
+//! order of the text, then by word, in the order of their offsets in the sentence, whatever
+//! the batch size, the sorting of the batches by length or the threads of `stream`.
+//! Tagging the same input with the same model and settings gives the same file, so that
+//! tagged corpora can be kept under version control and re-tagged with readable diffs; only
+//! the run metadata (`metadata`), which records the time of the run, differs.
+
+use std::fmt::Debug;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::depparse::Dependency;
+use crate::lemma::lemmatize;
+use crate::metadata::RunMetadata;
+use crate::morph::features;
+use crate::pos_tagging::POSTag;
+use crate::quantities::normalize;
+use crate::tagset::to_upos;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+/// # Output format of the tagged text
+pub enum TagFormat {
+    /// `Debug` representation of each sentence, one sentence per line
+    Debug,
+    /// One `word<TAB>label` pair per line (`word<TAB>label<TAB>lemma` with lemmas, then the
+    /// features and the normalized value when enabled), sentences separated by a blank line
+    Tsv,
+    /// JSON array of sentences, each a `{"text", "start", "end", "tokens"}` object with the
+    /// original sentence, its byte offsets in the document and an array of `{"word", "label"}`
+    /// objects. With documents, an array of `{"id", "sentences"}` objects.
+    Json,
+    /// CoNLL-U, with the lemma, universal tag and features filled in, the head and
+    /// relation when a dependency parser is configured, and the original sentence as
+    /// `# text` comment
+    Conllu,
+}
+
+impl Default for TagFormat {
+    fn default() -> TagFormat {
+        TagFormat::Debug
+    }
+}
+
+impl FromStr for TagFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<TagFormat, Self::Err> {
+        match s {
+            "debug" => Ok(TagFormat::Debug),
+            "tsv" => Ok(TagFormat::Tsv),
+            "json" => Ok(TagFormat::Json),
+            "conllu" => Ok(TagFormat::Conllu),
+            _ => Err(anyhow::anyhow!(
+                "Unknown format '{}', expected one of debug, tsv, json, conllu",
+                s
+            )),
+        }
+    }
+}
+
+/// # Incremental writer of tagged sentences
+/// Writes sentences one at a time so that output does not have to be held in memory.
+/// `finish` must be called to close the JSON array and flush the writer.
+///
+/// Sentences can be grouped into documents with `start_document`, written as a
+/// `# newdoc id = ...` comment line in the debug, TSV and CoNLL-U formats.
+///
+/// With `with_metadata`, the output starts with the description of the run, as comment
+/// lines or as the `metadata` field of a JSON object also holding the `sentences` (or
+/// `documents`) array.
+pub struct TagWriter<W: Write> {
+    writer: W,
+    format: TagFormat,
+    lemmas: bool,
+    features: bool,
+    values: bool,
+    sentences_written: usize,
+    documents_started: usize,
+    /// Sentences written since the start of the current document
+    document_sentences: usize,
+    metadata: Option<RunMetadata>,
+    header_written: bool,
+}
+
+impl<W: Write> TagWriter<W> {
+    pub fn new(writer: W, format: TagFormat) -> TagWriter<W> {
+        TagWriter {
+            writer,
+            format,
+            lemmas: false,
+            features: false,
+            values: false,
+            sentences_written: 0,
+            documents_started: 0,
+            document_sentences: 0,
+            metadata: None,
+            header_written: false,
+        }
+    }
+
+    /// Starts the output with the description of the run
+    pub fn with_metadata(mut self, metadata: Option<RunMetadata>) -> TagWriter<W> {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Write the metadata before the first document or sentence
+    fn write_header(&mut self) -> io::Result<()> {
+        if self.header_written {
+            return Ok(());
+        }
+        self.header_written = true;
+        let metadata = match &self.metadata {
+            Some(metadata) => metadata,
+            None => return Ok(()),
+        };
+        match self.format {
+            TagFormat::Json => {
+                self.writer.write_all(b"{\"metadata\":")?;
+                serde_json::to_writer(&mut self.writer, metadata)?;
+                self.writer.write_all(b",")?;
+            }
+            _ => {
+                for (key, value) in metadata.comments() {
+                    writeln!(self.writer, "# {} = {}", key, value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Open the JSON array of sentences or documents, named `key` in the metadata object
+    fn open_array(&mut self, key: &str) -> io::Result<()> {
+        if self.metadata.is_some() {
+            write!(self.writer, "\"{}\":", key)?;
+        }
+        self.writer.write_all(b"[")
+    }
+
+    /// Adds a lemma column (TSV) or field (JSON) to each tag; the debug format is unchanged
+    pub fn with_lemmas(mut self, lemmas: bool) -> TagWriter<W> {
+        self.lemmas = lemmas;
+        self
+    }
+
+    /// Adds a morphological features column (TSV) or `feats` field (JSON) to each tag;
+    /// the debug format is unchanged
+    pub fn with_features(mut self, features: bool) -> TagWriter<W> {
+        self.features = features;
+        self
+    }
+
+    /// Adds the normalized value of numbers, dates, times and quantities (see
+    /// `quantities::normalize`) as a column (TSV, `_` for other words), a `value` field
+    /// (JSON) or a `Value=` attribute of the MISC column (CoNLL-U)
+    pub fn with_values(mut self, values: bool) -> TagWriter<W> {
+        self.values = values;
+        self
+    }
+
+    /// Normalized value of `tag`, if values are written
+    fn value(&self, tag: &POSTag) -> Option<String> {
+        if self.values {
+            normalize(&tag.word, &tag.label)
+        } else {
+            None
+        }
+    }
+
+    /// Start a new document, identified by `id`, holding the sentences written next
+    pub fn start_document(&mut self, id: &str) -> io::Result<()> {
+        self.write_header()?;
+        match self.format {
+            TagFormat::Json => {
+                if self.documents_started == 0 {
+                    self.open_array("documents")?;
+                } else {
+                    self.writer.write_all(b"]},")?;
+                }
+                write!(self.writer, "{{\"id\":{},\"sentences\":[", json!(id))?;
+            }
+            _ => writeln!(self.writer, "# newdoc id = {}", id)?,
+        }
+        self.documents_started += 1;
+        self.document_sentences = 0;
+        Ok(())
+    }
+
+    /// Write the tags of one sentence
+    pub fn write_sentence(&mut self, sentence: &[POSTag]) -> io::Result<()> {
+        self.write_parsed_sentence(sentence, None)
+    }
+
+    /// Write the tags of one sentence with the dependency of each word, added as head and
+    /// relation columns (TSV, CoNLL-U) or `head` and `deprel` fields (JSON)
+    pub fn write_parsed_sentence(
+        &mut self,
+        sentence: &[POSTag],
+        dependencies: Option<&[Dependency]>,
+    ) -> io::Result<()> {
+        self.write_sentence_from(None, sentence, dependencies)
+    }
+
+    /// Write the tags of one sentence along with its original `text`, found at byte offset
+    /// `start` of the document (JSON and CoNLL-U)
+    pub fn write_located_sentence(
+        &mut self,
+        text: &str,
+        start: usize,
+        sentence: &[POSTag],
+        dependencies: Option<&[Dependency]>,
+    ) -> io::Result<()> {
+        self.write_sentence_from(Some((text, start)), sentence, dependencies)
+    }
+
+    fn write_sentence_from(
+        &mut self,
+        source: Option<(&str, usize)>,
+        sentence: &[POSTag],
+        dependencies: Option<&[Dependency]>,
+    ) -> io::Result<()> {
+        self.write_header()?;
+        let dependency = |index: usize| dependencies.and_then(|deps| deps.get(index));
+        match self.format {
+            TagFormat::Debug => writeln!(self.writer, "{:?}", sentence)?,
+            TagFormat::Tsv => {
+                for (index, tag) in sentence.iter().enumerate() {
+                    write!(self.writer, "{}\t{}", tag.word, tag.label)?;
+                    if self.lemmas {
+                        write!(self.writer, "\t{}", lemmatize(&tag.word, &tag.label))?;
+                    }
+                    if self.features {
+                        write!(self.writer, "\t{}", features(&tag.word, &tag.label))?;
+                    }
+                    if self.values {
+                        let value = self.value(tag);
+                        write!(self.writer, "\t{}", value.as_deref().unwrap_or("_"))?;
+                    }
+                    if let Some(dependency) = dependency(index) {
+                        write!(self.writer, "\t{}\t{}", dependency.head, dependency.deprel)?;
+                    }
+                    writeln!(self.writer)?;
+                }
+                writeln!(self.writer)?;
+            }
+            TagFormat::Json => {
+                match (self.documents_started, self.document_sentences) {
+                    (0, 0) => self.open_array("sentences")?,
+                    (_, 0) => {}
+                    _ => self.writer.write_all(b",")?,
+                }
+                let tokens: Vec<serde_json::Value> = sentence
+                    .iter()
+                    .enumerate()
+                    .map(|(index, tag)| {
+                        let mut value = json!({ "word": tag.word, "label": tag.label });
+                        if self.lemmas {
+                            value["lemma"] = lemmatize(&tag.word, &tag.label).into();
+                        }
+                        if self.features {
+                            value["feats"] = features(&tag.word, &tag.label).into();
+                        }
+                        if let Some(normalized) = self.value(tag) {
+                            value["value"] = normalized.into();
+                        }
+                        if let Some(dependency) = dependency(index) {
+                            value["head"] = dependency.head.into();
+                            value["deprel"] = dependency.deprel.as_str().into();
+                        }
+                        value
+                    })
+                    .collect();
+                let value = match source {
+                    Some((text, start)) => json!({
+                        "text": text,
+                        "start": start,
+                        "end": start + text.len(),
+                        "tokens": tokens,
+                    }),
+                    None => json!({ "tokens": tokens }),
+                };
+                serde_json::to_writer(&mut self.writer, &value)?;
+            }
+            TagFormat::Conllu => {
+                writeln!(self.writer, "# sent_id = {}", self.sentences_written + 1)?;
+                if let Some((text, _)) = source {
+                    //the comment is a single line
+                    let text: Vec<&str> = text.split_whitespace().collect();
+                    writeln!(self.writer, "# text = {}", text.join(" "))?;
+                }
+                for (index, tag) in sentence.iter().enumerate() {
+                    let (head, deprel) = match dependency(index) {
+                        Some(dependency) => (dependency.head.to_string(), dependency.deprel.as_str()),
+                        None => (String::from("_"), "_"),
+                    };
+                    let misc = match self.value(tag) {
+                        Some(value) => format!("Value={}", value.replace(' ', "_")),
+                        None => String::from("_"),
+                    };
+                    writeln!(
+                        self.writer,
+                        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t_\t{}",
+                        index + 1,
+                        tag.word,
+                        lemmatize(&tag.word, &tag.label),
+                        to_upos(&tag.label),
+                        tag.label,
+                        features(&tag.word, &tag.label),
+                        head,
+                        deprel,
+                        misc
+                    )?;
+                }
+                writeln!(self.writer)?;
+            }
+        }
+        self.sentences_written += 1;
+        self.document_sentences += 1;
+        Ok(())
+    }
+
+    /// Flush the sentences written so far to the underlying writer
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Close the output and return the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_header()?;
+        if self.format == TagFormat::Json {
+            if self.documents_started > 0 {
+                self.writer.write_all(b"]}")?;
+            } else if self.sentences_written == 0 {
+                self.open_array("sentences")?;
+            }
+            self.writer.write_all(b"]")?;
+            if self.metadata.is_some() {
+                self.writer.write_all(b"}")?;
+            }
+            self.writer.write_all(b"\n")?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// # Incremental writer of sentence classification results
+/// Writes one label and score per sentence: the `Debug` representation of the result,
+/// `text<TAB>label<TAB>score` lines, or a JSON array of `{"text", "label", "score"}`
+/// objects. CoNLL-U does not apply to sentence labels.
+pub struct LabelWriter<W: Write> {
+    writer: W,
+    format: TagFormat,
+    sentences_written: usize,
+}
+
+impl<W: Write> LabelWriter<W> {
+    pub fn new(writer: W, format: TagFormat) -> anyhow::Result<LabelWriter<W>> {
+        if format == TagFormat::Conllu {
+            anyhow::bail!("The conllu format does not apply to sentence labels, use debug, tsv or json");
+        }
+        Ok(LabelWriter {
+            writer,
+            format,
+            sentences_written: 0,
+        })
+    }
+
+    /// Write the result of one sentence
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - Sentence
+    /// * `label` - Predicted label
+    /// * `score` - Confidence of the label
+    /// * `result` - Result of the pipeline, written as is in the debug format
+    pub fn write_label<D: Debug>(
+        &mut self,
+        text: &str,
+        label: &str,
+        score: f64,
+        result: &D,
+    ) -> io::Result<()> {
+        match self.format {
+            TagFormat::Tsv => writeln!(
+                self.writer,
+                "{}\t{}\t{:.4}",
+                text.replace(|c: char| c == '\t' || c == '\n', " "),
+                label,
+                score
+            )?,
+            TagFormat::Json => {
+                let separator = if self.sentences_written == 0 { "[" } else { "," };
+                self.writer.write_all(separator.as_bytes())?;
+                let value = json!({ "text": text, "label": label, "score": score });
+                serde_json::to_writer(&mut self.writer, &value)?;
+            }
+            _ => writeln!(self.writer, "{:?}", result)?,
+        }
+        self.sentences_written += 1;
+        Ok(())
+    }
+
+    /// Close the output and return the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.format == TagFormat::Json {
+            if self.sentences_written == 0 {
+                self.writer.write_all(b"[")?;
+            }
+            self.writer.write_all(b"]\n")?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Format tagged sentences
+///
+/// # Arguments
+///
+/// * `sentences` - Part of Speech tags, one `Vec` per sentence
+/// * `format` - `TagFormat` to serialize to
+pub fn format_tags(sentences: &[Vec<POSTag>], format: TagFormat) -> String {
+    let mut tag_writer = TagWriter::new(Vec::new(), format);
+    for sentence in sentences {
+        tag_writer
+            .write_sentence(sentence)
+            .expect("writing to a Vec cannot fail");
+    }
+    let out = tag_writer.finish().expect("writing to a Vec cannot fail");
+    String::from_utf8(out).expect("formatted tags are valid UTF-8")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tag(word: &str, label: &str) -> POSTag {
+        POSTag {
+            word: word.to_owned(),
+            label: label.to_owned(),
+        }
+    }
+
+    #[test]
+    fn groups_json_sentences_into_documents() {
+        let mut tag_writer = TagWriter::new(Vec::new(), TagFormat::Json);
+        tag_writer.start_document("a.txt").unwrap();
+        tag_writer.write_located_sentence("Hi!", 0, &[tag("Hi", "UH")], None).unwrap();
+        tag_writer.write_sentence(&[tag("Bye", "UH")]).unwrap();
+        tag_writer.start_document("empty.txt").unwrap();
+        let out = String::from_utf8(tag_writer.finish().unwrap()).unwrap();
+        assert_eq!(
+            out,
+            concat!(
+                r#"[{"id":"a.txt","sentences":["#,
+                r#"{"end":3,"start":0,"text":"Hi!","tokens":[{"label":"UH","word":"Hi"}]},"#,
+                r#"{"tokens":[{"label":"UH","word":"Bye"}]}]},"#,
+                r#"{"id":"empty.txt","sentences":[]}]"#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn starts_outputs_with_the_metadata() {
+        let metadata = RunMetadata {
+            generator: String::from("berttagr 0.1.0"),
+            model: String::from("en-pos-mobilebert"),
+            options: vec![String::from("in.txt"), String::from("out.json")],
+            created: String::from("2021-06-01T12:00:00Z"),
+            inputs: Vec::new(),
+        };
+        let write = |format| {
+            let mut tag_writer = TagWriter::new(Vec::new(), format).with_metadata(Some(metadata.clone()));
+            tag_writer.write_sentence(&[tag("Hi", "UH")]).unwrap();
+            String::from_utf8(tag_writer.finish().unwrap()).unwrap()
+        };
+        assert_eq!(
+            write(TagFormat::Tsv),
+            concat!(
+                "# generator = berttagr 0.1.0\n# model = en-pos-mobilebert\n",
+                "# options = in.txt out.json\n# created = 2021-06-01T12:00:00Z\n",
+                "Hi\tUH\n\n"
+            )
+        );
+        let json: serde_json::Value = serde_json::from_str(&write(TagFormat::Json)).unwrap();
+        assert_eq!(json["metadata"]["model"], "en-pos-mobilebert");
+        assert_eq!(json["sentences"][0]["tokens"][0]["label"], "UH");
+    }
+
+    #[test]
+    fn adds_normalized_values() {
+        let sentence = [tag("paid", "VBD"), tag("$1,500", "QUANTITY")];
+        let mut tag_writer = TagWriter::new(Vec::new(), TagFormat::Tsv).with_values(true);
+        tag_writer.write_sentence(&sentence).unwrap();
+        let out = String::from_utf8(tag_writer.finish().unwrap()).unwrap();
+        assert_eq!(out, "paid\tVBD\t_\n$1,500\tQUANTITY\t1500 USD\n\n");
+        let mut tag_writer = TagWriter::new(Vec::new(), TagFormat::Conllu).with_values(true);
+        tag_writer.write_sentence(&sentence).unwrap();
+        let out = String::from_utf8(tag_writer.finish().unwrap()).unwrap();
+        assert!(out.contains("\t_\tValue=1500_USD\n"));
+    }
+}
@@ -0,0 +1,108 @@
+//! # Pre and post-processing hooks
+//! Extension points run by `POSModel` around its predictions, so that library users can
+//! normalize the input (e.g. de-hyphenation) or rewrite the output without changing the
+//! model code. Closures of the right signature implement the traits.
+//!
+//! A `Replacer` swaps words for placeholders before tagging and gets them back with the tags
+//! of the same text, for words the tokenizer would shred (see `protect` and `quantities`).
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rustlib::pos_tagging::{POSModel, ScoredTag};
+//!
+//! let mut pos_model = POSModel::new(Default::default())?;
+//! pos_model.add_pre_processor(|text: &str| text.replace("-\n", ""));
+//! pos_model.add_post_processor(|sentence: &mut Vec<ScoredTag>| {
+//!     sentence.retain(|scored_tag| scored_tag.score > 0.5)
+//! });
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::Arc;
+
+use crate::pos_tagging::{POSTag, ScoredTag};
+
+/// # Rewrites each input text before it is tagged
+pub trait PreProcessor: Send {
+    fn process(&self, text: &str) -> String;
+}
+
+impl<F> PreProcessor for F
+where
+    F: Fn(&str) -> String + Send,
+{
+    fn process(&self, text: &str) -> String {
+        self(text)
+    }
+}
+
+/// A processor shared by both hooks, keeping state from one to the other
+impl<P: PreProcessor + Sync + ?Sized> PreProcessor for Arc<P> {
+    fn process(&self, text: &str) -> String {
+        (**self).process(text)
+    }
+}
+
+/// # Rewrites the tags of each sentence after tagging
+pub trait PostProcessor: Send {
+    fn process(&self, sentence: &mut Vec<ScoredTag>);
+}
+
+impl<F> PostProcessor for F
+where
+    F: Fn(&mut Vec<ScoredTag>) + Send,
+{
+    fn process(&self, sentence: &mut Vec<ScoredTag>) {
+        self(sentence)
+    }
+}
+
+impl<P: PostProcessor + Sync + ?Sized> PostProcessor for Arc<P> {
+    fn process(&self, sentence: &mut Vec<ScoredTag>) {
+        (**self).process(sentence)
+    }
+}
+
+/// # Replaces words of each input text with placeholders restored after tagging
+/// The replaced words are returned with the text and handed back with the tags of that same
+/// text, so that nothing is kept from one call to the next.
+pub trait Replacer: Send {
+    /// `text` with placeholder words in place of the replaced ones, listed in order
+    fn replace(&self, text: &str) -> (String, Vec<Replacement>);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// # Word replaced with a placeholder
+pub struct Replacement {
+    /// Word standing for it in the model input
+    pub placeholder: &'static str,
+    /// Original word
+    pub word: String,
+    /// Label it gets once restored
+    pub label: &'static str,
+}
+
+/// Put the words of `replacements` back in place of their placeholders in `sentence`, with
+/// their label and a score of 1
+pub fn restore(sentence: &mut [ScoredTag], mut replacements: Vec<Replacement>) {
+    for scored_tag in sentence.iter_mut() {
+        //placeholders beyond the model's maximum length were truncated, so the words are
+        //matched by placeholder and order
+        let index = match replacements
+            .iter()
+            .position(|replacement| replacement.placeholder == scored_tag.tag.word)
+        {
+            Some(index) => index,
+            None => continue,
+        };
+        let replacement = replacements.remove(index);
+        *scored_tag = ScoredTag {
+            tag: POSTag {
+                word: replacement.word,
+                label: replacement.label.to_owned(),
+            },
+            score: 1.0,
+        };
+    }
+}
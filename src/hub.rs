@@ -0,0 +1,139 @@
+//! # Hugging Face Hub models
+//! Loads token classification checkpoints from the Hub by repository id, with
+//! `--hub-model vblagoje/bert-english-uncased-finetuned-pos`. The files are downloaded to the
+//! rust-bert cache from the `main` branch, or from the revision given after `@` (a branch,
+//! tag or commit), which pins the model: `--hub-model
+//! vblagoje/bert-english-uncased-finetuned-pos@<commit>`.
+//!
+//! The architecture is read from `config.json`, and the lower-casing from
+//! `tokenizer_config.json` when the repository has one. The repository must hold the weights
+//! converted to `rust_model.ot`, see the rust-bert `convert_model.py` utility.
+
+use std::fs;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context};
+use rust_bert::resources::{RemoteResource, Resource};
+
+use crate::download::DownloadPolicy;
+use crate::pos_tagging::{parse_model_type, vocabulary_files, POSConfig};
+
+/// Revision downloaded when none is given
+pub const DEFAULT_REVISION: &str = "main";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// # Model repository on the Hub, at a revision
+pub struct HubModel {
+    pub repo_id: String,
+    pub revision: String,
+}
+
+impl FromStr for HubModel {
+    type Err = anyhow::Error;
+
+    /// Parse `owner/name` or `owner/name@revision`
+    fn from_str(s: &str) -> Result<HubModel, Self::Err> {
+        let (repo_id, revision) = match s.find('@') {
+            Some(index) => (&s[..index], &s[index + 1..]),
+            None => (s, DEFAULT_REVISION),
+        };
+        let valid_part = |part: &str| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        };
+        let parts: Vec<&str> = repo_id.split('/').collect();
+        if parts.len() != 2 || !parts.iter().all(|part| valid_part(part)) {
+            bail!("Invalid Hub repository '{}', expected owner/name", repo_id);
+        }
+        if revision.is_empty() || revision.contains(char::is_whitespace) {
+            bail!(
+                "Invalid revision '{}' of Hub repository {}",
+                revision,
+                repo_id
+            );
+        }
+        Ok(HubModel {
+            repo_id: repo_id.to_owned(),
+            revision: revision.to_owned(),
+        })
+    }
+}
+
+impl HubModel {
+    /// Download URL of `file`
+    pub fn url(&self, file: &str) -> String {
+        format!(
+            "https://huggingface.co/{}/resolve/{}/{}",
+            self.repo_id, self.revision, file
+        )
+    }
+
+    /// Resource of `file`, cached per repository and revision
+    pub fn resource(&self, file: &str) -> Resource {
+        let cache_subdir = format!("hub/{}/{}", self.repo_id, self.revision);
+        Resource::Remote(RemoteResource::from_pretrained((
+            cache_subdir.as_str(),
+            self.url(file).as_str(),
+        )))
+    }
+
+    /// The `POSConfig` loading this model. Downloads `config.json` to find the architecture
+    /// and the vocabulary files.
+    pub fn pos_config(&self, policy: &DownloadPolicy) -> anyhow::Result<POSConfig> {
+        let config_resource = self.resource("config.json");
+        let config_path = policy.local_path(&config_resource)?;
+        let model_config: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&config_path)?)
+                .with_context(|| format!("Invalid config.json in {}", self.repo_id))?;
+        let model_type = model_config
+            .get("model_type")
+            .and_then(serde_json::Value::as_str)
+            .ok_or_else(|| anyhow!("No model_type in the config.json of {}", self.repo_id))?;
+        let model_type = parse_model_type(model_type)?;
+
+        let mut pos_config = POSConfig::default().model_type(model_type);
+        //optional, the tokenizer defaults apply without it
+        if let Ok(path) = policy.local_path(&self.resource("tokenizer_config.json")) {
+            let tokenizer_config: serde_json::Value =
+                serde_json::from_str(&fs::read_to_string(&path)?).unwrap_or_default();
+            if let Some(lower_case) = tokenizer_config
+                .get("do_lower_case")
+                .and_then(serde_json::Value::as_bool)
+            {
+                pos_config = pos_config.lower_case(lower_case);
+            }
+        }
+        let (vocab, merges) = vocabulary_files(model_type);
+        let config = pos_config.token_classification_config_mut();
+        config.model_resource = self.resource("rust_model.ot");
+        config.config_resource = config_resource;
+        config.vocab_resource = self.resource(vocab);
+        config.merges_resource = merges.map(|merges| self.resource(merges));
+        Ok(pos_config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_repositories_and_revisions() {
+        let model: HubModel = "vblagoje/bert-english-uncased-finetuned-pos"
+            .parse()
+            .unwrap();
+        assert_eq!(model.revision, "main");
+        assert_eq!(
+            model.url("config.json"),
+            "https://huggingface.co/vblagoje/bert-english-uncased-finetuned-pos/resolve/main/config.json"
+        );
+        let pinned: HubModel = "owner/name@0123abc".parse().unwrap();
+        assert_eq!(pinned.repo_id, "owner/name");
+        assert_eq!(pinned.revision, "0123abc");
+        assert!("name".parse::<HubModel>().is_err());
+        assert!("owner/name/extra".parse::<HubModel>().is_err());
+        assert!("owner/name@".parse::<HubModel>().is_err());
+    }
+}
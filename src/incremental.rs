@@ -0,0 +1,182 @@
+//! # Incremental tagging
+//! Editors re-tag a document on every keystroke, and tagging the whole document each time
+//! is too slow for long ones. `TaggedText` keeps the tags of each sentence with its byte
+//! range in the text; `TaggedText::edit` applies an edit, splits the new text into
+//! sentences again, which is cheap, and only sends the model the sentences that changed.
+//! The other ones keep their tags, moved by the length difference of the edit when after
+//! it.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rustlib::incremental::TaggedText;
+//! use rustlib::pos_tagging::POSModel;
+//!
+//! let pos_model = POSModel::new(Default::default())?;
+//! let tagged = TaggedText::new(&pos_model, "My name is Amy. I live in Paris.".to_owned());
+//! //"Paris" replaced with "London"
+//! let tagged = tagged.edit(&pos_model, 26..31, "London")?;
+//! assert_eq!(tagged.sentences[1].tags[3].tag.word, "London");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use anyhow::bail;
+
+use crate::pos_tagging::ScoredTag;
+use crate::sentences;
+use crate::tagger::Tagger;
+
+#[derive(Debug, Clone, PartialEq)]
+/// # Tags of a sentence of a text
+pub struct TaggedSentence {
+    /// Byte range of the sentence in the text
+    pub range: Range<usize>,
+    pub tags: Vec<ScoredTag>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// # Text with the tags of its sentences
+pub struct TaggedText {
+    pub text: String,
+    /// Sentences of `text`, in order
+    pub sentences: Vec<TaggedSentence>,
+}
+
+impl TaggedText {
+    /// Split `text` into sentences and tag all of them with `tagger`
+    pub fn new(tagger: &dyn Tagger, text: String) -> TaggedText {
+        let ranges = sentence_ranges(&text);
+        let input: Vec<&str> = ranges.iter().map(|range| &text[range.clone()]).collect();
+        let sentences = ranges
+            .iter()
+            .cloned()
+            .zip(tag_all(tagger, &input))
+            .map(|(range, tags)| TaggedSentence { range, tags })
+            .collect();
+        TaggedText { text, sentences }
+    }
+
+    /// Tags of the text with the bytes at `range` replaced with `replacement`. Only the
+    /// sentences that are new or whose text changed are tagged with `tagger`, the tags of
+    /// the others are taken from `self`.
+    pub fn edit(&self, tagger: &dyn Tagger, range: Range<usize>, replacement: &str) -> anyhow::Result<TaggedText> {
+        if range.start > range.end || self.text.get(range.clone()).is_none() {
+            bail!("Invalid edit range {:?} of a text of {} bytes", range, self.text.len());
+        }
+        let mut text = String::with_capacity(self.text.len() - range.len() + replacement.len());
+        text.push_str(&self.text[..range.start]);
+        text.push_str(replacement);
+        text.push_str(&self.text[range.end..]);
+        let inserted_end = range.start + replacement.len();
+
+        let previous: HashMap<Range<usize>, &TaggedSentence> = self
+            .sentences
+            .iter()
+            .map(|sentence| (sentence.range.clone(), sentence))
+            .collect();
+        let ranges = sentence_ranges(&text);
+        //tags of the unchanged sentences, and the indices of those to tag
+        let mut tags = Vec::with_capacity(ranges.len());
+        let mut changed = Vec::new();
+        for (index, new_range) in ranges.iter().enumerate() {
+            let old_range = if new_range.end <= range.start {
+                Some(new_range.clone())
+            } else if new_range.start >= inserted_end {
+                Some(new_range.start - inserted_end + range.end..new_range.end - inserted_end + range.end)
+            } else {
+                None
+            };
+            let kept = old_range
+                .and_then(|old_range| previous.get(&old_range))
+                .filter(|sentence| self.text[sentence.range.clone()] == text[new_range.clone()]);
+            match kept {
+                Some(sentence) => tags.push(Some(sentence.tags.clone())),
+                None => {
+                    tags.push(None);
+                    changed.push(index);
+                }
+            }
+        }
+
+        let input: Vec<&str> = changed.iter().map(|&index| &text[ranges[index].clone()]).collect();
+        for (index, sentence_tags) in changed.into_iter().zip(tag_all(tagger, &input)) {
+            tags[index] = Some(sentence_tags);
+        }
+        let sentences = ranges
+            .into_iter()
+            .zip(tags)
+            .map(|(range, tags)| TaggedSentence {
+                range,
+                tags: tags.unwrap_or_default(),
+            })
+            .collect();
+        Ok(TaggedText { text, sentences })
+    }
+}
+
+/// Byte ranges of the sentences of `text`
+fn sentence_ranges(text: &str) -> Vec<Range<usize>> {
+    sentences::iter(text)
+        .map(|sentence| {
+            let start = sentences::offset_in(text, sentence);
+            start..start + sentence.len()
+        })
+        .collect()
+}
+
+/// Tags of `input`, without calling `tagger` if there is nothing to tag
+fn tag_all(tagger: &dyn Tagger, input: &[&str]) -> Vec<Vec<ScoredTag>> {
+    if input.is_empty() {
+        Vec::new()
+    } else {
+        tagger.tag(input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pos_tagging::POSTag;
+    use std::cell::RefCell;
+
+    /// Tags every word `W` and records the sentences it is given
+    struct RecordingTagger(RefCell<Vec<String>>);
+
+    impl Tagger for RecordingTagger {
+        fn tag(&self, input: &[&str]) -> Vec<Vec<ScoredTag>> {
+            self.0.borrow_mut().extend(input.iter().map(|sentence| sentence.to_string()));
+            input
+                .iter()
+                .map(|sentence| {
+                    sentence
+                        .split_whitespace()
+                        .map(|word| ScoredTag {
+                            tag: POSTag {
+                                word: word.to_owned(),
+                                label: "W".to_owned(),
+                            },
+                            score: 1.0,
+                        })
+                        .collect()
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn retags_only_the_edited_sentences() {
+        let tagger = RecordingTagger(RefCell::new(Vec::new()));
+        let tagged = TaggedText::new(&tagger, "One two. Three four. Five six.".to_owned());
+        tagger.0.borrow_mut().clear();
+
+        let edited = tagged.edit(&tagger, 9..14, "Seven eight").unwrap();
+        assert_eq!(edited.text, "One two. Seven eight four. Five six.");
+        assert_eq!(*tagger.0.borrow(), ["Seven eight four."]);
+        assert_eq!(edited, TaggedText::new(&tagger, edited.text.clone()));
+        assert_eq!(edited.sentences[2].range, 27..36);
+        assert!(tagged.edit(&tagger, 20..40, "").is_err());
+    }
+}
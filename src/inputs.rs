@@ -0,0 +1,80 @@
+//! # Input file lists
+//! Reads the list of input files given with `--file-list` or `--files-from`, from a file or
+//! from stdin with `-`. Paths are one per line, or separated by NUL bytes with `-0` so that
+//! any file name can be passed, as in `find corpus -name '*.txt' -print0 | berttagr_file tag
+//! --files-from - -0 output.txt`. Empty entries are ignored.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+/// Path standing for stdin
+pub const STDIN: &str = "-";
+
+/// Paths listed in the file at `path`, or on stdin if it is `-`, separated by NUL bytes if
+/// `null_separated` is set and by newlines otherwise
+pub fn read_file_list(path: &Path, null_separated: bool) -> anyhow::Result<Vec<PathBuf>> {
+    let contents = if path == Path::new(STDIN) {
+        let mut contents = Vec::new();
+        io::stdin()
+            .lock()
+            .read_to_end(&mut contents)
+            .context("Could not read the file list from stdin")?;
+        contents
+    } else {
+        fs::read(path).with_context(|| format!("Could not read file list {}", path.display()))?
+    };
+    Ok(parse_file_list(&contents, null_separated))
+}
+
+/// Paths listed in `contents`, separated by NUL bytes if `null_separated` is set and one per
+/// line otherwise
+pub fn parse_file_list(contents: &[u8], null_separated: bool) -> Vec<PathBuf> {
+    let separator = if null_separated { b'\0' } else { b'\n' };
+    contents
+        .split(|&byte| byte == separator)
+        .map(|entry| match entry {
+            [rest @ .., b'\r'] if !null_separated => rest,
+            _ => entry,
+        })
+        .filter(|entry| {
+            if null_separated {
+                !entry.is_empty()
+            } else {
+                !entry.iter().all(u8::is_ascii_whitespace)
+            }
+        })
+        .map(path_from_bytes)
+        .collect()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splits_lines_and_nul_separated_lists() {
+        assert_eq!(
+            parse_file_list(b"a.txt\r\n\nb c.txt\n", false),
+            vec![PathBuf::from("a.txt"), PathBuf::from("b c.txt")]
+        );
+        assert_eq!(
+            parse_file_list(b"a\nb.txt\0 \0", true),
+            vec![PathBuf::from("a\nb.txt"), PathBuf::from(" ")]
+        );
+    }
+}
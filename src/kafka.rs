@@ -0,0 +1,163 @@
+//! # Kafka consumer and producer
+//! With the `kafka` feature, `--kafka-in topic --kafka-out topic` tags the messages of a
+//! topic and publishes their tags as JSON (the format of `--format json`) to another topic,
+//! under the key of the input message.
+//!
+//! Messages are consumed in batches of up to `batch_size`, waiting at most
+//! `BATCH_TIMEOUT` for a batch to fill. Delivery is at least once: the offsets of a batch
+//! are only committed once all of its results are acknowledged by the brokers, so messages
+//! tagged when the run stops or a delivery fails are tagged again on the next run. Messages
+//! whose payload is not UTF-8 text are skipped with a warning.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context};
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, CommitMode, Consumer};
+use rdkafka::message::Message;
+use rdkafka::producer::{BaseProducer, BaseRecord, DeliveryResult, Producer, ProducerContext};
+use rdkafka::ClientContext;
+
+use crate::config::Config;
+use crate::format::TagFormat;
+use crate::rusttagr;
+use crate::tagger::Tagger;
+use crate::warnings::{SharedSink, Warning, WarningSink};
+
+/// Longest wait for a batch of messages to fill
+pub const BATCH_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Consumer group of the tagger when none is given
+pub const DEFAULT_GROUP: &str = "berttagr";
+
+/// Brokers connected to when none are given
+pub const DEFAULT_BROKERS: &str = "localhost:9092";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// # Topics and brokers of the Kafka mode
+pub struct KafkaOptions {
+    /// Comma separated `host:port` of the bootstrap brokers
+    pub brokers: String,
+    /// Topic of the texts to tag
+    pub input_topic: String,
+    /// Topic the tags are published to
+    pub output_topic: String,
+    /// Consumer group, sharing the partitions of the input topic between taggers
+    pub group_id: String,
+}
+
+/// Producer context counting the messages the brokers did not acknowledge
+struct DeliveryContext {
+    failed: AtomicUsize,
+    warnings: SharedSink,
+}
+
+impl ClientContext for DeliveryContext {}
+
+impl ProducerContext for DeliveryContext {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, delivery_result: &DeliveryResult<'_>, _: Self::DeliveryOpaque) {
+        if let Err((err, _)) = delivery_result {
+            self.warnings.warn(Warning::PublishFailed { error: err.to_string() });
+            self.failed.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Tag the messages of the input topic and publish the results until the process is
+/// interrupted or a delivery fails, passing the warnings of the run to `warnings`
+pub fn run(options: &KafkaOptions, config: &Config, warnings: &SharedSink) -> anyhow::Result<()> {
+    if config.parser_command.is_some() {
+        bail!("The Kafka mode cannot be combined with a dependency parser");
+    }
+    let tagger = config.tagger(warnings.as_ref())?;
+    let config = Config {
+        format: Some(TagFormat::Json),
+        ..config.with_tuned_batch_size(tagger.as_ref(), warnings.as_ref())
+    };
+    let consumer: BaseConsumer = ClientConfig::new()
+        .set("bootstrap.servers", &options.brokers)
+        .set("group.id", &options.group_id)
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest")
+        .create()
+        .context("Could not create the Kafka consumer")?;
+    consumer
+        .subscribe(&[options.input_topic.as_str()])
+        .with_context(|| format!("Could not subscribe to {}", options.input_topic))?;
+    let producer: BaseProducer<DeliveryContext> = ClientConfig::new()
+        .set("bootstrap.servers", &options.brokers)
+        .set("acks", "all")
+        .create_with_context(DeliveryContext {
+            failed: AtomicUsize::new(0),
+            warnings: Arc::clone(warnings),
+        })
+        .context("Could not create the Kafka producer")?;
+
+    loop {
+        let batch = poll_batch(&consumer, config.batch_size(), warnings.as_ref())?;
+        if batch.is_empty() {
+            continue;
+        }
+        for (key, text) in &batch {
+            let payload = tag_message(&*tagger, text, &config, warnings.as_ref())?;
+            let mut record = BaseRecord::to(&options.output_topic).payload(&payload);
+            if let Some(key) = key {
+                record = record.key(key);
+            }
+            if let Err((err, _)) = producer.send(record) {
+                bail!("Could not publish to {}: {}", options.output_topic, err);
+            }
+        }
+        //acknowledged before the input offsets are committed
+        while producer.in_flight_count() > 0 {
+            producer.poll(Duration::from_millis(100));
+        }
+        let failed = producer.context().failed.load(Ordering::SeqCst);
+        if failed > 0 {
+            bail!("{} results could not be published, the batch will be tagged again", failed);
+        }
+        consumer
+            .commit_consumer_state(CommitMode::Sync)
+            .context("Could not commit the consumed offsets")?;
+    }
+}
+
+/// Key and text of up to `batch_size` messages, polled for at most `BATCH_TIMEOUT`
+fn poll_batch(
+    consumer: &BaseConsumer,
+    batch_size: usize,
+    warnings: &dyn WarningSink,
+) -> anyhow::Result<Vec<(Option<Vec<u8>>, String)>> {
+    let start = Instant::now();
+    let mut batch = Vec::with_capacity(batch_size);
+    while batch.len() < batch_size {
+        let remaining = match BATCH_TIMEOUT.checked_sub(start.elapsed()) {
+            Some(remaining) => remaining,
+            None => break,
+        };
+        let message = match consumer.poll(remaining) {
+            Some(message) => message.context("Could not consume a message")?,
+            None => break,
+        };
+        match message.payload_view::<str>() {
+            Some(Ok(text)) => batch.push((message.key().map(<[u8]>::to_vec), text.to_owned())),
+            Some(Err(_)) => warnings.warn(Warning::SkippedMessage {
+                topic: message.topic().to_owned(),
+                offset: message.offset(),
+            }),
+            None => {}
+        }
+    }
+    Ok(batch)
+}
+
+/// Tags of the sentences of `text` as JSON
+fn tag_message(tagger: &dyn Tagger, text: &str, config: &Config, warnings: &dyn WarningSink) -> anyhow::Result<Vec<u8>> {
+    let mut tag_writer = config.tag_writer(Vec::new());
+    rusttagr::tag_text_with(tagger, text, &mut tag_writer, config, warnings)?;
+    Ok(tag_writer.finish()?)
+}
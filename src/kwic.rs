@@ -0,0 +1,186 @@
+//! # Concordance
+//! Keyword-in-context lines of the `kwic` subcommand: every occurrence of a word (ignoring
+//! case), a label, or a word with a label, shown with the tagged words around it within its
+//! sentence, e.g. for `--word run --tag VB --context 2`:
+//!
+//! ```text
+//! notes.txt:3    to/TO go/VB  [run/VB]  every/DT morning/NN
+//! ```
+//!
+//! with the file and the number of the sentence in it.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+
+use crate::config::Config;
+use crate::pos_tagging::POSTag;
+use crate::tagger::{for_each_sentence, Tagger};
+use crate::warnings::WarningSink;
+
+/// Number of words shown on each side of a match when none is given
+pub const DEFAULT_CONTEXT: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// # Words looked up
+pub struct KwicQuery {
+    /// Word, matched ignoring case
+    pub word: Option<String>,
+    /// Exact label
+    pub tag: Option<String>,
+    /// Number of words shown on each side
+    pub context: usize,
+}
+
+impl KwicQuery {
+    /// Query matching `word` and/or `tag`, at least one of which must be given
+    pub fn new(word: Option<String>, tag: Option<String>, context: usize) -> anyhow::Result<KwicQuery> {
+        if word.is_none() && tag.is_none() {
+            bail!("kwic requires --word or --tag");
+        }
+        Ok(KwicQuery {
+            word: word.map(|word| word.to_lowercase()),
+            tag,
+            context,
+        })
+    }
+
+    pub fn matches(&self, tag: &POSTag) -> bool {
+        self.word.as_ref().map_or(true, |word| tag.word.to_lowercase() == *word)
+            && self.tag.as_ref().map_or(true, |label| tag.label == *label)
+    }
+
+    /// Concordance lines of the matches in `sentence`, the `number`-th sentence of `source`
+    pub fn find(&self, source: &str, number: usize, sentence: &[POSTag]) -> Vec<KwicLine> {
+        sentence
+            .iter()
+            .enumerate()
+            .filter(|(_, tag)| self.matches(tag))
+            .map(|(index, tag)| KwicLine {
+                source: source.to_owned(),
+                sentence: number,
+                left: sentence[index.saturating_sub(self.context)..index].to_vec(),
+                keyword: tag.clone(),
+                right: sentence[index + 1..(index + 1 + self.context).min(sentence.len())].to_vec(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// # Occurrence of a keyword with its context
+pub struct KwicLine {
+    /// File of the occurrence
+    pub source: String,
+    /// Number of the sentence in the file, from 1
+    pub sentence: usize,
+    pub left: Vec<POSTag>,
+    pub keyword: POSTag,
+    pub right: Vec<POSTag>,
+}
+
+impl KwicLine {
+    /// Left context, as `word/label` pairs separated by spaces
+    pub fn left_text(&self) -> String {
+        join(&self.left)
+    }
+
+    pub fn right_text(&self) -> String {
+        join(&self.right)
+    }
+}
+
+fn join(tags: &[POSTag]) -> String {
+    tags.iter().map(POSTag::to_string).collect::<Vec<String>>().join(" ")
+}
+
+impl fmt::Display for KwicLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}\t{}  [{}]  {}",
+            self.source,
+            self.sentence,
+            self.left_text(),
+            self.keyword,
+            self.right_text()
+        )
+    }
+}
+
+/// Print the concordance lines of `paths`, tagged with the configured tagger, with the left
+/// contexts aligned. Returns the number of matches.
+pub fn print_kwic(
+    paths: &[PathBuf],
+    query: &KwicQuery,
+    config: &Config,
+    warnings: &dyn WarningSink,
+) -> anyhow::Result<usize> {
+    let tagger = config.tagger(warnings)?;
+    let mut lines = Vec::new();
+    for path in paths {
+        lines.extend(kwic_file(&*tagger, path, query, config.batch_size())?);
+    }
+    let width = lines
+        .iter()
+        .map(|line| line.left_text().chars().count())
+        .max()
+        .unwrap_or(0);
+    for line in &lines {
+        println!(
+            "{}:{}\t{:>width$}  [{}]  {}",
+            line.source,
+            line.sentence,
+            line.left_text(),
+            line.keyword,
+            line.right_text(),
+            width = width
+        );
+    }
+    Ok(lines.len())
+}
+
+/// Concordance lines of the file at `path`, tagged by `tagger` in batches of `batch_size`
+/// sentences
+pub fn kwic_file(
+    tagger: &dyn Tagger,
+    path: &Path,
+    query: &KwicQuery,
+    batch_size: usize,
+) -> anyhow::Result<Vec<KwicLine>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Could not read {}", path.display()))?;
+    let source = path.to_string_lossy();
+    let mut lines = Vec::new();
+    let mut number = 0;
+    for_each_sentence(tagger, &contents, batch_size, |sentence| {
+        number += 1;
+        lines.extend(query.find(&source, number, &sentence));
+    });
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finds_keywords_in_context() {
+        let sentence: Vec<POSTag> = [("I", "PRP"), ("run", "VBP"), ("to", "TO"), ("Run", "VB"), ("home", "NN")]
+            .iter()
+            .map(|(word, label)| POSTag {
+                word: word.to_string(),
+                label: label.to_string(),
+            })
+            .collect();
+        let query = KwicQuery::new(Some(String::from("RUN")), Some(String::from("VB")), 2).unwrap();
+        let lines = query.find("a.txt", 4, &sentence);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].to_string(), "a.txt:4\trun/VBP to/TO  [Run/VB]  home/NN");
+        let by_word = KwicQuery::new(Some(String::from("run")), None, 1).unwrap();
+        assert_eq!(by_word.find("a.txt", 1, &sentence).len(), 2);
+        assert!(KwicQuery::new(None, None, 1).is_err());
+    }
+}
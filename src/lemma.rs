@@ -0,0 +1,228 @@
+//! # Lemmatization
+//! Rule-based English lemmatizer keyed by the Penn Treebank tag predicted for a word:
+//! irregular forms are looked up in tables, regular inflections are stripped by suffix
+//! rules for the tag. Lemmas are lower-cased, except for proper nouns.
+
+/// Irregular verb forms (past, participle, third person, progressive) and their lemma
+const IRREGULAR_VERBS: &[(&str, &str)] = &[
+    ("am", "be"), ("is", "be"), ("are", "be"), ("was", "be"), ("were", "be"), ("been", "be"),
+    ("being", "be"), ("'m", "be"), ("'re", "be"), ("has", "have"), ("had", "have"),
+    ("'ve", "have"), ("does", "do"), ("did", "do"), ("done", "do"), ("goes", "go"),
+    ("went", "go"), ("gone", "go"), ("began", "begin"), ("begun", "begin"), ("bore", "bear"),
+    ("born", "bear"), ("became", "become"), ("brought", "bring"), ("built", "build"),
+    ("bought", "buy"), ("came", "come"), ("caught", "catch"), ("chose", "choose"),
+    ("chosen", "choose"), ("dealt", "deal"), ("drew", "draw"), ("drawn", "draw"),
+    ("drank", "drink"), ("drove", "drive"), ("driven", "drive"), ("ate", "eat"),
+    ("eaten", "eat"), ("fell", "fall"), ("fallen", "fall"), ("felt", "feel"), ("fought", "fight"),
+    ("found", "find"), ("fled", "flee"), ("flew", "fly"), ("flown", "fly"), ("forgot", "forget"),
+    ("forgotten", "forget"), ("gave", "give"), ("given", "give"), ("got", "get"),
+    ("gotten", "get"), ("grew", "grow"), ("grown", "grow"), ("heard", "hear"), ("held", "hold"),
+    ("kept", "keep"), ("knew", "know"), ("known", "know"), ("laid", "lay"), ("led", "lead"),
+    ("left", "leave"), ("lent", "lend"), ("lay", "lie"), ("lain", "lie"), ("lost", "lose"),
+    ("made", "make"), ("meant", "mean"), ("met", "meet"), ("paid", "pay"), ("ran", "run"),
+    ("rode", "ride"), ("rose", "rise"), ("risen", "rise"), ("said", "say"), ("saw", "see"),
+    ("seen", "see"), ("sought", "seek"), ("sold", "sell"), ("sent", "send"), ("shook", "shake"),
+    ("shone", "shine"), ("shot", "shoot"), ("sang", "sing"), ("sung", "sing"), ("sat", "sit"),
+    ("slept", "sleep"), ("spoke", "speak"), ("spoken", "speak"), ("spent", "spend"),
+    ("stood", "stand"), ("stole", "steal"), ("stolen", "steal"), ("struck", "strike"),
+    ("swore", "swear"), ("sworn", "swear"), ("took", "take"), ("taken", "take"),
+    ("taught", "teach"), ("tore", "tear"), ("torn", "tear"), ("told", "tell"),
+    ("thought", "think"), ("threw", "throw"), ("thrown", "throw"), ("understood", "understand"),
+    ("woke", "wake"), ("woken", "wake"), ("wore", "wear"), ("worn", "wear"), ("won", "win"),
+    ("wrote", "write"), ("written", "write"),
+];
+
+/// Irregular plural nouns and their singular
+const IRREGULAR_NOUNS: &[(&str, &str)] = &[
+    ("men", "man"), ("women", "woman"), ("children", "child"), ("people", "person"),
+    ("feet", "foot"), ("teeth", "tooth"), ("geese", "goose"), ("mice", "mouse"),
+    ("oxen", "ox"), ("lives", "life"), ("wives", "wife"), ("knives", "knife"),
+    ("leaves", "leaf"), ("halves", "half"), ("selves", "self"), ("wolves", "wolf"),
+    ("data", "datum"), ("criteria", "criterion"), ("phenomena", "phenomenon"),
+];
+
+/// Irregular comparatives and superlatives
+const IRREGULAR_ADJECTIVES: &[(&str, &str)] = &[
+    ("better", "good"), ("best", "good"), ("worse", "bad"), ("worst", "bad"),
+    ("more", "much"), ("most", "much"), ("less", "little"), ("least", "little"),
+    ("further", "far"), ("furthest", "far"), ("farther", "far"), ("farthest", "far"),
+];
+
+/// Lemma of `word` given its Penn Treebank `label`
+///
+/// # Arguments
+///
+/// * `word` - Word as it appears in the text
+/// * `label` - Part-of-speech label predicted for the word (e.g. NNS, VBD...)
+///
+/// # Example
+///
+/// ```
+/// use rustlib::lemma::lemmatize;
+///
+/// assert_eq!(lemmatize("Studies", "NNS"), "study");
+/// assert_eq!(lemmatize("went", "VBD"), "go");
+/// assert_eq!(lemmatize("happier", "JJR"), "happy");
+/// ```
+pub fn lemmatize(word: &str, label: &str) -> String {
+    if label == "NNP" || label == "NNPS" {
+        return word.to_owned();
+    }
+    let word = word.to_lowercase();
+    match label {
+        "NNS" => lookup(IRREGULAR_NOUNS, &word).unwrap_or_else(|| plural_noun(&word)),
+        "VBD" | "VBN" | "VBZ" | "VBG" | "VBP" => {
+            lookup(IRREGULAR_VERBS, &word).unwrap_or_else(|| match label {
+                "VBD" | "VBN" => past_verb(&word),
+                "VBZ" => third_person_verb(&word),
+                "VBG" => progressive_verb(&word),
+                _ => word.clone(),
+            })
+        }
+        "JJR" | "JJS" | "RBR" | "RBS" => {
+            lookup(IRREGULAR_ADJECTIVES, &word).unwrap_or_else(|| comparative(&word, label))
+        }
+        _ => word,
+    }
+}
+
+fn lookup(table: &[(&str, &str)], word: &str) -> Option<String> {
+    table
+        .iter()
+        .find(|(form, _)| *form == word)
+        .map(|(_, lemma)| (*lemma).to_owned())
+}
+
+fn plural_noun(word: &str) -> String {
+    if let Some(stem) = word.strip_suffix("ies").filter(|stem| stem.len() > 1) {
+        format!("{}y", stem)
+    } else if let Some(stem) = strip_sibilant_es(word) {
+        stem.to_owned()
+    } else if word.ends_with('s') && !word.ends_with("ss") && !word.ends_with("us") {
+        word[..word.len() - 1].to_owned()
+    } else {
+        word.to_owned()
+    }
+}
+
+fn third_person_verb(word: &str) -> String {
+    if let Some(stem) = word.strip_suffix("ies").filter(|stem| stem.len() > 1) {
+        format!("{}y", stem)
+    } else if let Some(stem) = strip_sibilant_es(word).or_else(|| word.strip_suffix("oes")) {
+        if word.ends_with("oes") {
+            format!("{}o", stem)
+        } else {
+            stem.to_owned()
+        }
+    } else if word.ends_with('s') && !word.ends_with("ss") {
+        word[..word.len() - 1].to_owned()
+    } else {
+        word.to_owned()
+    }
+}
+
+fn past_verb(word: &str) -> String {
+    if let Some(stem) = word.strip_suffix("ied").filter(|stem| stem.len() > 1) {
+        format!("{}y", stem)
+    } else if let Some(stem) = word.strip_suffix("ed").filter(|stem| stem.len() > 1) {
+        restore_stem(stem)
+    } else {
+        word.to_owned()
+    }
+}
+
+fn progressive_verb(word: &str) -> String {
+    if let Some(stem) = word.strip_suffix("ying").filter(|stem| stem.len() == 1) {
+        //dying, lying, tying
+        format!("{}ie", stem)
+    } else if let Some(stem) = word.strip_suffix("ing").filter(|stem| stem.len() > 1) {
+        restore_stem(stem)
+    } else {
+        word.to_owned()
+    }
+}
+
+fn comparative(word: &str, label: &str) -> String {
+    let suffix = if label.ends_with('R') { "er" } else { "est" };
+    match word.strip_suffix(suffix).filter(|stem| stem.len() > 1) {
+        Some(stem) if stem.ends_with('i') => format!("{}y", &stem[..stem.len() - 1]),
+        Some(stem) => undouble(stem).to_owned(),
+        None => word.to_owned(),
+    }
+}
+
+/// `-es` after a sibilant (boxes, churches, buzzes)
+fn strip_sibilant_es(word: &str) -> Option<&str> {
+    let stem = word.strip_suffix("es")?;
+    let sibilant = ["s", "x", "z", "ch", "sh"]
+        .iter()
+        .any(|ending| stem.ends_with(ending));
+    if sibilant {
+        Some(stem)
+    } else {
+        None
+    }
+}
+
+/// Undo consonant doubling (stopped) and restore a dropped final `e` (created, loved)
+fn restore_stem(stem: &str) -> String {
+    let undoubled = undouble(stem);
+    if undoubled.len() < stem.len() {
+        return undoubled.to_owned();
+    }
+    let ends_with = |endings: &[&str]| endings.iter().any(|ending| stem.ends_with(ending));
+    //treated, floated, waited keep their stem; created does not
+    let needs_e = ends_with(&["creat", "bl", "iz", "v", "uc", "dg"])
+        || (stem.ends_with("at") && !ends_with(&["eat", "oat", "ait"]));
+    if needs_e {
+        format!("{}e", stem)
+    } else {
+        stem.to_owned()
+    }
+}
+
+/// Drop the last letter of a stem ending in a doubled consonant, keeping ll, ss, ff, zz
+fn undouble(stem: &str) -> &str {
+    let bytes = stem.as_bytes();
+    let n = bytes.len();
+    if n >= 3
+        && bytes[n - 1] == bytes[n - 2]
+        && bytes[n - 1].is_ascii_alphabetic()
+        && !b"aeiouylsfz".contains(&bytes[n - 1])
+    {
+        &stem[..n - 1]
+    } else {
+        stem
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lemmatizes_by_tag() {
+        let cases = [
+            ("boxes", "NNS", "box"),
+            ("cities", "NNS", "city"),
+            ("children", "NNS", "child"),
+            ("Paris", "NNP", "Paris"),
+            ("stopped", "VBD", "stop"),
+            ("created", "VBN", "create"),
+            ("walked", "VBD", "walk"),
+            ("treated", "VBD", "treat"),
+            ("speaking", "VBG", "speak"),
+            ("running", "VBG", "run"),
+            ("dying", "VBG", "die"),
+            ("goes", "VBZ", "go"),
+            ("watches", "VBZ", "watch"),
+            ("were", "VBD", "be"),
+            ("biggest", "JJS", "big"),
+            ("worse", "JJR", "bad"),
+            ("The", "DT", "the"),
+        ];
+        for (word, label, lemma) in cases.iter() {
+            assert_eq!(lemmatize(word, label), *lemma, "{}/{}", word, label);
+        }
+    }
+}
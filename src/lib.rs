@@ -1,2 +1,106 @@
+//! # berttagr
+//! Part-of-speech tagging with MobileBERT.
+//!
+//! Cargo features, all enabled by default:
+//!
+//! * `cli`: the `berttagr_file` binary and the modules only it uses (`cli`, `clipboard`,
+//!   `distributed`, `editor`, `follow`, `inputs`, `plan`, `quality`, `stream`, `summary`)
+//! * `remote-models`: download the pretrained models when no model directory is set, and
+//!   the `sentiment`, `classify` and `redact` pipelines built on them. Without it the
+//!   default model is read from the `model` directory. Downloads are retried and can go
+//!   through a proxy, see `download`.
+//! * `cuda`: run on CUDA devices when available. Without it every model runs on the CPU.
+//!
+//! The `test-util` feature, off by default, adds `mock::MockTagger`, returning canned or
+//! rule-based tags so that pipelines can be tested without a model. The `kafka` and
+//! `redis-worker` features, off by default too, add the Kafka mode (`kafka`, linking
+//! librdkafka) and the Redis queue worker mode (`redis_worker`) of the binary. `arrow-ipc`,
+//! off by default as well, tags Arrow IPC streams (`arrow_ipc`) in the library and with the
+//! `arrow` subcommand.
+//!
+//! Embedders only needing the tagging API can depend on the crate with
+//! `default-features = false`.
+
+#[cfg(feature = "arrow-ipc")]
+pub mod arrow_ipc;
+pub mod bench;
+pub mod calibration;
+pub mod checksums;
+#[cfg(feature = "remote-models")]
+pub mod classify;
+#[cfg(feature = "cli")]
+pub mod cli;
+#[cfg(feature = "cli")]
+pub mod clipboard;
+pub mod collocations;
+pub mod config;
+pub mod confusion;
+pub mod coverage;
+pub mod depparse;
+pub mod diff;
+#[cfg(feature = "cli")]
+pub mod distributed;
+#[cfg(feature = "remote-models")]
+pub mod download;
+#[cfg(feature = "cli")]
+pub mod editor;
+pub mod email;
+pub mod emoji;
+pub mod ensemble;
+pub mod exit;
+pub mod export;
+#[cfg(feature = "cli")]
+pub mod follow;
+pub mod format;
+pub mod hooks;
+#[cfg(feature = "remote-models")]
+pub mod hub;
+pub mod incremental;
+#[cfg(feature = "cli")]
+pub mod inputs;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod kwic;
+pub mod lemma;
+pub mod limits;
+pub mod lint;
+pub mod metadata;
+pub mod metrics;
+#[cfg(feature = "test-util")]
+pub mod mock;
+pub mod model_info;
+pub mod models;
+pub mod morph;
+pub mod ngrams;
+pub mod normalize;
+pub mod output;
+pub mod pipeline;
+#[cfg(feature = "cli")]
+pub mod plan;
 pub mod pos_tagging;
-pub mod rusttagr;
\ No newline at end of file
+pub mod prelude;
+pub mod pretokenized;
+pub mod progress;
+pub mod protect;
+#[cfg(feature = "cli")]
+pub mod quality;
+pub mod quantities;
+pub mod records;
+#[cfg(feature = "remote-models")]
+pub mod redact;
+#[cfg(feature = "redis-worker")]
+pub mod redis_worker;
+pub mod rusttagr;
+pub mod sentences;
+#[cfg(feature = "remote-models")]
+pub mod sentiment;
+pub mod shard;
+#[cfg(feature = "cli")]
+pub mod stream;
+#[cfg(feature = "cli")]
+pub mod summary;
+pub mod tagger;
+pub mod tagset;
+pub mod train;
+pub mod tuning;
+pub mod warnings;
\ No newline at end of file
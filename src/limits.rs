@@ -0,0 +1,212 @@
+//! # Input limits
+//! Guards against inputs too large for a run: files above `max_file_size` bytes and
+//! sentences above `max_sentence_length` characters are skipped or truncated with a warning,
+//! or rejected with an error, as set by `on_limit`. Without a sentence limit the model
+//! truncates long sentences silently at its maximum number of tokens.
+//!
+//! ```toml
+//! max_file_size = 104857600
+//! max_sentence_length = 1000
+//! on_limit = "skip"
+//! ```
+
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::bail;
+use serde::Deserialize;
+
+use crate::warnings::{Warning, WarningSink};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// # Handling of an input above a limit
+pub enum LimitAction {
+    /// Leave the input out with a warning
+    Skip,
+    /// Keep the beginning of the input, up to the limit
+    Truncate,
+    /// Stop the run with an error
+    Error,
+}
+
+impl Default for LimitAction {
+    fn default() -> LimitAction {
+        LimitAction::Skip
+    }
+}
+
+impl FromStr for LimitAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<LimitAction, Self::Err> {
+        match s {
+            "skip" => Ok(LimitAction::Skip),
+            "truncate" => Ok(LimitAction::Truncate),
+            "error" => Ok(LimitAction::Error),
+            _ => Err(anyhow::anyhow!(
+                "Unknown limit action '{}', expected one of skip, truncate, error",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+/// # Inputs skipped or truncated by the limits
+pub struct LimitReport {
+    pub skipped_files: usize,
+    pub truncated_files: usize,
+    pub skipped_sentences: usize,
+    pub truncated_sentences: usize,
+}
+
+impl LimitReport {
+    /// Accumulate the report of another input
+    pub fn add(&mut self, other: &LimitReport) {
+        self.skipped_files += other.skipped_files;
+        self.truncated_files += other.truncated_files;
+        self.skipped_sentences += other.skipped_sentences;
+        self.truncated_sentences += other.truncated_sentences;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == LimitReport::default()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+/// # Limits on the size of the input
+pub struct Limits {
+    pub max_file_size: Option<u64>,
+    pub max_sentence_length: Option<usize>,
+    pub action: LimitAction,
+}
+
+impl Limits {
+    /// Number of bytes of the file at `path`, of size `len`, to process: all of them, those
+    /// up to the limit, or none when it is skipped, passing the warning to `warnings`
+    pub fn check_file(
+        &self,
+        path: &Path,
+        len: u64,
+        report: &mut LimitReport,
+        warnings: &dyn WarningSink,
+    ) -> anyhow::Result<u64> {
+        let max = match self.max_file_size {
+            Some(max) if len > max => max,
+            _ => return Ok(len),
+        };
+        match self.action {
+            LimitAction::Skip => {
+                warnings.warn(Warning::SkippedFile {
+                    path: path.display().to_string(),
+                    size: len,
+                    limit: max,
+                });
+                report.skipped_files += 1;
+                Ok(0)
+            }
+            LimitAction::Truncate => {
+                warnings.warn(Warning::TruncatedFile {
+                    path: path.display().to_string(),
+                    size: len,
+                    limit: max,
+                });
+                report.truncated_files += 1;
+                Ok(max)
+            }
+            LimitAction::Error => bail!("{} is {} bytes, above the limit of {}", path.display(), len, max),
+        }
+    }
+
+    /// `sentence`, numbered `index` in its input, within the sentence limit, or `None` when
+    /// it is skipped, passing the warning of a skipped or truncated sentence to `warnings`
+    pub fn check_sentence<'a>(
+        &self,
+        sentence: &'a str,
+        index: usize,
+        report: &mut LimitReport,
+        warnings: &dyn WarningSink,
+    ) -> anyhow::Result<Option<&'a str>> {
+        let max = match self.max_sentence_length {
+            Some(max) => max,
+            None => return Ok(Some(sentence)),
+        };
+        let end = match sentence.char_indices().nth(max) {
+            Some((end, _)) => end,
+            None => return Ok(Some(sentence)),
+        };
+        let length = sentence.chars().count();
+        match self.action {
+            LimitAction::Skip => {
+                warnings.warn(Warning::SkippedSentence {
+                    sentence: index,
+                    length,
+                    limit: max,
+                });
+                report.skipped_sentences += 1;
+                Ok(None)
+            }
+            LimitAction::Truncate => {
+                warnings.warn(Warning::TruncatedSentence {
+                    sentence: index,
+                    tagged: max,
+                    length,
+                });
+                report.truncated_sentences += 1;
+                Ok(Some(&sentence[..end]))
+            }
+            LimitAction::Error => bail!(
+                "Sentence {} is {} characters long, above the limit of {}",
+                index + 1,
+                length,
+                max
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn sentences_above_the_limit_are_skipped_or_truncated() {
+        let mut report = LimitReport::default();
+        let warnings = Mutex::new(Vec::new());
+        let skip = Limits {
+            max_sentence_length: Some(5),
+            ..Limits::default()
+        };
+        assert_eq!(skip.check_sentence("short", 0, &mut report, &warnings).unwrap(), Some("short"));
+        assert_eq!(skip.check_sentence("too long", 1, &mut report, &warnings).unwrap(), None);
+        let truncate = Limits {
+            action: LimitAction::Truncate,
+            ..skip
+        };
+        assert_eq!(truncate.check_sentence("héllo wörld", 2, &mut report, &warnings).unwrap(), Some("héllo"));
+        assert_eq!((report.skipped_sentences, report.truncated_sentences), (1, 1));
+        assert_eq!(
+            warnings.lock().unwrap().as_slice(),
+            &[
+                Warning::SkippedSentence {
+                    sentence: 1,
+                    length: 8,
+                    limit: 5,
+                },
+                Warning::TruncatedSentence {
+                    sentence: 2,
+                    tagged: 5,
+                    length: 11,
+                },
+            ]
+        );
+        let error = Limits {
+            action: LimitAction::Error,
+            ..skip
+        };
+        assert!(error.check_sentence("too long", 3, &mut report, &warnings).is_err());
+    }
+}
@@ -0,0 +1,266 @@
+//! # Grammar-pattern linting
+//! The `lint` subcommand flags sequences of tagged words matching rules, such as the
+//! passive voice or noun pileups, and reports them like a compiler, one per line with the
+//! file, line and column of their first word:
+//!
+//! ```text
+//! report.txt:3:14: passive-voice: passive voice "was written"
+//! ```
+//!
+//! A rule is a pattern of elements separated by `+`, matching consecutive words of a
+//! sentence. As in `ngrams::TagPattern`, an element is a label of the model (`VBN`), a
+//! universal tag (`NOUN`) or any word (`*`); it can also be `lemma/TAG`, a word of that
+//! lemma (see `lemma`) with that tag, `TAG` being `*` for any tag. The rules default to
+//! `DEFAULT_RULES`, and are replaced with `lint_rules` in the configuration file:
+//!
+//! ```toml
+//! [[lint_rules]]
+//! name = "double-negative"
+//! pattern = "not/*+*+no/*"
+//! message = "double negative"
+//! ```
+//!
+//! Overlapping matches of a rule are reported once.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::lemma::lemmatize;
+use crate::pos_tagging::POSTag;
+use crate::sentences;
+use crate::tagger::Tagger;
+use crate::tagset::to_upos;
+use crate::warnings::WarningSink;
+
+/// Rules used when none are configured: name, pattern and message
+pub const DEFAULT_RULES: &[(&str, &str, &str)] = &[
+    ("passive-voice", "be/*+VBN", "passive voice"),
+    ("double-modal", "MD+MD", "double modal"),
+    ("noun-pileup", "NOUN+NOUN+NOUN", "three or more nouns in a row"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// # Element of a lint pattern
+pub struct PatternElement {
+    /// Lemma of the word, any if `None`
+    pub lemma: Option<String>,
+    /// Label or universal tag of the word, or `*`
+    pub tag: String,
+}
+
+impl PatternElement {
+    pub fn matches(&self, tag: &POSTag) -> bool {
+        (self.tag == "*" || self.tag == tag.label || self.tag == to_upos(&tag.label))
+            && self.lemma.as_ref().map_or(true, |lemma| *lemma == lemmatize(&tag.word, &tag.label))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+/// # Sequence of words a rule matches
+pub struct LintPattern {
+    pub elements: Vec<PatternElement>,
+}
+
+impl FromStr for LintPattern {
+    type Err = anyhow::Error;
+
+    /// Parse elements separated by `+`, each `TAG` or `lemma/TAG`
+    fn from_str(s: &str) -> Result<LintPattern, Self::Err> {
+        let mut elements = Vec::new();
+        for element in s.split('+').map(str::trim) {
+            let (lemma, tag) = match element.rfind('/') {
+                Some(index) => (Some(element[..index].to_lowercase()), &element[index + 1..]),
+                None => (None, element),
+            };
+            if tag.is_empty() || lemma.as_deref() == Some("") {
+                bail!("Invalid lint pattern '{}', expected TAG or lemma/TAG separated by + (e.g. be/*+VBN)", s);
+            }
+            elements.push(PatternElement {
+                lemma,
+                tag: tag.to_owned(),
+            });
+        }
+        Ok(LintPattern { elements })
+    }
+}
+
+impl TryFrom<String> for LintPattern {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<LintPattern, Self::Error> {
+        s.parse()
+    }
+}
+
+impl LintPattern {
+    /// Ranges of the words of `sentence` matching the pattern, without overlaps
+    pub fn find(&self, sentence: &[POSTag]) -> Vec<Range<usize>> {
+        let len = self.elements.len();
+        let mut matches = Vec::new();
+        let mut start = 0;
+        while start + len <= sentence.len() {
+            let window = &sentence[start..start + len];
+            if self.elements.iter().zip(window).all(|(element, tag)| element.matches(tag)) {
+                matches.push(start..start + len);
+                start += len;
+            } else {
+                start += 1;
+            }
+        }
+        matches
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// # Lint rule
+pub struct LintRule {
+    /// Identifier reported with the diagnostics
+    pub name: String,
+    pub pattern: LintPattern,
+    /// Description of the problem
+    pub message: String,
+}
+
+/// The `DEFAULT_RULES`
+pub fn default_rules() -> Vec<LintRule> {
+    DEFAULT_RULES
+        .iter()
+        .map(|(name, pattern, message)| LintRule {
+            name: (*name).to_owned(),
+            pattern: pattern.parse().expect("invalid default lint pattern"),
+            message: (*message).to_owned(),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// # Match of a rule
+pub struct Diagnostic {
+    pub path: String,
+    /// Line of the first word, from 1
+    pub line: usize,
+    /// Column of the first word in characters, from 1
+    pub column: usize,
+    pub rule: String,
+    pub message: String,
+    /// Words matched, separated by spaces
+    pub text: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}: {} \"{}\"",
+            self.path, self.line, self.column, self.rule, self.message, self.text
+        )
+    }
+}
+
+/// Diagnostics of the files of `paths`, tagged with the configured tagger and checked with
+/// the configured rules
+pub fn lint_files(paths: &[PathBuf], config: &Config, warnings: &dyn WarningSink) -> anyhow::Result<Vec<Diagnostic>> {
+    let rules = config.lint_rules.clone().unwrap_or_else(default_rules);
+    let tagger = config.tagger(warnings)?;
+    let mut diagnostics = Vec::new();
+    for path in paths {
+        diagnostics.extend(lint_file(&*tagger, path, &rules, config.batch_size())?);
+    }
+    Ok(diagnostics)
+}
+
+/// Diagnostics of the file at `path`, tagged by `tagger` in batches of `batch_size`
+/// sentences, in the order of the text
+pub fn lint_file(
+    tagger: &dyn Tagger,
+    path: &Path,
+    rules: &[LintRule],
+    batch_size: usize,
+) -> anyhow::Result<Vec<Diagnostic>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Could not read {}", path.display()))?;
+    let source = path.to_string_lossy();
+    let mut diagnostics = Vec::new();
+    let sentences = sentences::split(&contents);
+    for batch in sentences.chunks(batch_size.max(1)) {
+        for (sentence, tags) in batch.iter().zip(tagger.tag(batch)) {
+            let tags: Vec<POSTag> = tags.into_iter().map(|scored_tag| scored_tag.tag).collect();
+            let offset = sentences::offset_in(&contents, sentence);
+            //byte offset of each word in the file, when found in the sentence
+            let mut position = 0;
+            let offsets: Vec<Option<usize>> = tags
+                .iter()
+                .map(|tag| {
+                    let found = sentences::find_word(sentence, position, &tag.word)?;
+                    position = found.end;
+                    Some(offset + found.start)
+                })
+                .collect();
+            let mut found = Vec::new();
+            for rule in rules {
+                for range in rule.pattern.find(&tags) {
+                    let start = offsets[range.clone()].iter().flatten().next().copied().unwrap_or(offset);
+                    let (line, column) = line_column(&contents, start);
+                    let words: Vec<&str> = tags[range].iter().map(|tag| tag.word.as_str()).collect();
+                    found.push(Diagnostic {
+                        path: source.to_string(),
+                        line,
+                        column,
+                        rule: rule.name.clone(),
+                        message: rule.message.clone(),
+                        text: words.join(" "),
+                    });
+                }
+            }
+            found.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+            diagnostics.extend(found);
+        }
+    }
+    Ok(diagnostics)
+}
+
+/// Line and column, from 1, of the byte `offset` of `text`
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sentence(tags: &[(&str, &str)]) -> Vec<POSTag> {
+        tags.iter()
+            .map(|(word, label)| POSTag {
+                word: word.to_string(),
+                label: label.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_lemmas_and_tags() {
+        let rules = default_rules();
+        let passive = sentence(&[("It", "PRP"), ("was", "VBD"), ("written", "VBN"), ("today", "NN")]);
+        assert_eq!(rules[0].pattern.find(&passive), vec![1..3]);
+        let pileup = sentence(&[("tax", "NN"), ("reform", "NN"), ("bill", "NN"), ("draft", "NN")]);
+        assert_eq!(rules[2].pattern.find(&pileup), vec![0..3]);
+        assert!("be/".parse::<LintPattern>().is_err());
+        assert!("MD++MD".parse::<LintPattern>().is_err());
+        assert_eq!(line_column("ab\ncdé f", 8), (2, 5));
+    }
+}
@@ -9,31 +9,437 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.extern crate anyhow;
-use std::fs;
+use std::fs::{self, File};
 use std::env;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::time::Instant;
+use anyhow::{anyhow, Context};
+#[cfg(feature = "arrow-ipc")]
+use rustlib::arrow_ipc;
+use rustlib::bench;
+use rustlib::calibration;
+use rustlib::classify;
+use rustlib::cli::{self, Command};
+use rustlib::clipboard;
+use rustlib::collocations;
+use rustlib::config::{self, Config};
+use rustlib::coverage;
+use rustlib::diff;
+use rustlib::distributed;
+use rustlib::editor;
+use rustlib::exit::{self, ErrorFormat, ExitCode, ModelLoadError, UsageError};
+use rustlib::export;
+use rustlib::follow;
+use rustlib::format::TagFormat;
+use rustlib::inputs::read_file_list;
+#[cfg(feature = "kafka")]
+use rustlib::kafka;
+use rustlib::kwic;
+use rustlib::limits::LimitReport;
+use rustlib::lint;
+use rustlib::metadata::RunMetadata;
+use rustlib::metrics;
+use rustlib::model_info::ModelInfo;
+use rustlib::ngrams;
+use rustlib::output;
+use rustlib::pipeline;
+use rustlib::plan::Plan;
+use rustlib::pos_tagging::POSModel;
+use rustlib::quality::QualityReport;
+use rustlib::records::InputFormat;
+use rustlib::redact;
+#[cfg(feature = "redis-worker")]
+use rustlib::redis_worker;
+use rustlib::rusttagr;
+use rustlib::sentiment;
+use rustlib::stream;
+use rustlib::summary::RunSummary;
+use rustlib::train;
+use rustlib::warnings::{SharedSink, Stderr};
 
 fn main()  {
     //get command line arguments
     let cmd_args: Vec<String> = env::args().collect();
+    let error_format = cli::error_format(&cmd_args[1..]);
 
-    if cmd_args.len() != 3{
-        println!("Requires two arguments.\nUSAGE: berttagr_file input.txt output.txt");
+    if let Err(err) = run(&cmd_args[1..]) {
+        let code = exit::report(&err, error_format);
+        if code == ExitCode::Usage && error_format == ErrorFormat::Text {
+            eprintln!("{}", cli::USAGE);
+        }
+        process::exit(code as i32);
     }
-    else {
+}
 
-        println!("In file {}", cmd_args[1]);
-        println!("Out file {}", cmd_args[2]);
+fn run(cmd_args: &[String]) -> anyhow::Result<()> {
+    let args = cli::parse_args(cmd_args).context(UsageError)?;
 
-        let in_path = cmd_args[1].as_str();
-        let out_path = cmd_args[2].as_str();
+    //config file settings, overridden by the command line flags
+    let config = Config::load(args.config_path.as_deref())
+        .context("Something went wrong reading the config file")?
+        .merge(args.overrides);
 
-        let contents = fs::read_to_string(in_path)
-            .expect("Something went wrong reading the file");
+    //existing outputs are skipped or rejected before loading any model
+    if let Some(output) = args.command.output() {
+        if !config.output_policy().should_write(Path::new(output), &Stderr)? {
+            return Ok(());
+        }
+    }
+
+    if let Command::Tag { inputs, file_list, null_separated, output, dry_run: true, .. } = &args.command {
+        let mut in_paths: Vec<PathBuf> = inputs.iter().map(PathBuf::from).collect();
+        if let Some(file_list) = file_list {
+            in_paths.extend(read_file_list(Path::new(file_list), *null_separated)?);
+        }
+        println!("{}", Plan::new(&in_paths, output, &config)?);
+        return Ok(());
+    }
+
+    match args.command {
+        Command::Tag { inputs, file_list, null_separated, output, summary, report, in_place: None, .. } => {
+            let mut in_paths: Vec<PathBuf> = inputs.iter().map(PathBuf::from).collect();
+            if let Some(file_list) = &file_list {
+                in_paths.extend(read_file_list(Path::new(file_list), null_separated)?);
+            }
+            let documents = file_list.is_some();
+            tag(&in_paths, documents, &output, summary.as_deref(), report.as_deref(), cmd_args, &config)?
+        }
+        Command::Tag { inputs, summary, report, in_place: Some(suffix), .. } => {
+            if config.shard.is_some() {
+                return Err(anyhow!("--in-place replaces the input, it cannot tag a shard of it")).context(UsageError);
+            }
+            let input = &inputs[0];
+            //tag into a temporary file next to the input, renamed over it on success
+            let temp = output::temp_path(Path::new(input));
+            let in_paths = [PathBuf::from(input)];
+            let temp_output = temp.to_string_lossy();
+            let result = tag(&in_paths, false, &temp_output, summary.as_deref(), report.as_deref(), cmd_args, &config)
+                .and_then(|()| {
+                    let backup = Some(suffix.as_str()).filter(|suffix| !suffix.is_empty());
+                    output::replace(Path::new(input), &temp, backup)
+                });
+            if result.is_err() {
+                let _ = fs::remove_file(&temp);
+            }
+            result?
+        }
+        Command::Clipboard { copy } => {
+            let text = clipboard::read().context("Something went wrong reading the clipboard")?;
+            let tagged = rusttagr::tag_with_config(&text, &config, &Stderr)
+                .context("Something went wrong tagging the clipboard")?;
+            if copy {
+                clipboard::write(&tagged).context("Something went wrong writing the clipboard")?;
+                println!("Copied the tagged text to the clipboard");
+            } else {
+                print!("{}", tagged);
+            }
+        }
+        Command::Follow { input, output } => {
+            println!("Following {} into {}", input, output);
+            follow::follow_file(Path::new(&input), Path::new(&output), &config, &Stderr)
+                .context("Something went wrong tagging the file")?
+        }
+        #[cfg(feature = "kafka")]
+        Command::Kafka { brokers, input_topic, output_topic, group_id } => {
+            let options = kafka::KafkaOptions {
+                brokers: brokers.unwrap_or_else(|| kafka::DEFAULT_BROKERS.to_owned()),
+                input_topic,
+                output_topic,
+                group_id: group_id.unwrap_or_else(|| kafka::DEFAULT_GROUP.to_owned()),
+            };
+            kafka::run(&options, &config, &stderr()).context("Something went wrong in the Kafka mode")?
+        }
+        #[cfg(not(feature = "kafka"))]
+        Command::Kafka { .. } => {
+            return Err(anyhow!("Built without the kafka feature, rebuild with --features kafka"))
+                .context(UsageError)
+        }
+        #[cfg(feature = "redis-worker")]
+        Command::Redis { url, input_key, output_key, retries } => {
+            let options = redis_worker::WorkerOptions {
+                url: url.unwrap_or_else(|| redis_worker::DEFAULT_URL.to_owned()),
+                input_key,
+                output_key,
+                workers: config.workers(),
+                retries: retries.unwrap_or(redis_worker::DEFAULT_RETRIES),
+            };
+            redis_worker::run(&options, &config, &stderr()).context("Something went wrong in the Redis worker")?
+        }
+        #[cfg(not(feature = "redis-worker"))]
+        Command::Redis { .. } => {
+            return Err(anyhow!("Built without the redis-worker feature, rebuild with --features redis-worker"))
+                .context(UsageError)
+        }
+        Command::Worker { coordinator } => {
+            distributed::run_worker(&coordinator, &config, &Stderr).context("Something went wrong in the worker mode")?
+        }
+        Command::Bench { input, iterations } => run_bench(&input, iterations, &config)?,
+        Command::Sentiment { input, output } => {
+            sentiment::classify_file(Path::new(&input), Path::new(&output), &config)
+                .context("Something went wrong classifying the file")?
+        }
+        Command::Classify { input, output, labels } => {
+            classify::classify_file(Path::new(&input), Path::new(&output), &labels, &config)
+                .context("Something went wrong classifying the file")?
+        }
+        Command::Redact { input, output, mapping } => {
+            redact::redact_file(Path::new(&input), Path::new(&output), Path::new(&mapping), &config, &Stderr)
+                .context("Something went wrong redacting the file")?
+        }
+        Command::Train { corpus, output_dir, options } => {
+            train::train(Path::new(&corpus), Path::new(&output_dir), &options, &config, &Stderr, |metrics| {
+                println!("{}", metrics)
+            })
+                .context("Something went wrong training the model")?
+        }
+        Command::Export { output_dir } => {
+            export::export(Path::new(&output_dir), &config, &Stderr)
+                .context("Something went wrong exporting the model")?
+        }
+        #[cfg(feature = "arrow-ipc")]
+        Command::Arrow { input, output, text_column } => {
+            run_arrow(&input, &output, text_column.as_deref(), &config)
+                .context("Something went wrong tagging the Arrow stream")?
+        }
+        #[cfg(not(feature = "arrow-ipc"))]
+        Command::Arrow { .. } => {
+            return Err(anyhow!("Built without the arrow-ipc feature, rebuild with --features arrow-ipc"))
+                .context(UsageError)
+        }
+        Command::Diff { a, b, confusion_csv, confusion_html } => {
+            let report = diff::diff_files(Path::new(&a), Path::new(&b))
+                .context("Something went wrong comparing the files")?;
+            print!("{}", report);
+            if let Some(path) = confusion_csv {
+                let file = File::create(path).context("Something went wrong creating the file")?;
+                report.confusion.write_csv(BufWriter::new(file))
+                    .context("Something went wrong writing the confusion matrix")?;
+            }
+            if let Some(path) = confusion_html {
+                let file = File::create(path).context("Something went wrong creating the file")?;
+                report.confusion.write_html(BufWriter::new(file))
+                    .context("Something went wrong writing the confusion matrix")?;
+            }
+        }
+        Command::Editor => {
+            editor::run(&config, &Stderr).context("Something went wrong serving the editor")?
+        }
+        Command::Labels => print_labels(&config)?,
+        Command::ModelInfo => {
+            let info = ModelInfo::new(&config, &Stderr).context(ModelLoadError)?;
+            if config.format() == TagFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&info)?);
+            } else {
+                print!("{}", info);
+            }
+        }
+        Command::Kwic { inputs, query } => {
+            let in_paths: Vec<PathBuf> = inputs.iter().map(PathBuf::from).collect();
+            kwic::print_kwic(&in_paths, &query, &config, &Stderr)
+                .context("Something went wrong searching the files")?;
+        }
+        Command::Lint { inputs } => {
+            let in_paths: Vec<PathBuf> = inputs.iter().map(PathBuf::from).collect();
+            let diagnostics = lint::lint_files(&in_paths, &config, &Stderr)
+                .context("Something went wrong linting the files")?;
+            if config.format() == TagFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&diagnostics)?);
+            } else {
+                for diagnostic in &diagnostics {
+                    println!("{}", diagnostic);
+                }
+            }
+            //like compilers, fail when there are problems
+            if !diagnostics.is_empty() {
+                return Err(anyhow!("{} problems found", diagnostics.len()));
+            }
+        }
+        Command::Ngrams { inputs, output, mut counter, min_count } => {
+            let in_paths: Vec<PathBuf> = inputs.iter().map(PathBuf::from).collect();
+            ngrams::count_files(&in_paths, &mut counter, &config, &Stderr)
+                .context("Something went wrong counting the n-grams")?;
+            let file = File::create(&output).context("Something went wrong creating the file")?;
+            counter.write_csv(BufWriter::new(file), min_count)
+                .context("Something went wrong writing the n-grams")?
+        }
+        Command::Calibrate { corpus, output, bins } => {
+            let calibration = calibration::calibrate_file(Path::new(&corpus), bins, &config, &Stderr)
+                .context("Something went wrong calibrating the scores")?;
+            calibration.write(Path::new(&output))?
+        }
+        Command::Coverage { inputs, top } => {
+            let in_paths: Vec<PathBuf> = inputs.iter().map(PathBuf::from).collect();
+            let report = coverage::coverage_files(&in_paths, top, &config, &Stderr)
+                .context("Something went wrong measuring the vocabulary coverage")?;
+            if config.format() == TagFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&report.to_json())?);
+            } else {
+                print!("{}", report);
+            }
+        }
+        Command::Collocations { inputs, output, mut counter, min_count, measure } => {
+            let in_paths: Vec<PathBuf> = inputs.iter().map(PathBuf::from).collect();
+            collocations::count_files(&in_paths, &mut counter, &config, &Stderr)
+                .context("Something went wrong counting the collocations")?;
+            let file = File::create(&output).context("Something went wrong creating the file")?;
+            counter.write_csv(BufWriter::new(file), min_count, measure)
+                .context("Something went wrong writing the collocations")?
+        }
+        Command::Metrics { inputs, output } => {
+            let in_paths: Vec<PathBuf> = inputs.iter().map(PathBuf::from).collect();
+            let report = metrics::metrics_files(&in_paths, &config, &Stderr)
+                .context("Something went wrong measuring the files")?;
+            metrics::write_report(&report, Path::new(&output), config.format() == TagFormat::Json)
+                .context("Something went wrong writing the metrics")?
+        }
+    }
+    Ok(())
+}
+
+/// Tag `in_paths` into `out_path`, as documents if there are several of them or `documents`
+/// is set, writing the run summary to `summary_path` and the quality report to
+/// `report_path` if given. `cmd_args` are recorded in the run metadata.
+fn tag(
+    in_paths: &[PathBuf],
+    documents: bool,
+    out_path: &str,
+    summary_path: Option<&str>,
+    report_path: Option<&str>,
+    cmd_args: &[String],
+    config: &Config,
+) -> anyhow::Result<()> {
+    let documents = documents || in_paths.len() > 1;
+    let mut config = config.clone();
+    if config.metadata.unwrap_or(false) {
+        config.run_metadata = Some(RunMetadata::new(&config, cmd_args, in_paths)?);
+    }
+    let config = &config;
+    for in_path in in_paths {
+        println!("In file {}", in_path.display());
+    }
+    println!("Out file {}", out_path);
+    let start = Instant::now();
+    let mut quality = report_path.map(|_| QualityReport::default());
+    let warnings = stderr();
+
+    //read, tag and write on overlapping threads, unless custom stages are configured
+    let (stats, limit_report) = match (&config.pipeline, in_paths) {
+        (Some(_), _) if config.input_format() != InputFormat::Text => {
+            Err(anyhow!("A configured pipeline tags text input")).context(UsageError)
+        }
+        (Some(_), _) if config.pretokenized.unwrap_or(false) => {
+            Err(anyhow!("A configured pipeline cannot tag pretokenized input")).context(UsageError)
+        }
+        (Some(_), _) if config.shard.is_some() => {
+            Err(anyhow!("A configured pipeline cannot tag a shard of the input")).context(UsageError)
+        }
+        (Some(_), _) if config.coordinator.is_some() => {
+            Err(anyhow!("A configured pipeline cannot be distributed to workers")).context(UsageError)
+        }
+        (Some(_), _) if quality.is_some() => {
+            Err(anyhow!("A configured pipeline cannot write a quality report")).context(UsageError)
+        }
+        (Some(_), [in_path]) if !documents => {
+            pipeline::run_file(in_path, Path::new(out_path), config, &Stderr)
+                .map(|stats| (stats, LimitReport::default()))
+        }
+        (Some(_), _) => Err(anyhow!("A configured pipeline tags a single input file")).context(UsageError),
+        (None, _) if config.input_format() != InputFormat::Text => stream::tag_records(
+            in_paths,
+            config.input_format(),
+            Path::new(out_path),
+            config,
+            quality.as_mut(),
+            &warnings,
+        ),
+        (None, [in_path]) if !documents => {
+            stream::tag_file(in_path, Path::new(out_path), config, quality.as_mut(), &warnings)
+        }
+        (None, _) => stream::tag_documents(in_paths, Path::new(out_path), config, quality.as_mut(), &warnings),
+    }
+    .context("Something went wrong tagging the files")?;
+
+    let mut summary = RunSummary::default();
+    summary.add_files(in_paths.len(), &stats, &limit_report);
+    summary.elapsed = start.elapsed();
+    println!("{}", summary);
+    if let Some(path) = summary_path {
+        summary.write_json(Path::new(path))
+            .context("Something went wrong writing the summary")?;
+    }
+    if let (Some(path), Some(mut quality)) = (report_path, quality) {
+        quality.summary = summary;
+        quality.write(Path::new(path))
+            .context("Something went wrong writing the report")?;
+    }
+    Ok(())
+}
+
+/// Print the labels of the configured model, one per line or as JSON
+fn print_labels(config: &Config) -> anyhow::Result<()> {
+    //not `config.pos_model()`, which rejects unknown filter_tags this is meant to help fix
+    let pos_model = POSModel::new(config.pos_config(&Stderr)?).context(ModelLoadError)?;
+    let labels = pos_model.label_set();
+    if config.format() == TagFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&labels)?);
+        return Ok(());
+    }
+    let width = labels.iter().map(|label| label.label.len()).max().unwrap_or(0);
+    for label in labels {
+        let line = format!("{:width$}  {}", label.label, label.description.unwrap_or(""), width = width);
+        println!("{}", line.trim_end());
+    }
+    Ok(())
+}
+
+/// Tag the Arrow IPC stream `input` into `output`, stdin and stdout being `-`
+#[cfg(feature = "arrow-ipc")]
+fn run_arrow(input: &str, output: &str, text_column: Option<&str>, config: &Config) -> anyhow::Result<()> {
+    use rustlib::inputs::STDIN;
+    use std::io::{self, Read, Write};
+
+    let text_column = text_column.unwrap_or(arrow_ipc::DEFAULT_TEXT_COLUMN);
+    let reader: Box<dyn Read> = if input == STDIN {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(input).with_context(|| format!("Could not open {}", input))?)
+    };
+    if output == STDIN {
+        let stdout = io::stdout();
+        let mut writer = stdout.lock();
+        arrow_ipc::tag_stream(reader, &mut writer, text_column, config, &Stderr)?;
+        writer.flush()?;
+    } else {
+        let (pending, file) = config.output_policy().begin(Path::new(output))?;
+        let mut writer = BufWriter::new(file);
+        arrow_ipc::tag_stream(reader, &mut writer, text_column, config, &Stderr)?;
+        writer.flush()?;
+        pending.commit()?;
+    }
+    Ok(())
+}
+
+/// Warnings printed on stderr, shared by the threads of a run
+fn stderr() -> SharedSink {
+    Arc::new(Stderr)
+}
 
-        let result: String = rustlib::rusttagr::rust_tag_r(contents.as_str());
+fn run_bench(in_path: &str, iterations: usize, config: &Config) -> anyhow::Result<()> {
+    let contents = fs::read_to_string(in_path)
+        .context("Something went wrong reading the file")?;
 
-        //write to a file
-        fs::write(out_path, result.as_str())
-            .expect("Something went wrong reading the file");
+    //an explicit device is benchmarked alone, otherwise CPU and GPU
+    let devices = match &config.device {
+        Some(device) => vec![config::parse_device(device).context(UsageError)?],
+        None => bench::default_devices(),
+    };
+    for device in devices {
+        let report = bench::run(config, device, contents.as_str(), iterations, &Stderr)
+            .context("Something went wrong running the benchmark")?;
+        println!("{}", report);
     }
+    Ok(())
 }
\ No newline at end of file
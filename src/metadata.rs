@@ -0,0 +1,153 @@
+//! # Run metadata
+//! With `--metadata` (or `metadata = true` in the configuration file), tagged outputs start
+//! with a description of the run that produced them, so that a corpus can be traced back to
+//! its model and inputs long after it was tagged:
+//!
+//! ```text
+//! # generator = berttagr 0.1.0
+//! # model = en-pos-mobilebert
+//! # options = --format conllu --metadata corpus.txt corpus.conllu
+//! # created = 2021-06-01T12:00:00Z
+//! # input = corpus.txt sha256:9f86d08...
+//! ```
+//!
+//! as comment lines in the debug, TSV and CoNLL-U formats. The JSON output becomes an object
+//! holding the `metadata` and the `sentences` (or `documents`).
+
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// # Description of a tagging run
+pub struct RunMetadata {
+    /// Name and version of the crate
+    pub generator: String,
+    /// Registry name, Hub repository and revision, or directory of the model
+    pub model: String,
+    /// Command line arguments
+    pub options: Vec<String>,
+    /// Start of the run, UTC in RFC 3339 format
+    pub created: String,
+    pub inputs: Vec<InputDigest>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// # Input file and the SHA-256 digest of its contents
+pub struct InputDigest {
+    pub path: String,
+    pub sha256: String,
+}
+
+impl RunMetadata {
+    /// Describe a run tagging `in_paths` with `config`, started with the arguments `options`.
+    /// Reads the inputs to hash them.
+    pub fn new(
+        config: &Config,
+        options: &[String],
+        in_paths: &[PathBuf],
+    ) -> anyhow::Result<RunMetadata> {
+        let inputs = in_paths
+            .iter()
+            .map(|path| {
+                Ok(InputDigest {
+                    path: path.to_string_lossy().into_owned(),
+                    sha256: sha256_file(path)
+                        .with_context(|| format!("Could not hash {}", path.display()))?,
+                })
+            })
+            .collect::<anyhow::Result<Vec<InputDigest>>>()?;
+        Ok(RunMetadata {
+            generator: format!("berttagr {}", env!("CARGO_PKG_VERSION")),
+            model: model_name(config),
+            options: options.to_vec(),
+            created: rfc3339(SystemTime::now()),
+            inputs,
+        })
+    }
+
+    /// `(key, value)` pairs written as comment lines by the text formats
+    pub fn comments(&self) -> Vec<(&'static str, String)> {
+        let mut comments = vec![
+            ("generator", self.generator.clone()),
+            ("model", self.model.clone()),
+            ("options", self.options.join(" ")),
+            ("created", self.created.clone()),
+        ];
+        comments.extend(
+            self.inputs
+                .iter()
+                .map(|input| ("input", format!("{} sha256:{}", input.path, input.sha256))),
+        );
+        comments
+    }
+}
+
+/// Model loaded by `config`, as it was selected
+pub fn model_name(config: &Config) -> String {
+    if let Some(name) = &config.model {
+        name.clone()
+    } else if let Some(hub_model) = &config.hub_model {
+        format!("hf:{}", hub_model)
+    } else if let Some(model_dir) = &config.model_dir {
+        let model_type = config.model_type.as_deref().unwrap_or("mobilebert");
+        format!("{} ({})", model_dir.display(), model_type)
+    } else {
+        String::from("en-pos-mobilebert")
+    }
+}
+
+/// Hexadecimal SHA-256 digest of the file at `path`
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// `time` as `YYYY-MM-DDTHH:MM:SSZ`
+fn rfc3339(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+    //civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let time = UNIX_EPOCH + Duration::from_secs(951_827_696);
+        assert_eq!(rfc3339(time), "2000-02-29T12:34:56Z");
+    }
+}
@@ -0,0 +1,272 @@
+//! # Document metrics
+//! Part-of-speech based measures of the `metrics` subcommand, per file and for the whole
+//! corpus: lexical density (content words among words), noun/verb ratio, average sentence
+//! length, and the Flesch reading ease and Flesch-Kincaid grade estimated with a syllable
+//! count heuristic. Written as CSV, one row per file and a last `TOTAL` row, or as JSON.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde_json::json;
+
+use crate::config::Config;
+use crate::confusion::csv_field;
+use crate::pos_tagging::POSTag;
+use crate::tagger::{for_each_sentence, Tagger};
+use crate::tagset::to_upos;
+use crate::warnings::WarningSink;
+
+/// Name of the corpus row in the CSV output
+pub const TOTAL: &str = "TOTAL";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+/// # Counts of a tagged text
+pub struct DocumentMetrics {
+    pub sentences: usize,
+    /// Tokens that are not punctuation or symbols
+    pub words: usize,
+    /// Nouns, proper nouns, verbs, adjectives and adverbs
+    pub content_words: usize,
+    /// Nouns and proper nouns
+    pub nouns: usize,
+    pub verbs: usize,
+    /// Estimated syllables of the words
+    pub syllables: usize,
+}
+
+impl DocumentMetrics {
+    /// Count the words of tagged sentences
+    pub fn from_sentences(sentences: &[Vec<POSTag>]) -> DocumentMetrics {
+        let mut metrics = DocumentMetrics {
+            sentences: sentences.len(),
+            ..DocumentMetrics::default()
+        };
+        for tag in sentences.iter().flatten() {
+            let upos = to_upos(&tag.label);
+            if upos == "PUNCT" || upos == "SYM" {
+                continue;
+            }
+            metrics.words += 1;
+            metrics.syllables += syllables(&tag.word);
+            match upos {
+                "NOUN" | "PROPN" => metrics.nouns += 1,
+                "VERB" => metrics.verbs += 1,
+                _ => {}
+            }
+            if ["NOUN", "PROPN", "VERB", "ADJ", "ADV"].contains(&upos) {
+                metrics.content_words += 1;
+            }
+        }
+        metrics
+    }
+
+    /// Add the counts of another text
+    pub fn add(&mut self, other: &DocumentMetrics) {
+        self.sentences += other.sentences;
+        self.words += other.words;
+        self.content_words += other.content_words;
+        self.nouns += other.nouns;
+        self.verbs += other.verbs;
+        self.syllables += other.syllables;
+    }
+
+    /// Fraction of the words that are content words
+    pub fn lexical_density(&self) -> f64 {
+        ratio(self.content_words, self.words)
+    }
+
+    pub fn noun_verb_ratio(&self) -> f64 {
+        ratio(self.nouns, self.verbs)
+    }
+
+    /// Words per sentence
+    pub fn average_sentence_length(&self) -> f64 {
+        ratio(self.words, self.sentences)
+    }
+
+    fn syllables_per_word(&self) -> f64 {
+        ratio(self.syllables, self.words)
+    }
+
+    /// Flesch reading ease, from about 0 (very difficult) to 100 (very easy)
+    pub fn flesch_reading_ease(&self) -> f64 {
+        206.835 - 1.015 * self.average_sentence_length() - 84.6 * self.syllables_per_word()
+    }
+
+    /// Flesch-Kincaid grade level, the US school grade able to read the text
+    pub fn flesch_kincaid_grade(&self) -> f64 {
+        0.39 * self.average_sentence_length() + 11.8 * self.syllables_per_word() - 15.59
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "sentences": self.sentences,
+            "words": self.words,
+            "lexical_density": self.lexical_density(),
+            "noun_verb_ratio": self.noun_verb_ratio(),
+            "average_sentence_length": self.average_sentence_length(),
+            "flesch_reading_ease": self.flesch_reading_ease(),
+            "flesch_kincaid_grade": self.flesch_kincaid_grade(),
+        })
+    }
+}
+
+/// `a / b`, 0 when `b` is 0
+fn ratio(a: usize, b: usize) -> f64 {
+    if b == 0 {
+        0.0
+    } else {
+        a as f64 / b as f64
+    }
+}
+
+/// Estimated number of syllables of `word`: groups of vowels, not counting a final silent
+/// `e`, at least one
+pub fn syllables(word: &str) -> usize {
+    let lower = word.to_lowercase();
+    let is_vowel = |c: char| "aeiouy".contains(c);
+    let mut count = 0;
+    let mut previous_vowel = false;
+    for c in lower.chars() {
+        let vowel = is_vowel(c);
+        if vowel && !previous_vowel {
+            count += 1;
+        }
+        previous_vowel = vowel;
+    }
+    if count > 1 && lower.ends_with('e') && !lower.ends_with("le") {
+        count -= 1;
+    }
+    count.max(1)
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+/// # Metrics of each file and of the corpus
+pub struct MetricsReport {
+    pub files: Vec<(PathBuf, DocumentMetrics)>,
+    pub corpus: DocumentMetrics,
+}
+
+impl MetricsReport {
+    pub fn add_file(&mut self, path: PathBuf, metrics: DocumentMetrics) {
+        self.corpus.add(&metrics);
+        self.files.push((path, metrics));
+    }
+
+    /// Write one row per file and a `TOTAL` row
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "file,sentences,words,lexical_density,noun_verb_ratio,average_sentence_length,flesch_reading_ease,flesch_kincaid_grade"
+        )?;
+        let rows = self
+            .files
+            .iter()
+            .map(|(path, metrics)| (path.to_string_lossy(), metrics))
+            .chain(std::iter::once((TOTAL.into(), &self.corpus)));
+        for (name, metrics) in rows {
+            writeln!(
+                writer,
+                "{},{},{},{:.4},{:.4},{:.2},{:.2},{:.2}",
+                csv_field(&name),
+                metrics.sentences,
+                metrics.words,
+                metrics.lexical_density(),
+                metrics.noun_verb_ratio(),
+                metrics.average_sentence_length(),
+                metrics.flesch_reading_ease(),
+                metrics.flesch_kincaid_grade()
+            )?;
+        }
+        writer.flush()
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let files: Vec<serde_json::Value> = self
+            .files
+            .iter()
+            .map(|(path, metrics)| {
+                let mut value = metrics.to_json();
+                value["file"] = path.to_string_lossy().into();
+                value
+            })
+            .collect();
+        json!({ "files": files, "corpus": self.corpus.to_json() })
+    }
+}
+
+/// Tag each file of `paths` with the configured tagger and measure it
+pub fn metrics_files(paths: &[PathBuf], config: &Config, warnings: &dyn WarningSink) -> anyhow::Result<MetricsReport> {
+    let tagger = config.tagger(warnings)?;
+    let mut report = MetricsReport::default();
+    for path in paths {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        report.add_file(path.clone(), metrics_text(&*tagger, &contents, config.batch_size()));
+    }
+    Ok(report)
+}
+
+/// Tag `text` with `tagger` in batches of `batch_size` sentences and measure it
+pub fn metrics_text(tagger: &dyn Tagger, text: &str, batch_size: usize) -> DocumentMetrics {
+    let mut metrics = DocumentMetrics::default();
+    for_each_sentence(tagger, text, batch_size, |sentence| {
+        metrics.add(&DocumentMetrics::from_sentences(&[sentence]));
+    });
+    metrics
+}
+
+/// Write `report` to `path`, as JSON if `json` is set and as CSV otherwise
+pub fn write_report(report: &MetricsReport, path: &Path, json: bool) -> anyhow::Result<()> {
+    let file = fs::File::create(path)
+        .with_context(|| format!("Could not create {}", path.display()))?;
+    let mut writer = io::BufWriter::new(file);
+    if json {
+        serde_json::to_writer_pretty(&mut writer, &report.to_json())?;
+        writeln!(writer)?;
+        writer.flush()?;
+    } else {
+        report.write_csv(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sentence(tags: &[(&str, &str)]) -> Vec<POSTag> {
+        tags.iter()
+            .map(|(word, label)| POSTag {
+                word: word.to_string(),
+                label: label.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn measures_tagged_sentences() {
+        let sentences = [
+            sentence(&[("The", "DT"), ("cat", "NN"), ("sleeps", "VBZ"), (".", ".")]),
+            sentence(&[("Dogs", "NNS"), ("bark", "VBP"), ("loudly", "RB"), ("!", ".")]),
+        ];
+        let metrics = DocumentMetrics::from_sentences(&sentences);
+        assert_eq!(metrics.words, 6);
+        assert_eq!(metrics.content_words, 5);
+        assert_eq!(metrics.noun_verb_ratio(), 1.0);
+        assert_eq!(metrics.average_sentence_length(), 3.0);
+        assert_eq!(syllables("loudly"), 2);
+        assert_eq!(syllables("table"), 2);
+        assert_eq!(syllables("sleeps"), 1);
+
+        let mut report = MetricsReport::default();
+        report.add_file(PathBuf::from("a,b.txt"), metrics);
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.lines().nth(1).unwrap().starts_with("\"a,b.txt\",2,6,0.8333,1.0000,3.00,"));
+        assert!(csv.lines().nth(2).unwrap().starts_with("TOTAL,2,6,"));
+    }
+}
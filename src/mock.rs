@@ -0,0 +1,201 @@
+//! # Mock tagger for tests
+//! A `Tagger` returning canned tags for known inputs and labelling other words with a few
+//! spelling rules and a small lexicon of function words. It stands in for the model where
+//! the output only has to be deterministic, so that applications embedding the crate can
+//! unit-test their pipelines without downloading MobileBERT or needing a GPU:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! berttagr = { version = "0.1", features = ["test-util"] }
+//! ```
+//!
+//! ```
+//! use rustlib::mock::MockTagger;
+//! use rustlib::tagger::Tagger;
+//!
+//! let tagger = MockTagger::new()
+//!     .with_response("Time flies.", &[("Time", "NN"), ("flies", "VBZ"), (".", ".")])
+//!     .with_word("Amy", "NNP-PERSON");
+//! assert_eq!(tagger.tag(&["Time flies."])[0][1].tag.label, "VBZ");
+//! assert_eq!(tagger.tag(&["Amy sings."])[0][0].tag.label, "NNP-PERSON");
+//! ```
+//!
+//! The golden-file tests of the command line run on it too: the CLI uses it when the
+//! `BERTTAGR_MOCK_TAGGER` environment variable is set.
+
+use std::collections::HashMap;
+
+use crate::pos_tagging::{POSTag, ScoredTag};
+use crate::tagger::Tagger;
+
+/// Environment variable making the CLI tag with `MockTagger`
+pub const MOCK_TAGGER_VAR: &str = "BERTTAGR_MOCK_TAGGER";
+
+/// Function words and their labels
+const LEXICON: &[(&str, &str)] = &[
+    ("a", "DT"),
+    ("an", "DT"),
+    ("the", "DT"),
+    ("and", "CC"),
+    ("or", "CC"),
+    ("but", "CC"),
+    ("at", "IN"),
+    ("in", "IN"),
+    ("of", "IN"),
+    ("on", "IN"),
+    ("i", "PRP"),
+    ("it", "PRP"),
+    ("he", "PRP"),
+    ("she", "PRP"),
+    ("they", "PRP"),
+    ("is", "VBZ"),
+    ("are", "VBP"),
+    ("was", "VBD"),
+    ("then", "RB"),
+];
+
+#[derive(Debug, Default, Clone, PartialEq)]
+/// # Deterministic tagger returning canned or rule-based tags
+pub struct MockTagger {
+    /// Tags returned for an exact input text
+    responses: HashMap<String, Vec<(String, String)>>,
+    /// Labels of words, taking precedence over the rules
+    words: HashMap<String, String>,
+}
+
+impl MockTagger {
+    /// Tagger labelling every word by the rules
+    pub fn new() -> MockTagger {
+        MockTagger::default()
+    }
+
+    /// Return `tags`, as `(word, label)` pairs, when the input text is exactly `text`
+    pub fn with_response(mut self, text: &str, tags: &[(&str, &str)]) -> MockTagger {
+        let tags = tags
+            .iter()
+            .map(|&(word, label)| (word.to_owned(), label.to_owned()))
+            .collect();
+        self.responses.insert(text.to_owned(), tags);
+        self
+    }
+
+    /// Label `word`, matched exactly, with `label` instead of the rules
+    pub fn with_word(mut self, word: &str, label: &str) -> MockTagger {
+        self.words.insert(word.to_owned(), label.to_owned());
+        self
+    }
+
+    /// Label of `word`: its own one if set with `with_word`, otherwise by the rules
+    pub fn label<'a>(&'a self, word: &str) -> &'a str {
+        match self.words.get(word) {
+            Some(label) => label,
+            None => rule_label(word),
+        }
+    }
+
+    fn tag_text(&self, text: &str) -> Vec<ScoredTag> {
+        let tags: Vec<(&str, &str)> = match self.responses.get(text) {
+            Some(tags) => tags.iter().map(|(word, label)| (word.as_str(), label.as_str())).collect(),
+            None => words(text).into_iter().map(|word| (word, self.label(word))).collect(),
+        };
+        tags.into_iter()
+            .map(|(word, label)| ScoredTag {
+                tag: POSTag {
+                    word: word.to_owned(),
+                    label: label.to_owned(),
+                },
+                score: 1.0,
+            })
+            .collect()
+    }
+}
+
+/// Label of `word` by the spelling rules and the lexicon of function words
+pub fn rule_label(word: &str) -> &'static str {
+    let lower = word.to_lowercase();
+    if let Some((_, label)) = LEXICON.iter().find(|(entry, _)| *entry == lower) {
+        return label;
+    }
+    let first = word.chars().next().unwrap_or(' ');
+    if word.chars().all(|c| c.is_ascii_punctuation()) {
+        match word {
+            "." | "!" | "?" => ".",
+            "," => ",",
+            _ => ":",
+        }
+    } else if word.chars().all(|c| c.is_ascii_digit()) {
+        "CD"
+    } else if first.is_uppercase() {
+        "NNP"
+    } else if lower.ends_with("ly") {
+        "RB"
+    } else if lower.ends_with("ing") {
+        "VBG"
+    } else if lower.ends_with("ed") {
+        "VBD"
+    } else if lower.ends_with('s') && lower.len() > 3 {
+        "NNS"
+    } else {
+        "NN"
+    }
+}
+
+/// Words of `text`: whitespace-separated, with each leading and trailing punctuation
+/// character as a word of its own
+pub fn words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    for word in text.split_whitespace() {
+        let core = word.trim_matches(|c: char| c.is_ascii_punctuation());
+        if core.is_empty() {
+            words.extend(word.char_indices().map(|(index, c)| &word[index..index + c.len_utf8()]));
+            continue;
+        }
+        let start = word.find(core).expect("trimmed from the word");
+        let end = start + core.len();
+        words.extend((0..start).map(|index| &word[index..index + 1]));
+        words.push(core);
+        words.extend((end..word.len()).map(|index| &word[index..index + 1]));
+    }
+    words
+}
+
+impl Tagger for MockTagger {
+    fn tag(&self, input: &[&str]) -> Vec<Vec<ScoredTag>> {
+        input.iter().map(|text| self.tag_text(text)).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn canned_responses_take_precedence_over_rules() {
+        let tagger = MockTagger::new()
+            .with_response("Time flies.", &[("Time flies", "NN"), (".", ".")])
+            .with_word("dogs", "NNS-ANIMAL");
+        let tagged = tagger.tag(&["Time flies.", "The dogs barked loudly, twice!"]);
+        let labels: Vec<Vec<(&str, &str)>> = tagged
+            .iter()
+            .map(|sentence| {
+                sentence
+                    .iter()
+                    .map(|t| (t.tag.word.as_str(), t.tag.label.as_str()))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(labels[0], vec![("Time flies", "NN"), (".", ".")]);
+        assert_eq!(
+            labels[1],
+            vec![
+                ("The", "DT"),
+                ("dogs", "NNS-ANIMAL"),
+                ("barked", "VBD"),
+                ("loudly", "RB"),
+                (",", ","),
+                ("twice", "NN"),
+                ("!", "."),
+            ]
+        );
+    }
+}
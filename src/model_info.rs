@@ -0,0 +1,173 @@
+//! # Model provenance
+//! `berttagr_file model-info` describes the model the settings select, to check what a run
+//! would load or to record it next to a corpus: its architecture and size, its labels, and
+//! where each of its files comes from, with their SHA-256 digests. With `--format json` the
+//! description is printed as JSON.
+//!
+//! The remote files are downloaded first, as for a run, and the weights are read to count
+//! their parameters.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use rust_bert::resources::Resource;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::metadata::{model_name, sha256_file};
+use crate::pos_tagging::read_labels;
+use crate::warnings::WarningSink;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// # Description of a model
+pub struct ModelInfo {
+    /// Registry name, Hub repository or directory of the model
+    pub model: String,
+    /// rust-bert model type
+    pub model_type: String,
+    /// `architectures` of `config.json`
+    pub architectures: Vec<String>,
+    pub hidden_size: Option<u64>,
+    pub layers: Option<u64>,
+    /// Number of weights of the model
+    pub parameters: usize,
+    /// Labels of the output layer, by index
+    pub labels: Vec<String>,
+    pub files: Vec<FileInfo>,
+    /// Directory the remote files are cached in
+    pub cache: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// # File of a model
+pub struct FileInfo {
+    /// `model`, `config`, `vocab` or `merges`
+    pub kind: &'static str,
+    /// URL or path the file was selected with
+    pub source: String,
+    /// Path of the file, in the cache if downloaded
+    pub path: String,
+    pub sha256: String,
+    /// Whether the digest matches the pinned one, if pinned
+    pub verified: Option<bool>,
+}
+
+impl ModelInfo {
+    /// Describe the model selected by `config`, downloading its remote files
+    pub fn new(config: &Config, warnings: &dyn WarningSink) -> anyhow::Result<ModelInfo> {
+        let remote = config.remote_pos_config()?;
+        let local = config.pos_config(warnings)?;
+        let checksums = config.checksums()?.unwrap_or_default();
+        let remote = remote.token_classification_config();
+        let local = local.token_classification_config();
+
+        let resources = [
+            ("model", Some(&remote.model_resource), Some(&local.model_resource), &checksums.model),
+            ("config", Some(&remote.config_resource), Some(&local.config_resource), &checksums.config),
+            ("vocab", Some(&remote.vocab_resource), Some(&local.vocab_resource), &checksums.vocab),
+            ("merges", remote.merges_resource.as_ref(), local.merges_resource.as_ref(), &checksums.merges),
+        ];
+        let mut files = Vec::new();
+        for (kind, source, resource, pinned) in resources.iter() {
+            let (source, resource) = match (source, resource) {
+                (Some(source), Some(resource)) => (source, resource),
+                _ => continue,
+            };
+            let path = resource.get_local_path()?;
+            let sha256 = sha256_file(&path).with_context(|| format!("Could not hash {}", path.display()))?;
+            files.push(FileInfo {
+                kind: *kind,
+                source: location(source),
+                path: path.to_string_lossy().into_owned(),
+                verified: pinned.as_ref().map(|pinned| pinned.trim().eq_ignore_ascii_case(&sha256)),
+                sha256,
+            });
+        }
+
+        let config_path = local.config_resource.get_local_path()?;
+        let model_config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&config_path)?)
+            .with_context(|| format!("Invalid model configuration {}", config_path.display()))?;
+        let architectures = model_config
+            .get("architectures")
+            .and_then(serde_json::Value::as_array)
+            .map(|names| names.iter().filter_map(|name| name.as_str().map(str::to_owned)).collect())
+            .unwrap_or_default();
+        let number = |key: &str| model_config.get(key).and_then(serde_json::Value::as_u64);
+        let weights_path = local.model_resource.get_local_path()?;
+        Ok(ModelInfo {
+            model: model_name(config),
+            model_type: format!("{:?}", local.model_type),
+            architectures,
+            hidden_size: number("hidden_size").or_else(|| number("dim")),
+            layers: number("num_hidden_layers").or_else(|| number("n_layers")),
+            parameters: count_parameters(&weights_path)?,
+            labels: read_labels(&config_path)?,
+            files,
+            cache: cache_dir(),
+        })
+    }
+}
+
+impl fmt::Display for ModelInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "model:         {}", self.model)?;
+        writeln!(f, "type:          {}", self.model_type)?;
+        if !self.architectures.is_empty() {
+            writeln!(f, "architecture:  {}", self.architectures.join(", "))?;
+        }
+        if let Some(hidden_size) = self.hidden_size {
+            writeln!(f, "hidden size:   {}", hidden_size)?;
+        }
+        if let Some(layers) = self.layers {
+            writeln!(f, "layers:        {}", layers)?;
+        }
+        writeln!(f, "parameters:    {}", self.parameters)?;
+        writeln!(f, "labels:        {} ({})", self.labels.join(" "), self.labels.len())?;
+        if let Some(cache) = &self.cache {
+            writeln!(f, "cache:         {}", cache)?;
+        }
+        for file in &self.files {
+            writeln!(f, "{}:", file.kind)?;
+            writeln!(f, "  source:      {}", file.source)?;
+            if file.path != file.source {
+                writeln!(f, "  path:        {}", file.path)?;
+            }
+            let verified = match file.verified {
+                Some(true) => " (verified)",
+                Some(false) => " (does not match the pinned digest)",
+                None => "",
+            };
+            writeln!(f, "  sha256:      {}{}", file.sha256, verified)?;
+        }
+        Ok(())
+    }
+}
+
+/// URL or path of `resource`
+fn location(resource: &Resource) -> String {
+    match resource {
+        Resource::Local(local) => local.local_path.to_string_lossy().into_owned(),
+        Resource::Remote(remote) => remote.url.clone(),
+    }
+}
+
+/// Number of weights in the file at `weights_path`
+fn count_parameters(weights_path: &Path) -> anyhow::Result<usize> {
+    let tensors = tch::Tensor::load_multi(weights_path)
+        .with_context(|| format!("Could not read the weights {}", weights_path.display()))?;
+    Ok(tensors.iter().map(|(_, tensor)| tensor.numel()).sum())
+}
+
+#[cfg(feature = "remote-models")]
+fn cache_dir() -> Option<String> {
+    crate::download::cache_dir()
+        .ok()
+        .map(|dir| dir.to_string_lossy().into_owned())
+}
+
+#[cfg(not(feature = "remote-models"))]
+fn cache_dir() -> Option<String> {
+    None
+}
@@ -0,0 +1,271 @@
+//! # Model registry
+//! Named models selectable with `--model NAME`, each wiring the resources of a checkpoint
+//! and its default settings in one place. The registry bundled with the crate
+//! (`src/models.toml`) is extended by a `models.toml` file next to the configuration file:
+//!
+//! ```toml
+//! [models.de-pos-xlmr]
+//! description = "German part-of-speech tagging"
+//! model_type = "xlm-roberta"
+//! model_dir = "models/xlm-roberta-german-pos"
+//!
+//! [models.en-pos-custom]
+//! model = "https://example.com/en-pos/rust_model.ot"
+//! config = "https://example.com/en-pos/config.json"
+//! vocab = "https://example.com/en-pos/vocab.txt"
+//! lower_case = false
+//!
+//! [models.en-pos-custom.sha256]
+//! model = "3b8c1f..."
+//! ```
+//!
+//! Relative paths are resolved from the directory of the registry file. URLs are downloaded
+//! to the rust-bert cache on first use, which requires the `remote-models` feature. The
+//! `sha256` digests pin the files of the entry, see `checksums`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context};
+use rust_bert::bert::{BertConfigResources, BertModelResources, BertVocabResources};
+use rust_bert::pipelines::common::ModelType;
+use rust_bert::resources::{LocalResource, RemoteResource, Resource};
+use rust_bert::xlm_roberta::{
+    XLMRobertaConfigResources, XLMRobertaModelResources, XLMRobertaVocabResources,
+};
+use serde::Deserialize;
+
+use crate::checksums::Checksums;
+use crate::config::Config;
+use crate::pos_tagging::{parse_model_type, LabelAggregation, POSConfig};
+
+/// Registry bundled with the crate
+pub const BUNDLED: &str = include_str!("models.toml");
+
+/// Name of the user registry, looked up next to the configuration file
+pub const REGISTRY_FILE_NAME: &str = "models.toml";
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// # Named models
+pub struct ModelRegistry {
+    #[serde(default)]
+    pub models: BTreeMap<String, ModelEntry>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// # Resources and default settings of a named model
+pub struct ModelEntry {
+    pub description: Option<String>,
+    /// Model known to rust-bert: `mobilebert-english-pos`, `bert-ner` or `xlm-roberta-ner-de`
+    pub pretrained: Option<String>,
+    /// Directory holding `rust_model.ot`, `config.json` and the vocabulary
+    pub model_dir: Option<PathBuf>,
+    /// URL or path of the weights
+    pub model: Option<String>,
+    /// URL or path of `config.json`
+    pub config: Option<String>,
+    /// URL or path of the vocabulary
+    pub vocab: Option<String>,
+    /// URL or path of the merges, for RoBERTa models
+    pub merges: Option<String>,
+    pub model_type: Option<String>,
+    pub lower_case: Option<bool>,
+    pub strip_accents: Option<bool>,
+    pub label_aggregation: Option<LabelAggregation>,
+    /// Expected digests of the files, unless the configuration pins its own
+    pub sha256: Option<Checksums>,
+}
+
+impl ModelRegistry {
+    /// The registry bundled with the crate
+    pub fn bundled() -> ModelRegistry {
+        toml::from_str(BUNDLED).expect("the bundled registry is valid")
+    }
+
+    /// Read a registry file, resolving relative paths from its directory
+    pub fn from_file(path: &Path) -> anyhow::Result<ModelRegistry> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read model registry {}", path.display()))?;
+        let mut registry: ModelRegistry = toml::from_str(&contents)
+            .with_context(|| format!("Invalid model registry {}", path.display()))?;
+        if let Some(base) = path.parent() {
+            for entry in registry.models.values_mut() {
+                entry.resolve_paths(base);
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Default location of the user registry, next to the configuration file
+    pub fn user_path() -> Option<PathBuf> {
+        Config::default_path().map(|path| path.with_file_name(REGISTRY_FILE_NAME))
+    }
+
+    /// The bundled registry extended by the user registry if it exists
+    pub fn load() -> anyhow::Result<ModelRegistry> {
+        let mut registry = ModelRegistry::bundled();
+        if let Some(path) = ModelRegistry::user_path() {
+            if path.is_file() {
+                registry.extend(ModelRegistry::from_file(&path)?);
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Add the entries of `other`, replacing those with the same name
+    pub fn extend(&mut self, other: ModelRegistry) {
+        self.models.extend(other.models);
+    }
+
+    /// The entry named `name`
+    pub fn get(&self, name: &str) -> anyhow::Result<&ModelEntry> {
+        self.models.get(name).ok_or_else(|| {
+            let names: Vec<&str> = self.models.keys().map(String::as_str).collect();
+            anyhow!("Unknown model '{}', expected one of {}", name, names.join(", "))
+        })
+    }
+}
+
+impl ModelEntry {
+    /// The `POSConfig` loading this model, named `name` in the registry
+    pub fn pos_config(&self, name: &str) -> anyhow::Result<POSConfig> {
+        let mut pos_config = POSConfig::default();
+        if let Some(pretrained) = &self.pretrained {
+            pos_config = pretrained_config(pretrained)?;
+        }
+        if let Some(model_type) = &self.model_type {
+            pos_config = pos_config.model_type(parse_model_type(model_type)?);
+        }
+        if let Some(model_dir) = &self.model_dir {
+            pos_config = pos_config.local_model(model_dir);
+        }
+        let config = pos_config.token_classification_config_mut();
+        if let Some(model) = &self.model {
+            config.model_resource = resource(name, "model", model)?;
+        }
+        if let Some(config_file) = &self.config {
+            config.config_resource = resource(name, "config", config_file)?;
+        }
+        if let Some(vocab) = &self.vocab {
+            config.vocab_resource = resource(name, "vocab", vocab)?;
+        }
+        if let Some(merges) = &self.merges {
+            config.merges_resource = Some(resource(name, "merges", merges)?);
+        }
+        if let Some(lower_case) = self.lower_case {
+            pos_config = pos_config.lower_case(lower_case);
+        }
+        if let Some(strip_accents) = self.strip_accents {
+            pos_config = pos_config.strip_accents(strip_accents);
+        }
+        if let Some(aggregation) = self.label_aggregation {
+            pos_config = pos_config.label_aggregation(aggregation);
+        }
+        Ok(pos_config)
+    }
+
+    fn resolve_paths(&mut self, base: &Path) {
+        if let Some(model_dir) = &mut self.model_dir {
+            *model_dir = base.join(&*model_dir);
+        }
+        let locations = [&mut self.model, &mut self.config, &mut self.vocab, &mut self.merges];
+        for location in locations.iter_mut() {
+            if let Some(path) = location {
+                if !is_url(path) {
+                    *path = base.join(&*path).to_string_lossy().into_owned();
+                }
+            }
+        }
+    }
+}
+
+fn is_url(location: &str) -> bool {
+    location.starts_with("https://") || location.starts_with("http://")
+}
+
+/// Resource at `location`, a URL cached under `name/kind` or a path
+fn resource(name: &str, kind: &str, location: &str) -> anyhow::Result<Resource> {
+    if !is_url(location) {
+        return Ok(Resource::Local(LocalResource {
+            local_path: PathBuf::from(location),
+        }));
+    }
+    if !cfg!(feature = "remote-models") {
+        bail!("Model '{}' is downloaded, which requires the remote-models feature", name);
+    }
+    let cache_subdir = format!("{}/{}", name, kind);
+    Ok(Resource::Remote(RemoteResource::from_pretrained((
+        cache_subdir.as_str(),
+        location,
+    ))))
+}
+
+/// Configuration of a model known to rust-bert
+fn pretrained_config(name: &str) -> anyhow::Result<POSConfig> {
+    if !cfg!(feature = "remote-models") {
+        bail!("Pretrained model '{}' requires the remote-models feature", name);
+    }
+    let remote = |resource| Resource::Remote(RemoteResource::from_pretrained(resource));
+    let (model_type, resources) = match name {
+        "mobilebert-english-pos" => return Ok(POSConfig::default()),
+        "bert-ner" => (
+            ModelType::Bert,
+            [
+                BertModelResources::BERT_NER,
+                BertConfigResources::BERT_NER,
+                BertVocabResources::BERT_NER,
+            ],
+        ),
+        "xlm-roberta-ner-de" => (
+            ModelType::XLMRoberta,
+            [
+                XLMRobertaModelResources::XLM_ROBERTA_NER_DE,
+                XLMRobertaConfigResources::XLM_ROBERTA_NER_DE,
+                XLMRobertaVocabResources::XLM_ROBERTA_NER_DE,
+            ],
+        ),
+        _ => bail!(
+            "Unknown pretrained model '{}', expected one of mobilebert-english-pos, bert-ner, xlm-roberta-ner-de",
+            name
+        ),
+    };
+    //both are cased
+    let mut pos_config = POSConfig::default().model_type(model_type).lower_case(false);
+    let config = pos_config.token_classification_config_mut();
+    config.model_resource = remote(resources[0]);
+    config.config_resource = remote(resources[1]);
+    config.vocab_resource = remote(resources[2]);
+    Ok(pos_config)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn user_entries_extend_the_bundled_ones() {
+        let mut registry = ModelRegistry::bundled();
+        assert!(registry.get("en-pos-mobilebert").is_ok());
+        let mut user: ModelRegistry = toml::from_str(
+            "[models.en-ner-bert]\nmodel_dir = \"ner\"\n\n[models.de-pos-xlmr]\nmodel_type = \"xlm-roberta\"\nmodel_dir = \"/models/de\"\nvocab = \"https://example.com/vocab\"",
+        )
+        .unwrap();
+        for entry in user.models.values_mut() {
+            entry.resolve_paths(Path::new("/home/me/.config/berttagr_file"));
+        }
+        registry.extend(user);
+        let ner = registry.get("en-ner-bert").unwrap();
+        assert_eq!(ner.pretrained, None);
+        assert_eq!(
+            ner.model_dir.as_deref(),
+            Some(Path::new("/home/me/.config/berttagr_file/ner"))
+        );
+        let de = registry.get("de-pos-xlmr").unwrap();
+        assert_eq!(de.model_dir.as_deref(), Some(Path::new("/models/de")));
+        assert_eq!(de.vocab.as_deref(), Some("https://example.com/vocab"));
+        assert!(registry.get("fr-pos").is_err());
+    }
+}
@@ -0,0 +1,95 @@
+//! # Morphological features
+//! Universal Dependencies features (number, tense, person...) derived from the Penn Treebank
+//! tag predicted for a word, with word tables for pronouns and determiners whose features
+//! the tag alone does not carry. Used for the FEATS column of CoNLL-U output.
+
+/// Features of personal and possessive pronouns
+const PRONOUNS: &[(&str, &str)] = &[
+    ("i", "Case=Nom|Number=Sing|Person=1|PronType=Prs"),
+    ("me", "Case=Acc|Number=Sing|Person=1|PronType=Prs"),
+    ("we", "Case=Nom|Number=Plur|Person=1|PronType=Prs"),
+    ("us", "Case=Acc|Number=Plur|Person=1|PronType=Prs"),
+    ("you", "Person=2|PronType=Prs"),
+    ("he", "Case=Nom|Gender=Masc|Number=Sing|Person=3|PronType=Prs"),
+    ("him", "Case=Acc|Gender=Masc|Number=Sing|Person=3|PronType=Prs"),
+    ("she", "Case=Nom|Gender=Fem|Number=Sing|Person=3|PronType=Prs"),
+    ("her", "Case=Acc|Gender=Fem|Number=Sing|Person=3|PronType=Prs"),
+    ("it", "Gender=Neut|Number=Sing|Person=3|PronType=Prs"),
+    ("they", "Case=Nom|Number=Plur|Person=3|PronType=Prs"),
+    ("them", "Case=Acc|Number=Plur|Person=3|PronType=Prs"),
+    ("myself", "Number=Sing|Person=1|PronType=Prs|Reflex=Yes"),
+    ("yourself", "Number=Sing|Person=2|PronType=Prs|Reflex=Yes"),
+    ("himself", "Gender=Masc|Number=Sing|Person=3|PronType=Prs|Reflex=Yes"),
+    ("herself", "Gender=Fem|Number=Sing|Person=3|PronType=Prs|Reflex=Yes"),
+    ("itself", "Gender=Neut|Number=Sing|Person=3|PronType=Prs|Reflex=Yes"),
+    ("ourselves", "Number=Plur|Person=1|PronType=Prs|Reflex=Yes"),
+    ("themselves", "Number=Plur|Person=3|PronType=Prs|Reflex=Yes"),
+];
+
+/// Features of possessive determiners
+const POSSESSIVES: &[(&str, &str)] = &[
+    ("my", "Number=Sing|Person=1|Poss=Yes|PronType=Prs"),
+    ("our", "Number=Plur|Person=1|Poss=Yes|PronType=Prs"),
+    ("your", "Person=2|Poss=Yes|PronType=Prs"),
+    ("his", "Gender=Masc|Number=Sing|Person=3|Poss=Yes|PronType=Prs"),
+    ("her", "Gender=Fem|Number=Sing|Person=3|Poss=Yes|PronType=Prs"),
+    ("its", "Gender=Neut|Number=Sing|Person=3|Poss=Yes|PronType=Prs"),
+    ("their", "Number=Plur|Person=3|Poss=Yes|PronType=Prs"),
+];
+
+/// Features of determiners
+const DETERMINERS: &[(&str, &str)] = &[
+    ("the", "Definite=Def|PronType=Art"),
+    ("a", "Definite=Ind|PronType=Art"),
+    ("an", "Definite=Ind|PronType=Art"),
+    ("this", "Number=Sing|PronType=Dem"),
+    ("that", "Number=Sing|PronType=Dem"),
+    ("these", "Number=Plur|PronType=Dem"),
+    ("those", "Number=Plur|PronType=Dem"),
+];
+
+/// Morphological features of `word` given its Penn Treebank `label`, in the CoNLL-U FEATS
+/// syntax (`Feature=Value` pairs sorted by feature and separated by `|`), or `_` if none
+///
+/// # Example
+///
+/// ```
+/// use rustlib::morph::features;
+///
+/// assert_eq!(features("walks", "VBZ"), "Mood=Ind|Number=Sing|Person=3|Tense=Pres|VerbForm=Fin");
+/// assert_eq!(features("dogs", "NNS"), "Number=Plur");
+/// assert_eq!(features("and", "CC"), "_");
+/// ```
+pub fn features(word: &str, label: &str) -> &'static str {
+    let word = word.to_lowercase();
+    let by_word = match label {
+        "PRP" => lookup(PRONOUNS, &word),
+        "PRP$" => lookup(POSSESSIVES, &word),
+        "DT" => lookup(DETERMINERS, &word),
+        _ => None,
+    };
+    by_word.unwrap_or(match label {
+        "NN" | "NNP" => "Number=Sing",
+        "NNS" | "NNPS" => "Number=Plur",
+        "VB" => "VerbForm=Inf",
+        "VBD" => "Mood=Ind|Tense=Past|VerbForm=Fin",
+        "VBG" => "Tense=Pres|VerbForm=Part",
+        "VBN" => "Tense=Past|VerbForm=Part",
+        "VBP" => "Mood=Ind|Tense=Pres|VerbForm=Fin",
+        "VBZ" => "Mood=Ind|Number=Sing|Person=3|Tense=Pres|VerbForm=Fin",
+        "JJ" => "Degree=Pos",
+        "JJR" | "RBR" => "Degree=Cmp",
+        "JJS" | "RBS" => "Degree=Sup",
+        "CD" => "NumType=Card",
+        "WDT" | "WP" | "WRB" => "PronType=Int",
+        "WP$" => "Poss=Yes|PronType=Int",
+        _ => "_",
+    })
+}
+
+fn lookup(table: &[(&str, &'static str)], word: &str) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(form, _)| *form == word)
+        .map(|&(_, features)| features)
+}
@@ -0,0 +1,181 @@
+//! # N-gram frequencies
+//! Frequency lists of the `ngrams` subcommand: sequences of `n` words within a sentence,
+//! counted ignoring case, optionally restricted by a tag pattern such as `ADJ+NOUN` (an
+//! adjective followed by a noun). Each element of a pattern matches a label of the model
+//! (`JJ`), its universal tag (`ADJ`), or any word (`*`). Without a pattern, n-grams holding
+//! punctuation are left out.
+//!
+//! Written as CSV, most frequent first:
+//!
+//! ```text
+//! ngram,count
+//! new york,12
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+
+use crate::config::Config;
+use crate::confusion::csv_field;
+use crate::pos_tagging::POSTag;
+use crate::tagger::for_each_sentence;
+use crate::tagset::to_upos;
+use crate::warnings::WarningSink;
+
+/// Length of the n-grams when neither a length nor a pattern is given
+pub const DEFAULT_N: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// # Sequence of tags an n-gram must match
+pub struct TagPattern {
+    pub tags: Vec<String>,
+}
+
+impl FromStr for TagPattern {
+    type Err = anyhow::Error;
+
+    /// Parse tags separated by `+`
+    fn from_str(s: &str) -> Result<TagPattern, Self::Err> {
+        let tags: Vec<String> = s.split('+').map(|tag| tag.trim().to_owned()).collect();
+        if tags.iter().any(String::is_empty) {
+            bail!("Invalid tag pattern '{}', expected tags separated by + (e.g. ADJ+NOUN)", s);
+        }
+        Ok(TagPattern { tags })
+    }
+}
+
+impl TagPattern {
+    /// Whether the labels of `tags` match the pattern, element by element
+    pub fn matches(&self, tags: &[POSTag]) -> bool {
+        tags.len() == self.tags.len()
+            && self
+                .tags
+                .iter()
+                .zip(tags)
+                .all(|(pattern, tag)| pattern == "*" || *pattern == tag.label || pattern == to_upos(&tag.label))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// # Counts of the n-grams of tagged sentences
+pub struct NgramCounter {
+    pub n: usize,
+    pub pattern: Option<TagPattern>,
+    /// Count of each n-gram, as lower-cased words
+    pub counts: HashMap<Vec<String>, usize>,
+}
+
+impl NgramCounter {
+    /// Counter of the n-grams of `n` words, or of the length of `pattern` if given
+    pub fn new(n: Option<usize>, pattern: Option<TagPattern>) -> anyhow::Result<NgramCounter> {
+        let n = match (n, &pattern) {
+            (Some(n), Some(pattern)) if n != pattern.tags.len() => bail!(
+                "The n-gram length {} differs from the length of the tag pattern {}",
+                n,
+                pattern.tags.len()
+            ),
+            (_, Some(pattern)) => pattern.tags.len(),
+            (Some(n), None) => n,
+            (None, None) => DEFAULT_N,
+        };
+        if n == 0 {
+            bail!("The n-gram length must be at least 1");
+        }
+        Ok(NgramCounter {
+            n,
+            pattern,
+            counts: HashMap::new(),
+        })
+    }
+
+    /// Count the n-grams of one sentence
+    pub fn add_sentence(&mut self, sentence: &[POSTag]) {
+        for window in sentence.windows(self.n) {
+            let keep = match &self.pattern {
+                Some(pattern) => pattern.matches(window),
+                None => window.iter().all(|tag| to_upos(&tag.label) != "PUNCT"),
+            };
+            if keep {
+                let words = window.iter().map(|tag| tag.word.to_lowercase()).collect();
+                *self.counts.entry(words).or_default() += 1;
+            }
+        }
+    }
+
+    /// N-grams seen at least `min_count` times, most frequent first, then alphabetically
+    pub fn frequencies(&self, min_count: usize) -> Vec<(&[String], usize)> {
+        let mut frequencies: Vec<(&[String], usize)> = self
+            .counts
+            .iter()
+            .filter(|(_, count)| **count >= min_count)
+            .map(|(words, &count)| (words.as_slice(), count))
+            .collect();
+        frequencies.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        frequencies
+    }
+
+    /// Write the `ngram,count` table of the n-grams seen at least `min_count` times
+    pub fn write_csv<W: Write>(&self, mut writer: W, min_count: usize) -> io::Result<()> {
+        writeln!(writer, "ngram,count")?;
+        for (words, count) in self.frequencies(min_count) {
+            writeln!(writer, "{},{}", csv_field(&words.join(" ")), count)?;
+        }
+        writer.flush()
+    }
+}
+
+/// Count the n-grams of each file of `paths`, tagged with the configured tagger
+pub fn count_files(
+    paths: &[PathBuf],
+    counter: &mut NgramCounter,
+    config: &Config,
+    warnings: &dyn WarningSink,
+) -> anyhow::Result<()> {
+    let tagger = config.tagger(warnings)?;
+    for path in paths {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        for_each_sentence(&*tagger, &contents, config.batch_size(), |sentence| {
+            counter.add_sentence(&sentence)
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sentence(tags: &[(&str, &str)]) -> Vec<POSTag> {
+        tags.iter()
+            .map(|(word, label)| POSTag {
+                word: word.to_string(),
+                label: label.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn counts_ngrams_matching_a_pattern() {
+        let pattern: TagPattern = "ADJ+NN".parse().unwrap();
+        let mut counter = NgramCounter::new(None, Some(pattern)).unwrap();
+        counter.add_sentence(&sentence(&[("A", "DT"), ("big", "JJ"), ("dog", "NN"), ("barks", "VBZ")]));
+        counter.add_sentence(&sentence(&[("Big", "JJ"), ("dog", "NN"), ("!", ".")]));
+        counter.add_sentence(&sentence(&[("big", "JJ"), ("dogs", "NNS")]));
+        assert_eq!(counter.n, 2);
+        let mut csv = Vec::new();
+        counter.write_csv(&mut csv, 2).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "ngram,count\nbig dog,2\n");
+
+        let mut counter = NgramCounter::new(Some(2), None).unwrap();
+        counter.add_sentence(&sentence(&[("Big", "JJ"), ("dog", "NN"), ("!", ".")]));
+        assert_eq!(counter.counts.len(), 1);
+        assert!(NgramCounter::new(Some(3), Some("ADJ+NOUN".parse().unwrap())).is_err());
+        assert!("ADJ++NOUN".parse::<TagPattern>().is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! # Text normalization
+//! Reduces the surface noise of user-generated text before tagging: typographic quotes and
+//! dashes, characters repeated for emphasis ("soooo") and contractions, which the model
+//! tags best when split the Penn Treebank way ("do n't"). Enabled with the `normalizer`
+//! section of the configuration file or with `--normalize`, and run as a `PreProcessor`.
+//!
+//! ```toml
+//! [normalizer]
+//! contractions = "split"
+//! unicode_punctuation = true
+//! squash_repeats = true
+//! ```
+
+use serde::Deserialize;
+
+use crate::hooks::PreProcessor;
+
+/// Longest run of a repeated character kept by `squash_repeats`
+const MAX_REPEAT: usize = 2;
+
+/// Clitics split from the word they are attached to, Penn Treebank style
+const CLITICS: &[&str] = &["n't", "'s", "'re", "'ve", "'ll", "'d", "'m"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// # Handling of contractions
+pub enum ContractionMode {
+    /// Split clitics from their word: "don't" becomes "do n't"
+    Split,
+    /// Keep contractions whole
+    Keep,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+/// # Normalizer settings
+pub struct Normalizer {
+    pub contractions: ContractionMode,
+    /// Replace typographic quotes, dashes, ellipses and non-breaking spaces by ASCII ones
+    pub unicode_punctuation: bool,
+    /// Shorten runs of a repeated letter or punctuation mark to two ("soooo!!!" to "soo!!")
+    pub squash_repeats: bool,
+}
+
+impl Default for Normalizer {
+    fn default() -> Normalizer {
+        Normalizer {
+            contractions: ContractionMode::Split,
+            unicode_punctuation: true,
+            squash_repeats: true,
+        }
+    }
+}
+
+impl Normalizer {
+    /// Normalize `text` with the enabled steps
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rustlib::normalize::Normalizer;
+    ///
+    /// let normalizer = Normalizer::default();
+    /// assert_eq!(normalizer.normalize("I don’t knooooow…"), "I do n't knoow...");
+    /// ```
+    pub fn normalize(&self, text: &str) -> String {
+        //squash first so that ellipses expanded to "..." are kept
+        let mut text = if self.squash_repeats {
+            squash_repeats(text)
+        } else {
+            text.to_owned()
+        };
+        if self.unicode_punctuation {
+            text = ascii_punctuation(&text);
+        }
+        if self.contractions == ContractionMode::Split {
+            text = split_contractions(&text);
+        }
+        text
+    }
+}
+
+impl PreProcessor for Normalizer {
+    fn process(&self, text: &str) -> String {
+        self.normalize(text)
+    }
+}
+
+fn ascii_punctuation(text: &str) -> String {
+    let mut normalized = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' => normalized.push('\''),
+            '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' | '«' | '»' => {
+                normalized.push('"')
+            }
+            '\u{2010}' | '\u{2011}' | '\u{2012}' | '\u{2013}' | '\u{2014}' | '\u{2015}' | '\u{2212}' => {
+                normalized.push('-')
+            }
+            '\u{2026}' => normalized.push_str("..."),
+            '\u{00A0}' | '\u{2007}' | '\u{202F}' => normalized.push(' '),
+            _ => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+/// Digits and whitespace are left alone, numbers and layout being meaningful
+fn squash_repeats(text: &str) -> String {
+    let mut squashed = String::with_capacity(text.len());
+    let mut previous = None;
+    let mut run = 0;
+    for c in text.chars() {
+        if previous == Some(c) {
+            run += 1;
+        } else {
+            previous = Some(c);
+            run = 1;
+        }
+        if run <= MAX_REPEAT || c.is_numeric() || c.is_whitespace() {
+            squashed.push(c);
+        }
+    }
+    squashed
+}
+
+fn split_contractions(text: &str) -> String {
+    let mut split = String::with_capacity(text.len() + 8);
+    let mut word_start = None;
+    for (index, c) in text.char_indices() {
+        if c.is_alphabetic() || c == '\'' {
+            word_start.get_or_insert(index);
+        } else {
+            if let Some(start) = word_start.take() {
+                push_split_word(&mut split, &text[start..index]);
+            }
+            split.push(c);
+        }
+    }
+    if let Some(start) = word_start {
+        push_split_word(&mut split, &text[start..]);
+    }
+    split
+}
+
+fn push_split_word(split: &mut String, word: &str) {
+    for clitic in CLITICS {
+        if word.len() > clitic.len() {
+            let boundary = word.len() - clitic.len();
+            if word.is_char_boundary(boundary) && word[boundary..].eq_ignore_ascii_case(clitic) {
+                split.push_str(&word[..boundary]);
+                split.push(' ');
+                split.push_str(&word[boundary..]);
+                return;
+            }
+        }
+    }
+    split.push_str(word);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn normalizes_social_media_text() {
+        let normalizer = Normalizer::default();
+        assert_eq!(
+            normalizer.normalize("We’re sooooo happy!!!! It’s 1000 times better — isn't it?"),
+            "We 're soo happy!! It 's 1000 times better - is n't it?"
+        );
+        let keep = Normalizer {
+            contractions: ContractionMode::Keep,
+            ..Normalizer::default()
+        };
+        assert_eq!(keep.normalize("don’t"), "don't");
+    }
+}
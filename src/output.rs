@@ -0,0 +1,216 @@
+//! # Existing output files
+//! What a run does when its output file already exists, set with `output_exists` in the
+//! configuration file or with `--output-exists`:
+//!
+//! * `error` (default): stop before loading the model, leaving the file untouched
+//! * `skip`: leave the file untouched and move on, so that an interrupted run over many
+//!   files can be started again
+//! * `overwrite`: replace the file
+//! * `append`: add the new results at the end of the file
+//!
+//! With `--in-place` the input file itself is replaced by its tagged version, written to a
+//! temporary file first and renamed over the input, after copying it to a backup if a suffix
+//! is given (`--in-place=.bak`).
+//!
+//! Outputs are written to a temporary file in their destination directory and renamed to
+//! their name once the run has succeeded, so an interrupted run never leaves a truncated
+//! output that a later job could take for a complete one. Appending copies the existing
+//! output to the temporary file first.
+//!
+//! The policy applies to the main output of a run. Side files such as redaction mappings,
+//! run summaries and confusion matrices are always replaced.
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+
+use crate::warnings::{Warning, WarningSink};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// # Handling of an output file that already exists
+pub enum OutputPolicy {
+    Overwrite,
+    Skip,
+    Append,
+    Error,
+}
+
+impl Default for OutputPolicy {
+    fn default() -> OutputPolicy {
+        OutputPolicy::Error
+    }
+}
+
+impl FromStr for OutputPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<OutputPolicy, Self::Err> {
+        match s {
+            "overwrite" => Ok(OutputPolicy::Overwrite),
+            "skip" => Ok(OutputPolicy::Skip),
+            "append" => Ok(OutputPolicy::Append),
+            "error" => Ok(OutputPolicy::Error),
+            _ => Err(anyhow!(
+                "Unknown output policy '{}', expected one of overwrite, skip, append, error",
+                s
+            )),
+        }
+    }
+}
+
+impl OutputPolicy {
+    /// Whether the run writing to `path` should go ahead: false when the file exists and is
+    /// skipped, which is passed to `warnings`, an error when it exists and the policy is
+    /// `error`
+    pub fn should_write(self, path: &Path, warnings: &dyn WarningSink) -> anyhow::Result<bool> {
+        if !path.exists() {
+            return Ok(true);
+        }
+        match self {
+            OutputPolicy::Overwrite | OutputPolicy::Append => Ok(true),
+            OutputPolicy::Skip => {
+                warnings.warn(Warning::SkippedOutput {
+                    path: path.display().to_string(),
+                });
+                Ok(false)
+            }
+            OutputPolicy::Error => Err(already_exists(path)),
+        }
+    }
+
+    /// Start writing the output file at `path` under this policy, returning the file to
+    /// write to and the `PendingOutput` that puts it in place. An output created since
+    /// `should_write` is only replaced if the policy is `overwrite` or `append`.
+    pub fn begin(self, path: &Path) -> anyhow::Result<(PendingOutput, File)> {
+        let exists = path.exists();
+        if exists && (self == OutputPolicy::Skip || self == OutputPolicy::Error) {
+            return Err(already_exists(path));
+        }
+        let pending = PendingOutput {
+            path: path.to_path_buf(),
+            temp: temp_path(path),
+            committed: false,
+        };
+        if exists && self == OutputPolicy::Append {
+            fs::copy(path, &pending.temp)
+                .with_context(|| format!("Could not copy {}", path.display()))?;
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(self == OutputPolicy::Append)
+            .truncate(self != OutputPolicy::Append)
+            .open(&pending.temp)
+            .with_context(|| format!("Could not create {}", pending.temp.display()))?;
+        Ok((pending, file))
+    }
+}
+
+#[derive(Debug)]
+/// # Output file being written under a temporary name
+/// The temporary file is removed if the output is dropped without being committed.
+pub struct PendingOutput {
+    path: PathBuf,
+    temp: PathBuf,
+    committed: bool,
+}
+
+impl PendingOutput {
+    /// Flush the written file to disk and rename it to the output path
+    pub fn commit(mut self) -> anyhow::Result<()> {
+        File::open(&self.temp)
+            .and_then(|file| file.sync_all())
+            .with_context(|| format!("Could not write {}", self.temp.display()))?;
+        fs::rename(&self.temp, &self.path)
+            .with_context(|| format!("Could not rename to {}", self.path.display()))?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for PendingOutput {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// Path of a temporary file in the directory of `path`, so that it can be renamed over it
+pub fn temp_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", process::id()));
+    path.with_file_name(name)
+}
+
+/// Replace the file at `path` with `temp`, keeping a copy of the original with `backup_suffix`
+/// appended to its name when one is given. The rename is atomic, so `path` holds either the
+/// original or the new contents, even if the process is killed.
+pub fn replace(path: &Path, temp: &Path, backup_suffix: Option<&str>) -> anyhow::Result<()> {
+    if let Some(suffix) = backup_suffix {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(suffix);
+        fs::copy(path, &backup)
+            .with_context(|| format!("Could not back up {}", path.display()))?;
+    }
+    fs::rename(temp, path).with_context(|| format!("Could not replace {}", path.display()))
+}
+
+fn already_exists(path: &Path) -> anyhow::Error {
+    let err = io::Error::new(io::ErrorKind::AlreadyExists, "file exists");
+    anyhow::Error::new(err).context(format!(
+        "{} already exists, set --output-exists to overwrite, skip or append",
+        path.display()
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+    use std::sync::Mutex;
+
+    #[test]
+    fn applies_the_policy_to_existing_files() {
+        let path = std::env::temp_dir().join(format!("berttagr-output-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let write = |policy: OutputPolicy, contents: &[u8]| -> anyhow::Result<()> {
+            let (pending, mut file) = policy.begin(&path)?;
+            file.write_all(contents)?;
+            pending.commit()
+        };
+        let warnings = Mutex::new(Vec::new());
+        assert!(OutputPolicy::Error.should_write(&path, &warnings).unwrap());
+        write(OutputPolicy::Error, b"a").unwrap();
+
+        assert!(OutputPolicy::Error.should_write(&path, &warnings).is_err());
+        assert!(!OutputPolicy::Skip.should_write(&path, &warnings).unwrap());
+        assert_eq!(
+            warnings.into_inner().unwrap(),
+            vec![Warning::SkippedOutput {
+                path: path.display().to_string()
+            }]
+        );
+        assert!(write(OutputPolicy::Skip, b"b").is_err());
+        write(OutputPolicy::Append, b"b").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "ab");
+        write(OutputPolicy::Overwrite, b"c").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "c");
+
+        //an output dropped before being committed leaves the existing file untouched
+        let (pending, mut file) = OutputPolicy::Overwrite.begin(&path).unwrap();
+        file.write_all(b"partial").unwrap();
+        drop(pending);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "c");
+        assert!(!temp_path(&path).exists());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,342 @@
+//! # Processing pipelines
+//! Chains of stages (sentence splitting, tagging, lexicon overrides, filtering, output format)
+//! described in the `pipeline` section of the configuration file, so that custom processing
+//! can be set up without changing the tagging code. Without a `pipeline` section, tagging
+//! runs the default chain: split, tag, filter (with `filter_tags`) and format.
+//!
+//! ```toml
+//! [[pipeline]]
+//! stage = "split"
+//!
+//! [[pipeline]]
+//! stage = "tag"
+//!
+//! [[pipeline]]
+//! stage = "lexicon"
+//! entries = { lol = "UH", brb = "UH" }
+//!
+//! [[pipeline]]
+//! stage = "filter"
+//! tags = ["NN", "NNS", "UH"]
+//!
+//! [[pipeline]]
+//! stage = "format"
+//! format = "tsv"
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::exit;
+use crate::format::TagFormat;
+use crate::pos_tagging::{POSModel, POSTag, PredictStats};
+use crate::rusttagr::{filter_labels, tag_batched_with_stats};
+use crate::sentences;
+use crate::warnings::WarningSink;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "stage", rename_all = "lowercase", deny_unknown_fields)]
+/// # Stage of a pipeline in the configuration file
+pub enum StageConfig {
+    /// Split the text into sentences
+    Split,
+    /// Tag the sentences with the configured model
+    Tag,
+    /// Replace the label of the words found in `entries`
+    Lexicon {
+        entries: BTreeMap<String, String>,
+        /// Match the words exactly rather than ignoring case
+        #[serde(default)]
+        case_sensitive: bool,
+    },
+    /// Only keep the words whose label is in `tags`
+    Filter { tags: Vec<String> },
+    /// Output format, the configured one if not set. Must be the last stage.
+    Format { format: Option<TagFormat> },
+}
+
+/// # Text and annotations passed from stage to stage
+pub struct Document<'a> {
+    pub text: &'a str,
+    pub sentences: Vec<&'a str>,
+    /// Tags of each sentence, once tagged
+    pub tags: Vec<Vec<POSTag>>,
+    /// Statistics of the tagging stages
+    pub stats: PredictStats,
+}
+
+impl<'a> Document<'a> {
+    pub fn new(text: &'a str) -> Document<'a> {
+        Document {
+            text,
+            sentences: Vec::new(),
+            tags: Vec::new(),
+            stats: PredictStats::default(),
+        }
+    }
+}
+
+/// # Stage of a pipeline
+pub trait Stage {
+    fn run(&self, document: &mut Document) -> anyhow::Result<()>;
+}
+
+/// Splits the text into sentences
+pub struct SplitStage {
+    /// Every line is a sentence, see `Config::pretokenized_sentences`
+    pub by_line: bool,
+}
+
+impl Stage for SplitStage {
+    fn run(&self, document: &mut Document) -> anyhow::Result<()> {
+        document.sentences = if self.by_line {
+            sentences::iter_lines(document.text).collect()
+        } else {
+            sentences::split(document.text)
+        };
+        Ok(())
+    }
+}
+
+/// Tags the sentences in length-sorted batches
+pub struct TagStage {
+    pos_model: POSModel,
+    batch_size: usize,
+}
+
+impl TagStage {
+    pub fn new(pos_model: POSModel, batch_size: usize) -> TagStage {
+        TagStage {
+            pos_model,
+            batch_size,
+        }
+    }
+}
+
+impl Stage for TagStage {
+    fn run(&self, document: &mut Document) -> anyhow::Result<()> {
+        let (tags, stats) = exit::catch_inference(|| {
+            tag_batched_with_stats(&self.pos_model, &document.sentences, self.batch_size)
+        })?;
+        document.tags = tags;
+        document.stats.add(&stats);
+        Ok(())
+    }
+}
+
+/// Replaces the label of known words, e.g. for domain vocabulary the model gets wrong
+pub struct LexiconStage {
+    entries: BTreeMap<String, String>,
+    case_sensitive: bool,
+}
+
+impl LexiconStage {
+    pub fn new(entries: BTreeMap<String, String>, case_sensitive: bool) -> LexiconStage {
+        let entries = if case_sensitive {
+            entries
+        } else {
+            entries
+                .into_iter()
+                .map(|(word, label)| (word.to_lowercase(), label))
+                .collect()
+        };
+        LexiconStage {
+            entries,
+            case_sensitive,
+        }
+    }
+}
+
+impl Stage for LexiconStage {
+    fn run(&self, document: &mut Document) -> anyhow::Result<()> {
+        for pos_tag in document.tags.iter_mut().flatten() {
+            let label = if self.case_sensitive {
+                self.entries.get(&pos_tag.word)
+            } else {
+                self.entries.get(&pos_tag.word.to_lowercase())
+            };
+            if let Some(label) = label {
+                pos_tag.label = label.clone();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the words whose label is in a list
+pub struct FilterStage {
+    tags: Vec<String>,
+}
+
+impl FilterStage {
+    pub fn new(tags: Vec<String>) -> FilterStage {
+        FilterStage { tags }
+    }
+}
+
+impl Stage for FilterStage {
+    fn run(&self, document: &mut Document) -> anyhow::Result<()> {
+        for sentence in document.tags.iter_mut() {
+            filter_labels(sentence, &self.tags);
+        }
+        Ok(())
+    }
+}
+
+/// # Chain of stages ending with an output format
+pub struct Pipeline {
+    stages: Vec<Box<dyn Stage>>,
+    /// Settings of the output writer, with the format of the format stage
+    writer_config: Config,
+}
+
+impl Pipeline {
+    /// Build the pipeline described by `config`, or the default one, passing the warnings of
+    /// the model to `warnings`
+    pub fn from_config(config: &Config, warnings: &dyn WarningSink) -> anyhow::Result<Pipeline> {
+        let stage_configs = match &config.pipeline {
+            Some(stage_configs) => stage_configs.clone(),
+            None => default_stages(config),
+        };
+        validate(&stage_configs)?;
+
+        let mut writer_config = config.clone();
+        let mut stages: Vec<Box<dyn Stage>> = Vec::with_capacity(stage_configs.len());
+        for stage_config in stage_configs {
+            match stage_config {
+                StageConfig::Split => stages.push(Box::new(SplitStage {
+                    by_line: config.pretokenized_sentences.unwrap_or(false),
+                })),
+                StageConfig::Tag => {
+                    let pos_model = config.pos_model(warnings)?;
+                    stages.push(Box::new(TagStage::new(pos_model, config.batch_size())));
+                }
+                StageConfig::Lexicon {
+                    entries,
+                    case_sensitive,
+                } => stages.push(Box::new(LexiconStage::new(entries, case_sensitive))),
+                StageConfig::Filter { tags } => stages.push(Box::new(FilterStage::new(tags))),
+                StageConfig::Format { format } => {
+                    writer_config.format = format.or(writer_config.format)
+                }
+            }
+        }
+        Ok(Pipeline {
+            stages,
+            writer_config,
+        })
+    }
+
+    /// Add a custom stage at the end of the chain, before the output
+    pub fn stage<S: Stage + 'static>(mut self, stage: S) -> Pipeline {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// Run the stages on `input` and write the tagged sentences to `writer`
+    pub fn run<W: Write>(&self, input: &str, writer: W) -> anyhow::Result<PredictStats> {
+        let mut document = Document::new(input);
+        for stage in &self.stages {
+            stage.run(&mut document)?;
+        }
+        let mut tag_writer = self.writer_config.tag_writer(writer);
+        for (index, sentence) in document.tags.iter().enumerate() {
+            //stages may replace the sentences with text that is not part of the input
+            let text = document.sentences.get(index).copied();
+            match text.and_then(|text| Some((text, sentences::find_offset_in(input, text)?))) {
+                Some((text, start)) => tag_writer.write_located_sentence(text, start, sentence, None)?,
+                None => tag_writer.write_sentence(sentence)?,
+            }
+        }
+        tag_writer.finish()?;
+        Ok(document.stats)
+    }
+}
+
+/// Run the pipeline of `config` on the file at `in_path`, writing to `out_path`
+pub fn run_file(
+    in_path: &Path,
+    out_path: &Path,
+    config: &Config,
+    warnings: &dyn WarningSink,
+) -> anyhow::Result<PredictStats> {
+    let pipeline = Pipeline::from_config(config, warnings)?;
+    let contents = fs::read_to_string(in_path)
+        .with_context(|| format!("Could not read {}", in_path.display()))?;
+    let (output, file) = config.output_policy().begin(out_path)?;
+    let stats = pipeline.run(&contents, BufWriter::new(file))?;
+    output.commit()?;
+    Ok(stats)
+}
+
+fn default_stages(config: &Config) -> Vec<StageConfig> {
+    let mut stages = vec![StageConfig::Split, StageConfig::Tag];
+    if let Some(tags) = &config.filter_tags {
+        stages.push(StageConfig::Filter { tags: tags.clone() });
+    }
+    stages.push(StageConfig::Format { format: None });
+    stages
+}
+
+/// Check that sentences are split before tagging, tagged before being changed, and that
+/// the format stage comes last
+fn validate(stages: &[StageConfig]) -> anyhow::Result<()> {
+    let mut split = false;
+    let mut tagged = false;
+    for (index, stage) in stages.iter().enumerate() {
+        match stage {
+            StageConfig::Split => split = true,
+            StageConfig::Tag if !split => bail!("The tag stage must come after a split stage"),
+            StageConfig::Tag => tagged = true,
+            StageConfig::Lexicon { .. } | StageConfig::Filter { .. } if !tagged => {
+                bail!("The lexicon and filter stages must come after a tag stage")
+            }
+            StageConfig::Format { .. } if index + 1 != stages.len() => {
+                bail!("The format stage must be the last stage")
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lexicon_and_filter_stages_apply_in_order() {
+        let mut document = Document::new("lol ok");
+        document.tags = vec![vec![
+            POSTag {
+                word: String::from("LOL"),
+                label: String::from("NNP"),
+            },
+            POSTag {
+                word: String::from("ok"),
+                label: String::from("JJ"),
+            },
+        ]];
+        let entries = vec![(String::from("lol"), String::from("UH"))].into_iter().collect();
+        LexiconStage::new(entries, false).run(&mut document).unwrap();
+        FilterStage::new(vec![String::from("UH")]).run(&mut document).unwrap();
+        assert_eq!(document.tags[0].len(), 1);
+        assert_eq!(document.tags[0][0].label, "UH");
+    }
+
+    #[test]
+    fn rejects_stages_out_of_order() {
+        let stages: Config = toml::from_str(
+            "[[pipeline]]\nstage = \"tag\"\n\n[[pipeline]]\nstage = \"split\"\n",
+        )
+        .unwrap();
+        assert!(validate(&stages.pipeline.unwrap()).is_err());
+        assert!(validate(&[StageConfig::Split, StageConfig::Tag]).is_ok());
+    }
+}
@@ -0,0 +1,195 @@
+//! # Dry runs
+//! `--dry-run` checks a tagging run without running it: the inputs are read and split into
+//! sentences, the settings are validated, and the files of the model are looked up, local
+//! ones on disk and remote ones in the cache, without loading the model. The plan printed
+//! lists the inputs with their size and number of sentences, the inputs over the limits, and
+//! the files of the model with whether they are found, cached or still to be downloaded.
+//!
+//! A missing local model file stops the dry run with an error, as it would the run.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use rust_bert::resources::Resource;
+
+use crate::config::Config;
+use crate::metadata::model_name;
+use crate::records::{parse_records, InputFormat};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// # What a tagging run would do
+pub struct Plan {
+    pub inputs: Vec<InputPlan>,
+    pub output: String,
+    /// Registry name, Hub repository or directory of the model
+    pub model: String,
+    pub files: Vec<ModelFile>,
+    pub batch_size: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// # Input file of a run
+pub struct InputPlan {
+    pub path: PathBuf,
+    pub bytes: u64,
+    /// Records of the file, for the record input formats
+    pub records: Option<usize>,
+    pub sentences: usize,
+    /// Sentences above `max_sentence_length`
+    pub long_sentences: usize,
+    /// The file is above `max_file_size`
+    pub too_large: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// # File of the model and where the run would find it
+pub struct ModelFile {
+    /// `model`, `config`, `vocab` or `merges`
+    pub kind: &'static str,
+    /// URL or path
+    pub location: String,
+    /// Found on disk or in the cache, rather than downloaded at the start of the run
+    pub available: bool,
+}
+
+impl Plan {
+    /// Plan of tagging `in_paths` into `out_path` with `config`
+    pub fn new(in_paths: &[PathBuf], out_path: &str, config: &Config) -> anyhow::Result<Plan> {
+        let pos_config = config.remote_pos_config()?;
+        let model_config = pos_config.token_classification_config();
+        let resources = [
+            ("model", Some(&model_config.model_resource)),
+            ("config", Some(&model_config.config_resource)),
+            ("vocab", Some(&model_config.vocab_resource)),
+            ("merges", model_config.merges_resource.as_ref()),
+        ];
+        let mut files = Vec::new();
+        for (kind, resource) in resources.iter() {
+            let file = match resource {
+                Some(Resource::Local(local)) => {
+                    if !local.local_path.is_file() {
+                        bail!("The {} file {} does not exist", kind, local.local_path.display());
+                    }
+                    ModelFile {
+                        kind: *kind,
+                        location: local.local_path.to_string_lossy().into_owned(),
+                        available: true,
+                    }
+                }
+                Some(Resource::Remote(remote)) => ModelFile {
+                    kind: *kind,
+                    location: remote.url.clone(),
+                    available: is_cached(remote),
+                },
+                None => continue,
+            };
+            files.push(file);
+        }
+
+        let inputs = in_paths
+            .iter()
+            .map(|path| plan_input(path, config))
+            .collect::<anyhow::Result<Vec<InputPlan>>>()?;
+        Ok(Plan {
+            inputs,
+            output: out_path.to_owned(),
+            model: model_name(config),
+            files,
+            batch_size: config.batch_size(),
+        })
+    }
+
+    pub fn sentences(&self) -> usize {
+        self.inputs.iter().map(|input| input.sentences).sum()
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.inputs.iter().map(|input| input.bytes).sum()
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Dry run, nothing is tagged")?;
+        for input in &self.inputs {
+            write!(f, "In file {}: {} bytes", input.path.display(), input.bytes)?;
+            if let Some(records) = input.records {
+                write!(f, ", {} records", records)?;
+            }
+            write!(f, ", {} sentences", input.sentences)?;
+            if input.long_sentences > 0 {
+                write!(f, " ({} above the sentence limit)", input.long_sentences)?;
+            }
+            if input.too_large {
+                write!(f, ", above the file size limit")?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "Out file {}", self.output)?;
+        writeln!(f, "Model {}", self.model)?;
+        for file in &self.files {
+            let status = match (file.available, file.location.contains("://")) {
+                (true, true) => "cached",
+                (true, false) => "found",
+                (false, _) => "downloaded at the start of the run",
+            };
+            writeln!(f, "  {} {} ({})", file.kind, file.location, status)?;
+        }
+        let sentences = self.sentences();
+        write!(
+            f,
+            "{} files, {} bytes, {} sentences in about {} batches of {}",
+            self.inputs.len(),
+            self.bytes(),
+            sentences,
+            (sentences + self.batch_size - 1) / self.batch_size,
+            self.batch_size
+        )
+    }
+}
+
+/// Size and sentences of the input at `path`
+fn plan_input(path: &Path, config: &Config) -> anyhow::Result<InputPlan> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Could not read {}", path.display()))?;
+    let bytes = contents.len() as u64;
+    let format = config.input_format();
+    let texts = match format {
+        InputFormat::Text => vec![contents],
+        _ => parse_records(&contents, format, &path.to_string_lossy())
+            .with_context(|| format!("Invalid records in {}", path.display()))?
+            .into_iter()
+            .map(|record| record.text)
+            .collect(),
+    };
+    let mut sentences = 0;
+    let mut long_sentences = 0;
+    for text in &texts {
+        for sentence in config.sentences(text) {
+            sentences += 1;
+            if matches!(config.max_sentence_length, Some(max) if sentence.chars().count() > max) {
+                long_sentences += 1;
+            }
+        }
+    }
+    Ok(InputPlan {
+        path: path.to_owned(),
+        bytes,
+        records: Some(texts.len()).filter(|_| format != InputFormat::Text),
+        sentences,
+        long_sentences,
+        too_large: matches!(config.max_file_size, Some(max) if bytes > max),
+    })
+}
+
+#[cfg(feature = "remote-models")]
+fn is_cached(remote: &rust_bert::resources::RemoteResource) -> bool {
+    crate::download::cached_path(remote).is_some()
+}
+
+#[cfg(not(feature = "remote-models"))]
+fn is_cached(_remote: &rust_bert::resources::RemoteResource) -> bool {
+    false
+}
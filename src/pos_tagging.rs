@@ -15,19 +15,33 @@
 //! Mostly copied off of a lightweight pretrained model using MobileBERT 
 //! from the rust-bert library
 
+use crate::hooks::{self, PostProcessor, PreProcessor, Replacement, Replacer};
+use crate::pretokenized;
+use crate::progress::{CancellationToken, Cancelled, Progress};
+use crate::sentences;
+use crate::tagset;
+use crate::warnings::{self, Warning};
 use rust_bert::RustBertError;
 use rust_bert::mobilebert::{
     MobileBertConfigResources, MobileBertModelResources, MobileBertVocabResources,
 };
-use rust_bert::pipelines::common::ModelType;
+use rust_bert::pipelines::common::{ModelType, TokenizerOption};
 use rust_bert::pipelines::token_classification::{
-    LabelAggregationOption, TokenClassificationConfig, TokenClassificationModel,
+    LabelAggregationOption, Token, TokenClassificationConfig, TokenClassificationModel,
 };
-use rust_bert::resources::{RemoteResource, Resource};
+use rust_bert::resources::{LocalResource, RemoteResource, Resource};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 use tch::Device;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 /// # Part of Speech tag
+/// Displayed as `word/label`.
 pub struct POSTag {
     /// String representation of the word
     pub word: String,
@@ -35,35 +49,523 @@ pub struct POSTag {
     pub label: String,
 }
 
+impl fmt::Display for POSTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.word, self.label)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// # Part of Speech tags of a batch of texts
+/// Owned result of `POSModel::predict`, one sentence of tags per input text, that can be
+/// stored, compared and serialized. Displayed one sentence per line, as `word/label` pairs
+/// separated by spaces.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> anyhow::Result<()> {
+/// # use rustlib::pos_tagging::{POSModel, POSOutput};
+///
+/// let pos_model = POSModel::new(Default::default())?;
+/// let output = POSOutput::from(pos_model.predict(&["My name is Amy."]));
+/// let json = serde_json::to_string(&output)?;
+/// assert_eq!(serde_json::from_str::<POSOutput>(&json)?, output);
+/// println!("{}", output);
+/// # Ok(())
+/// # }
+/// ```
+pub struct POSOutput {
+    pub sentences: Vec<Vec<POSTag>>,
+}
+
+impl From<Vec<Vec<POSTag>>> for POSOutput {
+    fn from(sentences: Vec<Vec<POSTag>>) -> POSOutput {
+        POSOutput { sentences }
+    }
+}
+
+impl fmt::Display for POSOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for sentence in &self.sentences {
+            for (index, tag) in sentence.iter().enumerate() {
+                if index > 0 {
+                    f.write_str(" ")?;
+                }
+                write!(f, "{}", tag)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// # Part of Speech tag with the confidence of the model in its label
+/// Returned by `POSModel::predict_scored` and by `Tagger` implementations.
+pub struct ScoredTag {
+    pub tag: POSTag,
+    /// Probability of the label, between 0 and 1
+    pub score: f64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+/// # Size and timing of predictions
+/// Returned by `POSModel::predict_with_stats`, and summed over batches with `add`.
+pub struct PredictStats {
+    /// Number of input texts
+    pub sentences: usize,
+    /// Number of tags produced
+    pub tokens: usize,
+    /// Number of calls to the model
+    pub batches: usize,
+    /// Time spent in the model. rust-bert tokenizes inside its prediction call,
+    /// so tokenization is included.
+    pub inference_time: Duration,
+}
+
+impl PredictStats {
+    /// Accumulate the statistics of another prediction
+    pub fn add(&mut self, other: &PredictStats) {
+        self.sentences += other.sentences;
+        self.tokens += other.tokens;
+        self.batches += other.batches;
+        self.inference_time += other.inference_time;
+    }
+
+    /// Tokens tagged per second of inference, 0 when no time was measured
+    pub fn tokens_per_sec(&self) -> f64 {
+        let secs = self.inference_time.as_secs_f64();
+        if secs > 0.0 {
+            self.tokens as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// # Label predicted by a model
+/// Returned by `POSModel::label_set`.
+pub struct Label {
+    pub label: String,
+    /// What the label stands for, if it is a Penn Treebank label
+    pub description: Option<&'static str>,
+}
+
+#[derive(Debug)]
+/// # Part of Speech tag borrowing its word from the input text
+/// Returned by `POSModel::predict_ref`, avoids allocating a `String` per word.
+pub struct POSTagRef<'a> {
+    /// Slice of the input text covered by the word
+    pub word: &'a str,
+    /// Part-of-speech label (e.g. NN, VB...)
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+/// # Label of a word split into several sub-tokens by the tokenizer
+pub enum LabelAggregation {
+    /// Label of the first sub-token (default)
+    First,
+    /// Label of the last sub-token
+    Last,
+    /// Most frequent label of the sub-tokens
+    Mode,
+    /// Label of the sub-token the model is most confident about
+    MaxScore,
+}
+
+impl Default for LabelAggregation {
+    fn default() -> LabelAggregation {
+        LabelAggregation::First
+    }
+}
+
+impl FromStr for LabelAggregation {
+    type Err = RustBertError;
+
+    fn from_str(s: &str) -> Result<LabelAggregation, Self::Err> {
+        match s {
+            "first" => Ok(LabelAggregation::First),
+            "last" => Ok(LabelAggregation::Last),
+            "mode" => Ok(LabelAggregation::Mode),
+            "max-score" => Ok(LabelAggregation::MaxScore),
+            _ => Err(RustBertError::InvalidConfigurationError(format!(
+                "Unknown label aggregation '{}', expected one of first, last, mode, max-score",
+                s
+            ))),
+        }
+    }
+}
+
+impl From<LabelAggregation> for LabelAggregationOption {
+    fn from(aggregation: LabelAggregation) -> Self {
+        match aggregation {
+            LabelAggregation::First => LabelAggregationOption::First,
+            LabelAggregation::Last => LabelAggregationOption::Last,
+            LabelAggregation::Mode => LabelAggregationOption::Mode,
+            LabelAggregation::MaxScore => LabelAggregationOption::Custom(Box::new(
+                max_score_label as fn(&[Token]) -> (i64, String),
+            )),
+        }
+    }
+}
+
+/// Label index and label of the sub-token with the highest score
+fn max_score_label(tokens: &[Token]) -> (i64, String) {
+    tokens
+        .iter()
+        .fold(None, |best: Option<&Token>, token| match best {
+            Some(best) if best.score >= token.score => Some(best),
+            _ => Some(token),
+        })
+        .map(|token| (token.label_index, token.label.clone()))
+        .unwrap_or_default()
+}
+
 //type alias for some backward compatibility
 pub struct POSConfig {
     token_classification_config: TokenClassificationConfig,
+    cpu_threads: Option<usize>,
+    interop_threads: Option<usize>,
+    deterministic: bool,
+    seed: u64,
 }
 
+/// Directory the default model is read from in builds without the `remote-models` feature
+pub const DEFAULT_MODEL_DIR: &str = "model";
+
 impl Default for POSConfig {
-    /// Provides a Part of speech tagging model (English)
+    /// Provides a Part of speech tagging model (English), downloaded with the
+    /// `remote-models` feature and read from `DEFAULT_MODEL_DIR` otherwise
     fn default() -> POSConfig {
+        let pos_config = POSConfig::from_token_classification_config(TokenClassificationConfig {
+            model_type: ModelType::MobileBert,
+            model_resource: Resource::Remote(RemoteResource::from_pretrained(
+                MobileBertModelResources::MOBILEBERT_ENGLISH_POS,
+            )),
+            config_resource: Resource::Remote(RemoteResource::from_pretrained(
+                MobileBertConfigResources::MOBILEBERT_ENGLISH_POS,
+            )),
+            vocab_resource: Resource::Remote(RemoteResource::from_pretrained(
+                MobileBertVocabResources::MOBILEBERT_ENGLISH_POS,
+            )),
+            merges_resource: None,
+            lower_case: true,
+            //follows lower_case
+            strip_accents: None,
+            add_prefix_space: None,
+            device: default_device(),
+            label_aggregation_function: LabelAggregationOption::First,
+        });
+        if cfg!(feature = "remote-models") {
+            pos_config
+        } else {
+            pos_config.local_model(Path::new(DEFAULT_MODEL_DIR))
+        }
+    }
+}
+
+/// The first GPU if there is one and the `cuda` feature is enabled, the CPU otherwise
+pub fn default_device() -> Device {
+    if cfg!(feature = "cuda") {
+        Device::cuda_if_available()
+    } else {
+        Device::Cpu
+    }
+}
+
+impl POSConfig {
+    /// Wraps a rust-bert `TokenClassificationConfig`, for settings `POSConfig` does not
+    /// expose such as custom resources, model types or the tokenizer options. The other
+    /// settings (threads, determinism) keep their defaults.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// use rust_bert::pipelines::common::ModelType;
+    /// use rust_bert::pipelines::token_classification::{
+    ///     LabelAggregationOption, TokenClassificationConfig,
+    /// };
+    /// use rust_bert::resources::{LocalResource, Resource};
+    /// use rustlib::pos_tagging::{POSConfig, POSModel};
+    /// # use std::path::PathBuf;
+    /// let resource = |name: &str| {
+    ///     Resource::Local(LocalResource {
+    ///         local_path: PathBuf::from("bert-pos").join(name),
+    ///     })
+    /// };
+    ///
+    /// let config = TokenClassificationConfig::new(
+    ///     ModelType::Bert,
+    ///     resource("rust_model.ot"),
+    ///     resource("config.json"),
+    ///     resource("vocab.txt"),
+    ///     None,
+    ///     false,
+    ///     None,
+    ///     None,
+    ///     LabelAggregationOption::First,
+    /// );
+    /// let pos_model = POSModel::new(POSConfig::from_token_classification_config(config))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_token_classification_config(config: TokenClassificationConfig) -> POSConfig {
         POSConfig {
-            token_classification_config: TokenClassificationConfig {
-                model_type: ModelType::MobileBert,
-                model_resource: Resource::Remote(RemoteResource::from_pretrained(
-                    MobileBertModelResources::MOBILEBERT_ENGLISH_POS,
-                )),
-                config_resource: Resource::Remote(RemoteResource::from_pretrained(
-                    MobileBertConfigResources::MOBILEBERT_ENGLISH_POS,
-                )),
-                vocab_resource: Resource::Remote(RemoteResource::from_pretrained(
-                    MobileBertVocabResources::MOBILEBERT_ENGLISH_POS,
-                )),
-                merges_resource: None,
-                lower_case: true,
-                strip_accents: Some(true),
-                add_prefix_space: None,
-                device: Device::cuda_if_available(),
-                label_aggregation_function: LabelAggregationOption::First,
-            },
+            token_classification_config: config,
+            cpu_threads: None,
+            interop_threads: None,
+            deterministic: false,
+            seed: 0,
         }
     }
+
+    /// The underlying rust-bert `TokenClassificationConfig`
+    pub fn token_classification_config(&self) -> &TokenClassificationConfig {
+        &self.token_classification_config
+    }
+
+    /// The underlying rust-bert `TokenClassificationConfig`, to change any of its settings
+    pub fn token_classification_config_mut(&mut self) -> &mut TokenClassificationConfig {
+        &mut self.token_classification_config
+    }
+
+    /// Loads the model from a local directory instead of the remote MobileBERT resources.
+    /// The files expected depend on the model type, set first with `model_type`.
+    ///
+    /// # Arguments
+    ///
+    /// * `model_dir` - Directory containing `rust_model.ot`, `config.json` and the
+    /// vocabulary: `vocab.txt` for BERT models, `vocab.json` and `merges.txt` for RoBERTa,
+    /// `sentencepiece.bpe.model` for XLM-RoBERTa
+    pub fn local_model(mut self, model_dir: &Path) -> POSConfig {
+        let config = &mut self.token_classification_config;
+        let (vocab, merges) = vocabulary_files(config.model_type);
+        config.model_resource = Resource::Local(LocalResource {
+            local_path: model_dir.join("rust_model.ot"),
+        });
+        config.config_resource = Resource::Local(LocalResource {
+            local_path: model_dir.join("config.json"),
+        });
+        config.vocab_resource = Resource::Local(LocalResource {
+            local_path: model_dir.join(vocab),
+        });
+        config.merges_resource = merges.map(|merges| {
+            Resource::Local(LocalResource {
+                local_path: model_dir.join(merges),
+            })
+        });
+        self
+    }
+
+    /// Sets the architecture of the model, to load token classification checkpoints other
+    /// than MobileBERT with `local_model`. RoBERTa models are cased and tokenize words with
+    /// a leading space, as they were trained.
+    pub fn model_type(mut self, model_type: ModelType) -> POSConfig {
+        let config = &mut self.token_classification_config;
+        config.model_type = model_type;
+        if let ModelType::Roberta | ModelType::XLMRoberta = model_type {
+            config.lower_case = false;
+            config.strip_accents = None;
+            config.add_prefix_space = Some(true);
+        }
+        self
+    }
+
+    /// Sets how the label of a word split into several sub-tokens is chosen
+    pub fn label_aggregation(mut self, aggregation: LabelAggregation) -> POSConfig {
+        self.token_classification_config.label_aggregation_function = aggregation.into();
+        self
+    }
+
+    /// Sets whether the text is lower-cased before tokenization, which the default uncased
+    /// model requires. Cased models keep the capitalization that sets proper nouns apart.
+    pub fn lower_case(mut self, lower_case: bool) -> POSConfig {
+        self.token_classification_config.lower_case = lower_case;
+        self
+    }
+
+    /// Sets whether accents are removed before tokenization, by default along with
+    /// lower-casing
+    pub fn strip_accents(mut self, strip_accents: bool) -> POSConfig {
+        self.token_classification_config.strip_accents = Some(strip_accents);
+        self
+    }
+
+    /// Checks the lower-casing and accent stripping against the tokenizer the model was
+    /// trained with: the uncased vocabulary of the default model, or the
+    /// `tokenizer_config.json` of a local model when it has one. A mismatch turns words into
+    /// unknown tokens or splits them differently, degrading the tags without any error.
+    pub fn check_tokenizer(&self) -> Result<(), RustBertError> {
+        let config = &self.token_classification_config;
+        let (expected_lower_case, expected_strip_accents) = match &config.vocab_resource {
+            Resource::Local(vocab) => {
+                let tokenizer_config = vocab.local_path.with_file_name("tokenizer_config.json");
+                match fs::read_to_string(&tokenizer_config) {
+                    Ok(contents) => tokenizer_expectations(&contents)?,
+                    Err(_) => return Ok(()),
+                }
+            }
+            Resource::Remote(vocab)
+                if vocab.url == MobileBertVocabResources::MOBILEBERT_ENGLISH_POS.1 =>
+            {
+                (Some(true), None)
+            }
+            Resource::Remote(_) => return Ok(()),
+        };
+        check_casing(
+            expected_lower_case,
+            expected_strip_accents,
+            config.lower_case,
+            config.strip_accents,
+        )
+        .map_err(RustBertError::InvalidConfigurationError)
+    }
+
+    /// The tokenizer of the model, with the configured lower-casing and accent stripping.
+    /// Downloads the vocabulary if it is remote.
+    pub fn tokenizer(&self) -> Result<TokenizerOption, RustBertError> {
+        let config = &self.token_classification_config;
+        let vocab_path = config.vocab_resource.get_local_path()?;
+        let merges_path = match &config.merges_resource {
+            Some(merges) => Some(merges.get_local_path()?),
+            None => None,
+        };
+        TokenizerOption::from_file(
+            config.model_type,
+            &vocab_path.to_string_lossy(),
+            merges_path.as_ref().map(|path| path.to_string_lossy()).as_deref(),
+            config.lower_case,
+            config.strip_accents,
+            config.add_prefix_space,
+        )
+    }
+
+    /// Sets the device (CPU/GPU) the model is placed on
+    pub fn device(mut self, device: Device) -> POSConfig {
+        self.token_classification_config.device = device;
+        self
+    }
+
+    /// Sets the number of threads used within an operation on the CPU.
+    /// This is a process-wide LibTorch setting, applied when the model is built.
+    pub fn cpu_threads(mut self, threads: usize) -> POSConfig {
+        self.cpu_threads = Some(threads);
+        self
+    }
+
+    /// Sets the number of threads running independent operations in parallel on the CPU.
+    /// This is a process-wide LibTorch setting that can only be changed before the first
+    /// model runs.
+    pub fn interop_threads(mut self, threads: usize) -> POSConfig {
+        self.interop_threads = Some(threads);
+        self
+    }
+
+    /// Makes repeated runs on the CPU produce identical outputs for the same input: seeds the
+    /// LibTorch random number generators (CPU and all GPUs) and disables cuDNN benchmarking,
+    /// which picks convolution kernels by timing them. LibTorch 1.8 as bound by tch 0.4 has
+    /// no switch to its deterministic algorithms, so some CUDA kernels (atomic additions in
+    /// reductions) may still vary slightly between GPU runs. The seeds and the cuDNN setting
+    /// are global to the process: they are set by `POSModel::new`, and affect every model of
+    /// the process built before or after it.
+    pub fn deterministic(mut self, deterministic: bool) -> POSConfig {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Sets the seed used in deterministic mode (0 by default) and enables it
+    pub fn seed(mut self, seed: u64) -> POSConfig {
+        self.seed = seed;
+        self.deterministic(true)
+    }
+}
+
+/// Parse a model type: `mobilebert`, `bert`, `distilbert`, `roberta` or `xlm-roberta`
+pub fn parse_model_type(name: &str) -> Result<ModelType, RustBertError> {
+    match name {
+        "mobilebert" => Ok(ModelType::MobileBert),
+        "bert" => Ok(ModelType::Bert),
+        "distilbert" => Ok(ModelType::DistilBert),
+        "roberta" => Ok(ModelType::Roberta),
+        "xlm-roberta" => Ok(ModelType::XLMRoberta),
+        _ => Err(RustBertError::InvalidConfigurationError(format!(
+            "Unknown model type '{}', expected one of mobilebert, bert, distilbert, roberta, xlm-roberta",
+            name
+        ))),
+    }
+}
+
+/// Names of the vocabulary and merges files of a model directory
+pub fn vocabulary_files(model_type: ModelType) -> (&'static str, Option<&'static str>) {
+    match model_type {
+        ModelType::Roberta => ("vocab.json", Some("merges.txt")),
+        ModelType::XLMRoberta => ("sentencepiece.bpe.model", None),
+        _ => ("vocab.txt", None),
+    }
+}
+
+/// Labels of the `id2label` map of the model configuration at `config_path`, by index
+pub fn read_labels(config_path: &Path) -> Result<Vec<String>, RustBertError> {
+    let invalid = |message: String| RustBertError::InvalidConfigurationError(message);
+    let contents = fs::read_to_string(config_path)?;
+    let config: serde_json::Value = serde_json::from_str(&contents)
+        .map_err(|err| invalid(format!("Invalid model configuration: {}", err)))?;
+    let id2label = config
+        .get("id2label")
+        .and_then(serde_json::Value::as_object)
+        .ok_or_else(|| invalid(String::from("No id2label in the model configuration")))?;
+    let mut labels = id2label
+        .iter()
+        .map(|(id, label)| match (id.parse::<usize>(), label.as_str()) {
+            (Ok(id), Some(label)) => Ok((id, label.to_owned())),
+            _ => Err(invalid(format!("Invalid id2label entry {}: {}", id, label))),
+        })
+        .collect::<Result<Vec<(usize, String)>, RustBertError>>()?;
+    labels.sort();
+    Ok(labels.into_iter().map(|(_, label)| label).collect())
+}
+
+/// `do_lower_case` and `strip_accents` of a Hugging Face `tokenizer_config.json`
+fn tokenizer_expectations(contents: &str) -> Result<(Option<bool>, Option<bool>), RustBertError> {
+    let json: serde_json::Value = serde_json::from_str(contents).map_err(|err| {
+        RustBertError::InvalidConfigurationError(format!("Invalid tokenizer_config.json: {}", err))
+    })?;
+    Ok((
+        json.get("do_lower_case").and_then(serde_json::Value::as_bool),
+        json.get("strip_accents").and_then(serde_json::Value::as_bool),
+    ))
+}
+
+/// Compare the casing settings with those the tokenizer expects. As in rust-bert and the
+/// Hugging Face tokenizers, accents are stripped along with lower-casing unless set otherwise.
+fn check_casing(
+    expected_lower_case: Option<bool>,
+    expected_strip_accents: Option<bool>,
+    lower_case: bool,
+    strip_accents: Option<bool>,
+) -> Result<(), String> {
+    if let Some(expected) = expected_lower_case {
+        if expected != lower_case {
+            return Err(format!(
+                "The model expects lower_case = {}, its vocabulary being {}",
+                expected,
+                if expected { "uncased" } else { "cased" }
+            ));
+        }
+    }
+    let expected_strip_accents = expected_strip_accents.or(expected_lower_case);
+    if let Some(expected) = expected_strip_accents {
+        if expected != strip_accents.unwrap_or(lower_case) {
+            return Err(format!("The model expects strip_accents = {}", expected));
+        }
+    }
+    Ok(())
 }
 
 impl From<POSConfig> for TokenClassificationConfig {
@@ -72,11 +574,40 @@ impl From<POSConfig> for TokenClassificationConfig {
     }
 }
 
+impl From<TokenClassificationConfig> for POSConfig {
+    fn from(config: TokenClassificationConfig) -> Self {
+        POSConfig::from_token_classification_config(config)
+    }
+}
+
+/// Sentence tagged by `POSModel::warm_up`
+const WARM_UP_SENTENCE: &str = "The quick brown fox jumps over the lazy dog.";
+
 /// # POSModel to extract Part of Speech tags
+///
+/// Dropping the model frees its weights. On the GPU, LibTorch's caching allocator keeps the
+/// freed blocks reserved for the process and reuses them for the next model loaded, so
+/// models can be swapped with `close` and `new`, or with `reload`, without growing GPU memory.
 pub struct POSModel {
     token_classification_model: TokenClassificationModel,
+    /// Labels of the model, by index
+    labels: Vec<String>,
+    pre_processors: Vec<Box<dyn PreProcessor>>,
+    post_processors: Vec<Box<dyn PostProcessor>>,
+    replacers: Vec<RegisteredReplacer>,
 }
 
+/// `Replacer` with the number of pre and post-processors registered before it, which run
+/// before it replaces and restores words
+struct RegisteredReplacer {
+    replacer: Box<dyn Replacer>,
+    pre_processors: usize,
+    post_processors: usize,
+}
+
+/// Words replaced in one text, by replacer
+type Replaced = Vec<Vec<Replacement>>;
+
 impl POSModel {
     /// Build a new `POSModel`
     ///
@@ -95,12 +626,134 @@ impl POSModel {
     /// # }
     /// ```
     pub fn new(pos_config: POSConfig) -> Result<POSModel, RustBertError> {
+        pos_config.check_tokenizer()?;
+        if let Some(threads) = pos_config.cpu_threads {
+            tch::set_num_threads(threads as i32);
+        }
+        if let Some(threads) = pos_config.interop_threads {
+            tch::set_num_interop_threads(threads as i32);
+        }
+        if pos_config.deterministic {
+            tch::manual_seed(pos_config.seed as i64);
+            tch::Cuda::manual_seed_all(pos_config.seed);
+            tch::Cuda::cudnn_set_benchmark(false);
+        }
+        let config_resource = pos_config.token_classification_config.config_resource.clone();
         let model = TokenClassificationModel::new(pos_config.into())?;
+        //downloaded by the model if remote
+        let labels = read_labels(&config_resource.get_local_path()?)?;
         Ok(POSModel {
             token_classification_model: model,
+            labels,
+            pre_processors: Vec::new(),
+            post_processors: Vec::new(),
+            replacers: Vec::new(),
         })
     }
 
+    /// Unload the model, freeing its weights before the caller loads another one
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rustlib::pos_tagging::{POSConfig, POSModel};
+    /// # use std::path::Path;
+    ///
+    /// let pos_model = POSModel::new(Default::default())?;
+    /// pos_model.close();
+    /// let pos_model = POSModel::new(POSConfig::default().local_model(Path::new("model")))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn close(self) {
+        drop(self);
+    }
+
+    /// Replace the model with the one described by `pos_config`.
+    /// The new model is loaded before the current one is freed, so that a failed load
+    /// leaves it usable; memory use peaks at both models during the swap. Use `close`
+    /// followed by `new` where that peak does not fit. Registered processors are kept.
+    pub fn reload(&mut self, pos_config: POSConfig) -> Result<(), RustBertError> {
+        let pos_model = POSModel::new(pos_config)?;
+        self.token_classification_model = pos_model.token_classification_model;
+        self.labels = pos_model.labels;
+        Ok(())
+    }
+
+    /// Labels the model predicts, in the order of its output layer, with their description.
+    /// Post-processors may add others, and uncertain punctuation is labelled ".".
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rustlib::pos_tagging::POSModel;
+    ///
+    /// let pos_model = POSModel::new(Default::default())?;
+    /// for label in pos_model.label_set() {
+    ///     println!("{}\t{}", label.label, label.description.unwrap_or(""));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn label_set(&self) -> Vec<Label> {
+        self.labels
+            .iter()
+            .map(|label| Label {
+                label: label.clone(),
+                description: tagset::describe(label),
+            })
+            .collect()
+    }
+
+    /// Register a `PreProcessor` rewriting each input text before prediction.
+    /// Pre-processors run in registration order.
+    pub fn add_pre_processor<P: PreProcessor + 'static>(&mut self, pre_processor: P) {
+        self.pre_processors.push(Box::new(pre_processor));
+    }
+
+    /// Register a `PostProcessor` rewriting the tags of each sentence after prediction.
+    /// Post-processors run in registration order.
+    pub fn add_post_processor<P: PostProcessor + 'static>(&mut self, post_processor: P) {
+        self.post_processors.push(Box::new(post_processor));
+    }
+
+    /// Register a `Replacer`, replacing words of each input text after the pre-processors
+    /// registered so far and restoring them before the post-processors registered after it
+    pub fn add_replacer<R: Replacer + 'static>(&mut self, replacer: R) {
+        self.replacers.push(RegisteredReplacer {
+            replacer: Box::new(replacer),
+            pre_processors: self.pre_processors.len(),
+            post_processors: self.post_processors.len(),
+        });
+    }
+
+    /// Run a dummy prediction so that the first real request does not pay for lazy
+    /// initialization (CUDA context creation, kernel selection, allocator growth)
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rustlib::pos_tagging::POSModel;
+    ///
+    /// let pos_model = POSModel::new(Default::default())?;
+    /// pos_model.warm_up();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn warm_up(&self) {
+        self.warm_up_batch(1);
+    }
+
+    /// Like `warm_up`, with a batch of `batch_size` sentences so that the memory needed
+    /// for full batches is allocated up front
+    pub fn warm_up_batch(&self, batch_size: usize) {
+        let input = vec![WARM_UP_SENTENCE; batch_size.max(1)];
+        let _ = self.predict(&input);
+    }
+
     /// Extract entities from a text
     ///
     /// # Arguments
@@ -130,28 +783,496 @@ impl POSModel {
     where
         S: AsRef<[&'a str]>,
     {
+        let scored = self.predict_scored(input);
+        let mut output = Vec::with_capacity(scored.len());
+        for sequence_tags in scored {
+            let mut tags = Vec::with_capacity(sequence_tags.len());
+            tags.extend(sequence_tags.into_iter().map(|scored_tag| scored_tag.tag));
+            output.push(tags);
+        }
+        output
+    }
+
+    /// Like `predict`, keeping the confidence of the model in each label
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rustlib::pos_tagging::POSModel;
+    ///
+    /// let pos_model = POSModel::new(Default::default())?;
+    /// let output = pos_model.predict_scored(&["My name is Amy."]);
+    /// println!("{} {:.2}", output[0][0].tag.label, output[0][0].score);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict_scored<'a, S>(&self, input: S) -> Vec<Vec<ScoredTag>>
+    where
+        S: AsRef<[&'a str]>,
+    {
+        self.predict_checked(input.as_ref()).into_iter().map(|(tags, _)| tags).collect()
+    }
+
+    /// Like `predict_scored`, also returning the `Warning`s of the input: the sentences
+    /// changed by the pre-processors, those longer than the model reads, whose end is not
+    /// tagged, and the runs of words scored below `low_confidence`
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rustlib::pos_tagging::POSModel;
+    /// use rustlib::warnings::{Warning, DEFAULT_LOW_CONFIDENCE};
+    ///
+    /// let pos_model = POSModel::new(Default::default())?;
+    /// let (output, warnings) = pos_model.predict_with_warnings(&["Buffalo buffalo buffalo."], DEFAULT_LOW_CONFIDENCE);
+    /// for warning in warnings {
+    ///     if let Warning::LowConfidence { words, .. } = &warning {
+    ///         println!("{:?}: {}", &output[0][words.clone()], warning);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict_with_warnings<'a, S>(&self, input: S, low_confidence: f64) -> (Vec<Vec<ScoredTag>>, Vec<Warning>)
+    where
+        S: AsRef<[&'a str]>,
+    {
+        let input = input.as_ref();
+        let (processed, replaced): (Vec<String>, Vec<Replaced>) =
+            input.iter().map(|&text| self.pre_process(text)).unzip();
+        let texts: Vec<&str> = processed.iter().map(String::as_str).collect();
+        let mut output = Vec::with_capacity(input.len());
+        let mut warnings = Vec::new();
+        for (sentence, (tags, tagged)) in self.predict_processed(&texts, replaced).into_iter().enumerate() {
+            if input[sentence] != texts[sentence] {
+                warnings.push(Warning::Normalized {
+                    sentence,
+                    original: input[sentence].to_owned(),
+                    normalized: processed[sentence].clone(),
+                });
+            }
+            if let Some(tagged) = tagged {
+                warnings.push(Warning::TruncatedSentence {
+                    sentence,
+                    tagged,
+                    length: texts[sentence].chars().count(),
+                });
+            }
+            warnings.extend(warnings::low_confidence(sentence, &tags, low_confidence));
+            output.push(tags);
+        }
+        (output, warnings)
+    }
+
+    /// Like `predict_scored`, for sentences given as tokens: the sub-word tokens of the model
+    /// are aligned to the given ones, one tag per token (see `pretokenized`). Pre-processors
+    /// are not applied.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rustlib::pos_tagging::POSModel;
+    ///
+    /// let pos_model = POSModel::new(Default::default())?;
+    /// let output = pos_model.predict_tokens(&[vec!["New York", "is", "n't", "big"]]);
+    /// assert_eq!(output[0].len(), 4);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict_tokens(&self, input: &[Vec<&str>]) -> Vec<Vec<ScoredTag>> {
+        let texts: Vec<String> = input.iter().map(|tokens| pretokenized::join(tokens).0).collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
         self.token_classification_model
-            .predict(input, true, false)
+            .predict(&texts, true, false)
             .into_iter()
-            .map(|sequence_tokens| {
-                sequence_tokens
+            .zip(input)
+            .map(|(sequence_tokens, tokens)| {
+                let tagged: Vec<(Range<usize>, ScoredTag)> = sequence_tokens
                     .into_iter()
-                    .map(|mut token| {
-                        if (Self::is_punctuation(token.text.as_str()))
-                            & ((token.score < 0.5) | token.score.is_nan())
-                        {
-                            token.label = String::from(".");
-                            token.score = 1f64;
+                    .filter_map(|mut token| {
+                        Self::fix_punctuation(&mut token);
+                        let offset = token.offset?;
+                        let scored_tag = ScoredTag {
+                            tag: POSTag {
+                                word: token.text,
+                                label: token.label,
+                            },
+                            score: token.score,
                         };
-                        token
+                        Some((offset.begin as usize..offset.end as usize, scored_tag))
                     })
-                    .map(|token| POSTag {
+                    .collect();
+                let mut sequence_tags = pretokenized::align(tokens, &tagged);
+                self.post_process(&mut sequence_tags, Vec::new());
+                sequence_tags
+            })
+            .collect()
+    }
+
+    /// Like `predict`, for any collection of texts such as `Vec<String>` or an iterator of
+    /// owned strings
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rustlib::pos_tagging::POSModel;
+    ///
+    /// let pos_model = POSModel::new(Default::default())?;
+    /// let input = vec![String::from("My name is Amy."), String::from("I live in Paris.")];
+    /// let output = pos_model.predict_texts(&input);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict_texts<I>(&self, input: I) -> Vec<Vec<POSTag>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let input: Vec<I::Item> = input.into_iter().collect();
+        let texts: Vec<&str> = input.iter().map(AsRef::as_ref).collect();
+        self.predict(&texts)
+    }
+
+    /// Lazily tag the texts of `input`, `batch_size` at a time, yielding the tags of each
+    /// text in order. Only one batch of texts is held at once, so streaming callers do not
+    /// have to collect their input first.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rustlib::pos_tagging::POSModel;
+    /// use std::io::{self, BufRead};
+    ///
+    /// let pos_model = POSModel::new(Default::default())?;
+    /// let lines = io::stdin().lock().lines().map(|line| line.unwrap());
+    /// for tags in pos_model.predict_iter(lines, 32) {
+    ///     println!("{:?}", tags);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict_iter<I>(&self, input: I, batch_size: usize) -> PredictIter<'_, I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        PredictIter {
+            pos_model: self,
+            input: input.into_iter(),
+            batch_size: batch_size.max(1),
+            tagged: Vec::new().into_iter(),
+        }
+    }
+
+    /// `text` rewritten by the pre-processors and replacers, in registration order, with the
+    /// words each replacer replaced
+    fn pre_process(&self, text: &str) -> (String, Replaced) {
+        let mut text = text.to_owned();
+        let mut replaced = Vec::with_capacity(self.replacers.len());
+        for index in 0..=self.pre_processors.len() {
+            for registered in self.replacers.iter().filter(|registered| registered.pre_processors == index) {
+                let (replaced_text, replacements) = registered.replacer.replace(&text);
+                text = replaced_text;
+                replaced.push(replacements);
+            }
+            if let Some(pre_processor) = self.pre_processors.get(index) {
+                text = pre_processor.process(&text);
+            }
+        }
+        (text, replaced)
+    }
+
+    /// Run the post-processors on `sentence`, restoring the words `replaced` in its text in
+    /// registration order
+    fn post_process(&self, sentence: &mut Vec<ScoredTag>, mut replaced: Replaced) {
+        for index in 0..=self.post_processors.len() {
+            for (registered, replacements) in self.replacers.iter().zip(replaced.iter_mut()) {
+                if registered.post_processors == index {
+                    hooks::restore(sentence, std::mem::take(replacements));
+                }
+            }
+            if let Some(post_processor) = self.post_processors.get(index) {
+                post_processor.process(sentence);
+            }
+        }
+    }
+
+    /// Pre-processes and tags `input`, with the number of characters of each text tagged by
+    /// the model when it stopped before the end of the text
+    fn predict_checked(&self, input: &[&str]) -> Vec<(Vec<ScoredTag>, Option<usize>)> {
+        if self.pre_processors.is_empty() && self.replacers.is_empty() {
+            return self.predict_processed(input, Vec::new());
+        }
+        let (processed, replaced): (Vec<String>, Vec<Replaced>) =
+            input.iter().map(|&text| self.pre_process(text)).unzip();
+        let texts: Vec<&str> = processed.iter().map(String::as_str).collect();
+        self.predict_processed(&texts, replaced)
+    }
+
+    /// Tags `input`, already pre-processed, and runs the post-processors on every sentence,
+    /// blank ones included, with the words `replaced` in each text
+    fn predict_processed(&self, input: &[&str], replaced: Vec<Replaced>) -> Vec<(Vec<ScoredTag>, Option<usize>)> {
+        let mut replaced = replaced.into_iter();
+        self.tag_checked(input)
+            .into_iter()
+            .map(|(mut tags, tagged)| {
+                self.post_process(&mut tags, replaced.next().unwrap_or_default());
+                (tags, tagged)
+            })
+            .collect()
+    }
+
+    /// Tags of the model for `input`, with the number of characters of each text it tagged
+    /// when it stopped before the end of the text
+    fn tag_checked(&self, input: &[&str]) -> Vec<(Vec<ScoredTag>, Option<usize>)> {
+        //blank sentences, such as the blank lines of pretokenized input, are not sent to the model
+        let is_blank = |text: &&str| text.trim().is_empty();
+        if input.iter().any(is_blank) {
+            let texts: Vec<&str> = input.iter().copied().filter(|text| !is_blank(text)).collect();
+            let tagged = if texts.is_empty() { Vec::new() } else { self.tag_checked(&texts) };
+            let mut tagged = tagged.into_iter();
+            return input
+                .iter()
+                .map(|text| if is_blank(text) { (Vec::new(), None) } else { tagged.next().unwrap_or_default() })
+                .collect();
+        }
+        let tokens = self.token_classification_model.predict(input, true, false);
+        let mut output = Vec::with_capacity(tokens.len());
+        for (text, sequence_tokens) in input.iter().zip(tokens) {
+            //offsets are in characters; words left after the last token were cut off by the model
+            let tagged = sequence_tokens
+                .iter()
+                .filter_map(|token| token.offset)
+                .map(|offset| offset.end as usize)
+                .max()
+                .unwrap_or(0);
+            let truncated = text.chars().skip(tagged).any(char::is_alphanumeric);
+            //the text and label of each token move into its tag, only fixed labels are rewritten
+            let mut sequence_tags = Vec::with_capacity(sequence_tokens.len());
+            for mut token in sequence_tokens {
+                Self::fix_punctuation(&mut token);
+                sequence_tags.push(ScoredTag {
+                    tag: POSTag {
                         word: token.text,
                         label: token.label,
+                    },
+                    score: token.score,
+                });
+            }
+            output.push((sequence_tags, if truncated { Some(tagged) } else { None }));
+        }
+        output
+    }
+
+    /// Like `predict`, also returning the size and duration of the prediction
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rustlib::pos_tagging::POSModel;
+    ///
+    /// let pos_model = POSModel::new(Default::default())?;
+    /// let (output, stats) = pos_model.predict_with_stats(&["My name is Amy."]);
+    /// println!("{:.1} tokens/s", stats.tokens_per_sec());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict_with_stats<'a, S>(&self, input: S) -> (Vec<Vec<POSTag>>, PredictStats)
+    where
+        S: AsRef<[&'a str]>,
+    {
+        let sentences = input.as_ref().len();
+        let start = Instant::now();
+        let output = self.predict(input);
+        let stats = PredictStats {
+            sentences,
+            tokens: output.iter().map(Vec::len).sum(),
+            batches: 1,
+            inference_time: start.elapsed(),
+        };
+        (output, stats)
+    }
+
+    /// Like `predict`, sending the input to the model `batch_size` texts at a time, calling
+    /// `progress` after each batch and stopping between batches once `cancel` is cancelled
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to extract entities from.
+    /// * `batch_size` - Number of texts sent to the model at once
+    /// * `progress` - Called with the `Progress` after each batch
+    /// * `cancel` - `CancellationToken` checked before each batch
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Vec<POSTag>>` containing Part of Speech tags for the inputs provided,
+    /// or `Cancelled` if the prediction was stopped
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rustlib::pos_tagging::POSModel;
+    /// use rustlib::progress::CancellationToken;
+    ///
+    /// let pos_model = POSModel::new(Default::default())?;
+    /// let cancel = CancellationToken::new();
+    /// let output = pos_model.predict_with_progress(
+    ///     &["My name is Amy.", "I live in Paris."],
+    ///     1,
+    ///     |progress| println!("{:.0}%", progress.fraction() * 100.0),
+    ///     &cancel,
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict_with_progress<F>(
+        &self,
+        input: &[&str],
+        batch_size: usize,
+        progress: F,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Vec<POSTag>>, Cancelled>
+    where
+        F: Fn(Progress),
+    {
+        let batch_size = batch_size.max(1);
+        let mut current = Progress {
+            sentences_done: 0,
+            sentences_total: input.len(),
+            batches_done: 0,
+            batches_total: (input.len() + batch_size - 1) / batch_size,
+        };
+        let mut output = Vec::with_capacity(input.len());
+        for batch in input.chunks(batch_size) {
+            if cancel.is_cancelled() {
+                return Err(Cancelled);
+            }
+            output.extend(self.predict(batch));
+            current.sentences_done += batch.len();
+            current.batches_done += 1;
+            progress(current);
+        }
+        Ok(output)
+    }
+
+    /// Extract Part of Speech tags from a text, borrowing the words from the input.
+    /// Registered pre and post-processors are not run, the words being slices of the
+    /// unmodified input.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - `&[&str]` Array of texts to extract entities from.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Vec<POSTagRef>>` containing Part of Speech tags for the inputs provided,
+    /// with each word a slice of its input text
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rustlib::pos_tagging::POSModel;
+    ///
+    /// let pos_model = POSModel::new(Default::default())?;
+    /// let input = ["My name is Amy. I live in Paris."];
+    /// let output = pos_model.predict_ref(&input);
+    /// assert_eq!(output[0][0].word, "My");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn predict_ref<'a>(&self, input: &[&'a str]) -> Vec<Vec<POSTagRef<'a>>> {
+        self.token_classification_model
+            .predict(input, true, false)
+            .into_iter()
+            .zip(input.iter())
+            .map(|(sequence_tokens, &text)| {
+                //token offsets count characters, slices need byte positions
+                let byte_offsets = sentences::char_byte_offsets(text);
+                sequence_tokens
+                    .into_iter()
+                    .filter_map(|mut token| {
+                        Self::fix_punctuation(&mut token);
+                        let offset = token.offset?;
+                        let begin = *byte_offsets.get(offset.begin as usize)?;
+                        let end = *byte_offsets.get(offset.end as usize)?;
+                        Some(POSTagRef {
+                            word: &text[begin..end],
+                            label: token.label,
+                        })
                     })
-                    .collect::<Vec<POSTag>>()
+                    .collect::<Vec<POSTagRef<'a>>>()
             })
-            .collect::<Vec<Vec<POSTag>>>()
+            .collect::<Vec<Vec<POSTagRef<'a>>>>()
+    }
+
+    /// Tags of the words of `text` at the byte ranges `spans`, such as the tokens of a search
+    /// index or an annotation tool, one per span in order. The text is split into sentences
+    /// and tagged, and each span gets the label and score of the first model token it
+    /// overlaps, or `pretokenized::UNALIGNED_LABEL` with a score of 0 if none does.
+    /// Registered pre and post-processors are not run.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// # use rustlib::pos_tagging::POSModel;
+    ///
+    /// let pos_model = POSModel::new(Default::default())?;
+    /// let text = "New York is big.";
+    /// let output = pos_model.tag_spans(text, &[0..8, 9..11, 12..15]);
+    /// assert_eq!(output[0].tag.word, "New York");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn tag_spans(&self, text: &str, spans: &[Range<usize>]) -> Vec<ScoredTag> {
+        let sentences = sentences::split(text);
+        let mut tagged = Vec::new();
+        let predicted = self.token_classification_model.predict(&sentences, true, false);
+        for (sentence, sequence_tokens) in sentences.iter().zip(predicted) {
+            let start = sentences::offset_in(text, sentence);
+            //token offsets count characters, spans are byte positions
+            let byte_offsets = sentences::char_byte_offsets(sentence);
+            for mut token in sequence_tokens {
+                Self::fix_punctuation(&mut token);
+                let (begin, end) = match token.offset {
+                    Some(offset) => (offset.begin as usize, offset.end as usize),
+                    None => continue,
+                };
+                if let (Some(begin), Some(end)) = (byte_offsets.get(begin), byte_offsets.get(end)) {
+                    let scored_tag = ScoredTag {
+                        tag: POSTag {
+                            word: token.text,
+                            label: token.label,
+                        },
+                        score: token.score,
+                    };
+                    tagged.push((start + begin..start + end, scored_tag));
+                }
+            }
+        }
+        spans
+            .iter()
+            .map(|span| pretokenized::aligned_tag(text.get(span.clone()).unwrap_or(""), span, &tagged))
+            .collect()
+    }
+
+    /// Punctuation the model is unsure about is labelled ".", reusing the buffer of the label
+    fn fix_punctuation(token: &mut Token) {
+        if (token.score < 0.5 || token.score.is_nan()) && Self::is_punctuation(&token.text) {
+            if token.label != "." {
+                token.label.clear();
+                token.label.push('.');
+            }
+            token.score = 1f64;
+        }
     }
 
     fn is_punctuation(string: &str) -> bool {
@@ -159,14 +1280,91 @@ impl POSModel {
     }
 }
 
+/// # Iterator over the tags of a stream of texts
+/// Returned by `POSModel::predict_iter`.
+pub struct PredictIter<'m, I> {
+    pos_model: &'m POSModel,
+    input: I,
+    batch_size: usize,
+    /// Tags of the current batch not yet returned
+    tagged: std::vec::IntoIter<Vec<POSTag>>,
+}
+
+impl<'m, I> Iterator for PredictIter<'m, I>
+where
+    I: Iterator,
+    I::Item: AsRef<str>,
+{
+    type Item = Vec<POSTag>;
+
+    fn next(&mut self) -> Option<Vec<POSTag>> {
+        if let Some(tags) = self.tagged.next() {
+            return Some(tags);
+        }
+        let batch: Vec<I::Item> = self.input.by_ref().take(self.batch_size).collect();
+        if batch.is_empty() {
+            return None;
+        }
+        self.tagged = self.pos_model.predict_texts(&batch).into_iter();
+        self.tagged.next()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn throughput_of_an_empty_prediction() {
+        assert_eq!(PredictStats::default().tokens_per_sec(), 0.0);
+        let stats = PredictStats {
+            tokens: 10,
+            inference_time: Duration::from_millis(500),
+            ..PredictStats::default()
+        };
+        assert!((stats.tokens_per_sec() - 20.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn seed_implies_deterministic() {
+        let pos_config = POSConfig::default();
+        assert!(!pos_config.deterministic);
+        let pos_config = pos_config.seed(42);
+        assert!(pos_config.deterministic);
+        assert_eq!(pos_config.seed, 42);
+        let pos_config = pos_config.deterministic(false);
+        assert!(!pos_config.deterministic);
+        assert_eq!(pos_config.seed, 42);
+    }
+
     #[test]
     #[ignore] // no need to run, compilation is enough to verify it is Send
     fn test() {
         let config = POSConfig::default();
         let _: Box<dyn Send> = Box::new(POSModel::new(config));
     }
+
+    #[test]
+    fn checks_casing_against_the_tokenizer() {
+        let cased = tokenizer_expectations(r#"{"do_lower_case": false}"#).unwrap();
+        assert_eq!(cased, (Some(false), None));
+        assert!(check_casing(cased.0, cased.1, false, None).is_ok());
+        assert!(check_casing(cased.0, cased.1, true, None).is_err());
+        assert!(check_casing(cased.0, cased.1, false, Some(true)).is_err());
+        assert!(check_casing(Some(true), Some(false), true, None).is_err());
+        assert!(check_casing(Some(true), Some(false), true, Some(false)).is_ok());
+        assert!(check_casing(None, None, false, Some(true)).is_ok());
+    }
+
+    #[test]
+    fn displays_and_round_trips_output() {
+        let tag = |word: &str, label: &str| POSTag {
+            word: word.to_owned(),
+            label: label.to_owned(),
+        };
+        let output = POSOutput::from(vec![vec![tag("Hi", "UH"), tag("!", ".")], vec![tag("Bye", "UH")]]);
+        assert_eq!(output.to_string(), "Hi/UH !/.\nBye/UH\n");
+        let json = serde_json::to_string(&output).unwrap();
+        assert_eq!(serde_json::from_str::<POSOutput>(&json).unwrap(), output);
+    }
 }
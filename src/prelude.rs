@@ -0,0 +1,41 @@
+//! # Public API
+//! The types most programs need, importable at once:
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rustlib::prelude::*;
+//!
+//! let pos_model = POSModel::new(POSConfig::default())?;
+//! let output = POSOutput::from(pos_model.predict(&["My name is Amy."]));
+//! println!("{}", output);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Stability
+//!
+//! Everything re-exported here follows semantic versioning: within a major version (minor
+//! versions while below 1.0) items are not removed or renamed, function signatures do not
+//! change, and public struct fields are not removed. New items, new methods, new enum
+//! variants and new `Config` fields may be added: `TagFormat`, `LabelAggregation` and
+//! `Warning` are `#[non_exhaustive]`, so matches on them need a wildcard arm, and so is
+//! `Config`, which is built from `Config::default()` or a file and then has its fields set.
+//! The JSON, TSV and CoNLL-U output of `TagWriter` is covered by the same guarantees,
+//! including its order (see `format`).
+//!
+//! Items only reachable through their modules (e.g. `rusttagr`, `stream`, `pipeline`) may
+//! change in any release.
+
+pub use crate::config::Config;
+pub use crate::ensemble::EnsembleTagger;
+pub use crate::format::{format_tags, TagFormat, TagWriter};
+pub use crate::hooks::{PostProcessor, PreProcessor};
+#[cfg(feature = "test-util")]
+pub use crate::mock::MockTagger;
+pub use crate::pos_tagging::{
+    LabelAggregation, POSConfig, POSModel, POSOutput, POSTag, POSTagRef, PredictIter, PredictStats,
+    ScoredTag,
+};
+pub use crate::sentences::split as split_sentences;
+pub use crate::tagger::Tagger;
+pub use crate::warnings::{Warning, WarningSink};
@@ -0,0 +1,145 @@
+//! # Pre-tokenized input
+//! With `--pretokenized` (or `pretokenized = true`), every line of the input is a sentence
+//! whose tokens are given by the caller, separated by spaces, or as a JSON array of strings
+//! for tokens holding spaces:
+//!
+//! ```text
+//! The U.S. do n't agree .
+//! ["New York", "is", "big", "."]
+//! ```
+//!
+//! The tokens are joined with spaces for the model, and its sub-word tokens aligned back to
+//! them by their offsets: each token gets the label and score of the first model token it
+//! overlaps, so that the output has exactly one tag per input token, whatever the tokenizer
+//! of the model. A token the model drops (such as a control character) is labelled
+//! `UNALIGNED_LABEL` with a score of 0. Pre-processors, which may rewrite the text, are not
+//! applied to pre-tokenized input.
+
+use std::ops::Range;
+
+use anyhow::Context;
+
+use crate::pos_tagging::{POSTag, ScoredTag};
+
+/// Label of the tokens no model token overlaps
+pub const UNALIGNED_LABEL: &str = "X";
+
+/// Tokens of a line: a JSON array of strings if it starts with `[`, the words separated by
+/// whitespace otherwise
+pub fn parse_tokens(line: &str) -> anyhow::Result<Vec<String>> {
+    let line = line.trim();
+    if line.starts_with('[') {
+        return serde_json::from_str(line).context("Expected a JSON array of string tokens");
+    }
+    Ok(line.split_whitespace().map(str::to_owned).collect())
+}
+
+/// Text the model tags for `tokens`, and the character range of each token in it
+pub fn join(tokens: &[&str]) -> (String, Vec<Range<usize>>) {
+    let mut text = String::new();
+    let mut ranges = Vec::with_capacity(tokens.len());
+    let mut chars = 0;
+    for (index, token) in tokens.iter().enumerate() {
+        if index > 0 {
+            text.push(' ');
+            chars += 1;
+        }
+        text.push_str(token);
+        let len = token.chars().count();
+        ranges.push(chars..chars + len);
+        chars += len;
+    }
+    (text, ranges)
+}
+
+/// One tag per token of `tokens`, from the model tags `tagged` at character ranges of the
+/// text returned by `join`
+pub fn align(tokens: &[&str], tagged: &[(Range<usize>, ScoredTag)]) -> Vec<ScoredTag> {
+    let (_, ranges) = join(tokens);
+    tokens
+        .iter()
+        .zip(ranges)
+        .map(|(token, range)| aligned_tag(token, &range, tagged))
+        .collect()
+}
+
+/// Tag of `word` at `range`, with the label and score of the first of `tagged` overlapping
+/// it, or `UNALIGNED_LABEL` if none does
+pub fn aligned_tag(word: &str, range: &Range<usize>, tagged: &[(Range<usize>, ScoredTag)]) -> ScoredTag {
+    let overlapping = tagged
+        .iter()
+        .find(|(tag_range, _)| tag_range.start < range.end && range.start < tag_range.end);
+    let (label, score) = match overlapping {
+        Some((_, scored_tag)) => (scored_tag.tag.label.clone(), scored_tag.score),
+        None => (UNALIGNED_LABEL.to_owned(), 0.0),
+    };
+    ScoredTag {
+        tag: POSTag {
+            word: word.to_owned(),
+            label,
+        },
+        score,
+    }
+}
+
+/// Like `align`, for taggers that do not give offsets: the tagged words are looked up in
+/// order in the joined text, ignoring case, and those not found are left out
+pub fn align_words(tokens: &[&str], tagged: Vec<ScoredTag>) -> Vec<ScoredTag> {
+    let (text, _) = join(tokens);
+    let chars: Vec<char> = text.chars().collect();
+    let mut position = 0;
+    let mut located = Vec::with_capacity(tagged.len());
+    for scored_tag in tagged {
+        let word: Vec<char> = scored_tag.tag.word.chars().collect();
+        let found = (position..chars.len())
+            .find(|&start| {
+                start + word.len() <= chars.len()
+                    && chars[start..start + word.len()]
+                        .iter()
+                        .zip(&word)
+                        .all(|(a, b)| a.to_lowercase().eq(b.to_lowercase()))
+            });
+        if let Some(start) = found {
+            position = start + word.len();
+            located.push((start..position, scored_tag));
+        }
+    }
+    align(tokens, &located)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn scored(word: &str, label: &str) -> ScoredTag {
+        ScoredTag {
+            tag: POSTag {
+                word: word.to_owned(),
+                label: label.to_owned(),
+            },
+            score: 0.9,
+        }
+    }
+
+    #[test]
+    fn aligns_sub_words_to_the_tokens() {
+        let tokens = ["New York", "is", "n't", "big"];
+        assert_eq!(join(&tokens).0, "New York is n't big");
+        //the model splits "New York" and "n't" in two
+        let tagged = vec![
+            scored("new", "NNP"),
+            scored("york", "NNP"),
+            scored("is", "VBZ"),
+            scored("n", "RB"),
+            scored("'t", "RB"),
+            scored("big", "JJ"),
+        ];
+        let aligned = align_words(&tokens, tagged);
+        let words: Vec<&str> = aligned.iter().map(|tag| tag.tag.word.as_str()).collect();
+        let labels: Vec<&str> = aligned.iter().map(|tag| tag.tag.label.as_str()).collect();
+        assert_eq!(words, tokens);
+        assert_eq!(labels, ["NNP", "VBZ", "RB", "JJ"]);
+        assert_eq!(parse_tokens("[\"New York\", \"is\"]").unwrap(), ["New York", "is"]);
+        assert_eq!(parse_tokens(" a  b ").unwrap(), ["a", "b"]);
+    }
+}
@@ -0,0 +1,64 @@
+//! # Progress reporting and cancellation
+//! Types used by `POSModel::predict_with_progress` to report progress of long batch
+//! predictions and to stop them from another thread.
+
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// # Progress of a batch prediction
+pub struct Progress {
+    pub sentences_done: usize,
+    pub sentences_total: usize,
+    pub batches_done: usize,
+    pub batches_total: usize,
+}
+
+impl Progress {
+    /// Fraction of the sentences tagged, between 0 and 1
+    pub fn fraction(&self) -> f64 {
+        if self.sentences_total == 0 {
+            1.0
+        } else {
+            self.sentences_done as f64 / self.sentences_total as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// # Cancellation token
+/// Cloned handles share the same flag: cancelling one cancels the prediction holding another.
+/// A prediction checks the token between batches, so the batch running when it is cancelled
+/// completes first.
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Request the prediction to stop
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error returned by a prediction stopped through its `CancellationToken`
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Prediction cancelled")
+    }
+}
+
+impl Error for Cancelled {}
@@ -0,0 +1,192 @@
+//! # URL, email, hashtag and mention protection
+//! Detects URLs, email addresses, hashtags and @mentions before tagging and labels them
+//! `URL`, `EMAIL`, `HASHTAG` and `MENTION`. The subword tokenizer would otherwise shred them
+//! into dozens of pieces that use up the sequence length and get arbitrary labels. Enabled
+//! with `protect_tokens` in the configuration file or with `--protect-tokens`.
+//!
+//! `TokenProtector` is a `Replacer`: it replaces each of them with a placeholder word and
+//! returns the originals with the text, which `POSModel` restores with their label in the
+//! tags of that text.
+
+use crate::hooks::{Replacement, Replacer};
+
+/// Punctuation trimmed from the start of a word before detection
+const LEADING_PUNCTUATION: &[char] = &['(', '[', '"', '\''];
+/// Punctuation trimmed from the end of a word before detection
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?', ')', ']', '"', '\''];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// # Kind of protected token
+pub enum TokenKind {
+    Url,
+    Email,
+    Hashtag,
+    Mention,
+}
+
+impl TokenKind {
+    pub const ALL: [TokenKind; 4] = [
+        TokenKind::Url,
+        TokenKind::Email,
+        TokenKind::Hashtag,
+        TokenKind::Mention,
+    ];
+
+    /// Label given to the tokens of this kind
+    pub fn label(self) -> &'static str {
+        match self {
+            TokenKind::Url => "URL",
+            TokenKind::Email => "EMAIL",
+            TokenKind::Hashtag => "HASHTAG",
+            TokenKind::Mention => "MENTION",
+        }
+    }
+
+    /// Word standing for the tokens of this kind in the model input, a few subwords long
+    /// and unlikely to occur in text
+    fn placeholder(self) -> &'static str {
+        match self {
+            TokenKind::Url => "xxurl",
+            TokenKind::Email => "xxemail",
+            TokenKind::Hashtag => "xxhashtag",
+            TokenKind::Mention => "xxmention",
+        }
+    }
+}
+
+/// Kind of `word`, a whitespace-separated word stripped of surrounding punctuation,
+/// if it is to be protected
+///
+/// # Example
+///
+/// ```
+/// use rustlib::protect::{classify, TokenKind};
+///
+/// assert_eq!(classify("https://example.com/a?b=c"), Some(TokenKind::Url));
+/// assert_eq!(classify("amy@example.com"), Some(TokenKind::Email));
+/// assert_eq!(classify("#1"), None);
+/// ```
+pub fn classify(word: &str) -> Option<TokenKind> {
+    let lower = word.to_lowercase();
+    let is_name = |name: &str| !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if ["http://", "https://", "ftp://", "www."]
+        .iter()
+        .any(|prefix| lower.starts_with(prefix) && lower.len() > prefix.len())
+    {
+        Some(TokenKind::Url)
+    } else if let Some(name) = word.strip_prefix('@') {
+        Some(TokenKind::Mention).filter(|_| is_name(name))
+    } else if let Some(tag) = word.strip_prefix('#') {
+        Some(TokenKind::Hashtag).filter(|_| is_name(tag) && !tag.chars().all(|c| c.is_ascii_digit()))
+    } else {
+        let at = word.find('@')?;
+        let (user, domain) = (&word[..at], &word[at + 1..]);
+        let valid_domain = domain.contains('.')
+            && !domain.starts_with('.')
+            && !domain.ends_with('.')
+            && domain.chars().all(|c| c.is_alphanumeric() || c == '.' || c == '-');
+        Some(TokenKind::Email).filter(|_| !user.is_empty() && !user.contains(char::is_whitespace) && valid_domain)
+    }
+}
+
+/// Byte ranges and kinds of the protected tokens of `text`
+pub fn find_protected(text: &str) -> Vec<(usize, usize, TokenKind)> {
+    let mut found = Vec::new();
+    let mut start = None;
+    for (index, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        if !c.is_whitespace() {
+            start.get_or_insert(index);
+            continue;
+        }
+        if let Some(word_start) = start.take() {
+            let word = &text[word_start..index];
+            let trimmed_start = word.trim_start_matches(LEADING_PUNCTUATION);
+            let trimmed = trimmed_start.trim_end_matches(TRAILING_PUNCTUATION);
+            if let Some(kind) = classify(trimmed) {
+                let begin = word_start + (word.len() - trimmed_start.len());
+                found.push((begin, begin + trimmed.len(), kind));
+            }
+        }
+    }
+    found
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+/// # Protects URLs, emails, hashtags and mentions from the tokenizer
+pub struct TokenProtector;
+
+impl TokenProtector {
+    pub fn new() -> TokenProtector {
+        TokenProtector
+    }
+}
+
+impl Replacer for TokenProtector {
+    fn replace(&self, text: &str) -> (String, Vec<Replacement>) {
+        let mut protected = String::with_capacity(text.len());
+        let mut replacements = Vec::new();
+        let mut last = 0;
+        for (start, end, kind) in find_protected(text) {
+            protected.push_str(&text[last..start]);
+            protected.push_str(kind.placeholder());
+            replacements.push(Replacement {
+                placeholder: kind.placeholder(),
+                word: text[start..end].to_owned(),
+                label: kind.label(),
+            });
+            last = end;
+        }
+        protected.push_str(&text[last..]);
+        (protected, replacements)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hooks;
+    use crate::pos_tagging::{POSTag, ScoredTag};
+
+    fn scored_tags(words: &[&str]) -> Vec<ScoredTag> {
+        words
+            .iter()
+            .map(|word| ScoredTag {
+                tag: POSTag {
+                    word: word.to_string(),
+                    label: String::from("NN"),
+                },
+                score: 0.5,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn protects_and_restores_tokens() {
+        let protector = TokenProtector::new();
+        let text = "Thanks @amy_b! See (https://example.com/x?y=1), mail bob@example.org #rustlang #1";
+        let (protected, replacements) = protector.replace(text);
+        assert_eq!(protected, "Thanks xxmention! See (xxurl), mail xxemail xxhashtag #1");
+        assert_eq!(replacements.len(), 4);
+
+        let mut sentence = scored_tags(&["Thanks", "xxmention", "!", "xxemail"]);
+        hooks::restore(&mut sentence, replacements);
+        assert_eq!(sentence[1].tag.word, "@amy_b");
+        assert_eq!(sentence[1].tag.label, "MENTION");
+        assert_eq!(sentence[3].tag.word, "bob@example.org");
+        assert_eq!(sentence[3].tag.label, "EMAIL");
+    }
+
+    #[test]
+    fn restores_each_text_after_a_blank_line() {
+        let protector = TokenProtector::new();
+        //blank texts are not tagged, so their replacements are never restored
+        let replaced: Vec<(String, Vec<Replacement>)> = ["Mail amy@example.org", "", "Mail bob@example.org"]
+            .iter()
+            .map(|text| protector.replace(text))
+            .collect();
+        assert!(replaced[1].1.is_empty());
+        let mut sentence = scored_tags(&["Mail", "xxemail"]);
+        hooks::restore(&mut sentence, replaced[2].1.clone());
+        assert_eq!(sentence[1].tag.to_string(), "bob@example.org/EMAIL");
+    }
+}
@@ -0,0 +1,232 @@
+//! # Quality report
+//! Standalone HTML page written with `--report report.html`, for judging the output of a
+//! run at a glance without reading it: the histogram of the confidence scores, the count and
+//! mean score of each label, the words tagged with the least confidence in their sentence,
+//! and the totals and throughput of the run summary.
+//!
+//! The scores are those of the model, calibrated if configured, before `filter_tags` and
+//! `min_score` leave words out of the output.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::confusion::html_escape;
+use crate::pos_tagging::ScoredTag;
+use crate::summary::RunSummary;
+
+/// Number of bins of the score histogram
+pub const HISTOGRAM_BINS: usize = 10;
+
+/// Number of lowest-confidence words listed
+pub const LOWEST_EXAMPLES: usize = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+/// # Word tagged with a low score, in its sentence
+pub struct LowScore {
+    pub word: String,
+    pub label: String,
+    pub score: f64,
+    pub sentence: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// # Score and label statistics of a tagging run
+pub struct QualityReport {
+    /// Number of words in each score bin, from `[0, 0.1)` to `[0.9, 1]`
+    pub histogram: Vec<usize>,
+    /// Count and sum of the scores of each label
+    pub labels: BTreeMap<String, (usize, f64)>,
+    /// Words with the lowest scores, lowest first
+    pub lowest: Vec<LowScore>,
+    pub summary: RunSummary,
+}
+
+impl Default for QualityReport {
+    fn default() -> QualityReport {
+        QualityReport {
+            histogram: vec![0; HISTOGRAM_BINS],
+            labels: BTreeMap::new(),
+            lowest: Vec::new(),
+            summary: RunSummary::default(),
+        }
+    }
+}
+
+impl QualityReport {
+    /// Add the scored tags of the sentence `text`
+    pub fn add_sentence(&mut self, text: &str, sentence: &[ScoredTag]) {
+        for scored_tag in sentence {
+            let bin = ((scored_tag.score.max(0.0) * HISTOGRAM_BINS as f64) as usize).min(HISTOGRAM_BINS - 1);
+            self.histogram[bin] += 1;
+            let label = self.labels.entry(scored_tag.tag.label.clone()).or_insert((0, 0.0));
+            label.0 += 1;
+            label.1 += scored_tag.score;
+            let is_lower = self.lowest.len() < LOWEST_EXAMPLES
+                || self.lowest.last().map_or(true, |lowest| scored_tag.score < lowest.score);
+            if is_lower {
+                let example = LowScore {
+                    word: scored_tag.tag.word.clone(),
+                    label: scored_tag.tag.label.clone(),
+                    score: scored_tag.score,
+                    sentence: text.trim().to_owned(),
+                };
+                let index = self.lowest.iter().position(|lowest| lowest.score > example.score);
+                self.lowest.insert(index.unwrap_or(self.lowest.len()), example);
+                self.lowest.truncate(LOWEST_EXAMPLES);
+            }
+        }
+    }
+
+    /// Add the statistics of `other`, a report of other sentences of the same run
+    pub fn merge(&mut self, other: QualityReport) {
+        for (count, other_count) in self.histogram.iter_mut().zip(other.histogram) {
+            *count += other_count;
+        }
+        for (label, (count, total)) in other.labels {
+            let entry = self.labels.entry(label).or_insert((0, 0.0));
+            entry.0 += count;
+            entry.1 += total;
+        }
+        self.lowest.extend(other.lowest);
+        //stable, so that ties keep the examples of `self` first
+        self.lowest.sort_by(|a, b| a.score.partial_cmp(&b.score).unwrap_or(std::cmp::Ordering::Equal));
+        self.lowest.truncate(LOWEST_EXAMPLES);
+    }
+
+    /// Number of scored words
+    pub fn words(&self) -> usize {
+        self.histogram.iter().sum()
+    }
+
+    /// Mean score of the words labelled `label`
+    pub fn mean_score(&self, label: &str) -> f64 {
+        match self.labels.get(label) {
+            Some(&(count, total)) if count > 0 => total / count as f64,
+            _ => 0.0,
+        }
+    }
+
+    /// Write the report as a standalone HTML page
+    pub fn write_html<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "<!DOCTYPE html>")?;
+        writeln!(writer, "<html><head><meta charset=\"utf-8\"><title>Tagging report</title>")?;
+        writeln!(
+            writer,
+            "<style>body{{font-family:sans-serif}}table{{border-collapse:collapse}}\
+             td,th{{border:1px solid #ccc;padding:2px 6px;text-align:left}}\
+             .bar{{background:#4a7;height:1em}}</style>"
+        )?;
+        writeln!(writer, "</head><body><h1>Tagging report</h1>")?;
+
+        writeln!(writer, "<h2>Run</h2><pre>{}</pre>", html_escape(&self.summary.to_string()))?;
+
+        writeln!(writer, "<h2>Score histogram</h2><table>")?;
+        writeln!(writer, "<tr><th>score</th><th>words</th><th>share</th><th></th></tr>")?;
+        let words = self.words();
+        let largest = self.histogram.iter().copied().max().unwrap_or(0).max(1);
+        for (bin, &count) in self.histogram.iter().enumerate() {
+            let share = if words > 0 { count as f64 / words as f64 } else { 0.0 };
+            writeln!(
+                writer,
+                "<tr><td>{:.1}&ndash;{:.1}</td><td>{}</td><td>{:.2}%</td>\
+                 <td style=\"width:20em\"><div class=\"bar\" style=\"width:{:.1}%\"></div></td></tr>",
+                bin as f64 / HISTOGRAM_BINS as f64,
+                (bin + 1) as f64 / HISTOGRAM_BINS as f64,
+                count,
+                share * 100.0,
+                count as f64 / largest as f64 * 100.0
+            )?;
+        }
+        writeln!(writer, "</table>")?;
+
+        writeln!(writer, "<h2>Labels</h2><table>")?;
+        writeln!(writer, "<tr><th>label</th><th>count</th><th>mean score</th></tr>")?;
+        let mut labels: Vec<(&String, &(usize, f64))> = self.labels.iter().collect();
+        labels.sort_by(|a, b| (b.1).0.cmp(&(a.1).0).then_with(|| a.0.cmp(b.0)));
+        for (label, &(count, _)) in labels {
+            writeln!(
+                writer,
+                "<tr><td>{}</td><td>{}</td><td>{:.3}</td></tr>",
+                html_escape(label),
+                count,
+                self.mean_score(label)
+            )?;
+        }
+        writeln!(writer, "</table>")?;
+
+        writeln!(writer, "<h2>Lowest confidence</h2><table>")?;
+        writeln!(writer, "<tr><th>score</th><th>word</th><th>label</th><th>sentence</th></tr>")?;
+        for example in &self.lowest {
+            writeln!(
+                writer,
+                "<tr><td>{:.3}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                example.score,
+                html_escape(&example.word),
+                html_escape(&example.label),
+                html_escape(&example.sentence)
+            )?;
+        }
+        writeln!(writer, "</table></body></html>")?;
+        writer.flush()
+    }
+
+    /// Write the report as HTML to `path`
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let file = File::create(path)
+            .with_context(|| format!("Could not create {}", path.display()))?;
+        self.write_html(BufWriter::new(file))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pos_tagging::POSTag;
+
+    fn scored(word: &str, label: &str, score: f64) -> ScoredTag {
+        ScoredTag {
+            tag: POSTag {
+                word: word.to_string(),
+                label: label.to_string(),
+            },
+            score,
+        }
+    }
+
+    #[test]
+    fn collects_scores_and_lowest_words() {
+        let mut report = QualityReport::default();
+        report.add_sentence("Time flies.", &[scored("Time", "NN", 0.98), scored("flies", "VBZ", 0.41)]);
+        report.add_sentence("<b>", &[scored("<b>", "NN", 1.0)]);
+        assert_eq!(report.words(), 3);
+        assert_eq!(report.histogram[9], 2);
+        assert_eq!(report.histogram[4], 1);
+        assert!((report.mean_score("NN") - 0.99).abs() < 1e-9);
+        assert_eq!(report.lowest[0].word, "flies");
+        assert_eq!(report.lowest[0].sentence, "Time flies.");
+
+        let mut html = Vec::new();
+        report.write_html(&mut html).unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.contains("<td>&lt;b&gt;</td>"));
+        assert!(!html.contains("<b>"));
+    }
+
+    #[test]
+    fn merges_reports() {
+        let mut report = QualityReport::default();
+        report.add_sentence("Time flies.", &[scored("Time", "NN", 0.98), scored("flies", "VBZ", 0.41)]);
+        let mut other = QualityReport::default();
+        other.add_sentence("Fruit flies.", &[scored("Fruit", "NN", 0.2)]);
+        report.merge(other);
+        assert_eq!(report.words(), 3);
+        assert!((report.mean_score("NN") - 0.59).abs() < 1e-9);
+        assert_eq!(report.lowest[0].word, "Fruit");
+        assert_eq!(report.lowest[1].word, "flies");
+    }
+}
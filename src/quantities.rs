@@ -0,0 +1,370 @@
+//! # Numbers, dates, times and quantities
+//! Detects numbers (`1,250.5`), dates (`2021-06-01`, `6/1/2021`), times (`14:30`, `3pm`)
+//! and quantities (`5kg`, `20%`, `$15`) and labels them `CD`, `DATE`, `TIME` and
+//! `QUANTITY`, so that information extraction gets a canonical value rather than raw `CD`
+//! pieces. Enabled with `quantities` in the configuration file or with `--quantities`,
+//! which also adds the normalized value of each of them to the output (see `normalize`).
+//!
+//! Like `protect::TokenProtector`, `QuantityTagger` is a `Replacer`: it replaces each of them
+//! with a placeholder word before tagging, since the tokenizer splits them at every
+//! punctuation mark, and the original is restored with its label after tagging. Detection works on
+//! whitespace-separated words: `June 1` is left to the model.
+
+use crate::hooks::{Replacement, Replacer};
+
+/// Punctuation trimmed from the start of a word before detection
+const LEADING_PUNCTUATION: &[char] = &['(', '[', '"', '\''];
+/// Punctuation trimmed from the end of a word before detection
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?', ')', ']', '"', '\''];
+
+/// Units of quantities, written after the number, with their canonical spelling. Seconds
+/// are only `sec`, as `1990s` is a decade.
+const UNITS: &[(&str, &str)] = &[
+    ("%", "%"),
+    ("mg", "mg"),
+    ("g", "g"),
+    ("kg", "kg"),
+    ("lb", "lb"),
+    ("lbs", "lb"),
+    ("oz", "oz"),
+    ("mm", "mm"),
+    ("cm", "cm"),
+    ("m", "m"),
+    ("km", "km"),
+    ("ft", "ft"),
+    ("mi", "mi"),
+    ("ml", "ml"),
+    ("l", "l"),
+    ("ms", "ms"),
+    ("sec", "s"),
+    ("min", "min"),
+    ("h", "h"),
+    ("hr", "h"),
+    ("hrs", "h"),
+    ("kb", "kB"),
+    ("mb", "MB"),
+    ("gb", "GB"),
+    ("tb", "TB"),
+    ("°c", "°C"),
+    ("°f", "°F"),
+    ("mph", "mph"),
+];
+
+/// Currency symbols, written before the number, with their ISO 4217 code
+const CURRENCIES: &[(char, &str)] = &[('$', "USD"), ('€', "EUR"), ('£', "GBP"), ('¥', "JPY")];
+
+/// Numbers written as words
+const NUMBER_WORDS: &[&str] = &[
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    "eleven", "twelve", "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen",
+    "nineteen",
+];
+
+/// Tens written as words, from twenty
+const TENS_WORDS: &[&str] = &[
+    "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// # Kind of detected expression
+pub enum QuantityKind {
+    Number,
+    Date,
+    Time,
+    Quantity,
+}
+
+impl QuantityKind {
+    pub const ALL: [QuantityKind; 4] = [
+        QuantityKind::Number,
+        QuantityKind::Date,
+        QuantityKind::Time,
+        QuantityKind::Quantity,
+    ];
+
+    /// Label given to the expressions of this kind
+    pub fn label(self) -> &'static str {
+        match self {
+            QuantityKind::Number => "CD",
+            QuantityKind::Date => "DATE",
+            QuantityKind::Time => "TIME",
+            QuantityKind::Quantity => "QUANTITY",
+        }
+    }
+
+    /// Word standing for the expressions of this kind in the model input
+    fn placeholder(self) -> &'static str {
+        match self {
+            QuantityKind::Number => "xxnumber",
+            QuantityKind::Date => "xxdate",
+            QuantityKind::Time => "xxtime",
+            QuantityKind::Quantity => "xxquantity",
+        }
+    }
+}
+
+/// Kind of `word`, a whitespace-separated word stripped of surrounding punctuation, if it is
+/// a number, date, time or quantity. Single-digit numbers are left to the model.
+///
+/// # Example
+///
+/// ```
+/// use rustlib::quantities::{classify, QuantityKind};
+///
+/// assert_eq!(classify("1,250.5"), Some(QuantityKind::Number));
+/// assert_eq!(classify("2021-06-01"), Some(QuantityKind::Date));
+/// assert_eq!(classify("3pm"), Some(QuantityKind::Time));
+/// assert_eq!(classify("$15"), Some(QuantityKind::Quantity));
+/// assert_eq!(classify("B2B"), None);
+/// ```
+pub fn classify(word: &str) -> Option<QuantityKind> {
+    if parse_date(word).is_some() {
+        Some(QuantityKind::Date)
+    } else if parse_time(word).is_some() {
+        Some(QuantityKind::Time)
+    } else if parse_quantity(word).is_some() {
+        Some(QuantityKind::Quantity)
+    } else if word.len() > 1 && parse_number(word).is_some() {
+        Some(QuantityKind::Number)
+    } else {
+        None
+    }
+}
+
+/// Normalized value of `word` labelled `label`:
+///
+/// * `CD`: the number without thousands separators, numbers written as words in digits
+/// * `DATE`: `YYYY-MM-DD`, reading `a/b/yyyy` as month/day
+/// * `TIME`: `HH:MM` or `HH:MM:SS`, on 24 hours
+/// * `QUANTITY`: the number and the unit (`5 kg`) or currency code (`15 USD`)
+///
+/// # Example
+///
+/// ```
+/// use rustlib::quantities::normalize;
+///
+/// assert_eq!(normalize("1,250", "CD").as_deref(), Some("1250"));
+/// assert_eq!(normalize("twelve", "CD").as_deref(), Some("12"));
+/// assert_eq!(normalize("6/1/2021", "DATE").as_deref(), Some("2021-06-01"));
+/// assert_eq!(normalize("3:15pm", "TIME").as_deref(), Some("15:15"));
+/// assert_eq!(normalize("$15", "QUANTITY").as_deref(), Some("15 USD"));
+/// assert_eq!(normalize("dog", "NN"), None);
+/// ```
+pub fn normalize(word: &str, label: &str) -> Option<String> {
+    match label {
+        "CD" => parse_number(word).or_else(|| number_word(word).map(|n| n.to_string())),
+        "DATE" => parse_date(word),
+        "TIME" => parse_time(word),
+        "QUANTITY" => parse_quantity(word),
+        _ => None,
+    }
+}
+
+/// `word` without thousands separators and sign `+`, if it is a decimal number
+fn parse_number(word: &str) -> Option<String> {
+    let (sign, unsigned) = match word.chars().next()? {
+        '-' => ("-", &word[1..]),
+        '+' => ("", &word[1..]),
+        _ => ("", word),
+    };
+    let (integer, fraction) = match unsigned.find('.') {
+        Some(index) => (&unsigned[..index], Some(&unsigned[index + 1..])),
+        None => (unsigned, None),
+    };
+    let is_digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    let groups: Vec<&str> = integer.split(',').collect();
+    let grouped = groups.len() == 1
+        || (groups[0].len() <= 3 && groups[1..].iter().all(|group| group.len() == 3));
+    if !grouped || !groups.iter().all(|group| is_digits(group)) {
+        return None;
+    }
+    let mut number = format!("{}{}", sign, groups.concat());
+    if let Some(fraction) = fraction {
+        if !is_digits(fraction) {
+            return None;
+        }
+        number.push('.');
+        number.push_str(fraction);
+    }
+    Some(number)
+}
+
+/// Value of a number written as one word, from zero to nineteen and the tens
+fn number_word(word: &str) -> Option<u32> {
+    let lower = word.to_lowercase();
+    if let Some(n) = NUMBER_WORDS.iter().position(|&w| w == lower) {
+        return Some(n as u32);
+    }
+    TENS_WORDS
+        .iter()
+        .position(|&w| w == lower)
+        .map(|n| 20 + 10 * n as u32)
+}
+
+/// Parse `y-m-d`, `y/m/d` and `m/d/y` dates with a four-digit year into `YYYY-MM-DD`
+fn parse_date(word: &str) -> Option<String> {
+    let separator = if word.contains('-') { '-' } else { '/' };
+    let parts: Vec<&str> = word.split(separator).collect();
+    if parts.len() != 3 || !parts.iter().all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit())) {
+        return None;
+    }
+    let (year, month, day) = match (parts[0].len(), parts[2].len()) {
+        (4, 1..=2) if parts[1].len() <= 2 => (parts[0], parts[1], parts[2]),
+        (1..=2, 4) if separator == '/' && parts[1].len() <= 2 => (parts[2], parts[0], parts[1]),
+        _ => return None,
+    };
+    let (month, day): (u32, u32) = (month.parse().ok()?, day.parse().ok()?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    Some(format!("{}-{:02}-{:02}", year, month, day))
+}
+
+/// Parse `H:MM`, `H:MM:SS`, optionally followed by `am`/`pm`, and `Ham`/`Hpm` into
+/// `HH:MM(:SS)`
+fn parse_time(word: &str) -> Option<String> {
+    let lower = word.to_lowercase().replace('.', "");
+    let (clock, meridiem) = if let Some(clock) = lower.strip_suffix("am") {
+        (clock, Some(false))
+    } else if let Some(clock) = lower.strip_suffix("pm") {
+        (clock, Some(true))
+    } else {
+        (lower.as_str(), None)
+    };
+    let parts: Vec<&str> = clock.split(':').collect();
+    let is_digits = |s: &str| !s.is_empty() && s.len() <= 2 && s.chars().all(|c| c.is_ascii_digit());
+    if parts.len() > 3 || !parts.iter().all(|part| is_digits(part)) {
+        return None;
+    }
+    //a bare number is a time only with am/pm
+    if parts.len() == 1 && meridiem.is_none() {
+        return None;
+    }
+    let mut hour: u32 = parts[0].parse().ok()?;
+    let minute: u32 = parts.get(1).map_or(Some(0), |m| m.parse().ok())?;
+    let second: Option<u32> = match parts.get(2) {
+        Some(s) => Some(s.parse().ok()?),
+        None => None,
+    };
+    if parts[1..].iter().any(|part| part.len() != 2) || minute > 59 || second.map_or(false, |s| s > 59) {
+        return None;
+    }
+    match meridiem {
+        Some(pm) if (1..=12).contains(&hour) => hour = hour % 12 + if pm { 12 } else { 0 },
+        Some(_) => return None,
+        None if hour > 23 => return None,
+        None => {}
+    }
+    Some(match second {
+        Some(second) => format!("{:02}:{:02}:{:02}", hour, minute, second),
+        None => format!("{:02}:{:02}", hour, minute),
+    })
+}
+
+/// Parse an amount of a currency (`$15`) or a number with a unit (`5kg`) into
+/// `number unit`
+fn parse_quantity(word: &str) -> Option<String> {
+    let first = word.chars().next()?;
+    if let Some((_, code)) = CURRENCIES.iter().find(|(symbol, _)| *symbol == first) {
+        let number = parse_number(&word[first.len_utf8()..])?;
+        return Some(format!("{} {}", number, code));
+    }
+    let split = word.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))?;
+    let (number, unit) = (&word[..split], word[split..].to_lowercase());
+    let (_, canonical) = UNITS.iter().find(|(spelling, _)| *spelling == unit)?;
+    Some(format!("{} {}", parse_number(number)?, canonical))
+}
+
+/// Byte ranges and kinds of the numbers, dates, times and quantities of `text`
+pub fn find_quantities(text: &str) -> Vec<(usize, usize, QuantityKind)> {
+    let mut found = Vec::new();
+    let mut start = None;
+    for (index, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        if !c.is_whitespace() {
+            start.get_or_insert(index);
+            continue;
+        }
+        if let Some(word_start) = start.take() {
+            let word = &text[word_start..index];
+            let trimmed_start = word.trim_start_matches(LEADING_PUNCTUATION);
+            let trimmed = trimmed_start.trim_end_matches(TRAILING_PUNCTUATION);
+            if let Some(kind) = classify(trimmed) {
+                let begin = word_start + (word.len() - trimmed_start.len());
+                found.push((begin, begin + trimmed.len(), kind));
+            }
+        }
+    }
+    found
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+/// # Labels numbers, dates, times and quantities
+pub struct QuantityTagger;
+
+impl QuantityTagger {
+    pub fn new() -> QuantityTagger {
+        QuantityTagger
+    }
+}
+
+impl Replacer for QuantityTagger {
+    fn replace(&self, text: &str) -> (String, Vec<Replacement>) {
+        let mut replaced = String::with_capacity(text.len());
+        let mut replacements = Vec::new();
+        let mut last = 0;
+        for (start, end, kind) in find_quantities(text) {
+            replaced.push_str(&text[last..start]);
+            replaced.push_str(kind.placeholder());
+            replacements.push(Replacement {
+                placeholder: kind.placeholder(),
+                word: text[start..end].to_owned(),
+                label: kind.label(),
+            });
+            last = end;
+        }
+        replaced.push_str(&text[last..]);
+        (replaced, replacements)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hooks;
+    use crate::pos_tagging::{POSTag, ScoredTag};
+
+    #[test]
+    fn replaces_and_restores_quantities() {
+        let tagger = QuantityTagger::new();
+        let text = "At 3:15pm on 2021-06-01, 1,250 people paid $15 (or 20%) for 5 kg.";
+        let (replaced, replacements) = tagger.replace(text);
+        assert_eq!(
+            replaced,
+            "At xxtime on xxdate, xxnumber people paid xxquantity (or xxquantity) for 5 kg."
+        );
+        let mut sentence: Vec<ScoredTag> = ["At", "xxtime", "on", "xxdate", ",", "xxnumber"]
+            .iter()
+            .map(|word| ScoredTag {
+                tag: POSTag {
+                    word: word.to_string(),
+                    label: String::from("NN"),
+                },
+                score: 0.5,
+            })
+            .collect();
+        hooks::restore(&mut sentence, replacements);
+        let tags: Vec<String> = sentence.iter().map(|scored_tag| scored_tag.tag.to_string()).collect();
+        assert_eq!(tags, ["At/NN", "3:15pm/TIME", "on/NN", "2021-06-01/DATE", ",/NN", "1,250/CD"]);
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        assert_eq!(classify("1,25"), None);
+        assert_eq!(classify("13/45/2021"), None);
+        assert_eq!(classify("25:00"), None);
+        assert_eq!(classify("13pm"), None);
+        assert_eq!(classify("5xyz"), None);
+        assert_eq!(normalize("12am", "TIME").as_deref(), Some("00:00"));
+        assert_eq!(normalize("-3.5", "CD").as_deref(), Some("-3.5"));
+    }
+}
@@ -0,0 +1,168 @@
+//! # Document records
+//! Input of `--input-format json` or `jsonl`: documents given as records with an id and a
+//! text, in a JSON array or one JSON object per line, so that callers exchanging files keep
+//! track of which result belongs to which record.
+//!
+//! ```text
+//! [{"id": "review-1", "text": "Great phone."}, {"id": 2, "text": "Battery died."}]
+//! ```
+//!
+//! Each record is tagged as a document identified by its id, a string or a number, in every
+//! output format (the `id` of the documents in JSON, `# newdoc id` in TSV and CoNLL-U). Ids
+//! must be unique across the input files.
+//!
+//! Emails are read as records too, with `--input-format eml` (a message per file) or `mbox`:
+//! the body of each message, stripped of its headers and quoted replies (see `email`), is
+//! tagged as a document identified by its `Message-ID`, or by the file and the position of
+//! the message when it has none.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::email::parse_messages;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// # Format of the input files
+pub enum InputFormat {
+    /// Plain text, each file a document
+    Text,
+    /// JSON array of records
+    Json,
+    /// One JSON record per line
+    Jsonl,
+    /// RFC 822 email message
+    Eml,
+    /// Archive of email messages
+    Mbox,
+}
+
+impl Default for InputFormat {
+    fn default() -> InputFormat {
+        InputFormat::Text
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<InputFormat, Self::Err> {
+        match s {
+            "text" => Ok(InputFormat::Text),
+            "json" => Ok(InputFormat::Json),
+            "jsonl" => Ok(InputFormat::Jsonl),
+            "eml" => Ok(InputFormat::Eml),
+            "mbox" => Ok(InputFormat::Mbox),
+            _ => Err(anyhow!(
+                "Unknown input format '{}', expected one of text, json, jsonl, eml, mbox",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// # Document of a JSON or email input
+pub struct Record {
+    pub id: String,
+    pub text: String,
+}
+
+#[derive(Deserialize)]
+struct RawRecord {
+    id: Value,
+    text: String,
+}
+
+impl RawRecord {
+    fn into_record(self) -> anyhow::Result<Record> {
+        let id = match self.id {
+            Value::String(id) => id,
+            Value::Number(id) => id.to_string(),
+            other => bail!("Invalid record id {}, expected a string or a number", other),
+        };
+        Ok(Record { id, text: self.text })
+    }
+}
+
+/// Records of `contents`, a JSON array of records or, with `InputFormat::Jsonl`, one record
+/// per line. Emails without a message id are named `source#N`, `N` being their position.
+pub fn parse_records(contents: &str, format: InputFormat, source: &str) -> anyhow::Result<Vec<Record>> {
+    match format {
+        InputFormat::Eml | InputFormat::Mbox => Ok(parse_messages(contents, format == InputFormat::Mbox)
+            .into_iter()
+            .enumerate()
+            .map(|(index, (id, text))| Record {
+                id: id.unwrap_or_else(|| format!("{}#{}", source, index + 1)),
+                text,
+            })
+            .collect()),
+        InputFormat::Text => bail!("Text input holds no records"),
+        InputFormat::Json => {
+            let records: Vec<RawRecord> = serde_json::from_str(contents)
+                .context("Expected an array of {\"id\", \"text\"} records")?;
+            records.into_iter().map(RawRecord::into_record).collect()
+        }
+        InputFormat::Jsonl => contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(number, line)| {
+                parse_record(line).with_context(|| format!("Invalid record on line {}", number + 1))
+            })
+            .collect(),
+    }
+}
+
+/// Record of a single JSON object
+pub fn parse_record(json: &str) -> anyhow::Result<Record> {
+    serde_json::from_str::<RawRecord>(json)?.into_record()
+}
+
+/// Records of the files at `paths`, in order, checking that their ids are unique
+pub fn read_records(paths: &[PathBuf], format: InputFormat) -> anyhow::Result<Vec<Record>> {
+    let mut records = Vec::new();
+    let mut ids = HashSet::new();
+    for path in paths {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        let file_records = parse_records(&contents, format, &path.to_string_lossy())
+            .with_context(|| format!("Invalid records in {}", path.display()))?;
+        for record in file_records {
+            if !ids.insert(record.id.clone()) {
+                bail!("Duplicate record id '{}' in {}", record.id, path.display());
+            }
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_json_and_jsonl_records() {
+        let records = parse_records(r#"[{"id": "a", "text": "Hi."}, {"id": 2, "text": ""}]"#, InputFormat::Json, "a.json").unwrap();
+        assert_eq!(
+            records,
+            vec![
+                Record { id: "a".to_owned(), text: "Hi.".to_owned() },
+                Record { id: "2".to_owned(), text: String::new() },
+            ]
+        );
+        let records = parse_records("{\"id\": \"a\", \"text\": \"Hi.\"}\n\n{\"id\": 7, \"text\": \"Yo.\"}\n", InputFormat::Jsonl, "a.jsonl").unwrap();
+        assert_eq!(records[1].id, "7");
+        let err = parse_records("{\"id\": \"a\", \"text\": \"Hi.\"}\n{\"id\": null, \"text\": \"\"}", InputFormat::Jsonl, "a.jsonl").unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+        let records = parse_records("Subject: hi\n\nHello.\n", InputFormat::Eml, "a.eml").unwrap();
+        assert_eq!(records, vec![Record { id: "a.eml#1".to_owned(), text: "Hello.".to_owned() }]);
+    }
+}
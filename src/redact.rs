@@ -0,0 +1,189 @@
+//! # Redaction
+//! Replaces person names, locations and numbers in a text with placeholders (`[PERSON]`,
+//! `[LOCATION]`, `[NUMBER]`), for the `redact` subcommand. Names and locations come from
+//! rust-bert's named entity model (BERT fine-tuned on CoNLL-03), numbers from the `CD` tag
+//! of the part-of-speech model. Every replacement is recorded in a mapping to the original
+//! text, written alongside the redacted text.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Context;
+use rust_bert::pipelines::token_classification::{
+    TokenClassificationConfig, TokenClassificationModel,
+};
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::pos_tagging::POSModel;
+use crate::sentences;
+use crate::warnings::WarningSink;
+
+pub const PERSON: &str = "[PERSON]";
+pub const LOCATION: &str = "[LOCATION]";
+pub const NUMBER: &str = "[NUMBER]";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// # Replacement of a span of the original text by a placeholder
+pub struct Redaction {
+    pub placeholder: &'static str,
+    /// Replaced text
+    pub original: String,
+    /// Byte range of the replaced text in the original text
+    pub start: usize,
+    pub end: usize,
+    /// Byte offset of the placeholder in the redacted text
+    pub redacted_start: usize,
+}
+
+/// # Redactor combining the named entity and part-of-speech models
+pub struct Redactor {
+    ner_model: TokenClassificationModel,
+    pos_model: POSModel,
+    batch_size: usize,
+}
+
+impl Redactor {
+    /// Load both models with the device, threads and batch size from `config`, passing the
+    /// model files loaded unverified to `warnings`
+    pub fn new(config: &Config, warnings: &dyn WarningSink) -> anyhow::Result<Redactor> {
+        let mut ner_config = TokenClassificationConfig::default();
+        if let Some(device) = config.device()? {
+            ner_config.device = device;
+        }
+        Ok(Redactor {
+            pos_model: POSModel::new(config.pos_config(warnings)?)?,
+            ner_model: TokenClassificationModel::new(ner_config)?,
+            batch_size: config.batch_size(),
+        })
+    }
+
+    /// Redact `text`
+    ///
+    /// # Returns
+    ///
+    /// * The redacted text, and the `Redaction`s applied to it in text order
+    pub fn redact(&self, text: &str) -> (String, Vec<Redaction>) {
+        let spans = self.sensitive_spans(text);
+        let mut redacted = String::with_capacity(text.len());
+        let mut redactions = Vec::with_capacity(spans.len());
+        let mut last = 0;
+        for (start, end, placeholder) in spans {
+            redacted.push_str(&text[last..start]);
+            redactions.push(Redaction {
+                placeholder,
+                original: text[start..end].to_owned(),
+                start,
+                end,
+                redacted_start: redacted.len(),
+            });
+            redacted.push_str(placeholder);
+            last = end;
+        }
+        redacted.push_str(&text[last..]);
+        (redacted, redactions)
+    }
+
+    /// Byte ranges of `text` to redact with their placeholder, sorted and non-overlapping
+    fn sensitive_spans(&self, text: &str) -> Vec<(usize, usize, &'static str)> {
+        let mut spans = Vec::new();
+        let sentences = sentences::split(text);
+        for batch in sentences.chunks(self.batch_size) {
+            let entities = self.ner_model.predict(batch, true, false);
+            for (&sentence, tokens) in batch.iter().zip(entities) {
+                let base = sentences::offset_in(text, sentence);
+                let byte_offsets = sentences::char_byte_offsets(sentence);
+                for token in tokens {
+                    let placeholder = match token.label.trim_start_matches("B-").trim_start_matches("I-") {
+                        "PER" => PERSON,
+                        "LOC" => LOCATION,
+                        _ => continue,
+                    };
+                    let offset = match token.offset {
+                        Some(offset) => offset,
+                        None => continue,
+                    };
+                    if let (Some(&start), Some(&end)) = (
+                        byte_offsets.get(offset.begin as usize),
+                        byte_offsets.get(offset.end as usize),
+                    ) {
+                        spans.push((base + start, base + end, placeholder));
+                    }
+                }
+            }
+
+            for (&sentence, pos_tags) in batch.iter().zip(self.pos_model.predict_ref(batch)) {
+                let base = sentences::offset_in(text, sentence);
+                for pos_tag in pos_tags.iter().filter(|pos_tag| pos_tag.label == "CD") {
+                    let start = base + sentences::offset_in(sentence, pos_tag.word);
+                    spans.push((start, start + pos_tag.word.len(), NUMBER));
+                }
+            }
+        }
+        merge_spans(text, spans)
+    }
+}
+
+/// Sort spans, drop those overlapping an earlier one and join spans of the same kind
+/// separated only by whitespace ("Mary Shelley" is one `[PERSON]`)
+fn merge_spans(
+    text: &str,
+    mut spans: Vec<(usize, usize, &'static str)>,
+) -> Vec<(usize, usize, &'static str)> {
+    spans.sort_by_key(|&(start, end, _)| (start, std::cmp::Reverse(end)));
+    let mut merged: Vec<(usize, usize, &'static str)> = Vec::with_capacity(spans.len());
+    for (start, end, placeholder) in spans {
+        match merged.last_mut() {
+            Some(last) if start < last.1 => {}
+            Some(last)
+                if last.2 == placeholder && text[last.1..start].trim().is_empty() =>
+            {
+                last.1 = end
+            }
+            _ => merged.push((start, end, placeholder)),
+        }
+    }
+    merged
+}
+
+/// Redact the file at `in_path` into `out_path` and write the mapping as JSON to `mapping_path`
+pub fn redact_file(
+    in_path: &Path,
+    out_path: &Path,
+    mapping_path: &Path,
+    config: &Config,
+    warnings: &dyn WarningSink,
+) -> anyhow::Result<()> {
+    let contents = fs::read_to_string(in_path)
+        .with_context(|| format!("Could not read {}", in_path.display()))?;
+    let (redacted, redactions) = Redactor::new(config, warnings)?.redact(&contents);
+    let (output, mut file) = config.output_policy().begin(out_path)?;
+    file.write_all(redacted.as_bytes())
+        .with_context(|| format!("Could not write {}", out_path.display()))?;
+    output.commit()?;
+    fs::write(mapping_path, serde_json::to_string_pretty(&redactions)?)
+        .with_context(|| format!("Could not write {}", mapping_path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merges_adjacent_spans_of_the_same_kind() {
+        let text = "Mary Shelley lived in 1818 London.";
+        let spans = vec![
+            (22, 26, NUMBER),
+            (5, 12, PERSON),
+            (0, 4, PERSON),
+            (27, 33, LOCATION),
+            (0, 12, PERSON),
+        ];
+        assert_eq!(
+            merge_spans(text, spans),
+            vec![(0, 12, PERSON), (22, 26, NUMBER), (27, 33, LOCATION)]
+        );
+    }
+}
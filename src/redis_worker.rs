@@ -0,0 +1,171 @@
+//! # Redis queue worker
+//! With the `redis-worker` feature, `--redis-in jobs --redis-out results` pops text jobs
+//! from a Redis list, tags them and pushes the results to another list:
+//!
+//! ```text
+//! LPUSH jobs '{"id": "review-1", "text": "Great phone."}'
+//! BRPOP results 0
+//! {"id":"review-1","sentences":[[{"word":"Great","label":"JJ"}, ...]]}
+//! ```
+//!
+//! Jobs are records as in `records`; the sentences of a result are those of `--format json`.
+//! Each job is moved atomically to the `<jobs>:processing` list while it is tagged, so that
+//! the jobs of a stopped worker can be found and queued again. A job failing, with an error
+//! or a panic of the model, is retried up to `retries` times, then moved to the
+//! `<jobs>:failed` list with a warning, as are the jobs that are not valid records.
+//!
+//! `workers` threads pop jobs concurrently, each with its own connection and its own copy
+//! of the model.
+
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use anyhow::{anyhow, Context};
+use redis::{Commands, Connection};
+use serde_json::json;
+
+use crate::config::Config;
+use crate::exit;
+use crate::format::TagFormat;
+use crate::records::{parse_record, Record};
+use crate::rusttagr;
+use crate::tagger::Tagger;
+use crate::warnings::{SharedSink, Warning, WarningSink};
+
+/// Server connected to when none is given
+pub const DEFAULT_URL: &str = "redis://127.0.0.1/";
+
+/// Retries of a failing job when none are given
+pub const DEFAULT_RETRIES: usize = 3;
+
+/// Seconds a worker waits for a job before checking the connection again
+const POP_TIMEOUT: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// # Queues and concurrency of the worker mode
+pub struct WorkerOptions {
+    /// `redis://` URL of the server
+    pub url: String,
+    /// List the jobs are popped from
+    pub input_key: String,
+    /// List the results are pushed to
+    pub output_key: String,
+    /// Number of worker threads
+    pub workers: usize,
+    /// Attempts at a failing job after the first one
+    pub retries: usize,
+}
+
+impl WorkerOptions {
+    /// List of the jobs being tagged
+    pub fn processing_key(&self) -> String {
+        format!("{}:processing", self.input_key)
+    }
+
+    /// List of the jobs that could not be tagged
+    pub fn failed_key(&self) -> String {
+        format!("{}:failed", self.input_key)
+    }
+}
+
+/// Run `options.workers` workers until the process is interrupted or a worker loses its
+/// connection, passing the warnings of the workers to `warnings`
+pub fn run(options: &WorkerOptions, config: &Config, warnings: &SharedSink) -> anyhow::Result<()> {
+    let client = redis::Client::open(options.url.as_str())
+        .with_context(|| format!("Invalid Redis URL {}", options.url))?;
+    let config = Config {
+        format: Some(TagFormat::Json),
+        ..config.clone()
+    };
+    let (errors, first_error) = mpsc::channel();
+    for _ in 0..options.workers.max(1) {
+        let client = client.clone();
+        let options = options.clone();
+        let config = config.clone();
+        let errors = errors.clone();
+        let warnings = Arc::clone(warnings);
+        thread::spawn(move || {
+            let result = client
+                .get_connection()
+                .context("Could not connect to Redis")
+                .and_then(|mut connection| work(&mut connection, &options, &config, warnings.as_ref()));
+            let _ = errors.send(result);
+        });
+    }
+    drop(errors);
+    //workers only stop on errors
+    first_error
+        .recv()
+        .map_err(|_| anyhow!("Every worker stopped"))?
+}
+
+/// Pop, tag and push jobs on `connection` until an error of Redis or the model
+fn work(
+    connection: &mut Connection,
+    options: &WorkerOptions,
+    config: &Config,
+    warnings: &dyn WarningSink,
+) -> anyhow::Result<()> {
+    let tagger = config.tagger(warnings)?;
+    let config = &config.with_tuned_batch_size(tagger.as_ref(), warnings);
+    let processing = options.processing_key();
+    loop {
+        let job: Option<String> = redis::cmd("BRPOPLPUSH")
+            .arg(&options.input_key)
+            .arg(&processing)
+            .arg(POP_TIMEOUT)
+            .query(connection)
+            .context("Could not pop a job")?;
+        let job = match job {
+            Some(job) => job,
+            None => continue,
+        };
+        let result = parse_record(&job).and_then(|record| {
+            let mut retries = 0;
+            loop {
+                //a panic of the model is a failed attempt too, instead of ending the worker
+                //with the job left in the processing list
+                let attempt = exit::catch_inference(|| tag_record(&*tagger, &record, config, warnings));
+                match attempt.and_then(|result| result) {
+                    Err(err) if retries < options.retries => {
+                        retries += 1;
+                        warnings.warn(Warning::JobRetried {
+                            job: record.id.clone(),
+                            retry: retries,
+                            retries: options.retries,
+                            error: format!("{:#}", err),
+                        });
+                    }
+                    result => break result,
+                }
+            }
+        });
+        match result {
+            Ok(result) => {
+                let _: () = connection
+                    .lpush(&options.output_key, result)
+                    .context("Could not push a result")?;
+            }
+            Err(err) => {
+                warnings.warn(Warning::JobFailed {
+                    key: options.failed_key(),
+                    error: format!("{:#}", err),
+                });
+                let _: () = connection
+                    .lpush(options.failed_key(), &job)
+                    .context("Could not push a failed job")?;
+            }
+        }
+        let _: () = connection
+            .lrem(&processing, 1, &job)
+            .context("Could not remove a finished job")?;
+    }
+}
+
+/// JSON result of `record`: its id and the tags of its sentences
+fn tag_record(tagger: &dyn Tagger, record: &Record, config: &Config, warnings: &dyn WarningSink) -> anyhow::Result<String> {
+    let mut tag_writer = config.tag_writer(Vec::new());
+    rusttagr::tag_text_with(tagger, &record.text, &mut tag_writer, config, warnings)?;
+    let sentences: serde_json::Value = serde_json::from_slice(&tag_writer.finish()?)?;
+    Ok(json!({ "id": record.id, "sentences": sentences }).to_string())
+}
@@ -1,8 +1,15 @@
 extern crate anyhow;
 
 use std;
+use std::io::Write;
+use crate::config::Config;
+use crate::depparse::DependencyParser;
+use crate::format::TagWriter;
 use crate::pos_tagging;
-use crate::pos_tagging::POSModel;
+use crate::pos_tagging::{POSModel, PredictStats};
+use crate::sentences;
+use crate::tagger::{drop_scores, Tagger};
+use crate::warnings::WarningSink;
 
 fn try_tag(input: &str) -> anyhow::Result<std::vec::Vec<std::vec::Vec<pos_tagging::POSTag>>> {
   let format_vec = [input]; 
@@ -12,6 +19,11 @@ fn try_tag(input: &str) -> anyhow::Result<std::vec::Vec<std::vec::Vec<pos_taggin
   Ok(pos_model.predict(&format_vec))
 } 
 
+/// Tags `input` with the default model, returning the `Debug` representation of its tags.
+/// Loads the model on every call and panics on errors.
+#[deprecated(
+  note = "use `prelude::POSModel` from Rust, or `berttagr_tag_json` of the berttagr-ffi crate from other languages"
+)]
 #[no_mangle]
 pub fn rust_tag_r(input: &str) -> String {
   let output = match try_tag(input) {
@@ -25,3 +37,115 @@ pub fn rust_tag_r(input: &str) -> String {
   }
   str_out
 }
+
+
+/// Number of batches whose sentences are length-sorted together when tagging a stream
+pub const SORT_WINDOW_BATCHES: usize = 16;
+
+/// Groups sentence indices into batches of at most `batch_size` sentences of similar length,
+/// shortest first, so that little padding is wasted in each batch
+pub fn length_sorted_batches(sentences: &[&str], batch_size: usize) -> Vec<Vec<usize>> {
+  let mut order: Vec<usize> = (0..sentences.len()).collect();
+  order.sort_by_key(|&index| sentences[index].len());
+  order.chunks(batch_size.max(1)).map(|chunk| chunk.to_vec()).collect()
+}
+
+/// Tags `sentences`, sending at most `batch_size` of them to the model at once.
+/// Batches are built from length-sorted sentences; the output is in the original order.
+pub fn tag_batched(pos_model: &POSModel, sentences: &[&str], batch_size: usize) -> Vec<Vec<pos_tagging::POSTag>> {
+  tag_batched_with_stats(pos_model, sentences, batch_size).0
+}
+
+/// Like `tag_batched`, also returning the statistics summed over the batches
+pub fn tag_batched_with_stats(pos_model: &POSModel, sentences: &[&str], batch_size: usize) -> (Vec<Vec<pos_tagging::POSTag>>, PredictStats) {
+  let mut output: Vec<Option<Vec<pos_tagging::POSTag>>> = sentences.iter().map(|_| None).collect();
+  let mut stats = PredictStats::default();
+  for batch in length_sorted_batches(sentences, batch_size) {
+    let texts: Vec<&str> = batch.iter().map(|&index| sentences[index]).collect();
+    let (batch_output, batch_stats) = pos_model.predict_with_stats(&texts);
+    stats.add(&batch_stats);
+    for (index, pos_tags) in batch.into_iter().zip(batch_output) {
+      output[index] = Some(pos_tags);
+    }
+  }
+  (output.into_iter().map(Option::unwrap_or_default).collect(), stats)
+}
+
+/// Keeps only the tags of `sentence` whose label is in `labels`
+pub fn filter_labels(sentence: &mut Vec<pos_tagging::POSTag>, labels: &[String]) {
+  sentence.retain(|pos_tag| labels.contains(&pos_tag.label));
+}
+
+/// Tags `input` and writes the result to `writer` as it goes.
+/// Sentences are tagged one window of `SORT_WINDOW_BATCHES` batches at a time,
+/// so only that window's output is held in memory rather than the whole document's.
+/// Returns the prediction statistics of the whole input. The warnings of the model are passed to `warnings`.
+pub fn tag_to_writer<W: Write>(input: &str, writer: &mut W, config: &Config, warnings: &dyn WarningSink) -> anyhow::Result<PredictStats> {
+  let pos_model = config.pos_model(warnings)?;
+  let parser = config.dependency_parser()?;
+  let batch_size = config.batch_size();
+  let mut sentences = config.sentences(input);
+  let mut tag_writer = config.tag_writer(writer);
+  let mut stats = PredictStats::default();
+
+  loop {
+    let window: Vec<&str> = sentences.by_ref().take(batch_size * SORT_WINDOW_BATCHES).collect();
+    if window.is_empty() {
+      break;
+    }
+    let (mut output, window_stats) = if config.pretokenized.unwrap_or(false) {
+      let (scored, window_stats) = config.adaptive_batch().tag(&pos_model, &window, warnings)?;
+      (drop_scores(scored), window_stats)
+    } else {
+      tag_batched_with_stats(&pos_model, &window, batch_size)
+    };
+    stats.add(&window_stats);
+    let dependencies = match &parser {
+      Some(parser) => Some(parser.parse(&output)?),
+      None => None,
+    };
+    for (index, sentence) in output.iter_mut().enumerate() {
+      if let Some(labels) = &config.filter_tags {
+        filter_labels(sentence, labels);
+      }
+      let sentence_dependencies = dependencies.as_ref().map(|deps| deps[index].as_slice());
+      let text = window[index];
+      tag_writer.write_located_sentence(text, sentences::offset_in(input, text), sentence, sentence_dependencies)?;
+    }
+  }
+  tag_writer.finish()?;
+  Ok(stats)
+}
+
+/// Tags the sentences of `text` with `tagger`, in batches and filtered as set in `config`,
+/// and writes them to `tag_writer`. Panics of the model are returned as `InferenceError`s,
+/// lowered batch sizes passed to `warnings`.
+pub fn tag_text_with<W: Write>(tagger: &dyn Tagger, text: &str, tag_writer: &mut TagWriter<W>, config: &Config, warnings: &dyn WarningSink) -> anyhow::Result<()> {
+  let split: Vec<&str> = config.sentences(text).collect();
+  let (scored, _) = config.adaptive_batch().tag(tagger, &split, warnings)?;
+  for (sentence, mut pos_tags) in split.iter().zip(drop_scores(scored)) {
+    if let Some(labels) = &config.filter_tags {
+      filter_labels(&mut pos_tags, labels);
+    }
+    tag_writer.write_located_sentence(sentence, sentences::offset_in(text, sentence), &pos_tags, None)?;
+  }
+  Ok(())
+}
+
+/// Tags `input` sentence by sentence with the model, batching, filtering and format from `config`
+pub fn tag_with_config(input: &str, config: &Config, warnings: &dyn WarningSink) -> anyhow::Result<String> {
+  let mut out = Vec::new();
+  tag_to_writer(input, &mut out, config, warnings)?;
+  Ok(String::from_utf8(out)?)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn batches_group_similar_lengths() {
+    let sentences = ["a much longer sentence here.", "short.", "a medium one.", "tiny"];
+    assert_eq!(length_sorted_batches(&sentences, 2), vec![vec![3, 1], vec![2, 0]]);
+  }
+}
\ No newline at end of file
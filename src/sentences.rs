@@ -0,0 +1,192 @@
+//! # Sentence splitting
+//! Splits raw text into sentence-sized slices so that it can be sent to the model
+//! in batches rather than as a single sequence.
+
+use std::ops::Range;
+
+/// Words ending in a period that do not close a sentence
+const ABBREVIATIONS: &[&str] = &[
+    "Mr", "Mrs", "Ms", "Dr", "St", "Jr", "Sr", "Prof", "Gen", "Col", "Capt", "vs", "etc", "e.g",
+    "i.e", "Jan", "Feb", "Mar", "Apr", "Jun", "Jul", "Aug", "Sep", "Sept", "Oct", "Nov", "Dec",
+];
+
+/// Split a text into sentences
+///
+/// A sentence ends at `.`, `!` or `?` followed by whitespace, or at a blank line.
+/// The returned sentences are trimmed slices of `text`; empty ones are dropped.
+///
+/// # Arguments
+///
+/// * `text` - Raw input text
+pub fn split(text: &str) -> Vec<&str> {
+    iter(text).collect()
+}
+
+/// Iterate over the sentences of a text lazily, see `split`
+pub fn iter(text: &str) -> Sentences<'_> {
+    Sentences {
+        text,
+        start: 0,
+        position: 0,
+        by_line: false,
+    }
+}
+
+/// Iterate over the lines of a text already split into sentences, one per line. Lines are
+/// trimmed and blank ones kept as empty sentences, so that the n-th sentence is the n-th
+/// line.
+pub fn iter_lines(text: &str) -> Sentences<'_> {
+    Sentences {
+        text,
+        start: 0,
+        position: 0,
+        by_line: true,
+    }
+}
+
+/// # Iterator over the sentences of a text
+pub struct Sentences<'a> {
+    text: &'a str,
+    /// Start of the current sentence
+    start: usize,
+    /// Next character to scan
+    position: usize,
+    /// Every line is a sentence
+    by_line: bool,
+}
+
+impl<'a> Iterator for Sentences<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let text = self.text;
+        if self.by_line {
+            if self.start >= text.len() {
+                return None;
+            }
+            let end = text[self.start..]
+                .find('\n')
+                .map_or(text.len(), |index| self.start + index + 1);
+            let line = text[self.start..end].trim();
+            self.start = end;
+            return Some(line);
+        }
+        while let Some(c) = text[self.position..].chars().next() {
+            let index = self.position;
+            let end = index + c.len_utf8();
+            let next = text[end..].chars().next();
+            let is_boundary = match c {
+                '.' | '!' | '?' => {
+                    next.map_or(true, char::is_whitespace)
+                        && !ends_with_abbreviation(&text[self.start..index])
+                }
+                '\n' => text[end..]
+                    .trim_start_matches(|c: char| c == ' ' || c == '\t' || c == '\r')
+                    .starts_with('\n'),
+                _ => false,
+            };
+            self.position = end;
+            if is_boundary {
+                let sentence = text[self.start..end].trim();
+                self.start = end;
+                if !sentence.is_empty() {
+                    return Some(sentence);
+                }
+            }
+        }
+        let sentence = text[self.start..].trim();
+        self.start = text.len();
+        if sentence.is_empty() {
+            None
+        } else {
+            Some(sentence)
+        }
+    }
+}
+
+/// Byte position of each character of `text`, followed by the length of `text`.
+/// Converts the character offsets of model tokens into string slices.
+pub fn char_byte_offsets(text: &str) -> Vec<usize> {
+    text.char_indices()
+        .map(|(index, _)| index)
+        .chain(std::iter::once(text.len()))
+        .collect()
+}
+
+/// Byte offset of `slice` in `text`, where `slice` is a subslice of `text`
+/// such as the sentences returned by `split`
+pub fn offset_in(text: &str, slice: &str) -> usize {
+    find_offset_in(text, slice).expect("slice is not part of the text")
+}
+
+/// Byte offset of `slice` in `text`, or `None` if `slice` is not a subslice of `text`
+pub fn find_offset_in(text: &str, slice: &str) -> Option<usize> {
+    let offset = (slice.as_ptr() as usize).wrapping_sub(text.as_ptr() as usize);
+    Some(offset).filter(|&offset| offset <= text.len() && offset + slice.len() <= text.len())
+}
+
+/// Byte range of the first occurrence of `word` in `text` from the byte `from`, ignoring
+/// case as lower-casing models give lower-cased words
+pub fn find_word(text: &str, from: usize, word: &str) -> Option<Range<usize>> {
+    if word.is_empty() {
+        return None;
+    }
+    text[from..].char_indices().find_map(|(index, _)| {
+        let start = from + index;
+        let mut chars = text[start..].char_indices();
+        for expected in word.chars() {
+            match chars.next() {
+                Some((_, c)) if c.to_lowercase().eq(expected.to_lowercase()) => {}
+                _ => return None,
+            }
+        }
+        let end = chars.next().map_or(text.len(), |(index, _)| start + index);
+        Some(start..end)
+    })
+}
+
+fn ends_with_abbreviation(text: &str) -> bool {
+    let word = text.rsplit(char::is_whitespace).next().unwrap_or("");
+    let is_initial = word.chars().count() == 1 && word.chars().all(char::is_uppercase);
+    is_initial || ABBREVIATIONS.contains(&word)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splits_on_terminators_and_blank_lines() {
+        let text = "To Mrs. Saville, England. You will rejoice!\nIs it so?\n\nA new paragraph";
+        assert_eq!(
+            split(text),
+            vec![
+                "To Mrs. Saville, England.",
+                "You will rejoice!",
+                "Is it so?",
+                "A new paragraph",
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_wrapped_lines_together() {
+        let text = "an enterprise which you have regard-\ned with such evil forebodings.";
+        assert_eq!(split(text).len(), 1);
+    }
+
+    #[test]
+    fn keeps_one_sentence_per_line() {
+        let text = "Is it so? Yes.\n\n  Mr. Walton  \r\n";
+        let lines: Vec<&str> = iter_lines(text).collect();
+        assert_eq!(lines, vec!["Is it so? Yes.", "", "Mr. Walton"]);
+        assert_eq!(offset_in(text, lines[2]), 18);
+    }
+
+    #[test]
+    fn finds_words_ignoring_case() {
+        assert_eq!(find_word("The U.S. agree", 0, "u.s."), Some(4..8));
+        assert_eq!(find_word("the cat, the dog", 1, "THE"), Some(9..12));
+        assert_eq!(find_word("the cat", 0, "dog"), None);
+    }
+}
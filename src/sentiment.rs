@@ -0,0 +1,55 @@
+//! # Sentiment analysis
+//! Classifies the sentiment of each sentence of a file with rust-bert's sentiment pipeline
+//! (DistilBERT fine-tuned on SST-2), for the `sentiment` subcommand. Sentence splitting,
+//! batching, device placement and output formats are shared with tagging.
+
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::Context;
+use rust_bert::pipelines::sentiment::{SentimentConfig, SentimentModel};
+
+use crate::config::Config;
+use crate::format::LabelWriter;
+use crate::sentences;
+
+/// Classify the sentiment of each sentence of `in_path` and write the results to `out_path`
+///
+/// # Arguments
+///
+/// * `in_path` - Input text file
+/// * `out_path` - Output file, created, replaced or appended to as set by `output_exists`
+/// * `config` - Device, threads, batching and format settings
+pub fn classify_file(in_path: &Path, out_path: &Path, config: &Config) -> anyhow::Result<()> {
+    let contents = fs::read_to_string(in_path)
+        .with_context(|| format!("Could not read {}", in_path.display()))?;
+    let (output, file) = config.output_policy().begin(out_path)?;
+    classify_to_writer(&contents, BufWriter::new(file), config)?;
+    output.commit()
+}
+
+/// Classify the sentiment of each sentence of `input` and write the results to `writer`
+pub fn classify_to_writer<W: Write>(input: &str, writer: W, config: &Config) -> anyhow::Result<()> {
+    let mut label_writer = LabelWriter::new(writer, config.format())?;
+
+    let mut sentiment_config = SentimentConfig::default();
+    if let Some(device) = config.device()? {
+        sentiment_config.device = device;
+    }
+    if let Some(threads) = config.threads {
+        tch::set_num_threads(threads as i32);
+    }
+    let sentiment_model = SentimentModel::new(sentiment_config)?;
+
+    let sentences = sentences::split(input);
+    for batch in sentences.chunks(config.batch_size()) {
+        let output = sentiment_model.predict(batch);
+        for (sentence, sentiment) in batch.iter().zip(output) {
+            let label = format!("{:?}", sentiment.polarity);
+            label_writer.write_label(sentence, &label, sentiment.score, &sentiment)?;
+        }
+    }
+    label_writer.finish()?;
+    Ok(())
+}
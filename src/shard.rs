@@ -0,0 +1,97 @@
+//! # Sharding
+//! `--shard i/N` splits a run between N instances of the same command, on one machine or
+//! several, without a coordinator: instance `i` (from 1) tags the i-th of N contiguous
+//! shares of the input and the others skip it. The share is the files of a run over
+//! several inputs, the records with `--input-format`, or the sentences of a single input
+//! file, so that every instance gets work whatever the layout of the corpus.
+//!
+//! The shares only depend on the input, so every instance agrees on them, and are in the
+//! order of the input: concatenating the TSV or CoNLL-U outputs of the shards in order
+//! gives the output of the whole run.
+//!
+//! ```text
+//! berttagr_file --shard 1/2 --format tsv corpus.txt part1.tsv   # on host a
+//! berttagr_file --shard 2/2 --format tsv corpus.txt part2.tsv   # on host b
+//! cat part1.tsv part2.tsv > corpus.tsv
+//! ```
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+/// # Share of the input of a run
+pub struct Shard {
+    /// Number of the share, from 1 to `count`
+    pub index: usize,
+    pub count: usize,
+}
+
+impl Shard {
+    /// Indices of the share among `len` items: the i-th of `count` contiguous ranges whose
+    /// lengths differ by at most one
+    pub fn range(&self, len: usize) -> Range<usize> {
+        let bound = |index: usize| index * len / self.count;
+        bound(self.index - 1)..bound(self.index)
+    }
+
+    /// The share of `items`
+    pub fn select<'a, T>(&self, items: &'a [T]) -> &'a [T] {
+        &items[self.range(items.len())]
+    }
+}
+
+impl FromStr for Shard {
+    type Err = anyhow::Error;
+
+    /// Parse `i/N`
+    fn from_str(s: &str) -> Result<Shard, Self::Err> {
+        let invalid = || format!("Invalid shard '{}', expected i/N with i from 1 to N (e.g. 2/8)", s);
+        let index = s.find('/').with_context(invalid)?;
+        let shard = Shard {
+            index: s[..index].trim().parse().with_context(invalid)?,
+            count: s[index + 1..].trim().parse().with_context(invalid)?,
+        };
+        if shard.index == 0 || shard.index > shard.count {
+            bail!(invalid());
+        }
+        Ok(shard)
+    }
+}
+
+impl TryFrom<String> for Shard {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Shard, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splits_into_contiguous_shares() {
+        let shards: Vec<Shard> = (1..=3).map(|index| Shard { index, count: 3 }).collect();
+        let ranges: Vec<Range<usize>> = shards.iter().map(|shard| shard.range(10)).collect();
+        assert_eq!(ranges, vec![0..3, 3..6, 6..10]);
+        assert!(shards[0].select(&['a', 'b']).is_empty());
+        assert_eq!(shards[1].select(&['a', 'b']), &['a']);
+        assert_eq!("2/8".parse::<Shard>().unwrap(), Shard { index: 2, count: 8 });
+        assert!("0/8".parse::<Shard>().is_err());
+        assert!("9/8".parse::<Shard>().is_err());
+        assert!("2".parse::<Shard>().is_err());
+    }
+}
@@ -0,0 +1,840 @@
+//! # Streaming batch pipeline
+//! Tags a file with reading, inference and writing overlapped on three threads connected
+//! by bounded channels:
+//!
+//! * the reader thread memory-maps and splits the input, sorts sentences by length within
+//!   a window and sends them as batches
+//! * the calling thread loads the model, tags each batch and runs the dependency parser
+//!   if one is configured
+//! * the writer thread restores the original sentence order and serializes the results
+//!
+//! The bounded channels keep at most a few batches in flight, so a slow stage throttles
+//! the others instead of letting queues grow.
+//!
+//! Several input files can be tagged into one output with `tag_documents`: the reader goes
+//! through them in order and the writer starts a document, named after its file, before
+//! the first sentence of each. With `tag_records`, the documents are the records of JSON
+//! inputs (see `records`), named after their ids.
+//!
+//! The scores of the model are only kept on the inference thread, where they are added to
+//! the `QualityReport` if one is asked for.
+//!
+//! With `workers` above 1, that many inference threads take the batches in turn, so that a
+//! single large file uses several inference streams: the calling thread and threads each
+//! loading their own copy of the model, on the CPU, or sharing the GPU, whose queue runs
+//! their batches. The batches are numbered as they are taken and the writer puts them back
+//! in that order before restoring the order of the sentences, so the output is the same
+//! whatever the number of workers. The first inference thread to fail closes the queue of
+//! batches, which stops the reader and the other threads.
+//!
+//! With `coordinator` set, the model is not loaded: the batches are tagged by worker
+//! processes connecting to that address, possibly on other machines, see `distributed`.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::str;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use anyhow::{anyhow, Context};
+use memmap2::Mmap;
+
+use crate::config::Config;
+use crate::depparse::{Dependency, DependencyParser};
+use crate::distributed;
+use crate::limits::{LimitReport, Limits};
+use crate::pos_tagging::{POSTag, PredictStats, ScoredTag};
+use crate::quality::QualityReport;
+use crate::records::{read_records, InputFormat};
+use crate::rusttagr;
+use crate::sentences;
+use crate::tagger::{drop_scores, Tagger};
+use crate::warnings::{SharedSink, WarningSink};
+
+/// Number of batches buffered between two stages
+const CHANNEL_CAPACITY: usize = 4;
+
+/// Input of the reader, tagged as one document
+enum Source {
+    /// Text file, memory-mapped
+    File(PathBuf),
+    /// Text of a record
+    Text(String),
+}
+
+/// Sentences to tag, with their index in the output
+struct Batch {
+    /// Documents starting since the previous batch, with the index of their first sentence
+    documents: Vec<(usize, usize)>,
+    indices: Vec<usize>,
+    sentences: Vec<String>,
+    /// Byte offset of each sentence in its document
+    starts: Vec<usize>,
+}
+
+/// Batches of the reader, shared by the inference threads
+struct BatchQueue {
+    /// Receiver of the batches, and the number of batches taken, until the queue is closed
+    receiver: Mutex<Option<(Receiver<Batch>, usize)>>,
+    /// Set by `close`
+    closed: AtomicBool,
+}
+
+impl BatchQueue {
+    fn new(receiver: Receiver<Batch>) -> BatchQueue {
+        BatchQueue {
+            receiver: Mutex::new(Some((receiver, 0))),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Next batch with its number, in the order of the reader, `None` once all were taken
+    /// or the queue is closed
+    fn next(&self) -> Option<(usize, Batch)> {
+        let mut receiver = self.receiver.lock().ok()?;
+        if self.closed.load(Ordering::SeqCst) {
+            *receiver = None;
+        }
+        let (batches, taken) = receiver.as_mut()?;
+        let batch = batches.recv().ok()?;
+        if self.closed.load(Ordering::SeqCst) {
+            *receiver = None;
+            return None;
+        }
+        let sequence = *taken;
+        *taken += 1;
+        Some((sequence, batch))
+    }
+
+    /// Stop handing out batches after an inference thread failed, so that the other threads
+    /// and the reader stop instead of going through the rest of the input
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        //dropping the receiver stops the reader; a thread waiting for a batch drops it once
+        //the batch arrives
+        if let Ok(mut receiver) = self.receiver.try_lock() {
+            *receiver = None;
+        }
+    }
+}
+
+/// Tagged sentences, with their index in the output
+struct TaggedBatch {
+    /// Number of the batch, in the order of the reader
+    sequence: usize,
+    documents: Vec<(usize, usize)>,
+    indices: Vec<usize>,
+    sentences: Vec<String>,
+    starts: Vec<usize>,
+    pos_tags: Vec<Vec<POSTag>>,
+    /// Dependencies of each sentence, when a parser is configured
+    dependencies: Option<Vec<Vec<Dependency>>>,
+}
+
+/// Tag the file at `in_path` and write the result to `out_path`
+///
+/// # Arguments
+///
+/// * `in_path` - Input text file
+/// * `out_path` - Output file, put in place once the whole input is tagged
+/// * `config` - Model, batching, filtering and format settings
+/// * `quality` - Report the scores and labels of the tagged words are added to
+/// * `warnings` - Receives the warnings of the reader and inference threads
+///
+/// # Returns
+///
+/// * `PredictStats` of the whole file, and the inputs left out or truncated by the limits
+pub fn tag_file(
+    in_path: &Path,
+    out_path: &Path,
+    config: &Config,
+    quality: Option<&mut QualityReport>,
+    warnings: &SharedSink,
+) -> anyhow::Result<(PredictStats, LimitReport)> {
+    run(vec![Source::File(in_path.to_path_buf())], None, out_path, config, quality, warnings)
+}
+
+/// Tag the files at `in_paths` into one output at `out_path`, each starting a document
+/// whose id is its path, adding the scores and labels of the tagged words to `quality` and
+/// passing the warnings to `warnings`
+///
+/// # Returns
+///
+/// * `PredictStats` of all the files, and the inputs left out or truncated by the limits
+pub fn tag_documents(
+    in_paths: &[PathBuf],
+    out_path: &Path,
+    config: &Config,
+    quality: Option<&mut QualityReport>,
+    warnings: &SharedSink,
+) -> anyhow::Result<(PredictStats, LimitReport)> {
+    let in_paths = match config.shard {
+        Some(shard) => shard.select(in_paths),
+        None => in_paths,
+    };
+    let ids = in_paths
+        .iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    let sources = in_paths.iter().cloned().map(Source::File).collect();
+    run(sources, Some(ids), out_path, config, quality, warnings)
+}
+
+/// Tag the records of the `format` files at `in_paths` into one output at `out_path`, each
+/// starting a document whose id is the id of the record, as `tag_documents` does
+///
+/// # Returns
+///
+/// * `PredictStats` of all the records, and the sentences left out or truncated by the limits
+pub fn tag_records(
+    in_paths: &[PathBuf],
+    format: InputFormat,
+    out_path: &Path,
+    config: &Config,
+    quality: Option<&mut QualityReport>,
+    warnings: &SharedSink,
+) -> anyhow::Result<(PredictStats, LimitReport)> {
+    let mut records = read_records(in_paths, format)?;
+    if let Some(shard) = config.shard {
+        let share = shard.range(records.len());
+        records.truncate(share.end);
+        records.drain(..share.start);
+    }
+    let mut ids = Vec::with_capacity(records.len());
+    let mut sources = Vec::with_capacity(records.len());
+    for record in records {
+        ids.push(record.id);
+        sources.push(Source::Text(record.text));
+    }
+    run(sources, Some(ids), out_path, config, quality, warnings)
+}
+
+/// Tag `sources` into `out_path`, starting a document with each of `document_ids` if given
+fn run(
+    sources: Vec<Source>,
+    document_ids: Option<Vec<String>>,
+    out_path: &Path,
+    config: &Config,
+    quality: Option<&mut QualityReport>,
+    warnings: &SharedSink,
+) -> anyhow::Result<(PredictStats, LimitReport)> {
+    //loaded first, so that an `auto` batch size is tuned before the reader batches; with a
+    //coordinator, the workers load the model
+    let tagger = match config.coordinator {
+        Some(_) => None,
+        None => Some(config.tagger(warnings.as_ref())?),
+    };
+    let mut config = match &tagger {
+        Some(tagger) => config.with_tuned_batch_size(tagger.as_ref(), warnings.as_ref()),
+        None => config.clone(),
+    };
+    if document_ids.is_some() {
+        //the documents are sharded, not their sentences
+        config.shard = None;
+    }
+    let config = &config;
+    let (batch_sender, batch_receiver) = sync_channel(CHANNEL_CAPACITY);
+    let (tagged_sender, tagged_receiver) = sync_channel(CHANNEL_CAPACITY);
+
+    //the output only replaces `out_path` once every stage has succeeded
+    let (output, out_file) = config.output_policy().begin(out_path)?;
+    let reader = spawn_reader(sources, config.clone(), config.limits(), batch_sender, Arc::clone(warnings));
+    let writer = spawn_writer(out_file, document_ids, config.clone(), tagged_receiver);
+
+    let inference = match &tagger {
+        Some(tagger) => tag_locally(tagger.as_ref(), config, batch_receiver, tagged_sender, quality, warnings),
+        None => coordinate(config, batch_receiver, tagged_sender, quality, warnings),
+    };
+
+    //report the error of the stage that failed first: a closed channel in one stage
+    //is only a consequence of another one stopping
+    let reader = reader.join().map_err(|_| anyhow!("Reader thread panicked"))?;
+    let writer = writer.join().map_err(|_| anyhow!("Writer thread panicked"))?;
+    let limit_report = reader?;
+    writer?;
+    let stats = inference?;
+    output.commit()?;
+    Ok((stats, limit_report))
+}
+
+fn spawn_reader(
+    sources: Vec<Source>,
+    config: Config,
+    limits: Limits,
+    batches: SyncSender<Batch>,
+    warnings: SharedSink,
+) -> JoinHandle<anyhow::Result<LimitReport>> {
+    thread::spawn(move || {
+        let mut reader = Reader {
+            config,
+            limits,
+            report: LimitReport::default(),
+            documents: Vec::new(),
+            offset: 0,
+            batches,
+            warnings,
+        };
+        for (document, source) in sources.iter().enumerate() {
+            reader.documents.push((document, reader.offset));
+            let sent = match source {
+                Source::File(in_path) => reader.read_file(in_path)?,
+                Source::Text(text) => reader.send_sentences(text)?,
+            };
+            if !sent {
+                //inference stopped, its error is reported instead
+                return Ok(reader.report);
+            }
+        }
+        if !reader.documents.is_empty() {
+            //documents at the end without sentences
+            let batch = Batch {
+                documents: reader.documents,
+                indices: Vec::new(),
+                sentences: Vec::new(),
+                starts: Vec::new(),
+            };
+            let _ = reader.batches.send(batch);
+        }
+        Ok(reader.report)
+    })
+}
+
+/// State of the reader thread
+struct Reader {
+    config: Config,
+    limits: Limits,
+    report: LimitReport,
+    /// Documents started since the last batch, with the index of their first sentence
+    documents: Vec<(usize, usize)>,
+    /// Index of the next sentence in the output
+    offset: usize,
+    batches: SyncSender<Batch>,
+    warnings: SharedSink,
+}
+
+impl Reader {
+    /// Send the sentences of the file at `in_path` in batches, the first one carrying the
+    /// documents started since the last batch. Returns false when the receiver has stopped.
+    fn read_file(&mut self, in_path: &Path) -> anyhow::Result<bool> {
+        let file = File::open(in_path)
+            .with_context(|| format!("Could not read {}", in_path.display()))?;
+        let warnings = self.warnings.as_ref();
+        let len = self.limits.check_file(in_path, file.metadata()?.len(), &mut self.report, warnings)?;
+        let mmap;
+        let contents = if len == 0 {
+            ""
+        } else {
+            //the mapping is only valid as long as no other process truncates the file
+            mmap = unsafe { Mmap::map(&file) }
+                .with_context(|| format!("Could not map {}", in_path.display()))?;
+            let bytes = &mmap[..len as usize];
+            match str::from_utf8(bytes) {
+                Ok(contents) => contents,
+                //a truncated file may end in the middle of a character
+                Err(err) if err.error_len().is_none() => {
+                    str::from_utf8(&bytes[..err.valid_up_to()]).expect("valid up to here")
+                }
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("{} is not valid UTF-8", in_path.display()))
+                }
+            }
+        };
+        self.send_sentences(contents)
+    }
+
+    /// Send the sentences of `contents` in batches, as `read_file` does
+    fn send_sentences(&mut self, contents: &str) -> anyhow::Result<bool> {
+        let batch_size = self.config.batch_size();
+        let window_size = batch_size * rusttagr::SORT_WINDOW_BATCHES;
+        //the share of the sentences of a single input when sharded, see `shard`
+        let share = match self.config.shard {
+            Some(shard) => shard.range(self.config.sentences(contents).count()),
+            None => 0..usize::MAX,
+        };
+        let mut sentences = self.config.sentences(contents).skip(share.start).take(share.len());
+        let mut sentence_index = share.start;
+        loop {
+            let window_start = sentence_index;
+            let mut window = Vec::with_capacity(window_size);
+            for sentence in sentences.by_ref().take(window_size) {
+                let warnings = self.warnings.as_ref();
+                if let Some(sentence) = self.limits.check_sentence(sentence, sentence_index, &mut self.report, warnings)? {
+                    window.push(sentence);
+                }
+                sentence_index += 1;
+            }
+            if sentence_index == window_start {
+                return Ok(true);
+            }
+            for batch in rusttagr::length_sorted_batches(&window, batch_size) {
+                let batch = Batch {
+                    documents: std::mem::take(&mut self.documents),
+                    sentences: batch
+                        .iter()
+                        .map(|&index| window[index].to_owned())
+                        .collect(),
+                    starts: batch
+                        .iter()
+                        .map(|&index| sentences::offset_in(contents, window[index]))
+                        .collect(),
+                    indices: batch.into_iter().map(|index| self.offset + index).collect(),
+                };
+                if self.batches.send(batch).is_err() {
+                    return Ok(false);
+                }
+            }
+            self.offset += window.len();
+        }
+    }
+}
+
+/// Tag the batches of `batches` with `tagger` on the calling thread, and on the
+/// `config.workers()` threads started besides it
+fn tag_locally(
+    tagger: &dyn Tagger,
+    config: &Config,
+    batches: Receiver<Batch>,
+    tagged: SyncSender<TaggedBatch>,
+    mut quality: Option<&mut QualityReport>,
+    warnings: &SharedSink,
+) -> anyhow::Result<PredictStats> {
+    let batches = Arc::new(BatchQueue::new(batches));
+    let workers = spawn_workers(config, &batches, &tagged, quality.is_some(), warnings);
+    let inference = run_inference(tagger, config, &batches, tagged, quality.as_deref_mut(), warnings.as_ref());
+    //released before joining the workers, so that the reader stops once none is left to
+    //take its batches
+    drop(batches);
+    let mut stats = PredictStats::default();
+    for worker in workers {
+        let (worker_stats, worker_quality) = worker.join().map_err(|_| anyhow!("Inference thread panicked"))??;
+        stats.add(&worker_stats);
+        if let (Some(quality), Some(worker_quality)) = (quality.as_deref_mut(), worker_quality) {
+            quality.merge(worker_quality);
+        }
+    }
+    stats.add(&inference?);
+    Ok(stats)
+}
+
+/// Tag the batches of `batches` on the workers of `config.coordinator` (see `distributed`),
+/// parsing them and adding them to `quality` as they come back
+fn coordinate(
+    config: &Config,
+    batches: Receiver<Batch>,
+    tagged: SyncSender<TaggedBatch>,
+    quality: Option<&mut QualityReport>,
+    warnings: &SharedSink,
+) -> anyhow::Result<PredictStats> {
+    let address = config.coordinator.as_deref().context("No coordinator address")?;
+    let parser = config.dependency_parser()?;
+    let stats = Arc::new(Mutex::new(PredictStats::default()));
+    let report = Arc::new(Mutex::new(if quality.is_some() { Some(QualityReport::default()) } else { None }));
+    let tagged = Mutex::new(tagged);
+    let (batch_stats, batch_report) = (Arc::clone(&stats), Arc::clone(&report));
+    let done = move |sequence: usize, batch: Batch, scored: Vec<Vec<ScoredTag>>, worker_stats: PredictStats| {
+        batch_stats.lock().map_err(|_| anyhow!("Statistics poisoned"))?.add(&worker_stats);
+        if let Some(quality) = batch_report.lock().map_err(|_| anyhow!("Quality report poisoned"))?.as_mut() {
+            for (text, sentence) in batch.sentences.iter().zip(&scored) {
+                quality.add_sentence(text, sentence);
+            }
+        }
+        let pos_tags = drop_scores(scored);
+        let dependencies = match &parser {
+            Some(parser) if !pos_tags.is_empty() => Some(parser.parse(&pos_tags)?),
+            _ => None,
+        };
+        let tagged_batch = TaggedBatch {
+            sequence,
+            documents: batch.documents,
+            indices: batch.indices,
+            sentences: batch.sentences,
+            starts: batch.starts,
+            pos_tags,
+            dependencies,
+        };
+        //a closed channel means that the writer stopped, its error is reported instead
+        tagged
+            .lock()
+            .map_err(|_| anyhow!("Output channel poisoned"))?
+            .send(tagged_batch)
+            .map_err(|_| anyhow!("The writer stopped"))
+    };
+    distributed::coordinate(address, batches, batch_sentences, done, warnings)?;
+
+    if let (Some(quality), Ok(mut report)) = (quality, report.lock()) {
+        if let Some(report) = report.take() {
+            quality.merge(report);
+        }
+    }
+    let stats = *stats.lock().map_err(|_| anyhow!("Statistics poisoned"))?;
+    Ok(stats)
+}
+
+/// Sentences of `batch`, sent to the workers of a coordinator
+fn batch_sentences(batch: &Batch) -> &[String] {
+    &batch.sentences
+}
+
+/// Start the inference threads besides the calling one, each loading its own model and
+/// returning its statistics, with its quality report if `quality` is set
+fn spawn_workers(
+    config: &Config,
+    batches: &Arc<BatchQueue>,
+    tagged: &SyncSender<TaggedBatch>,
+    quality: bool,
+    warnings: &SharedSink,
+) -> Vec<JoinHandle<anyhow::Result<(PredictStats, Option<QualityReport>)>>> {
+    (1..config.workers())
+        .map(|_| {
+            let config = config.clone();
+            let batches = Arc::clone(batches);
+            let tagged = tagged.clone();
+            let warnings = Arc::clone(warnings);
+            thread::spawn(move || {
+                let tagger = match config.tagger(warnings.as_ref()) {
+                    Ok(tagger) => tagger,
+                    Err(err) => {
+                        batches.close();
+                        return Err(err);
+                    }
+                };
+                let mut report = if quality { Some(QualityReport::default()) } else { None };
+                let stats = run_inference(tagger.as_ref(), &config, &batches, tagged, report.as_mut(), warnings.as_ref())?;
+                Ok((stats, report))
+            })
+        })
+        .collect()
+}
+
+/// Tag the batches taken from `batches` until there are none left, closing the queue on
+/// failure
+fn run_inference(
+    tagger: &dyn Tagger,
+    config: &Config,
+    batches: &BatchQueue,
+    tagged: SyncSender<TaggedBatch>,
+    quality: Option<&mut QualityReport>,
+    warnings: &dyn WarningSink,
+) -> anyhow::Result<PredictStats> {
+    let result = tag_batches(tagger, config, batches, tagged, quality, warnings);
+    if result.is_err() {
+        batches.close();
+    }
+    result
+}
+
+fn tag_batches(
+    tagger: &dyn Tagger,
+    config: &Config,
+    batches: &BatchQueue,
+    tagged: SyncSender<TaggedBatch>,
+    mut quality: Option<&mut QualityReport>,
+    warnings: &dyn WarningSink,
+) -> anyhow::Result<PredictStats> {
+    let parser = config.dependency_parser()?;
+    let mut adaptive_batch = config.adaptive_batch();
+    let mut stats = PredictStats::default();
+    while let Some((sequence, batch)) = batches.next() {
+        if batch.sentences.is_empty() {
+            let tagged_batch = TaggedBatch {
+                sequence,
+                documents: batch.documents,
+                indices: Vec::new(),
+                sentences: Vec::new(),
+                starts: Vec::new(),
+                pos_tags: Vec::new(),
+                dependencies: None,
+            };
+            if tagged.send(tagged_batch).is_err() {
+                break;
+            }
+            continue;
+        }
+        let texts: Vec<&str> = batch.sentences.iter().map(String::as_str).collect();
+        let (scored, batch_stats) = adaptive_batch.tag(tagger, &texts, warnings)?;
+        stats.add(&batch_stats);
+        if let Some(quality) = quality.as_mut() {
+            for (text, sentence) in texts.iter().zip(&scored) {
+                quality.add_sentence(text, sentence);
+            }
+        }
+        let pos_tags = drop_scores(scored);
+        let dependencies = match &parser {
+            Some(parser) => Some(parser.parse(&pos_tags)?),
+            None => None,
+        };
+        let tagged_batch = TaggedBatch {
+            sequence,
+            documents: batch.documents,
+            indices: batch.indices,
+            sentences: batch.sentences,
+            starts: batch.starts,
+            pos_tags,
+            dependencies,
+        };
+        if tagged.send(tagged_batch).is_err() {
+            //the writer stopped, its error is reported instead
+            break;
+        }
+    }
+    Ok(stats)
+}
+
+fn spawn_writer(
+    out_file: File,
+    document_ids: Option<Vec<String>>,
+    config: Config,
+    tagged: Receiver<TaggedBatch>,
+) -> JoinHandle<anyhow::Result<()>> {
+    thread::spawn(move || {
+        let mut tag_writer = config.tag_writer(BufWriter::new(out_file));
+
+        //batches arrive out of order with several workers, and sentences out of order
+        //within the batches: hold them until all previous ones are written
+        let mut pending_batches = BTreeMap::new();
+        let mut next_batch = 0;
+        let mut pending = BTreeMap::new();
+        let mut documents = VecDeque::new();
+        let mut next_index = 0;
+        for batch in tagged {
+            pending_batches.insert(batch.sequence, batch);
+            while let Some(batch) = pending_batches.remove(&next_batch) {
+                next_batch += 1;
+                documents.extend(batch.documents);
+                let dependencies = batch
+                    .dependencies
+                    .map(|deps| deps.into_iter().map(Some).collect())
+                    .unwrap_or_else(|| vec![None; batch.indices.len()]);
+                let sources = batch.sentences.into_iter().zip(batch.starts);
+                pending.extend(
+                    batch
+                        .indices
+                        .into_iter()
+                        .zip(sources.zip(batch.pos_tags.into_iter().zip(dependencies))),
+                );
+                while let Some(((text, start), (mut pos_tags, dependencies))) = pending.remove(&next_index) {
+                    //documents are started in order, before their first sentence
+                    while let Some(&(document, _)) = documents.front().filter(|(_, first)| *first <= next_index) {
+                        if let Some(ids) = &document_ids {
+                            tag_writer.start_document(&ids[document])?;
+                        }
+                        documents.pop_front();
+                    }
+                    if let Some(labels) = &config.filter_tags {
+                        rusttagr::filter_labels(&mut pos_tags, labels);
+                    }
+                    tag_writer.write_located_sentence(&text, start, &pos_tags, dependencies.as_deref())?;
+                    next_index += 1;
+                }
+            }
+        }
+        for (document, _) in documents {
+            if let Some(ids) = &document_ids {
+                tag_writer.start_document(&ids[document])?;
+            }
+        }
+        tag_writer.finish()?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::TagFormat;
+    use crate::warnings::Warning;
+    use std::fs;
+
+    /// Tags every word `W`
+    struct WordTagger;
+
+    impl Tagger for WordTagger {
+        fn tag(&self, input: &[&str]) -> Vec<Vec<ScoredTag>> {
+            input
+                .iter()
+                .map(|sentence| {
+                    sentence
+                        .split_whitespace()
+                        .map(|word| ScoredTag {
+                            tag: POSTag {
+                                word: word.to_owned(),
+                                label: "W".to_owned(),
+                            },
+                            score: 1.0,
+                        })
+                        .collect()
+                })
+                .collect()
+        }
+    }
+
+    /// Fails on every batch, as the model does on an inference error
+    struct FailingTagger;
+
+    impl Tagger for FailingTagger {
+        fn tag(&self, _input: &[&str]) -> Vec<Vec<ScoredTag>> {
+            panic!("inference failed")
+        }
+    }
+
+    /// Results of the three stages of a run
+    struct Outcome {
+        reader: anyhow::Result<LimitReport>,
+        inference: anyhow::Result<PredictStats>,
+        writer: anyhow::Result<()>,
+        warnings: Vec<Warning>,
+    }
+
+    /// Run the stages as `run` does, with `tagger` instead of the configured model
+    fn tag_sources(tagger: &dyn Tagger, sources: Vec<Source>, out_file: File, config: &Config) -> Outcome {
+        let collected: Arc<Mutex<Vec<Warning>>> = Arc::new(Mutex::new(Vec::new()));
+        let warnings: SharedSink = collected.clone();
+        let (batch_sender, batch_receiver) = sync_channel(CHANNEL_CAPACITY);
+        let (tagged_sender, tagged_receiver) = sync_channel(CHANNEL_CAPACITY);
+        let reader = spawn_reader(sources, config.clone(), config.limits(), batch_sender, Arc::clone(&warnings));
+        let writer = spawn_writer(out_file, None, config.clone(), tagged_receiver);
+        let inference = tag_locally(tagger, config, batch_receiver, tagged_sender, None, &warnings);
+        let reader = reader.join().unwrap();
+        let writer = writer.join().unwrap();
+        let warnings = collected.lock().unwrap().clone();
+        Outcome {
+            reader,
+            inference,
+            writer,
+            warnings,
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("berttagr-stream-{}-{}.tsv", name, std::process::id()))
+    }
+
+    fn tsv_config() -> Config {
+        Config {
+            format: Some(TagFormat::Tsv),
+            ..Config::default()
+        }
+    }
+
+    /// Batch number `sequence` of the `(index, word)` sentences
+    fn tagged_batch(sequence: usize, documents: Vec<(usize, usize)>, sentences: &[(usize, &str)]) -> TaggedBatch {
+        TaggedBatch {
+            sequence,
+            documents,
+            indices: sentences.iter().map(|&(index, _)| index).collect(),
+            sentences: sentences.iter().map(|&(_, word)| word.to_owned()).collect(),
+            starts: vec![0; sentences.len()],
+            pos_tags: sentences
+                .iter()
+                .map(|&(_, word)| {
+                    vec![POSTag {
+                        word: word.to_owned(),
+                        label: "W".to_owned(),
+                    }]
+                })
+                .collect(),
+            dependencies: None,
+        }
+    }
+
+    #[test]
+    fn writes_batches_in_sequence() {
+        let path = temp_path("sequence");
+        let (sender, receiver) = sync_channel(CHANNEL_CAPACITY);
+        let ids = vec!["a".to_owned(), "b".to_owned()];
+        let writer = spawn_writer(File::create(&path).unwrap(), Some(ids), tsv_config(), receiver);
+        sender.send(tagged_batch(1, vec![(1, 2)], &[(3, "Four"), (2, "Three")])).unwrap();
+        sender.send(tagged_batch(0, vec![(0, 0)], &[(1, "Two"), (0, "One")])).unwrap();
+        drop(sender);
+        writer.join().unwrap().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# newdoc id = a\nOne\tW\n\nTwo\tW\n\n# newdoc id = b\nThree\tW\n\nFour\tW\n\n"
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn skipped_sentences_keep_their_index() {
+        let in_path = temp_path("limits-input");
+        fs::write(&in_path, "One two. A sentence above the limit. Three four.").unwrap();
+        let out_path = temp_path("limits");
+        let config = Config {
+            max_sentence_length: Some(12),
+            ..tsv_config()
+        };
+        let sources = vec![
+            Source::File(in_path.clone()),
+            Source::Text("Another long sentence. Five.".to_owned()),
+        ];
+        let outcome = tag_sources(&WordTagger, sources, File::create(&out_path).unwrap(), &config);
+        assert_eq!(outcome.reader.unwrap().skipped_sentences, 2);
+        assert_eq!(outcome.inference.unwrap().sentences, 3);
+        outcome.writer.unwrap();
+        assert_eq!(
+            outcome.warnings,
+            vec![
+                Warning::SkippedSentence {
+                    sentence: 1,
+                    length: 27,
+                    limit: 12
+                },
+                Warning::SkippedSentence {
+                    sentence: 0,
+                    length: 22,
+                    limit: 12
+                },
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(&out_path).unwrap(),
+            "One\tW\ntwo.\tW\n\nThree\tW\nfour.\tW\n\nFive.\tW\n\n"
+        );
+        fs::remove_file(&in_path).unwrap();
+        fs::remove_file(&out_path).unwrap();
+    }
+
+    #[test]
+    fn closing_the_queue_stops_the_reader() {
+        let batch = || Batch {
+            documents: Vec::new(),
+            indices: vec![0],
+            sentences: vec!["One.".to_owned()],
+            starts: vec![0],
+        };
+        let (sender, receiver) = sync_channel(CHANNEL_CAPACITY);
+        let queue = BatchQueue::new(receiver);
+        sender.send(batch()).unwrap();
+        sender.send(batch()).unwrap();
+        assert_eq!(queue.next().map(|(sequence, _)| sequence), Some(0));
+        queue.close();
+        assert!(queue.next().is_none());
+        assert!(sender.send(batch()).is_err());
+    }
+
+    #[test]
+    fn reports_the_error_of_each_stage() {
+        let path = temp_path("errors");
+        let text = || vec![Source::Text("One two. Three four.".to_owned())];
+
+        let missing = vec![Source::File(temp_path("missing"))];
+        let outcome = tag_sources(&WordTagger, missing, File::create(&path).unwrap(), &tsv_config());
+        assert!(outcome.reader.unwrap_err().to_string().starts_with("Could not read"));
+        assert!(outcome.inference.is_ok() && outcome.writer.is_ok());
+
+        let outcome = tag_sources(&FailingTagger, text(), File::create(&path).unwrap(), &tsv_config());
+        assert!(outcome.inference.is_err());
+        assert!(outcome.reader.is_ok() && outcome.writer.is_ok());
+
+        //opened read-only, the output cannot be written
+        let outcome = tag_sources(&WordTagger, text(), File::open(&path).unwrap(), &tsv_config());
+        assert!(outcome.writer.is_err());
+        assert!(outcome.reader.is_ok() && outcome.inference.is_ok());
+        fs::remove_file(&path).unwrap();
+    }
+}
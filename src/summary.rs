@@ -0,0 +1,110 @@
+//! # Run summary
+//! Totals of a tagging run (files, sentences, tokens, time, throughput, warnings and skipped
+//! inputs), printed at the end of the run and optionally written as JSON with
+//! `--summary-json` for auditing large ingestion jobs.
+
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Context;
+use serde_json::json;
+
+use crate::limits::LimitReport;
+use crate::pos_tagging::PredictStats;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+/// # Totals of a tagging run
+pub struct RunSummary {
+    /// Number of input files tagged
+    pub files: usize,
+    pub stats: PredictStats,
+    pub limits: LimitReport,
+    /// Wall-clock time of the run, including model loading and IO
+    pub elapsed: Duration,
+}
+
+impl RunSummary {
+    /// Add the results of tagging one file
+    pub fn add_file(&mut self, stats: &PredictStats, limits: &LimitReport) {
+        self.add_files(1, stats, limits);
+    }
+
+    /// Add the results of tagging `files` files together
+    pub fn add_files(&mut self, files: usize, stats: &PredictStats, limits: &LimitReport) {
+        self.files += files;
+        self.stats.add(stats);
+        self.limits.add(limits);
+    }
+
+    /// Number of warnings printed during the run
+    pub fn warnings(&self) -> usize {
+        self.limits.skipped_files + self.limits.truncated_files + self.limits.skipped_sentences
+    }
+
+    /// Number of files and sentences left out
+    pub fn skipped(&self) -> usize {
+        self.limits.skipped_files + self.limits.skipped_sentences
+    }
+
+    /// Tokens tagged per second of wall-clock time, 0 when no time was measured
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.stats.tokens as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "files": self.files,
+            "sentences": self.stats.sentences,
+            "tokens": self.stats.tokens,
+            "batches": self.stats.batches,
+            "elapsed_secs": self.elapsed.as_secs_f64(),
+            "inference_secs": self.stats.inference_time.as_secs_f64(),
+            "tokens_per_sec": self.throughput(),
+            "warnings": self.warnings(),
+            "skipped_files": self.limits.skipped_files,
+            "truncated_files": self.limits.truncated_files,
+            "skipped_sentences": self.limits.skipped_sentences,
+            "truncated_sentences": self.limits.truncated_sentences,
+        })
+    }
+
+    /// Write the summary as JSON to `path`
+    pub fn write_json(&self, path: &Path) -> anyhow::Result<()> {
+        let file = File::create(path)
+            .with_context(|| format!("Could not create {}", path.display()))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), &self.to_json())?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Tagged {} files, {} sentences, {} tokens in {:.2} s ({:.1} tokens/s, {:.2} s in the model)",
+            self.files,
+            self.stats.sentences,
+            self.stats.tokens,
+            self.elapsed.as_secs_f64(),
+            self.throughput(),
+            self.stats.inference_time.as_secs_f64()
+        )?;
+        write!(
+            f,
+            "{} warnings, {} files and {} sentences skipped, {} files and {} sentences truncated",
+            self.warnings(),
+            self.limits.skipped_files,
+            self.limits.skipped_sentences,
+            self.limits.truncated_files,
+            self.limits.truncated_sentences
+        )
+    }
+}
@@ -0,0 +1,231 @@
+//! # Tagger backends
+//! Common interface of the part-of-speech taggers, so that a pipeline can run the MobileBERT
+//! model, another transformer model, a rule-based tagger or a combination of them
+//! (see `ensemble::EnsembleTagger`).
+//!
+//! `AdaptiveBatch` keeps long runs alive when a batch does not fit in memory: batches are
+//! split to stay within a budget of characters, and the batch size is halved and the batch
+//! tagged again when the model runs out of memory.
+
+use std::time::Instant;
+
+use crate::exit;
+use crate::pos_tagging::{POSModel, POSTag, PredictStats, ScoredTag};
+use crate::pretokenized;
+use crate::sentences;
+use crate::warnings::{Warning, WarningSink};
+
+/// # Part of speech tagger
+pub trait Tagger {
+    /// Tags of the words of each input text, with the confidence in each label
+    fn tag(&self, input: &[&str]) -> Vec<Vec<ScoredTag>>;
+
+    /// Tags of each input sentence given as tokens, one per token (see `pretokenized`).
+    /// By default the tagged words are aligned to the tokens by their text.
+    fn tag_tokens(&self, input: &[Vec<&str>]) -> Vec<Vec<ScoredTag>> {
+        let texts: Vec<String> = input.iter().map(|tokens| pretokenized::join(tokens).0).collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        self.tag(&texts)
+            .into_iter()
+            .zip(input)
+            .map(|(tagged, tokens)| pretokenized::align_words(tokens, tagged))
+            .collect()
+    }
+}
+
+impl Tagger for POSModel {
+    fn tag(&self, input: &[&str]) -> Vec<Vec<ScoredTag>> {
+        self.predict_scored(input)
+    }
+
+    fn tag_tokens(&self, input: &[Vec<&str>]) -> Vec<Vec<ScoredTag>> {
+        self.predict_tokens(input)
+    }
+}
+
+/// Tag `input` with `tagger`, dropping the scores, and return the size and duration of the
+/// prediction as `POSModel::predict_with_stats` does
+pub fn tag_with_stats(tagger: &dyn Tagger, input: &[&str]) -> (Vec<Vec<POSTag>>, PredictStats) {
+    let (output, stats) = tag_scored_with_stats(tagger, input);
+    (drop_scores(output), stats)
+}
+
+/// Tag `input` with `tagger`, keeping the scores, and return the size and duration of the
+/// prediction
+pub fn tag_scored_with_stats(tagger: &dyn Tagger, input: &[&str]) -> (Vec<Vec<ScoredTag>>, PredictStats) {
+    let start = Instant::now();
+    let output = tagger.tag(input);
+    let stats = PredictStats {
+        sentences: input.len(),
+        tokens: output.iter().map(Vec::len).sum(),
+        batches: 1,
+        inference_time: start.elapsed(),
+    };
+    (output, stats)
+}
+
+/// Tags of scored sentences
+pub fn drop_scores(sentences: Vec<Vec<ScoredTag>>) -> Vec<Vec<POSTag>> {
+    sentences
+        .into_iter()
+        .map(|sentence| sentence.into_iter().map(|scored_tag| scored_tag.tag).collect())
+        .collect()
+}
+
+/// Split `text` into sentences, tag them with `tagger` in batches of `batch_size` and pass
+/// the tags of each sentence to `f`, in order
+pub fn for_each_sentence<F>(tagger: &dyn Tagger, text: &str, batch_size: usize, mut f: F)
+where
+    F: FnMut(Vec<POSTag>),
+{
+    let sentences = sentences::split(text);
+    for batch in sentences.chunks(batch_size.max(1)) {
+        for sentence in tagger.tag(batch) {
+            f(sentence.into_iter().map(|scored_tag| scored_tag.tag).collect());
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// # Batch size lowered when the model runs out of memory
+pub struct AdaptiveBatch {
+    /// Number of sentences sent to the model at once
+    pub size: usize,
+    /// Size below which an out-of-memory error is returned instead of retried
+    pub min_size: usize,
+    /// Memory budget of a batch, in bytes of text, if any
+    pub max_bytes: Option<usize>,
+    /// The input sentences are tokens to align the tags to, see `pretokenized`
+    pub pretokenized: bool,
+}
+
+impl AdaptiveBatch {
+    pub fn new(size: usize, min_size: usize, max_bytes: Option<usize>) -> AdaptiveBatch {
+        let min_size = min_size.max(1);
+        AdaptiveBatch {
+            size: size.max(min_size),
+            min_size,
+            max_bytes,
+            pretokenized: false,
+        }
+    }
+
+    /// Tag `input` with `tagger` in batches of the current size within the memory budget.
+    /// When the model runs out of memory, the batch size is halved, for the rest of the run,
+    /// and the batch tagged again, until `min_size`. Other panics of the model are returned
+    /// as `InferenceError`s. The lowered batch sizes are passed to `warnings`.
+    pub fn tag(
+        &mut self,
+        tagger: &dyn Tagger,
+        input: &[&str],
+        warnings: &dyn WarningSink,
+    ) -> anyhow::Result<(Vec<Vec<ScoredTag>>, PredictStats)> {
+        let start = Instant::now();
+        let pretokenized = self.pretokenized;
+        let tokens = if pretokenized {
+            input
+                .iter()
+                .map(|sentence| pretokenized::parse_tokens(sentence))
+                .collect::<anyhow::Result<Vec<Vec<String>>>>()?
+        } else {
+            Vec::new()
+        };
+        let mut output = Vec::with_capacity(input.len());
+        let mut batches = 0;
+        let mut rest = input;
+        while !rest.is_empty() {
+            let len = self.next_len(rest);
+            let done = input.len() - rest.len();
+            let predict = || {
+                if pretokenized {
+                    let batch: Vec<Vec<&str>> = tokens[done..done + len]
+                        .iter()
+                        .map(|tokens| tokens.iter().map(String::as_str).collect())
+                        .collect();
+                    tagger.tag_tokens(&batch)
+                } else {
+                    tagger.tag(&rest[..len])
+                }
+            };
+            match exit::catch_inference(predict) {
+                Ok(tags) => {
+                    output.extend(tags);
+                    rest = &rest[len..];
+                    batches += 1;
+                }
+                Err(err) if exit::is_out_of_memory(&err) && len > self.min_size => {
+                    self.size = (len / 2).max(self.min_size);
+                    warnings.warn(Warning::BatchSizeLowered {
+                        sentences: len,
+                        batch_size: self.size,
+                    });
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        let stats = PredictStats {
+            sentences: input.len(),
+            tokens: output.iter().map(Vec::len).sum(),
+            batches,
+            inference_time: start.elapsed(),
+        };
+        Ok((output, stats))
+    }
+
+    /// Number of the first sentences of `rest` making the next batch, at least one
+    fn next_len(&self, rest: &[&str]) -> usize {
+        let mut chars = 0;
+        let mut len = 0;
+        for sentence in rest.iter().take(self.size) {
+            chars += sentence.len();
+            if len > 0 && self.max_bytes.map_or(false, |max_bytes| chars > max_bytes) {
+                break;
+            }
+            len += 1;
+        }
+        len
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Runs out of memory on batches of more than two sentences
+    struct SmallMemoryTagger;
+
+    impl Tagger for SmallMemoryTagger {
+        fn tag(&self, input: &[&str]) -> Vec<Vec<ScoredTag>> {
+            if input.len() > 2 {
+                panic!("CUDA out of memory. Tried to allocate 2.00 GiB");
+            }
+            input.iter().map(|_| Vec::new()).collect()
+        }
+    }
+
+    #[test]
+    fn halves_the_batch_size_on_out_of_memory() {
+        let input = ["a", "b", "c", "d", "e"];
+        let warnings = Mutex::new(Vec::new());
+        let mut batch = AdaptiveBatch::new(8, 1, None);
+        let (output, stats) = batch.tag(&SmallMemoryTagger, &input, &warnings).unwrap();
+        assert_eq!(output.len(), 5);
+        assert_eq!(batch.size, 2);
+        assert_eq!(stats.batches, 3);
+        assert_eq!(
+            warnings.into_inner().unwrap(),
+            vec![Warning::BatchSizeLowered {
+                sentences: 5,
+                batch_size: 2
+            }]
+        );
+
+        let mut batch = AdaptiveBatch::new(8, 4, None);
+        assert!(batch.tag(&SmallMemoryTagger, &input, &Mutex::new(Vec::new())).is_err());
+
+        let batch = AdaptiveBatch::new(8, 1, Some(2));
+        assert_eq!(batch.next_len(&["ab", "c"]), 1);
+        assert_eq!(batch.next_len(&["abc", "d"]), 1);
+    }
+}
@@ -0,0 +1,179 @@
+//! # Tag sets
+//! Conversion from the Penn Treebank labels predicted by the English model to the
+//! Universal Dependencies part-of-speech tags (UPOS), and descriptions of the labels.
+//!
+//! Community checkpoints often predict labels of their own (`LABEL_3`, `NOUN-PL`...), which
+//! are reported when the model is loaded and can be renamed with a `LabelMap`.
+
+use std::collections::BTreeMap;
+
+use crate::hooks::PostProcessor;
+use crate::pos_tagging::ScoredTag;
+
+/// Universal POS tags
+pub const UPOS: [&str; 17] = [
+    "ADJ", "ADP", "ADV", "AUX", "CCONJ", "DET", "INTJ", "NOUN", "NUM", "PART", "PRON", "PROPN",
+    "PUNCT", "SCONJ", "SYM", "VERB", "X",
+];
+
+/// Universal POS tag of a Penn Treebank label, `X` for labels outside the tag set
+///
+/// # Example
+///
+/// ```
+/// use rustlib::tagset::to_upos;
+///
+/// assert_eq!(to_upos("NNS"), "NOUN");
+/// assert_eq!(to_upos("MD"), "AUX");
+/// ```
+pub fn to_upos(label: &str) -> &'static str {
+    match label {
+        "CC" => "CCONJ",
+        "CD" => "NUM",
+        "DT" | "PDT" | "WDT" => "DET",
+        "EX" | "PRP" | "PRP$" | "WP" | "WP$" => "PRON",
+        "IN" | "RP" => "ADP",
+        "JJ" | "JJR" | "JJS" => "ADJ",
+        "MD" => "AUX",
+        "NN" | "NNS" => "NOUN",
+        "NNP" | "NNPS" => "PROPN",
+        "POS" | "TO" => "PART",
+        "RB" | "RBR" | "RBS" | "WRB" => "ADV",
+        "UH" => "INTJ",
+        "VB" | "VBD" | "VBG" | "VBN" | "VBP" | "VBZ" => "VERB",
+        "SYM" | "#" | "$" => "SYM",
+        "." | "," | ":" | "``" | "''" | "-LRB-" | "-RRB-" | "HYPH" | "NFP" => "PUNCT",
+        _ => "X",
+    }
+}
+
+/// Description of a Penn Treebank label, `None` for labels outside the tag set
+///
+/// # Example
+///
+/// ```
+/// use rustlib::tagset::describe;
+///
+/// assert_eq!(describe("NNS"), Some("Noun, plural"));
+/// ```
+pub fn describe(label: &str) -> Option<&'static str> {
+    let description = match label {
+        "CC" => "Coordinating conjunction",
+        "CD" => "Cardinal number",
+        "DT" => "Determiner",
+        "EX" => "Existential there",
+        "FW" => "Foreign word",
+        "IN" => "Preposition or subordinating conjunction",
+        "JJ" => "Adjective",
+        "JJR" => "Adjective, comparative",
+        "JJS" => "Adjective, superlative",
+        "LS" => "List item marker",
+        "MD" => "Modal",
+        "NN" => "Noun, singular or mass",
+        "NNS" => "Noun, plural",
+        "NNP" => "Proper noun, singular",
+        "NNPS" => "Proper noun, plural",
+        "PDT" => "Predeterminer",
+        "POS" => "Possessive ending",
+        "PRP" => "Personal pronoun",
+        "PRP$" => "Possessive pronoun",
+        "RB" => "Adverb",
+        "RBR" => "Adverb, comparative",
+        "RBS" => "Adverb, superlative",
+        "RP" => "Particle",
+        "SYM" => "Symbol",
+        "TO" => "to",
+        "UH" => "Interjection",
+        "VB" => "Verb, base form",
+        "VBD" => "Verb, past tense",
+        "VBG" => "Verb, gerund or present participle",
+        "VBN" => "Verb, past participle",
+        "VBP" => "Verb, non-3rd person singular present",
+        "VBZ" => "Verb, 3rd person singular present",
+        "WDT" => "Wh-determiner",
+        "WP" => "Wh-pronoun",
+        "WP$" => "Possessive wh-pronoun",
+        "WRB" => "Wh-adverb",
+        "." => "Sentence-final punctuation",
+        "," => "Comma",
+        ":" => "Colon, semicolon or dash",
+        "``" => "Opening quotation mark",
+        "''" => "Closing quotation mark",
+        "-LRB-" => "Opening bracket",
+        "-RRB-" => "Closing bracket",
+        "#" => "Pound sign",
+        "$" => "Dollar sign",
+        "HYPH" => "Hyphen",
+        "NFP" => "Superfluous punctuation",
+        _ => return None,
+    };
+    Some(description)
+}
+
+/// Whether `label` belongs to the expected inventories: Penn Treebank, UPOS, or the
+/// `O`/`B-`/`I-` entity labels of token classification models
+pub fn is_known(label: &str) -> bool {
+    describe(label).is_some()
+        || UPOS.contains(&label)
+        || label == "O"
+        || label.starts_with("B-")
+        || label.starts_with("I-")
+}
+
+/// Labels of `labels` outside the expected inventories, see `is_known`
+pub fn unknown_labels<'a, I>(labels: I) -> Vec<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    labels.into_iter().filter(|label| !is_known(label)).collect()
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+/// # Renames labels predicted by a model
+/// Run as a `PostProcessor`, with the table of `label_map` in the configuration file:
+///
+/// ```toml
+/// [label_map]
+/// LABEL_0 = "NN"
+/// NOUN-PL = "NNS"
+/// ```
+pub struct LabelMap {
+    pub map: BTreeMap<String, String>,
+}
+
+impl LabelMap {
+    pub fn new(map: BTreeMap<String, String>) -> LabelMap {
+        LabelMap { map }
+    }
+
+    /// `label` as renamed by the table
+    pub fn apply<'a>(&'a self, label: &'a str) -> &'a str {
+        self.map.get(label).map(String::as_str).unwrap_or(label)
+    }
+}
+
+impl PostProcessor for LabelMap {
+    fn process(&self, sentence: &mut Vec<ScoredTag>) {
+        for scored_tag in sentence.iter_mut() {
+            if let Some(label) = self.map.get(&scored_tag.tag.label) {
+                scored_tag.tag.label = label.clone();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reports_and_maps_unknown_labels() {
+        let labels = ["NN", "NOUN", "B-PER", "O", "LABEL_3", "NOUN-PL"];
+        assert_eq!(unknown_labels(labels.iter().copied()), vec!["LABEL_3", "NOUN-PL"]);
+        let mut map = BTreeMap::new();
+        map.insert(String::from("LABEL_3"), String::from("NN"));
+        let label_map = LabelMap::new(map);
+        assert_eq!(label_map.apply("LABEL_3"), "NN");
+        assert_eq!(label_map.apply("NOUN-PL"), "NOUN-PL");
+    }
+}
@@ -0,0 +1,378 @@
+//! # Fine-tuning
+//! Fine-tunes the part-of-speech model on an annotated corpus for the `train` subcommand, to
+//! adapt it to a domain. The weights are saved with the model configuration and vocabulary
+//! in a directory that can be used as `model_dir`. Training starts from the configured model
+//! (MobileBERT) and keeps its label set.
+//!
+//! The corpus is either CoNLL-U (the FORM and XPOS columns are used) or a tab or space
+//! separated file with a word and its label on each line; sentences are separated by blank
+//! lines and lines starting with `#` are comments.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context};
+use rust_bert::mobilebert::{MobileBertConfig, MobileBertForTokenClassification};
+use rust_bert::pipelines::common::{ModelType, TokenizerOption};
+use rust_bert::pipelines::token_classification::TokenClassificationConfig;
+use rust_bert::Config as _;
+use tch::nn::{self, OptimizerConfig};
+use tch::{Device, Kind, Reduction, Tensor};
+
+use crate::config::Config;
+use crate::warnings::WarningSink;
+
+pub const DEFAULT_LEARNING_RATE: f64 = 5e-5;
+pub const DEFAULT_EPOCHS: usize = 3;
+pub const DEFAULT_EVAL_SPLIT: f64 = 0.1;
+
+/// Maximum length in tokens of a training sentence, longer ones are truncated
+const MAX_LENGTH: usize = 128;
+
+/// Label of the sub-word tokens and special tokens the loss ignores
+const IGNORE_INDEX: i64 = -100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// # Training hyperparameters
+pub struct TrainOptions {
+    /// Learning rate of the Adam optimizer
+    pub learning_rate: f64,
+    /// Number of passes over the training sentences
+    pub epochs: usize,
+    /// Fraction of the corpus, taken from its end, held out to measure accuracy after each epoch
+    pub eval_split: f64,
+}
+
+impl Default for TrainOptions {
+    fn default() -> TrainOptions {
+        TrainOptions {
+            learning_rate: DEFAULT_LEARNING_RATE,
+            epochs: DEFAULT_EPOCHS,
+            eval_split: DEFAULT_EVAL_SPLIT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// # Metrics of a training epoch
+pub struct EpochMetrics {
+    /// Number of the epoch, from 1
+    pub epoch: usize,
+    pub epochs: usize,
+    /// Mean loss of the training batches
+    pub loss: f64,
+    /// Accuracy on the held out sentences, if any, between 0 and 1
+    pub eval_accuracy: Option<f64>,
+}
+
+impl fmt::Display for EpochMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Epoch {}/{}: loss {:.4}", self.epoch, self.epochs, self.loss)?;
+        if let Some(accuracy) = self.eval_accuracy {
+            write!(f, ", eval accuracy {:.2}%", accuracy * 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sentence encoded for the model
+struct Example {
+    input_ids: Vec<i64>,
+    labels: Vec<i64>,
+}
+
+/// Fine-tune the configured model on the annotated corpus at `corpus_path` and save it in `out_dir`
+///
+/// # Arguments
+///
+/// * `corpus_path` - CoNLL-U or word and label per line corpus
+/// * `out_dir` - Directory created to hold `rust_model.ot`, `config.json` and `vocab.txt`
+/// * `options` - Learning rate, epochs and evaluation split
+/// * `config` - Model, device, threads, seed and batch size settings
+/// * `warnings` - Receives the model files loaded unverified
+/// * `on_epoch` - Called with the metrics of each epoch once it is over
+pub fn train<F>(
+    corpus_path: &Path,
+    out_dir: &Path,
+    options: &TrainOptions,
+    config: &Config,
+    warnings: &dyn WarningSink,
+    mut on_epoch: F,
+) -> anyhow::Result<()>
+where
+    F: FnMut(EpochMetrics),
+{
+    if !(0.0..1.0).contains(&options.eval_split) {
+        bail!("The evaluation split must be between 0 and 1");
+    }
+    let corpus = fs::read_to_string(corpus_path)
+        .with_context(|| format!("Could not read {}", corpus_path.display()))?;
+    let sentences = read_corpus(&corpus)?;
+    if sentences.is_empty() {
+        bail!("The corpus {} has no sentences", corpus_path.display());
+    }
+    if let Some(threads) = config.threads {
+        tch::set_num_threads(threads as i32);
+    }
+    if let Some(seed) = config.seed {
+        tch::manual_seed(seed as i64);
+    }
+
+    let model_config: TokenClassificationConfig = config.pos_config(warnings)?.into();
+    match model_config.model_type {
+        ModelType::MobileBert => {}
+        _ => bail!("train only supports MobileBERT models"),
+    }
+    let config_path = model_config.config_resource.get_local_path()?;
+    let vocab_path = model_config.vocab_resource.get_local_path()?;
+    let weights_path = model_config.model_resource.get_local_path()?;
+    let device = model_config.device;
+
+    let tokenizer = TokenizerOption::from_file(
+        ModelType::MobileBert,
+        vocab_path.to_str().ok_or_else(|| anyhow!("Invalid vocabulary path"))?,
+        None,
+        model_config.lower_case,
+        model_config.strip_accents,
+        model_config.add_prefix_space,
+    )?;
+    let mobilebert_config = MobileBertConfig::from_file(&config_path);
+    let label2id: HashMap<&str, i64> = mobilebert_config
+        .id2label
+        .as_ref()
+        .ok_or_else(|| anyhow!("The model configuration has no label set"))?
+        .iter()
+        .map(|(id, label)| (label.as_str(), *id))
+        .collect();
+    let num_labels = label2id.len() as i64;
+
+    let mut var_store = nn::VarStore::new(device);
+    let model = MobileBertForTokenClassification::new(&var_store.root(), &mobilebert_config);
+    var_store.load(&weights_path)?;
+
+    let examples = sentences
+        .iter()
+        .map(|sentence| encode(sentence, &tokenizer, &label2id))
+        .collect::<anyhow::Result<Vec<Example>>>()?;
+    let eval_len = (examples.len() as f64 * options.eval_split).round() as usize;
+    let (train_set, eval_set) = examples.split_at(examples.len() - eval_len);
+    if train_set.is_empty() {
+        bail!("No sentences left for training after the evaluation split");
+    }
+    let pad_id = special_token_id(&tokenizer, "[PAD]");
+
+    let mut optimizer = nn::Adam::default().build(&var_store, options.learning_rate)?;
+    for epoch in 1..=options.epochs {
+        let order = Vec::<i64>::from(&Tensor::randperm(
+            train_set.len() as i64,
+            (Kind::Int64, Device::Cpu),
+        ));
+        let mut total_loss = 0.0;
+        let mut batches = 0;
+        for chunk in order.chunks(config.batch_size()) {
+            let batch: Vec<&Example> = chunk.iter().map(|&i| &train_set[i as usize]).collect();
+            let (input_ids, attention_mask, labels) = batch_tensors(&batch, pad_id, device);
+            let output = model.forward_t(
+                Some(&input_ids),
+                None,
+                None,
+                None,
+                Some(&attention_mask),
+                true,
+            )?;
+            let loss = output
+                .logits
+                .view([-1, num_labels])
+                .log_softmax(-1, Kind::Float)
+                .nll_loss(&labels.view([-1]), None::<Tensor>, Reduction::Mean, IGNORE_INDEX);
+            optimizer.backward_step(&loss);
+            total_loss += f64::from(&loss);
+            batches += 1;
+        }
+        let eval_accuracy = if eval_set.is_empty() {
+            None
+        } else {
+            Some(evaluate(&model, eval_set, pad_id, device, config.batch_size())?)
+        };
+        on_epoch(EpochMetrics {
+            epoch,
+            epochs: options.epochs,
+            loss: total_loss / batches as f64,
+            eval_accuracy,
+        });
+    }
+
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("Could not create {}", out_dir.display()))?;
+    var_store.save(out_dir.join("rust_model.ot"))?;
+    fs::copy(&config_path, out_dir.join("config.json"))?;
+    fs::copy(&vocab_path, out_dir.join("vocab.txt"))?;
+    Ok(())
+}
+
+/// Words and labels of the sentences of an annotated corpus
+pub fn read_corpus(corpus: &str) -> anyhow::Result<Vec<Vec<(String, String)>>> {
+    let mut sentences = Vec::new();
+    let mut current = Vec::new();
+    for line in corpus.lines() {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                sentences.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = if line.contains('\t') {
+            line.split('\t').collect()
+        } else {
+            line.split_whitespace().collect()
+        };
+        let (word, label) = match fields.len() {
+            //CoNLL-U, skipping multiword tokens and empty nodes
+            10 if fields[0].contains('-') || fields[0].contains('.') => continue,
+            10 => (fields[1], fields[4]),
+            2 => (fields[0], fields[1]),
+            _ => bail!("Invalid corpus line: {}", line),
+        };
+        current.push((word.to_owned(), label.to_owned()));
+    }
+    if !current.is_empty() {
+        sentences.push(current);
+    }
+    Ok(sentences)
+}
+
+/// Token ids of a sentence, with its labels on the first token of each word
+fn encode(
+    sentence: &[(String, String)],
+    tokenizer: &TokenizerOption,
+    label2id: &HashMap<&str, i64>,
+) -> anyhow::Result<Example> {
+    let mut input_ids = vec![special_token_id(tokenizer, "[CLS]")];
+    let mut labels = vec![IGNORE_INDEX];
+    for (word, label) in sentence {
+        let label_id = *label2id
+            .get(label.as_str())
+            .ok_or_else(|| anyhow!("The label {} is not in the label set of the model", label))?;
+        let token_ids = tokenizer.convert_tokens_to_ids(&tokenizer.tokenize(word));
+        if token_ids.is_empty() {
+            continue;
+        }
+        labels.push(label_id);
+        labels.extend(std::iter::repeat(IGNORE_INDEX).take(token_ids.len() - 1));
+        input_ids.extend(token_ids);
+    }
+    input_ids.truncate(MAX_LENGTH - 1);
+    labels.truncate(MAX_LENGTH - 1);
+    input_ids.push(special_token_id(tokenizer, "[SEP]"));
+    labels.push(IGNORE_INDEX);
+    Ok(Example { input_ids, labels })
+}
+
+fn special_token_id(tokenizer: &TokenizerOption, token: &str) -> i64 {
+    tokenizer.convert_tokens_to_ids(&[token])[0]
+}
+
+/// Input ids, attention mask and labels of a batch, padded to its longest sentence
+fn batch_tensors(batch: &[&Example], pad_id: i64, device: Device) -> (Tensor, Tensor, Tensor) {
+    let max_len = batch.iter().map(|e| e.input_ids.len()).max().unwrap_or(0);
+    let mut input_ids = Vec::with_capacity(batch.len() * max_len);
+    let mut attention_mask = Vec::with_capacity(batch.len() * max_len);
+    let mut labels = Vec::with_capacity(batch.len() * max_len);
+    for example in batch {
+        let padding = max_len - example.input_ids.len();
+        input_ids.extend(&example.input_ids);
+        input_ids.extend(std::iter::repeat(pad_id).take(padding));
+        attention_mask.extend(std::iter::repeat(1i64).take(example.input_ids.len()));
+        attention_mask.extend(std::iter::repeat(0i64).take(padding));
+        labels.extend(&example.labels);
+        labels.extend(std::iter::repeat(IGNORE_INDEX).take(padding));
+    }
+    let shape = [batch.len() as i64, max_len as i64];
+    (
+        Tensor::of_slice(&input_ids).view(shape).to(device),
+        Tensor::of_slice(&attention_mask).view(shape).to(device),
+        Tensor::of_slice(&labels).view(shape).to(device),
+    )
+}
+
+/// Fraction of the words of `eval_set` the model labels correctly
+fn evaluate(
+    model: &MobileBertForTokenClassification,
+    eval_set: &[Example],
+    pad_id: i64,
+    device: Device,
+    batch_size: usize,
+) -> anyhow::Result<f64> {
+    let mut correct = 0;
+    let mut total = 0;
+    for chunk in eval_set.chunks(batch_size) {
+        let batch: Vec<&Example> = chunk.iter().collect();
+        let (input_ids, attention_mask, labels) = batch_tensors(&batch, pad_id, device);
+        let output = tch::no_grad(|| {
+            model.forward_t(
+                Some(&input_ids),
+                None,
+                None,
+                None,
+                Some(&attention_mask),
+                false,
+            )
+        })?;
+        let predictions = output.logits.argmax(-1, false);
+        let words = labels.ne(IGNORE_INDEX);
+        correct += i64::from(
+            predictions
+                .eq1(&labels)
+                .logical_and(&words)
+                .sum(Kind::Int64),
+        );
+        total += i64::from(words.sum(Kind::Int64));
+    }
+    Ok(if total > 0 {
+        correct as f64 / total as f64
+    } else {
+        0.0
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_conllu_and_two_column_corpora() {
+        let corpus = "# sent_id = 1\n\
+                      1-2\tdon't\t_\t_\t_\t_\t_\t_\t_\t_\n\
+                      1\tdo\tdo\tAUX\tVBP\t_\t0\troot\t_\t_\n\
+                      2\tn't\tnot\tPART\tRB\t_\t1\tadvmod\t_\t_\n\
+                      \n\
+                      lol UH\n\
+                      ok\tJJ\n";
+        let sentences = read_corpus(corpus).unwrap();
+        assert_eq!(sentences.len(), 2);
+        assert_eq!(sentences[0][1], (String::from("n't"), String::from("RB")));
+        assert_eq!(sentences[1][1], (String::from("ok"), String::from("JJ")));
+        assert!(read_corpus("one two three\n").is_err());
+    }
+
+    #[test]
+    fn displays_epoch_metrics() {
+        let metrics = EpochMetrics {
+            epoch: 2,
+            epochs: 3,
+            loss: 0.12345,
+            eval_accuracy: Some(0.9612),
+        };
+        assert_eq!(metrics.to_string(), "Epoch 2/3: loss 0.1235, eval accuracy 96.12%");
+        let metrics = EpochMetrics {
+            eval_accuracy: None,
+            ..metrics
+        };
+        assert_eq!(metrics.to_string(), "Epoch 2/3: loss 0.1235");
+    }
+}
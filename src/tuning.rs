@@ -0,0 +1,122 @@
+//! # Batch size tuning
+//! `batch_size = "auto"` (or `--batch-size auto`) chooses the batch size on the device at
+//! startup instead of leaving it to be tuned by hand: after a warm-up, batches of 1, 2, 4...
+//! sentences are tagged, until a batch takes longer than `MAX_BATCH_LATENCY`, runs out of
+//! memory, or reaches `MAX_AUTO_BATCH_SIZE`, or doubling the batch size stops raising the
+//! throughput by `MIN_GAIN`. The size with the best throughput is used for the run.
+//!
+//! Modes without a loaded model at startup use `DEFAULT_BATCH_SIZE` for `auto`.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use serde::Deserialize;
+
+use crate::exit;
+use crate::tagger::Tagger;
+
+/// Largest batch size probed
+pub const MAX_AUTO_BATCH_SIZE: usize = 256;
+
+/// Latency of a batch above which larger batches are not probed
+pub const MAX_BATCH_LATENCY: Duration = Duration::from_secs(2);
+
+/// Throughput gain, as a fraction, for which a batch size twice as large is kept
+pub const MIN_GAIN: f64 = 0.05;
+
+/// Sentence of typical length the batches are made of
+const PROBE_SENTENCE: &str =
+    "The committee reviewed the annual report on Tuesday and asked the board to publish its findings before the end of the month.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "RawBatchSize")]
+/// # Number of sentences sent to the model at once
+pub enum BatchSize {
+    Fixed(usize),
+    /// Tuned on the device at startup
+    Auto,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawBatchSize {
+    Fixed(usize),
+    Named(String),
+}
+
+impl TryFrom<RawBatchSize> for BatchSize {
+    type Error = String;
+
+    fn try_from(raw: RawBatchSize) -> Result<BatchSize, Self::Error> {
+        match raw {
+            RawBatchSize::Fixed(size) => Ok(BatchSize::Fixed(size)),
+            RawBatchSize::Named(name) => name.parse().map_err(|err: anyhow::Error| err.to_string()),
+        }
+    }
+}
+
+impl FromStr for BatchSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<BatchSize, Self::Err> {
+        match s {
+            "auto" => Ok(BatchSize::Auto),
+            _ => s
+                .parse()
+                .map(BatchSize::Fixed)
+                .map_err(|_| anyhow!("Invalid batch size '{}', expected a number or auto", s)),
+        }
+    }
+}
+
+impl fmt::Display for BatchSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchSize::Fixed(size) => write!(f, "{}", size),
+            BatchSize::Auto => f.write_str("auto"),
+        }
+    }
+}
+
+/// Batch size with the best throughput of `tagger`, probed as described above
+pub fn tune(tagger: &dyn Tagger) -> usize {
+    //the first prediction pays for the allocations of the model
+    if exit::catch_inference(|| tagger.tag(&[PROBE_SENTENCE])).is_err() {
+        return 1;
+    }
+    let mut best = (1, 0.0);
+    let mut size = 1;
+    while size <= MAX_AUTO_BATCH_SIZE {
+        let batch = vec![PROBE_SENTENCE; size];
+        let start = Instant::now();
+        if exit::catch_inference(|| tagger.tag(&batch)).is_err() {
+            break;
+        }
+        let latency = start.elapsed();
+        let throughput = size as f64 / latency.as_secs_f64().max(1e-9);
+        if throughput < best.1 * (1.0 + MIN_GAIN) {
+            break;
+        }
+        best = (size, throughput);
+        if latency > MAX_BATCH_LATENCY {
+            break;
+        }
+        size *= 2;
+    }
+    best.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_numbers_and_auto() {
+        assert_eq!("16".parse::<BatchSize>().unwrap(), BatchSize::Fixed(16));
+        assert_eq!("auto".parse::<BatchSize>().unwrap(), BatchSize::Auto);
+        assert!("big".parse::<BatchSize>().is_err());
+    }
+}
@@ -0,0 +1,264 @@
+//! # Warnings
+//! Problems that do not stop tagging, such as an input above the limits, a sentence longer
+//! than the model reads or words tagged with low confidence. They are `Warning` values
+//! rather than lines on stderr, so that embedders can log, count or export them to their
+//! own observability systems: `POSModel::predict_with_warnings` returns them alongside
+//! the tags, and the functions that report them as they go (`Limits`, `AdaptiveBatch::tag`,
+//! `Config::pos_model`, the streaming and worker modes...) pass them to the `WarningSink`
+//! they are given. The library never prints them: closures of the right signature
+//! implement the trait, and the binary prints the warnings with `Stderr`. Runs spreading
+//! over several threads take a `SharedSink`.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use rustlib::pos_tagging::POSModel;
+//! use rustlib::warnings::DEFAULT_LOW_CONFIDENCE;
+//!
+//! let pos_model = POSModel::new(Default::default())?;
+//! let (tags, warnings) = pos_model.predict_with_warnings(&["My name is Amy."], DEFAULT_LOW_CONFIDENCE);
+//! for warning in &warnings {
+//!     println!("{}", serde_json::to_string(warning)?);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+use crate::pos_tagging::ScoredTag;
+
+/// Score below which `predict_with_warnings` reports words, when no other is needed
+pub const DEFAULT_LOW_CONFIDENCE: f64 = 0.5;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+#[non_exhaustive]
+/// # Problem met while tagging
+/// Sentences are numbered from 0 in their input, and serialized with a `kind` field
+/// naming the variant.
+pub enum Warning {
+    /// File above `max_file_size` bytes, left out
+    SkippedFile { path: String, size: u64, limit: u64 },
+    /// File above `max_file_size` bytes, tagged up to the limit
+    TruncatedFile { path: String, size: u64, limit: u64 },
+    /// Sentence above `max_sentence_length` characters, left out
+    SkippedSentence { sentence: usize, length: usize, limit: usize },
+    /// Sentence above `max_sentence_length` characters, truncated, or longer than the model
+    /// reads: only its first `tagged` characters, of `length`, are tagged
+    TruncatedSentence { sentence: usize, tagged: usize, length: usize },
+    /// Consecutive words of a sentence whose scores are below the threshold
+    LowConfidence {
+        sentence: usize,
+        /// Indices of the words among the tags of the sentence
+        words: Range<usize>,
+        /// Words separated by spaces
+        text: String,
+        /// Lowest score of the words
+        score: f64,
+    },
+    /// Sentence rewritten by the pre-processors (e.g. the normalizer) before tagging
+    Normalized {
+        sentence: usize,
+        original: String,
+        normalized: String,
+    },
+    /// The model ran out of memory tagging `sentences` sentences at once, the batch size
+    /// was lowered to `batch_size`
+    BatchSizeLowered { sentences: usize, batch_size: usize },
+    /// Label of the model outside the Penn Treebank and UPOS tag sets, not renamed
+    UnknownLabel { label: String },
+    /// `auto` batch size tuned on the model
+    BatchSizeTuned { batch_size: usize },
+    /// Pinned model file whose digest could not be checked or did not match, loaded with
+    /// `allow_unverified`
+    UnverifiedFile { path: String, error: String },
+    /// Output left as it is, under the `skip` output policy
+    SkippedOutput { path: String },
+    /// Followed input that became shorter than what was read, tagged again from the start
+    RestartedFile { path: String },
+    /// Batch of a distributed run that failed on a worker, queued again
+    BatchRetried { batch: usize, error: String },
+    /// Worker of a distributed run whose connection could not be set up
+    WorkerDropped { worker: String, error: String },
+    /// Batch a worker could not tag, reported to the coordinator
+    BatchFailed { error: String },
+    /// Attempt to connect to the coordinator of a distributed run that failed
+    ConnectionFailed { address: String, error: String },
+    /// Message of a queue that is not UTF-8 text, left out
+    SkippedMessage { topic: String, offset: i64 },
+    /// Result that could not be published to a queue
+    PublishFailed { error: String },
+    /// Job that failed and is tagged again
+    JobRetried {
+        job: String,
+        retry: usize,
+        retries: usize,
+        error: String,
+    },
+    /// Job that failed for good, moved to the list of failed jobs `key`
+    JobFailed { key: String, error: String },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::SkippedFile { path, size, limit } => {
+                write!(f, "skipped {} ({} bytes, limit {})", path, size, limit)
+            }
+            Warning::TruncatedFile { path, size, limit } => {
+                write!(f, "truncated {} to {} bytes ({} bytes)", path, limit, size)
+            }
+            Warning::SkippedSentence { sentence, length, limit } => {
+                write!(f, "skipped sentence {} ({} characters, limit {})", sentence + 1, length, limit)
+            }
+            Warning::TruncatedSentence { sentence, tagged, length } => write!(
+                f,
+                "only tagged the first {} of the {} characters of sentence {}",
+                tagged,
+                length,
+                sentence + 1
+            ),
+            Warning::LowConfidence { sentence, text, score, .. } => {
+                write!(f, "low confidence ({:.2}) in \"{}\" of sentence {}", score, text, sentence + 1)
+            }
+            Warning::Normalized { sentence, normalized, .. } => {
+                write!(f, "tagged sentence {} as \"{}\"", sentence + 1, normalized)
+            }
+            Warning::BatchSizeLowered { sentences, batch_size } => write!(
+                f,
+                "out of memory tagging {} sentences, lowering the batch size to {}",
+                sentences, batch_size
+            ),
+            Warning::UnknownLabel { label } => write!(
+                f,
+                "the model predicts label {} outside the Penn Treebank and UPOS tag sets, rename it with label_map",
+                label
+            ),
+            Warning::BatchSizeTuned { batch_size } => write!(f, "tuned the batch size to {}", batch_size),
+            Warning::UnverifiedFile { error, .. } => write!(f, "{}", error),
+            Warning::SkippedOutput { path } => write!(f, "skipped {}, the output already exists", path),
+            Warning::RestartedFile { path } => write!(f, "{} was truncated, tagging it again from the start", path),
+            Warning::BatchRetried { batch, error } => write!(f, "{}, tagging batch {} again", error, batch),
+            Warning::WorkerDropped { worker, error } => write!(f, "dropped worker {}: {}", worker, error),
+            Warning::BatchFailed { error } => write!(f, "could not tag a batch: {}", error),
+            Warning::ConnectionFailed { address, error } => write!(f, "could not connect to {}: {}", address, error),
+            Warning::SkippedMessage { topic, offset } => {
+                write!(f, "skipped a message of {} at offset {}, not UTF-8 text", topic, offset)
+            }
+            Warning::PublishFailed { error } => write!(f, "could not publish a result: {}", error),
+            Warning::JobRetried { job, retry, retries, error } => {
+                write!(f, "job {} failed, retry {} of {}: {}", job, retry, retries, error)
+            }
+            Warning::JobFailed { key, error } => write!(f, "moved a job to {}: {}", key, error),
+        }
+    }
+}
+
+/// # Receives the warnings of an operation as they occur
+pub trait WarningSink {
+    fn warn(&self, warning: Warning);
+}
+
+impl<F> WarningSink for F
+where
+    F: Fn(Warning),
+{
+    fn warn(&self, warning: Warning) {
+        self(warning)
+    }
+}
+
+impl<S> WarningSink for Arc<S>
+where
+    S: WarningSink + ?Sized,
+{
+    fn warn(&self, warning: Warning) {
+        (**self).warn(warning)
+    }
+}
+
+/// Sink shared by the threads of a run
+pub type SharedSink = Arc<dyn WarningSink + Send + Sync>;
+
+/// Collects the warnings, in order
+impl WarningSink for Mutex<Vec<Warning>> {
+    fn warn(&self, warning: Warning) {
+        if let Ok(mut warnings) = self.lock() {
+            warnings.push(warning);
+        }
+    }
+}
+
+/// # Prints the warnings on stderr
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stderr;
+
+impl WarningSink for Stderr {
+    fn warn(&self, warning: Warning) {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+/// Runs of consecutive words of `tags`, sentence number `sentence`, scored below `threshold`
+pub fn low_confidence(sentence: usize, tags: &[ScoredTag], threshold: f64) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut start = 0;
+    while start < tags.len() {
+        if tags[start].score >= threshold {
+            start += 1;
+            continue;
+        }
+        let end = tags[start..]
+            .iter()
+            .position(|scored_tag| scored_tag.score >= threshold)
+            .map_or(tags.len(), |len| start + len);
+        let words: Vec<&str> = tags[start..end].iter().map(|scored_tag| scored_tag.tag.word.as_str()).collect();
+        warnings.push(Warning::LowConfidence {
+            sentence,
+            words: start..end,
+            text: words.join(" "),
+            score: tags[start..end].iter().map(|scored_tag| scored_tag.score).fold(f64::INFINITY, f64::min),
+        });
+        start = end;
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pos_tagging::POSTag;
+
+    #[test]
+    fn groups_consecutive_low_confidence_words() {
+        let tags: Vec<ScoredTag> = [("It", 0.9), ("rained", 0.3), ("cats", 0.4), ("today", 0.8), ("!", 0.1)]
+            .iter()
+            .map(|(word, score)| ScoredTag {
+                tag: POSTag {
+                    word: word.to_string(),
+                    label: "X".to_owned(),
+                },
+                score: *score,
+            })
+            .collect();
+        let warnings = low_confidence(2, &tags, DEFAULT_LOW_CONFIDENCE);
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(
+            warnings[0],
+            Warning::LowConfidence {
+                sentence: 2,
+                words: 1..3,
+                text: "rained cats".to_owned(),
+                score: 0.3,
+            }
+        );
+        assert_eq!(warnings[1].to_string(), "low confidence (0.10) in \"!\" of sentence 3");
+        let collected = Mutex::new(Vec::new());
+        collected.warn(warnings[1].clone());
+        assert_eq!(collected.into_inner().unwrap(), &warnings[1..]);
+    }
+}
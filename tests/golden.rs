@@ -0,0 +1,87 @@
+//! Golden-file tests of the command line: tags the files of `tests/fixtures` with the
+//! rule-based `MockTagger` and compares the output with `tests/golden`. Run
+//! `BLESS=1 cargo test --features test-util --test golden` to update the golden files
+//! after an intended change of the output.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+/// Output of the binary tagging `fixtures` into `output` with `args`
+fn run(fixtures: &[&str], output: &str, args: &[&str]) -> String {
+    let out_dir = env::temp_dir().join(format!("berttagr-golden-{}", std::process::id()));
+    fs::create_dir_all(&out_dir).unwrap();
+    let output_path = out_dir.join(output);
+    let _ = fs::remove_file(&output_path);
+
+    let status = Command::new(env!("CARGO_BIN_EXE_berttagr_file"))
+        .env("BERTTAGR_MOCK_TAGGER", "1")
+        //keep the configuration file of the user out of the tests
+        .env("XDG_CONFIG_HOME", &out_dir)
+        .args(args)
+        .args(fixtures.iter().map(|fixture| tests_dir().join("fixtures").join(fixture)))
+        .arg(&output_path)
+        .status()
+        .expect("the binary runs");
+    assert!(status.success(), "berttagr_file exited with {}", status);
+    fs::read_to_string(&output_path).unwrap()
+}
+
+/// Run the binary on `fixture` with `args`, and compare its output with `golden`
+fn check(fixture: &str, golden: &str, args: &[&str]) {
+    let output = run(&[fixture], golden, args);
+    let golden_path = tests_dir().join("golden").join(golden);
+    if env::var_os("BLESS").is_some() {
+        fs::write(&golden_path, &output).unwrap();
+    }
+    let expected = fs::read_to_string(&golden_path).unwrap();
+    assert_eq!(output, expected, "output differs from {}", golden_path.display());
+}
+
+#[test]
+fn tsv() {
+    check("sample.txt", "sample.tsv", &["--format", "tsv"]);
+}
+
+#[test]
+fn json() {
+    check("sample.txt", "sample.json", &["--format", "json"]);
+}
+
+#[test]
+fn order_is_stable() {
+    //batches are sorted by length, the output keeps the order of the text
+    let expected = fs::read_to_string(tests_dir().join("golden").join("sample.tsv")).unwrap();
+    for batch_size in &["1", "2", "64"] {
+        let output = run(&["sample.txt"], "ordered.tsv", &["--format", "tsv", "--batch-size", batch_size]);
+        assert_eq!(output, expected, "output differs with --batch-size {}", batch_size);
+    }
+    //batches tagged in parallel are written back in order
+    let output = run(&["sample.txt"], "ordered.tsv", &["--format", "tsv", "--batch-size", "1", "--workers", "3"]);
+    assert_eq!(output, expected, "output differs with --workers 3");
+
+    //documents in the order of the inputs
+    let documents = run(&["other.txt", "sample.txt"], "ordered.tsv", &["--format", "tsv", "--batch-size", "1"]);
+    let other = documents.find("other.txt").unwrap();
+    let sample = documents.find("sample.txt").unwrap();
+    assert!(other < sample);
+    assert!(documents[sample..].contains(&expected));
+    let again = run(&["other.txt", "sample.txt"], "ordered.tsv", &["--format", "tsv", "--batch-size", "7"]);
+    assert_eq!(again, documents);
+}
+
+#[test]
+fn shards_concatenate_to_the_whole_output() {
+    let expected = fs::read_to_string(tests_dir().join("golden").join("sample.tsv")).unwrap();
+    let shards: Vec<String> = ["1/3", "2/3", "3/3"]
+        .iter()
+        .map(|shard| run(&["sample.txt"], "shard.tsv", &["--format", "tsv", "--shard", shard]))
+        .collect();
+    assert!(shards.iter().all(|shard| !shard.is_empty()));
+    assert_eq!(shards.concat(), expected);
+}